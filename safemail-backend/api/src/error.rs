@@ -1,51 +1,342 @@
 use std::fmt::Display;
 
-use axum::{body::Body, http::StatusCode, response::IntoResponse};
-use domain::error::{SmError, UserError, ValidationError};
+use axum::{
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use domain::error::{
+    BlobError, BridgeError, CryptographyError, DatabaseError, FederationError, IdempotencyError,
+    MessageError, RateLimitError, SessionError, SmError, StampError, UserError, ValidationError,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use utoipa::ToSchema;
 
+use crate::request_id;
+
+/// An error response. Every error leaves the server as an [`ErrorEnvelope`] whose `code` is
+/// stable, so clients can branch on it instead of on the message.
 #[derive(Debug)]
-pub struct ApiError(pub SmError);
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+    details: Option<Value>,
+    retryable: bool,
+    retry_after_seconds: Option<i64>,
+    /// The chain of underlying errors, logged but not sent to the client.
+    cause: Option<String>,
+}
+
+/// Body of every error response.
+#[derive(Serialize, ToSchema)]
+pub struct ErrorEnvelope {
+    pub error: ErrorBody,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// Stable identifier of the error, such as `user_not_found`.
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<Value>,
+    /// Whether the same request may succeed if simply sent again.
+    pub retryable: bool,
+    pub request_id: Option<String>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            details: None,
+            retryable: false,
+            retry_after_seconds: None,
+            cause: None,
+        }
+    }
+
+    fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub fn internal() -> Self {
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            "Server error",
+        )
+    }
+
+    pub fn method_not_allowed() -> Self {
+        Self::new(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "method_not_allowed",
+            "Method not allowed",
+        )
+    }
+}
+
+/// Answers requests that match no route.
+pub async fn route_not_found() -> ApiError {
+    ApiError::new(StatusCode::NOT_FOUND, "route_not_found", "No such route")
+}
+
 impl From<SmError> for ApiError {
     fn from(e: SmError) -> Self {
-        Self(e)
+        let (status, code) = match &e {
+            SmError::Database(e) => match e {
+                DatabaseError::Arbitrary | DatabaseError::Query(_) => {
+                    (StatusCode::INTERNAL_SERVER_ERROR, "database_error")
+                }
+                DatabaseError::UniqueViolation { .. } => (StatusCode::CONFLICT, "unique_violation"),
+                DatabaseError::ForeignKeyViolation { .. } => {
+                    (StatusCode::CONFLICT, "foreign_key_violation")
+                }
+                DatabaseError::SerializationFailure(_) => {
+                    (StatusCode::SERVICE_UNAVAILABLE, "transaction_conflict")
+                }
+                DatabaseError::Timeout(_) => (StatusCode::SERVICE_UNAVAILABLE, "database_timeout"),
+            },
+            SmError::Storage(_) => (StatusCode::INTERNAL_SERVER_ERROR, "storage_error"),
+            SmError::User(e) => match e {
+                UserError::UserNotFound => (StatusCode::NOT_FOUND, "user_not_found"),
+                UserError::UserAlreadyExists => (StatusCode::CONFLICT, "user_already_exists"),
+                UserError::InvalidCredentials => (StatusCode::UNAUTHORIZED, "invalid_credentials"),
+                UserError::InvalidUsername => (StatusCode::BAD_REQUEST, "invalid_username"),
+                UserError::InvalidPassword => (StatusCode::BAD_REQUEST, "invalid_password"),
+                UserError::InvalidPublicKey => (StatusCode::BAD_REQUEST, "invalid_public_key"),
+            },
+            SmError::Validation(_) => (StatusCode::BAD_REQUEST, "validation_failed"),
+            SmError::Cryptography(e) => match e {
+                CryptographyError::InvalidSignature => {
+                    (StatusCode::BAD_REQUEST, "invalid_signature")
+                }
+                CryptographyError::InvalidSystemKeys => {
+                    (StatusCode::INTERNAL_SERVER_ERROR, "invalid_system_keys")
+                }
+            },
+            SmError::Session(e) => match e {
+                SessionError::SessionNotFound => (StatusCode::UNAUTHORIZED, "invalid_session"),
+            },
+            SmError::Stamp(e) => match e {
+                StampError::InvalidStamp => (StatusCode::UNAUTHORIZED, "invalid_stamp"),
+                StampError::InvalidTimePeriod => {
+                    (StatusCode::UNAUTHORIZED, "stamp_outside_validity")
+                }
+                StampError::InvalidProofOfWork => {
+                    (StatusCode::UNAUTHORIZED, "invalid_proof_of_work")
+                }
+                StampError::StampRequestNotFound => {
+                    (StatusCode::UNAUTHORIZED, "stamp_request_not_found")
+                }
+                StampError::StampRequestExpired => {
+                    (StatusCode::UNAUTHORIZED, "stamp_request_expired")
+                }
+                StampError::StampRequestSolved => (StatusCode::CONFLICT, "stamp_request_solved"),
+            },
+            SmError::Message(e) => match e {
+                MessageError::MessageNotFound => (StatusCode::NOT_FOUND, "message_not_found"),
+                MessageError::RecipientMetadataConflict { .. } => {
+                    (StatusCode::CONFLICT, "recipient_metadata_conflict")
+                }
+                MessageError::MailboxFull => (StatusCode::INSUFFICIENT_STORAGE, "mailbox_full"),
+                MessageError::OutboxFull => (StatusCode::INSUFFICIENT_STORAGE, "outbox_full"),
+                MessageError::OutboxEntryNotFound => {
+                    (StatusCode::NOT_FOUND, "outbox_entry_not_found")
+                }
+                MessageError::ScheduledMessageNotFound => {
+                    (StatusCode::NOT_FOUND, "scheduled_message_not_found")
+                }
+                MessageError::SenderNotAttributable => {
+                    (StatusCode::UNPROCESSABLE_ENTITY, "sender_not_attributable")
+                }
+            },
+            SmError::Blob(e) => match e {
+                BlobError::BlobNotFound => (StatusCode::NOT_FOUND, "blob_not_found"),
+                BlobError::UploadMismatch => (StatusCode::CONFLICT, "upload_mismatch"),
+                BlobError::InvalidChunk => (StatusCode::BAD_REQUEST, "invalid_chunk"),
+                BlobError::UploadIncomplete => (StatusCode::BAD_REQUEST, "upload_incomplete"),
+                BlobError::HashMismatch => (StatusCode::UNPROCESSABLE_ENTITY, "hash_mismatch"),
+            },
+            SmError::Idempotency(e) => match e {
+                IdempotencyError::RequestInProgress => {
+                    (StatusCode::CONFLICT, "request_in_progress")
+                }
+                IdempotencyError::KeyReused => {
+                    (StatusCode::UNPROCESSABLE_ENTITY, "idempotency_key_reused")
+                }
+            },
+            SmError::Federation(e) => match e {
+                FederationError::InvalidAddress => (StatusCode::BAD_REQUEST, "invalid_address"),
+                FederationError::PeerUnreachable => (StatusCode::BAD_GATEWAY, "peer_unreachable"),
+                // Passing on the remote status could be mistaken for one about this request
+                FederationError::RemoteRejected { status, .. } if *status < 500 => {
+                    (StatusCode::UNPROCESSABLE_ENTITY, "remote_rejected")
+                }
+                FederationError::RemoteRejected { .. } => {
+                    (StatusCode::BAD_GATEWAY, "remote_rejected")
+                }
+                FederationError::InvalidServerSignature => {
+                    (StatusCode::UNAUTHORIZED, "invalid_server_signature")
+                }
+                FederationError::RequestExpired => (StatusCode::UNAUTHORIZED, "request_expired"),
+                FederationError::RequestReplayed => (StatusCode::CONFLICT, "request_replayed"),
+            },
+            SmError::Bridge(e) => match e {
+                BridgeError::RateLimited => (StatusCode::TOO_MANY_REQUESTS, "rate_limited"),
+                BridgeError::RelayRejected(_) => (StatusCode::BAD_GATEWAY, "relay_rejected"),
+                BridgeError::RelayUnavailable(_) => (StatusCode::BAD_GATEWAY, "relay_unavailable"),
+            },
+            SmError::RateLimit(e) => match e {
+                RateLimitError::Exceeded { .. } => (StatusCode::TOO_MANY_REQUESTS, "rate_limited"),
+            },
+        };
+        let mut error = Self::new(status, code, e.to_string());
+        error.retryable = e.is_retryable();
+        if let SmError::Database(e) = &e {
+            error.cause = match e {
+                DatabaseError::UniqueViolation { constraint, .. }
+                | DatabaseError::ForeignKeyViolation { constraint, .. } => Some(format!(
+                    "constraint {}{}",
+                    constraint,
+                    causes(e)
+                        .map(|cause| format!(": {}", cause))
+                        .unwrap_or_default()
+                )),
+                _ => causes(e),
+            };
+        }
+        match e {
+            SmError::Message(MessageError::RecipientMetadataConflict { current_version }) => {
+                error.with_details(json!({ "current_version": current_version }))
+            }
+            SmError::Federation(FederationError::RemoteRejected { status, code, .. }) => {
+                error.with_details(json!({ "status": status, "code": code }))
+            }
+            SmError::RateLimit(RateLimitError::Exceeded {
+                retry_after_seconds,
+            }) => {
+                error.retry_after_seconds = Some(retry_after_seconds);
+                error.with_details(json!({ "retry_after_seconds": retry_after_seconds }))
+            }
+            _ => error,
+        }
+    }
+}
+/// The sources of `e`, outermost first, if it has any.
+fn causes(e: &dyn std::error::Error) -> Option<String> {
+    let mut causes = Vec::new();
+    let mut source = e.source();
+    while let Some(cause) = source {
+        let text = cause.to_string();
+        // Wrappers tend to repeat the message of what they wrap
+        if !causes
+            .last()
+            .is_some_and(|last: &String| last.ends_with(&text))
+        {
+            causes.push(text);
+        }
+        source = cause.source();
     }
+    (!causes.is_empty()).then(|| causes.join(": "))
 }
+
 impl From<ValidationError> for ApiError {
     fn from(e: ValidationError) -> Self {
-        Self(SmError::Validation(e))
+        SmError::Validation(e).into()
     }
 }
 impl From<UserError> for ApiError {
     fn from(e: UserError) -> Self {
-        Self(SmError::User(e))
+        SmError::User(e).into()
+    }
+}
+impl From<FederationError> for ApiError {
+    fn from(e: FederationError) -> Self {
+        SmError::Federation(e).into()
+    }
+}
+impl From<MessageError> for ApiError {
+    fn from(e: MessageError) -> Self {
+        SmError::Message(e).into()
+    }
+}
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        let code = match &rejection {
+            JsonRejection::JsonDataError(_) => "invalid_body",
+            JsonRejection::JsonSyntaxError(_) => "malformed_json",
+            JsonRejection::MissingJsonContentType(_) => "unsupported_media_type",
+            _ => "unreadable_body",
+        };
+        Self::new(rejection.status(), code, rejection.body_text())
+    }
+}
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::new(rejection.status(), "invalid_query", rejection.body_text())
+    }
+}
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        let code = if rejection.status().is_server_error() {
+            "internal_error"
+        } else {
+            "invalid_path"
+        };
+        Self::new(rejection.status(), code, rejection.body_text())
     }
 }
 impl Display for ApiError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", self.message)
+    }
+}
+
+impl ApiError {
+    /// Logs the error if it is worth logging and turns it into what the client is sent.
+    pub fn into_envelope(self, request_id: Option<String>) -> ErrorEnvelope {
+        if self.status.is_server_error() || self.cause.is_some() {
+            eprintln!(
+                "request {}: {}{}",
+                request_id.as_deref().unwrap_or("-"),
+                self.message,
+                self.cause
+                    .map(|cause| format!(": {}", cause))
+                    .unwrap_or_default()
+            );
+        }
+        ErrorEnvelope {
+            error: ErrorBody {
+                code: self.code,
+                message: self.message,
+                details: self.details,
+                retryable: self.retryable,
+                request_id,
+            },
+        }
     }
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> axum::response::Response {
-        let status = match &self.0 {
-            SmError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            SmError::User(e) => match e {
-                UserError::UserNotFound => StatusCode::NOT_FOUND,
-                UserError::UserAlreadyExists => StatusCode::CONFLICT,
-                UserError::InvalidCredentials => StatusCode::UNAUTHORIZED,
-                UserError::InvalidUsername => StatusCode::BAD_REQUEST,
-                UserError::InvalidPassword => StatusCode::BAD_REQUEST,
-                UserError::InvalidPublicKey => StatusCode::BAD_REQUEST,
-            },
-            SmError::Validation(_) => StatusCode::BAD_REQUEST,
-            SmError::Cryptography(_) => StatusCode::BAD_REQUEST,
-            SmError::Session(_) => StatusCode::UNAUTHORIZED,
-            SmError::Stamp(_) => StatusCode::UNAUTHORIZED,
-        };
-        axum::response::Response::builder()
-            .status(status)
-            .body(Body::from(self.0.to_string()))
-            .unwrap()
+        let status = self.status;
+        let retry_after = self.retry_after_seconds;
+        let envelope = self.into_envelope(request_id::current());
+        let mut response = (status, Json(envelope)).into_response();
+        if let Some(seconds) = retry_after {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
     }
 }
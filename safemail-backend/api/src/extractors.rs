@@ -1,14 +1,93 @@
+use std::net::{IpAddr, SocketAddr};
+
 use application::user::queries::GetUserBySessionQuery;
 use axum::{
     async_trait,
-    extract::FromRequestParts,
+    extract::{ConnectInfo, FromRequest, FromRequestParts},
     http::{request::Parts, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     Extension, RequestPartsExt,
 };
 use domain::uuid::Uuid;
-use domain::{error::SmError, user::User};
+use domain::{
+    error::SmError,
+    user::{User, UserRepository},
+};
+use serde::de::DeserializeOwned;
+
+use crate::{error::ApiError, state::AppState};
+
+const FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
+
+/// `axum::Json`, rejecting malformed bodies with the JSON error envelope.
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(ApiError))]
+pub struct Json<T>(pub T);
+
+impl<T: DeserializeOwned> Json<T> {
+    /// Parses a body that was read as bytes, e.g. to be kept for idempotent replays.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ApiError> {
+        let axum::Json(value) = axum::Json::from_bytes(bytes)?;
+        Ok(Json(value))
+    }
+}
+
+impl<T> IntoResponse for Json<T>
+where
+    axum::Json<T>: IntoResponse,
+{
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+/// `axum::extract::Query`, rejecting malformed query strings with the JSON error envelope.
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(ApiError))]
+pub struct Query<T>(pub T);
 
-use crate::state::AppState;
+/// `axum::extract::Path`, rejecting malformed path segments with the JSON error envelope.
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(ApiError))]
+pub struct Path<T>(pub T);
+
+/// The address of the client. Behind a proxy that sets `X-Forwarded-For`, and with
+/// `SM_TRUST_FORWARDED_FOR` set, this is the address the proxy saw the request come from.
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Extension(state) = parts
+            .extract::<Extension<AppState>>()
+            .await
+            .map_err(|_| ApiError::internal())?;
+        if state.trust_forwarded_for {
+            // The proxy appends the address it saw, anything before it came from the client
+            let forwarded = parts
+                .headers
+                .get_all(FORWARDED_FOR_HEADER)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .last()
+                .and_then(|ip| ip.trim().parse().ok());
+            if let Some(ip) = forwarded {
+                return Ok(ClientIp(ip));
+            }
+        }
+        let ConnectInfo(address) = parts
+            .extract::<ConnectInfo<SocketAddr>>()
+            .await
+            .map_err(|_| ApiError::internal())?;
+        Ok(ClientIp(address.ip()))
+    }
+}
 
 pub struct AuthUser(pub User);
 
@@ -17,41 +96,48 @@ impl<S> FromRequestParts<S> for AuthUser
 where
     S: Send + Sync,
 {
-    type Rejection = (StatusCode, String);
+    type Rejection = ApiError;
 
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        let Extension(state) = parts.extract::<Extension<AppState>>().await.map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to extract AppState".to_string(),
-            )
-        })?;
+        let Extension(state) = parts
+            .extract::<Extension<AppState>>()
+            .await
+            .map_err(|_| ApiError::internal())?;
 
-        let headers = parts.extract::<HeaderMap>().await.map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to extract headers".to_string(),
-            )
-        })?;
+        let headers = parts
+            .extract::<HeaderMap>()
+            .await
+            .map_err(|_| ApiError::internal())?;
 
         // Get the Authorization header
         let auth_header = headers
             .get(axum::http::header::AUTHORIZATION)
             .and_then(|header| header.to_str().ok())
-            .ok_or((
-                StatusCode::UNAUTHORIZED,
-                "Missing authorization header".to_string(),
-            ))?;
+            .ok_or_else(|| {
+                ApiError::new(
+                    StatusCode::UNAUTHORIZED,
+                    "missing_authorization",
+                    "Missing authorization header",
+                )
+            })?;
 
         // Check if it's a Bearer token and extract the value
-        let bearer_token = auth_header.strip_prefix("Bearer ").ok_or((
-            StatusCode::UNAUTHORIZED,
-            "Invalid authorization header".to_string(),
-        ))?;
+        let bearer_token = auth_header.strip_prefix("Bearer ").ok_or_else(|| {
+            ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "invalid_authorization",
+                "Invalid authorization header",
+            )
+        })?;
 
         // Parse the bearer token as a UUID
-        let session_id = Uuid::parse_str(bearer_token)
-            .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid session ID".to_string()))?;
+        let session_id = Uuid::parse_str(bearer_token).map_err(|_| {
+            ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "invalid_authorization",
+                "Invalid session ID",
+            )
+        })?;
 
         // Create and handle the GetUserBySessionQuery
         let query = GetUserBySessionQuery {
@@ -61,13 +147,44 @@ where
             .handle(&state.user_repository, &state.session_repository)
             .await
             .map_err(|e| match e {
-                SmError::Session(_) => (StatusCode::UNAUTHORIZED, "Invalid session".to_string()),
-                _ => (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Server error".to_string(),
-                ),
+                SmError::Session(_) => ApiError::from(e),
+                _ => ApiError::internal(),
             })?;
 
         Ok(AuthUser(user))
     }
 }
+
+/// Admits only authenticated users listed in `sm.admins`.
+pub struct AdminUser;
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AdminUser
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AuthUser(user) = AuthUser::from_request_parts(parts, state).await?;
+        let Extension(app_state) = parts
+            .extract::<Extension<AppState>>()
+            .await
+            .map_err(|_| ApiError::internal())?;
+
+        let is_admin = app_state
+            .user_repository
+            .is_admin(user.id)
+            .await
+            .map_err(|_| ApiError::internal())?;
+        if !is_admin {
+            return Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                "admin_required",
+                "Admin access required",
+            ));
+        }
+
+        Ok(AdminUser)
+    }
+}
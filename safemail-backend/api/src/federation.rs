@@ -0,0 +1,94 @@
+use application::federation::commands::VerifySignedRequestCommand;
+use application::rate_limit::commands::ConsumeRateLimitCommand;
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRequest, Request},
+    http::HeaderMap,
+    Extension, RequestExt,
+};
+use domain::{
+    chrono::{DateTime, Utc},
+    error::{FederationError, SmError},
+    federation::{normalize_host, SignedRequest},
+    rate_limit::{RateLimitKey, RouteClass},
+};
+use infrastructure::services::federation::{
+    DATE_HEADER, ORIGIN_HEADER, REQUEST_ID_HEADER, SIGNATURE_HEADER,
+};
+
+use crate::{error::ApiError, extractors::ClientIp, state::AppState};
+
+/// A request from another server, verified to be signed by the server it claims to come from.
+pub struct ServerRequest {
+    pub origin: String,
+    pub body: Bytes,
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Result<&'a str, FederationError> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(FederationError::InvalidServerSignature)
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for ServerRequest
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(mut req: Request, _state: &S) -> Result<Self, Self::Rejection> {
+        let Extension(state) = req
+            .extract_parts::<Extension<AppState>>()
+            .await
+            .map_err(|_| ApiError::internal())?;
+        let ClientIp(ip) = req.extract_parts::<ClientIp>().await?;
+        ConsumeRateLimitCommand {
+            class: RouteClass::Federation,
+            keys: vec![RateLimitKey::Ip(ip)],
+        }
+        .handle(&state.rate_limiter, &state.rate_limit_policy)
+        .await?;
+        verify(req, state).await.map_err(ApiError::from)
+    }
+}
+
+/// Reads the origin's signature off the headers and checks it against the body.
+async fn verify(req: Request, state: AppState) -> Result<ServerRequest, SmError> {
+    let headers = req.headers();
+    let origin = normalize_host(header(headers, ORIGIN_HEADER)?)?;
+    let id = header(headers, REQUEST_ID_HEADER)?
+        .parse()
+        .map_err(|_| FederationError::InvalidServerSignature)?;
+    let sent_at = DateTime::parse_from_rfc3339(header(headers, DATE_HEADER)?)
+        .map_err(|_| FederationError::InvalidServerSignature)?
+        .with_timezone(&Utc);
+    let signature = header(headers, SIGNATURE_HEADER)?.to_string();
+    let body = Bytes::from_request(req, &())
+        .await
+        .map_err(|_| FederationError::InvalidServerSignature)?;
+    let text =
+        String::from_utf8(body.to_vec()).map_err(|_| FederationError::InvalidServerSignature)?;
+
+    VerifySignedRequestCommand {
+        request: SignedRequest {
+            origin: origin.clone(),
+            id,
+            sent_at,
+            body: text,
+            signature,
+        },
+        local_host: state.local_host.clone(),
+    }
+    .handle(
+        &state.federation_client,
+        &state.federation_peer_repository,
+        &state.cryptography_service,
+        &state.serialize_service,
+    )
+    .await?;
+
+    Ok(ServerRequest { origin, body })
+}
@@ -0,0 +1,168 @@
+use std::{future::Future, time::Duration};
+
+use application::idempotency::commands::{
+    ClaimIdempotencyKeyCommand, CompleteIdempotencyKeyCommand, ReleaseIdempotencyKeyCommand,
+    RenewIdempotencyKeyCommand,
+};
+use axum::{
+    async_trait,
+    body::{Body, Bytes},
+    extract::FromRequestParts,
+    http::{header::CONTENT_TYPE, request::Parts, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use domain::{idempotency::IdempotentOutcome, uuid::Uuid};
+
+use crate::{error::ApiError, request_id, state::AppState};
+
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+const IDEMPOTENT_REPLAYED_HEADER: &str = "Idempotent-Replayed";
+const MAX_STORED_RESPONSE_SIZE: usize = 1024 * 1024;
+
+/// The optional `Idempotency-Key` request header.
+pub struct IdempotencyKey(pub Option<String>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for IdempotencyKey
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        match parts.headers.get(IDEMPOTENCY_KEY_HEADER) {
+            Some(header) => header
+                .to_str()
+                .map(|key| IdempotencyKey(Some(key.to_string())))
+                .map_err(|_| {
+                    ApiError::new(
+                        StatusCode::BAD_REQUEST,
+                        "invalid_idempotency_key",
+                        "Invalid idempotency key header",
+                    )
+                }),
+            None => Ok(IdempotencyKey(None)),
+        }
+    }
+}
+
+/// Processes a request at most once per sender and idempotency key, replaying the stored
+/// response for repeats. The claim on the key is renewed while the request is handled, so that
+/// only claims of requests that are no longer running go stale. Responses to failures on the
+/// server's side are not stored, so those requests can be retried. Without a key, the request
+/// is simply processed.
+pub async fn idempotent<R>(
+    state: AppState,
+    sender_id: Uuid,
+    key: Option<String>,
+    scope: &'static str,
+    body: Bytes,
+    handler: impl Future<Output = R> + Send + 'static,
+) -> Response
+where
+    R: IntoResponse,
+{
+    let Some(key) = key else {
+        return handler.await.into_response();
+    };
+
+    // Run detached from the connection: a client that gives up waiting must not cancel the
+    // request between delivering the message and storing the outcome
+    let request_id = request_id::current();
+    tokio::spawn(request_id::scope(request_id, async move {
+        let claim = ClaimIdempotencyKeyCommand {
+            sender_id,
+            key: key.clone(),
+            scope,
+            request_body: &body,
+        }
+        .handle(
+            &state.idempotency_repository,
+            &state.cryptography_service,
+            &state.idempotency_policy,
+        )
+        .await;
+        match claim {
+            Ok(None) => {}
+            Ok(Some(outcome)) => return replay(outcome),
+            Err(e) => return ApiError::from(e).into_response(),
+        }
+
+        let mut renewal = tokio::time::interval(Duration::from_millis(
+            state.idempotency_policy.stale_claim_seconds as u64 * 1000 / 3,
+        ));
+        renewal.tick().await;
+        let handler = async move { handler.await.into_response() };
+        tokio::pin!(handler);
+        let response = loop {
+            tokio::select! {
+                response = &mut handler => break response,
+                _ = renewal.tick() => renew(&state, sender_id, &key).await,
+            }
+        };
+        if response.status().is_server_error() {
+            release(&state, sender_id, key).await;
+            return response;
+        }
+        let (parts, response_body) = response.into_parts();
+        let Ok(response_body) = axum::body::to_bytes(response_body, MAX_STORED_RESPONSE_SIZE).await
+        else {
+            release(&state, sender_id, key).await;
+            return ApiError::internal().into_response();
+        };
+
+        let outcome = IdempotentOutcome {
+            status: parts.status.as_u16() as i32,
+            content_type: parts
+                .headers
+                .get(CONTENT_TYPE)
+                .and_then(|content_type| content_type.to_str().ok())
+                .map(str::to_string),
+            body: response_body.to_vec(),
+        };
+        let complete = CompleteIdempotencyKeyCommand {
+            sender_id,
+            key,
+            outcome,
+        };
+        if let Err(e) = complete.handle(&state.idempotency_repository).await {
+            eprintln!("failed to store idempotent outcome: {}", e);
+        }
+        Response::from_parts(parts, Body::from(response_body))
+    }))
+    .await
+    .unwrap_or_else(|_| ApiError::internal().into_response())
+}
+
+async fn renew(state: &AppState, sender_id: Uuid, key: &str) {
+    let command = RenewIdempotencyKeyCommand {
+        sender_id,
+        key: key.to_string(),
+    };
+    if let Err(e) = command.handle(&state.idempotency_repository).await {
+        eprintln!("failed to renew idempotency key: {}", e);
+    }
+}
+
+async fn release(state: &AppState, sender_id: Uuid, key: String) {
+    let command = ReleaseIdempotencyKeyCommand { sender_id, key };
+    if let Err(e) = command.handle(&state.idempotency_repository).await {
+        eprintln!("failed to release idempotency key: {}", e);
+    }
+}
+
+fn replay(outcome: IdempotentOutcome) -> Response {
+    let mut response = Response::new(Body::from(outcome.body));
+    *response.status_mut() =
+        StatusCode::from_u16(outcome.status as u16).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    if let Some(content_type) = outcome
+        .content_type
+        .and_then(|content_type| HeaderValue::from_str(&content_type).ok())
+    {
+        response.headers_mut().insert(CONTENT_TYPE, content_type);
+    }
+    response
+        .headers_mut()
+        .insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+    response
+}
@@ -1,9 +1,11 @@
 use axum::{
-    http::HeaderValue,
-    routing::{get, post},
+    http::header,
+    middleware,
+    routing::{delete, get, post, put},
     Extension, Router,
 };
-use tower_http::cors::{Any, CorsLayer};
+use infrastructure::config::{Config, ServerConfig};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 
 use std::net::SocketAddr;
 use tokio::net::TcpListener;
@@ -12,9 +14,22 @@ use crate::state::AppState;
 
 mod error;
 mod extractors;
+mod federation;
+mod idempotency;
+mod openapi;
+mod request_id;
 mod state;
+mod tasks;
+#[cfg(test)]
+mod tests;
+mod version;
 mod routes {
+    pub mod admin;
+    pub mod blob;
+    pub mod bridge;
+    pub mod federation;
     pub mod message;
+    pub mod outbox;
     pub mod stamp;
     pub mod user;
 }
@@ -23,13 +38,96 @@ mod routes {
 async fn main() {
     // load env vars
     dotenvy::dotenv().ok();
-    // build our application with a single route
-    let app = Router::new()
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let state = AppState::new(&config).await;
+    tasks::spawn_trash_purge(state.clone());
+    tasks::spawn_expired_message_purge(state.clone());
+    tasks::spawn_change_feed_pruning(state.clone(), config.retention.change_days);
+    tasks::spawn_blob_garbage_collection(state.clone());
+    tasks::spawn_orphaned_content_collection(state.clone());
+    tasks::spawn_idempotency_key_purge(state.clone(), config.retention.idempotency_key_hours);
+    tasks::spawn_scheduled_message_dispatch(state.clone());
+    tasks::spawn_rate_limit_purge(state.clone());
+    tasks::spawn_federation_request_purge(state.clone());
+    let app = app(&config, state);
+
+    let addr = config.server.listen_addr;
+    println!("listening on http://{}", addr);
+    let tcp_listener = TcpListener::bind(addr).await.unwrap();
+    axum::serve(
+        tcp_listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
+}
+
+/// The routes with the state and layers they run with.
+fn app(config: &Config, state: AppState) -> Router {
+    let cors_origins = config.server.cors_origin_headers();
+    router(&config.server)
+        .layer(Extension(state))
+        .layer(middleware::from_fn(request_id::assign))
+        .layer(
+            CorsLayer::new()
+                .allow_methods(Any)
+                .allow_headers(Any)
+                .expose_headers([
+                    version::DEPRECATION_HEADER,
+                    version::SUNSET_HEADER,
+                    header::LINK,
+                ])
+                .allow_origin(AllowOrigin::list(cors_origins)),
+        )
+}
+
+/// Every route of the API, without the state and layers they run with. The routes from before
+/// the API was versioned stay at the root as a deprecated alias of v1.
+fn router(config: &ServerConfig) -> Router {
+    Router::new()
+        .route("/openapi.json", get(openapi::get_spec))
+        .route(
+            "/.well-known/safemail",
+            get(routes::federation::get_server_identity),
+        )
+        .nest("/v1", v1())
+        .nest("/v2", v2())
+        .merge(v1().layer(middleware::from_fn_with_state(
+            version::unversioned(config.unversioned_sunset),
+            version::deprecated,
+        )))
+        .fallback(error::route_not_found)
+}
+
+/// Version 2 of the API, in the making. Handlers that change in v2 are routed here; requests for
+/// every other route are answered by v1.
+fn v2() -> Router {
+    Router::new()
+        .route(
+            "/stamp/request_system_issue",
+            post(routes::stamp::request_system_issue_v2),
+        )
+        .route("/stamp/system_issue", post(routes::stamp::system_issue_v2))
+        .fallback_service(v1().fallback(error::route_not_found))
+}
+
+/// Version 1 of the API.
+fn v1() -> Router {
+    Router::new()
         .route("/user/:username", get(routes::user::get_user))
         .route("/user/register", post(routes::user::register_user))
         .route("/user/login", post(routes::user::request_session))
         .route("/user/login/confirm", post(routes::user::activate_session))
         .route("/user/whoami", post(routes::user::whoami))
+        .route("/user/usage", get(routes::user::get_mailbox_usage))
+        .route(
+            "/user/blocked",
+            get(routes::user::get_blocked_senders).post(routes::user::block_sender),
+        )
+        .route("/user/blocked/:id", delete(routes::user::unblock_sender))
         .route(
             "/stamp/request_system_issue",
             post(routes::stamp::request_system_issue),
@@ -40,21 +138,72 @@ async fn main() {
             post(routes::message::send_periodic),
         )
         .route("/message/send_onetime", post(routes::message::send_onetime))
+        .route("/message/send", post(routes::message::send_to_recipients))
+        .route("/message/schedule", post(routes::message::schedule_message))
+        .route(
+            "/message/scheduled",
+            get(routes::message::get_scheduled_messages),
+        )
+        .route(
+            "/message/scheduled/:id",
+            delete(routes::message::cancel_scheduled_message),
+        )
         .route("/message/get_all", get(routes::message::get_all_messages))
-        .route("/message/:id", get(routes::message::get_message_by_id))
-        .layer(Extension(AppState::new().await))
-        .layer(
-            CorsLayer::new()
-                .allow_methods(Any)
-                .allow_headers(Any)
-                .allow_origin("http://localhost:4200".parse::<HeaderValue>().unwrap()),
-        );
-
-    // run our app with hyper on localhost:3000
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
-    println!("listening on http://{}", addr);
-    let tcp_listener = TcpListener::bind(addr).await.unwrap();
-    axum::serve(tcp_listener, app.into_make_service())
-        .await
-        .unwrap();
+        .route("/message/events", get(routes::message::message_events))
+        .route(
+            "/message/changes",
+            get(routes::message::get_message_changes),
+        )
+        .route("/message/delete", post(routes::message::delete_messages))
+        .route("/message/restore", post(routes::message::restore_messages))
+        .route(
+            "/message/:id",
+            get(routes::message::get_message_by_id).delete(routes::message::delete_message),
+        )
+        .route(
+            "/message/:id/recipient_metadata",
+            put(routes::message::update_recipient_metadata),
+        )
+        .route("/message/:id/report", post(routes::message::report_message))
+        .route("/outbox", get(routes::outbox::get_outbox))
+        .route("/bridge/relay", post(routes::bridge::relay))
+        .route("/bridge/mail", get(routes::bridge::get_outbound_mail))
+        .route(
+            "/outbox/:id",
+            get(routes::outbox::get_outbox_entry).delete(routes::outbox::delete_outbox_entry),
+        )
+        .route("/admin/reports", get(routes::admin::get_reports))
+        .route(
+            "/federation/user/:address",
+            get(routes::federation::resolve_address),
+        )
+        .route("/federation/send", post(routes::federation::send_remote))
+        .route(
+            "/federation/stamp/request_remote_issue",
+            post(routes::federation::request_remote_issue),
+        )
+        .route(
+            "/federation/stamp/remote_issue",
+            post(routes::federation::remote_issue),
+        )
+        .route("/federation/deliver", post(routes::federation::deliver))
+        .route(
+            "/federation/stamp/request_system_issue",
+            post(routes::federation::federated_request_system_issue),
+        )
+        .route(
+            "/federation/stamp/system_issue",
+            post(routes::federation::federated_system_issue),
+        )
+        .route("/blob/upload", post(routes::blob::start_upload))
+        .route("/blob/:hash", get(routes::blob::download))
+        .route("/blob/:hash/upload", get(routes::blob::get_upload_status))
+        .route(
+            "/blob/:hash/upload/complete",
+            post(routes::blob::complete_upload),
+        )
+        .route(
+            "/blob/:hash/chunks/:index",
+            get(routes::blob::download_chunk).put(routes::blob::upload_chunk),
+        )
 }
@@ -0,0 +1,231 @@
+use utoipa::{
+    openapi::{
+        header::HeaderBuilder,
+        security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+        ContentBuilder, Ref, RefOr, ResponseBuilder,
+    },
+    Modify, OpenApi,
+};
+
+use crate::{error::ErrorEnvelope, extractors::Json, routes};
+
+/// The OpenAPI document, generated from the handlers and the types they take and return.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Safemail",
+        description = "HTTP API of the Safemail backend. Errors are described in the README."
+    ),
+    paths(routes::federation::get_server_identity),
+    nest((path = "/v1", api = V1), (path = "/v2", api = V2)),
+    components(schemas(ErrorEnvelope)),
+    modifiers(&SessionAuth, &ErrorResponse),
+    tags(
+        (name = "user", description = "Registration, sign-in and the user's own settings"),
+        (name = "stamp", description = "System stamps, paid for with proof of work"),
+        (name = "message", description = "Sending and reading mail"),
+        (name = "outbox", description = "Copies of sent mail kept for the sender"),
+        (name = "blob", description = "Attachments, uploaded and downloaded in chunks"),
+        (name = "bridge", description = "Mail to plain email addresses"),
+        (name = "federation", description = "Mail to and from other servers"),
+        (name = "admin", description = "Moderation"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Version 1 of the API, nested under `/v1`.
+#[derive(OpenApi)]
+#[openapi(paths(
+    routes::user::get_user,
+    routes::user::register_user,
+    routes::user::request_session,
+    routes::user::activate_session,
+    routes::user::whoami,
+    routes::user::get_mailbox_usage,
+    routes::user::get_blocked_senders,
+    routes::user::block_sender,
+    routes::user::unblock_sender,
+    routes::stamp::request_system_issue,
+    routes::stamp::system_issue,
+    routes::message::send_periodic,
+    routes::message::send_onetime,
+    routes::message::send_to_recipients,
+    routes::message::schedule_message,
+    routes::message::get_scheduled_messages,
+    routes::message::cancel_scheduled_message,
+    routes::message::get_all_messages,
+    routes::message::message_events,
+    routes::message::get_message_changes,
+    routes::message::delete_messages,
+    routes::message::restore_messages,
+    routes::message::get_message_by_id,
+    routes::message::delete_message,
+    routes::message::update_recipient_metadata,
+    routes::message::report_message,
+    routes::outbox::get_outbox,
+    routes::outbox::get_outbox_entry,
+    routes::outbox::delete_outbox_entry,
+    routes::bridge::relay,
+    routes::bridge::get_outbound_mail,
+    routes::admin::get_reports,
+    routes::federation::resolve_address,
+    routes::federation::send_remote,
+    routes::federation::request_remote_issue,
+    routes::federation::remote_issue,
+    routes::federation::deliver,
+    routes::federation::federated_request_system_issue,
+    routes::federation::federated_system_issue,
+    routes::blob::start_upload,
+    routes::blob::download,
+    routes::blob::get_upload_status,
+    routes::blob::complete_upload,
+    routes::blob::download_chunk,
+    routes::blob::upload_chunk,
+))]
+struct V1;
+
+/// The handlers that differ in version 2 of the API, nested under `/v2`. Every other path is
+/// served as in v1.
+#[derive(OpenApi)]
+#[openapi(paths(routes::stamp::request_system_issue_v2, routes::stamp::system_issue_v2,))]
+struct V2;
+
+/// Sessions are passed as bearer tokens.
+struct SessionAuth;
+
+impl Modify for SessionAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "session",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some("Id of an activated session"))
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// Every operation may fail with the error envelope.
+struct ErrorResponse;
+
+const ERROR_RESPONSE: &str = "Error";
+
+impl Modify for ErrorResponse {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.responses.insert(
+            ERROR_RESPONSE.to_string(),
+            RefOr::T(
+                ResponseBuilder::new()
+                    .description("The request failed, see `code` for why")
+                    .header(
+                        "X-Request-Id",
+                        HeaderBuilder::new()
+                            .description(Some("Identifies the request in the server's log"))
+                            .build(),
+                    )
+                    .content(
+                        "application/json",
+                        ContentBuilder::new()
+                            .schema(Some(Ref::from_schema_name("ErrorEnvelope")))
+                            .build(),
+                    )
+                    .build(),
+            ),
+        );
+        for path in openapi.paths.paths.values_mut() {
+            let operations = [
+                &mut path.get,
+                &mut path.put,
+                &mut path.post,
+                &mut path.delete,
+            ];
+            for operation in operations.into_iter().flatten() {
+                operation.responses.responses.insert(
+                    "default".to_string(),
+                    RefOr::Ref(Ref::from_response_name(ERROR_RESPONSE)),
+                );
+            }
+        }
+    }
+}
+
+/// The document served to clients. The crate has no license, which utoipa would otherwise
+/// describe as one without a name.
+pub fn spec() -> utoipa::openapi::OpenApi {
+    let mut spec = ApiDoc::openapi();
+    spec.info.license = None;
+    spec
+}
+
+pub async fn get_spec() -> Json<utoipa::openapi::OpenApi> {
+    Json(spec())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Method, Request, StatusCode},
+    };
+    use infrastructure::config::Config;
+    use tower::ServiceExt;
+
+    use super::spec;
+
+    const SPEC_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+    /// `openapi.json` is what clients are generated from, so it has to match the code. Run the
+    /// tests with `SM_UPDATE_OPENAPI=1` to regenerate it.
+    #[test]
+    fn committed_spec_is_up_to_date() {
+        let spec = spec().to_pretty_json().unwrap() + "\n";
+        if std::env::var_os("SM_UPDATE_OPENAPI").is_some() {
+            std::fs::write(SPEC_PATH, &spec).unwrap();
+            return;
+        }
+        let committed = std::fs::read_to_string(SPEC_PATH).unwrap_or_default();
+        assert!(
+            committed == spec,
+            "openapi.json is out of date, regenerate it with SM_UPDATE_OPENAPI=1 cargo test"
+        );
+    }
+
+    /// Without state every handler fails, but a documented operation that isn't routed fails
+    /// with 404 or 405 before reaching one.
+    #[tokio::test]
+    async fn documented_operations_are_routed() {
+        for (path, item) in &spec().paths.paths {
+            let uri = path.replace(['{', '}'], "");
+            let methods = [
+                (Method::GET, &item.get),
+                (Method::PUT, &item.put),
+                (Method::POST, &item.post),
+                (Method::DELETE, &item.delete),
+            ];
+            for (method, _) in methods.iter().filter(|(_, operation)| operation.is_some()) {
+                let request = Request::builder()
+                    .method(method)
+                    .uri(&uri)
+                    .body(Body::empty())
+                    .unwrap();
+                let response = crate::router(&Config::default().server)
+                    .oneshot(request)
+                    .await
+                    .unwrap();
+                assert!(
+                    !matches!(
+                        response.status(),
+                        StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED
+                    ),
+                    "{} {} is documented but not routed",
+                    method,
+                    path
+                );
+            }
+        }
+    }
+}
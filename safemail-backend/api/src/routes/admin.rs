@@ -0,0 +1,31 @@
+use application::report::queries::{GetReportsQuery, GetReportsQueryDto, ReportPage};
+use axum::Extension;
+
+use crate::{
+    error::ApiError,
+    extractors::{AdminUser, Json, Query},
+    state::AppState,
+};
+
+#[utoipa::path(
+    get,
+    path = "/admin/reports",
+    tag = "admin",
+    security(("session" = [])),
+    params(GetReportsQueryDto),
+    responses((status = 200, body = ReportPage)),
+)]
+pub async fn get_reports(
+    Extension(app_state): Extension<AppState>,
+    _: AdminUser,
+    Query(query_dto): Query<GetReportsQueryDto>,
+) -> Result<Json<ReportPage>, ApiError> {
+    let query = GetReportsQuery {
+        before: query_dto.before,
+        limit: query_dto.limit,
+    };
+
+    let page = query.handle(&app_state.report_repository).await?;
+
+    Ok(Json(page))
+}
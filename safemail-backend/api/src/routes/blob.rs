@@ -0,0 +1,197 @@
+use application::blob::commands::{
+    BlobUploadStatus, CompleteBlobUploadCommand, StartBlobUploadCommand, StartBlobUploadCommandDto,
+    UploadBlobChunkCommand,
+};
+use application::blob::queries::{
+    GetAccessibleBlobQuery, GetBlobChunkQuery, GetBlobUploadStatusQuery,
+};
+use axum::body::{Body, Bytes};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use futures::StreamExt;
+
+use crate::{
+    error::ApiError,
+    extractors::{AuthUser, Json, Path},
+    state::AppState,
+};
+
+#[utoipa::path(
+    post,
+    path = "/blob/upload",
+    tag = "blob",
+    security(("session" = [])),
+    request_body = StartBlobUploadCommandDto,
+    responses((status = 200, body = BlobUploadStatus)),
+)]
+#[axum::debug_handler]
+pub async fn start_upload(
+    Extension(state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Json(command_dto): Json<StartBlobUploadCommandDto>,
+) -> Result<Json<BlobUploadStatus>, ApiError> {
+    let command = StartBlobUploadCommand {
+        uploader_id: user.id,
+        hash: command_dto.hash,
+        size: command_dto.size,
+        chunk_size: command_dto.chunk_size,
+    };
+    let status = command.handle(&state.blob_repository).await?;
+    Ok(Json(status))
+}
+
+#[utoipa::path(
+    get,
+    path = "/blob/{hash}/upload",
+    tag = "blob",
+    security(("session" = [])),
+    params(("hash" = String, Path, description = "Hex SHA-256 of the blob")),
+    responses((status = 200, body = BlobUploadStatus)),
+)]
+#[axum::debug_handler]
+pub async fn get_upload_status(
+    Extension(state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Path(hash): Path<String>,
+) -> Result<Json<BlobUploadStatus>, ApiError> {
+    let status = GetBlobUploadStatusQuery {
+        uploader_id: user.id,
+        hash,
+    }
+    .handle(&state.blob_repository)
+    .await?;
+    Ok(Json(status))
+}
+
+#[utoipa::path(
+    put,
+    path = "/blob/{hash}/chunks/{index}",
+    tag = "blob",
+    security(("session" = [])),
+    params(
+        ("hash" = String, Path, description = "Hex SHA-256 of the blob"),
+        ("index" = i32, Path, description = "Zero based index of the chunk"),
+    ),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses((status = 204, description = "The chunk is stored")),
+)]
+#[axum::debug_handler]
+pub async fn upload_chunk(
+    Extension(state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Path((hash, index)): Path<(String, i32)>,
+    data: Bytes,
+) -> Result<StatusCode, ApiError> {
+    UploadBlobChunkCommand {
+        uploader_id: user.id,
+        hash,
+        index,
+        data: data.to_vec(),
+    }
+    .handle(&state.blob_repository)
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/blob/{hash}/upload/complete",
+    tag = "blob",
+    security(("session" = [])),
+    params(("hash" = String, Path, description = "Hex SHA-256 of the blob")),
+    responses((status = 200, body = BlobUploadStatus)),
+)]
+#[axum::debug_handler]
+pub async fn complete_upload(
+    Extension(state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Path(hash): Path<String>,
+) -> Result<Json<BlobUploadStatus>, ApiError> {
+    let status = CompleteBlobUploadCommand {
+        uploader_id: user.id,
+        hash,
+    }
+    .handle(&state.blob_repository, &state.cryptography_service)
+    .await?;
+    Ok(Json(status))
+}
+
+/// Streams the whole blob chunk by chunk.
+#[utoipa::path(
+    get,
+    path = "/blob/{hash}",
+    tag = "blob",
+    security(("session" = [])),
+    params(("hash" = String, Path, description = "Hex SHA-256 of the blob")),
+    responses((status = 200, body = Vec<u8>, content_type = "application/octet-stream")),
+)]
+#[axum::debug_handler]
+pub async fn download(
+    Extension(state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Path(hash): Path<String>,
+) -> Result<Response, ApiError> {
+    let blob = GetAccessibleBlobQuery {
+        user_id: user.id,
+        hash,
+    }
+    .handle(&state.blob_repository)
+    .await?;
+
+    let hash = blob.hash.clone();
+    let chunks = futures::stream::iter(0..blob.chunk_count).then(move |index| {
+        let state = state.clone();
+        let hash = hash.clone();
+        async move {
+            GetBlobChunkQuery { hash, index }
+                .handle(&state.blob_repository)
+                .await
+                .map_err(|e| std::io::Error::other(e.to_string()))
+        }
+    });
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (header::CONTENT_LENGTH, blob.size.to_string()),
+        ],
+        Body::from_stream(chunks),
+    )
+        .into_response())
+}
+
+/// Downloads a single chunk, so that interrupted downloads can be resumed.
+#[utoipa::path(
+    get,
+    path = "/blob/{hash}/chunks/{index}",
+    tag = "blob",
+    security(("session" = [])),
+    params(
+        ("hash" = String, Path, description = "Hex SHA-256 of the blob"),
+        ("index" = i32, Path, description = "Zero based index of the chunk"),
+    ),
+    responses((status = 200, body = Vec<u8>, content_type = "application/octet-stream")),
+)]
+#[axum::debug_handler]
+pub async fn download_chunk(
+    Extension(state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Path((hash, index)): Path<(String, i32)>,
+) -> Result<Response, ApiError> {
+    let blob = GetAccessibleBlobQuery {
+        user_id: user.id,
+        hash,
+    }
+    .handle(&state.blob_repository)
+    .await?;
+
+    let chunk = GetBlobChunkQuery {
+        hash: blob.hash,
+        index,
+    }
+    .handle(&state.blob_repository)
+    .await?;
+
+    Ok(([(header::CONTENT_TYPE, "application/octet-stream")], chunk).into_response())
+}
@@ -0,0 +1,67 @@
+use application::bridge::commands::{
+    RelayOutboundMailCommand, RelayOutboundMailCommandDto, RelayReceipt,
+};
+use application::bridge::queries::{GetOutboundMailQuery, GetOutboundMailQueryDto};
+use axum::{http::StatusCode, Extension};
+use domain::outbound_mail::OutboundMailSummary;
+
+use crate::{
+    error::ApiError,
+    extractors::{AuthUser, Json, Query},
+    state::AppState,
+};
+
+#[utoipa::path(
+    post,
+    path = "/bridge/relay",
+    tag = "bridge",
+    security(("session" = [])),
+    request_body = RelayOutboundMailCommandDto,
+    responses((status = 202, body = RelayReceipt)),
+)]
+pub async fn relay(
+    Extension(app_state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Json(command_dto): Json<RelayOutboundMailCommandDto>,
+) -> Result<(StatusCode, Json<RelayReceipt>), ApiError> {
+    let receipt = RelayOutboundMailCommand {
+        sender_id: user.id,
+        recipients: command_dto.recipients,
+        content: command_dto.content,
+        signature: command_dto.signature,
+    }
+    .handle(
+        &app_state.user_repository,
+        &app_state.outbound_mail_repository,
+        &app_state.system_key_repository,
+        &app_state.cryptography_service,
+        &app_state.serialize_service,
+        &app_state.bridge_policy,
+    )
+    .await?;
+
+    Ok((StatusCode::ACCEPTED, Json(receipt)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/bridge/mail",
+    tag = "bridge",
+    security(("session" = [])),
+    params(GetOutboundMailQueryDto),
+    responses((status = 200, body = Vec<OutboundMailSummary>)),
+)]
+pub async fn get_outbound_mail(
+    Extension(app_state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Query(query_dto): Query<GetOutboundMailQueryDto>,
+) -> Result<Json<Vec<OutboundMailSummary>>, ApiError> {
+    let mail = GetOutboundMailQuery {
+        sender_id: user.id,
+        limit: query_dto.limit,
+    }
+    .handle(&app_state.outbound_mail_repository)
+    .await?;
+
+    Ok(Json(mail))
+}
@@ -0,0 +1,310 @@
+use application::federation::commands::{
+    DeliverFederatedMessageCommand, FederatedMessage, FederatedStampIssue, FederatedStampRequest,
+    HandleFederatedStampIssueCommand, HandleFederatedStampRequestCommand, IssueRemoteStampCommand,
+    IssueRemoteStampCommandDto, RequestRemoteStampIssueCommand, RequestRemoteStampIssueCommandDto,
+    SendRemoteMessageCommand, SendRemoteMessageCommandDto,
+};
+use application::federation::queries::{GetServerIdentityQuery, ResolveAddressQuery};
+use application::message::commands::SendReceipt;
+use application::rate_limit::commands::ConsumeRateLimitCommand;
+use application::user::queries::GetUserByUsernameQuery;
+use axum::Extension;
+use domain::federation::{RemoteDelivery, ServerIdentity};
+use domain::rate_limit::{RateLimitKey, RouteClass};
+use domain::stamp::{OneTimeStampRequest, OnetimeStamp};
+use domain::user::User;
+
+use crate::{
+    error::ApiError,
+    extractors::{AuthUser, ClientIp, Json, Path},
+    federation::ServerRequest,
+    state::AppState,
+};
+
+/// The host name and identity key of this server.
+#[utoipa::path(
+    get,
+    path = "/.well-known/safemail",
+    tag = "federation",
+    responses((status = 200, body = ServerIdentity)),
+)]
+pub async fn get_server_identity(
+    Extension(state): Extension<AppState>,
+) -> Result<Json<ServerIdentity>, ApiError> {
+    let identity = GetServerIdentityQuery {
+        local_host: state.local_host.clone(),
+    }
+    .handle(&state.system_key_repository)
+    .await?;
+    Ok(Json(identity))
+}
+
+/// Looks up a user by address. Users of other servers are returned as their local stand-in.
+#[utoipa::path(
+    get,
+    path = "/federation/user/{address}",
+    tag = "federation",
+    security(("session" = [])),
+    params(("address" = String, Path, description = "username@host")),
+    responses((status = 200, body = User)),
+)]
+pub async fn resolve_address(
+    Extension(state): Extension<AppState>,
+    AuthUser(_): AuthUser,
+    Path(address): Path<String>,
+) -> Result<Json<User>, ApiError> {
+    let user = ResolveAddressQuery {
+        address,
+        local_host: state.local_host.clone(),
+    }
+    .handle(
+        &state.user_repository,
+        &state.federation_client,
+        &state.remote_user_repository,
+        &state.cryptography_service,
+    )
+    .await?;
+    Ok(Json(user))
+}
+
+#[utoipa::path(
+    post,
+    path = "/federation/send",
+    tag = "federation",
+    security(("session" = [])),
+    request_body = SendRemoteMessageCommandDto,
+    responses((status = 200, body = RemoteDelivery)),
+)]
+pub async fn send_remote(
+    Extension(state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Json(command_dto): Json<SendRemoteMessageCommandDto>,
+) -> Result<Json<RemoteDelivery>, ApiError> {
+    let command = SendRemoteMessageCommand {
+        sender_id: user.id,
+        recipient: command_dto.recipient,
+        content: command_dto.content,
+        metadata: command_dto.metadata,
+        signature: command_dto.signature,
+        stamp: command_dto.stamp,
+        expires_at: command_dto.expires_at,
+    };
+    let delivery = command
+        .handle(
+            &state.local_host,
+            &state.user_repository,
+            &state.cryptography_service,
+            &state.serialize_service,
+            &state.system_key_repository,
+            &state.federation_client,
+            &state.federation_peer_repository,
+        )
+        .await?;
+    Ok(Json(delivery))
+}
+
+#[utoipa::path(
+    post,
+    path = "/federation/stamp/request_remote_issue",
+    tag = "federation",
+    security(("session" = [])),
+    request_body = RequestRemoteStampIssueCommandDto,
+    responses((status = 200, body = OneTimeStampRequest)),
+)]
+pub async fn request_remote_issue(
+    Extension(state): Extension<AppState>,
+    ClientIp(ip): ClientIp,
+    AuthUser(user): AuthUser,
+    Json(command_dto): Json<RequestRemoteStampIssueCommandDto>,
+) -> Result<Json<OneTimeStampRequest>, ApiError> {
+    ConsumeRateLimitCommand {
+        class: RouteClass::StampRequest,
+        keys: vec![
+            RateLimitKey::Ip(ip),
+            RateLimitKey::User(user.id.to_string()),
+        ],
+    }
+    .handle(&state.rate_limiter, &state.rate_limit_policy)
+    .await?;
+    let command = RequestRemoteStampIssueCommand {
+        sender_id: user.id,
+        recipient: command_dto.recipient,
+    };
+    let request = command
+        .handle(
+            &state.local_host,
+            &state.user_repository,
+            &state.cryptography_service,
+            &state.serialize_service,
+            &state.system_key_repository,
+            &state.federation_client,
+        )
+        .await?;
+    Ok(Json(request))
+}
+
+#[utoipa::path(
+    post,
+    path = "/federation/stamp/remote_issue",
+    tag = "federation",
+    security(("session" = [])),
+    request_body = IssueRemoteStampCommandDto,
+    responses((status = 200, body = OnetimeStamp)),
+)]
+pub async fn remote_issue(
+    Extension(state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Json(command_dto): Json<IssueRemoteStampCommandDto>,
+) -> Result<Json<OnetimeStamp>, ApiError> {
+    let command = IssueRemoteStampCommand {
+        sender_id: user.id,
+        host: command_dto.host,
+        stamp_request_id: command_dto.stamp_request_id,
+        proof_of_work: command_dto.proof_of_work,
+    };
+    let stamp = command
+        .handle(
+            &state.local_host,
+            &state.user_repository,
+            &state.cryptography_service,
+            &state.serialize_service,
+            &state.system_key_repository,
+            &state.federation_client,
+        )
+        .await?;
+    Ok(Json(stamp))
+}
+
+/// Delivers a message forwarded by the sender's server.
+#[utoipa::path(
+    post,
+    path = "/federation/deliver",
+    tag = "federation",
+    params(
+        ("X-Safemail-Origin" = String, Header, description = "Host of the calling server"),
+        ("X-Safemail-Request-Id" = String, Header, description = "Id of the request, unique to its origin"),
+        ("X-Safemail-Date" = String, Header, description = "RFC 3339 time the request was signed"),
+        ("X-Safemail-Signature" = String, Header, description = "Origin's signature over the request"),
+    ),
+    request_body = FederatedMessage,
+    responses((status = 200, body = SendReceipt)),
+)]
+pub async fn deliver(
+    Extension(state): Extension<AppState>,
+    request: ServerRequest,
+) -> Result<Json<SendReceipt>, ApiError> {
+    let Json(message) = Json::<FederatedMessage>::from_bytes(&request.body)?;
+    let receipt = DeliverFederatedMessageCommand {
+        origin: request.origin,
+        message,
+    }
+    .handle(
+        &state.user_repository,
+        &state.cryptography_service,
+        &state.serialize_service,
+        &state.tracker_repository,
+        &state.system_key_repository,
+        &state.message_repository,
+        &state.blob_repository,
+        &state.block_list_repository,
+        &state.stamp_revocation_repository,
+        &state.remote_user_repository,
+        &state.mailbox_quota,
+    )
+    .await?;
+    Ok(Json(receipt))
+}
+
+#[utoipa::path(
+    post,
+    path = "/federation/stamp/request_system_issue",
+    tag = "federation",
+    params(
+        ("X-Safemail-Origin" = String, Header, description = "Host of the calling server"),
+        ("X-Safemail-Request-Id" = String, Header, description = "Id of the request, unique to its origin"),
+        ("X-Safemail-Date" = String, Header, description = "RFC 3339 time the request was signed"),
+        ("X-Safemail-Signature" = String, Header, description = "Origin's signature over the request"),
+    ),
+    request_body = FederatedStampRequest,
+    responses((status = 200, body = OneTimeStampRequest)),
+)]
+pub async fn federated_request_system_issue(
+    Extension(state): Extension<AppState>,
+    ClientIp(ip): ClientIp,
+    request: ServerRequest,
+) -> Result<Json<OneTimeStampRequest>, ApiError> {
+    let Json(stamp_request) = Json::<FederatedStampRequest>::from_bytes(&request.body)?;
+    let mut keys = vec![
+        RateLimitKey::Ip(ip),
+        RateLimitKey::User(format!(
+            "{}@{}",
+            stamp_request.sender.username, request.origin
+        )),
+    ];
+    // Shares the recipient's budget with local senders; unknown recipients fail below anyway
+    let recipient = GetUserByUsernameQuery {
+        username: stamp_request.recipient.clone(),
+    }
+    .handle(&state.user_repository)
+    .await?;
+    keys.extend(recipient.map(|recipient| RateLimitKey::Recipient(recipient.id)));
+    ConsumeRateLimitCommand {
+        class: RouteClass::StampRequest,
+        keys,
+    }
+    .handle(&state.rate_limiter, &state.rate_limit_policy)
+    .await?;
+    let result = HandleFederatedStampRequestCommand {
+        origin: request.origin,
+        request: stamp_request,
+    }
+    .handle(
+        &state.user_repository,
+        &state.stamp_request_repository,
+        &state.block_list_repository,
+        &state.report_repository,
+        &state.remote_user_repository,
+        &state.cryptography_service,
+        &state.report_policy,
+        &state.stamp_policy,
+    )
+    .await?;
+    Ok(Json(result))
+}
+
+#[utoipa::path(
+    post,
+    path = "/federation/stamp/system_issue",
+    tag = "federation",
+    params(
+        ("X-Safemail-Origin" = String, Header, description = "Host of the calling server"),
+        ("X-Safemail-Request-Id" = String, Header, description = "Id of the request, unique to its origin"),
+        ("X-Safemail-Date" = String, Header, description = "RFC 3339 time the request was signed"),
+        ("X-Safemail-Signature" = String, Header, description = "Origin's signature over the request"),
+    ),
+    request_body = FederatedStampIssue,
+    responses((status = 200, body = OnetimeStamp)),
+)]
+pub async fn federated_system_issue(
+    Extension(state): Extension<AppState>,
+    request: ServerRequest,
+) -> Result<Json<OnetimeStamp>, ApiError> {
+    let Json(issue) = Json::<FederatedStampIssue>::from_bytes(&request.body)?;
+    let stamp = HandleFederatedStampIssueCommand {
+        origin: request.origin,
+        issue,
+    }
+    .handle(
+        &state.user_repository,
+        &state.stamp_request_repository,
+        &state.block_list_repository,
+        &state.tracker_repository,
+        &state.system_key_repository,
+        &state.remote_user_repository,
+        &state.cryptography_service,
+        &state.serialize_service,
+        &state.stamp_policy,
+    )
+    .await?;
+    Ok(Json(stamp))
+}
@@ -1,28 +1,85 @@
+use std::{collections::VecDeque, convert::Infallible, time::Duration};
+
 use application::message::commands::{
+    CancelScheduledMessageCommand, DeleteMessageCommandDto, DeleteMessagesCommand,
+    DeleteMessagesCommandDto, FanOutReport, RestoreMessagesCommand, RestoreMessagesCommandDto,
+    ScheduleMessageCommand, ScheduleMessageCommandDto, ScheduleReceipt,
+    SendMessageToRecipientsCommand, SendMessageToRecipientsCommandDto,
     SendMessageWithOnetimeStampCommand, SendMessageWithOnetimeStampCommandDto,
-    SendMessageWithPeriodicStampCommand, SendMessageWithPeriodicStampCommandDto,
+    SendMessageWithPeriodicStampCommand, SendMessageWithPeriodicStampCommandDto, SendReceipt,
+    UpdateRecipientMetadataCommand, UpdateRecipientMetadataCommandDto,
 };
 use application::message::queries::*;
-use axum::{http::StatusCode, Extension, Json};
-use domain::message::MessageMetadata;
+use application::report::commands::{ReportMessageCommand, ReportMessageCommandDto};
+use application::user::queries::ResolveRecipientQuery;
+use axum::body::Bytes;
+use axum::http::HeaderMap;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::Response;
+use axum::{http::StatusCode, Extension};
+use domain::error::MessageError;
+use domain::message::{Message, MessageSummary, NewMessageNotification};
+use domain::scheduled_message::ScheduledMessageSummary;
+use domain::uuid::Uuid;
+use futures::Stream;
+use tokio::sync::broadcast;
+
+use crate::{
+    error::ApiError,
+    extractors::{AuthUser, Json, Path, Query},
+    idempotency::{idempotent, IdempotencyKey},
+    request_id,
+    state::AppState,
+};
 
-use crate::{error::ApiError, extractors::AuthUser, state::AppState};
+const EVENT_RECHECK_INTERVAL: Duration = Duration::from_secs(30);
 
+#[utoipa::path(
+    post,
+    path = "/message/send_onetime",
+    tag = "message",
+    security(("session" = [])),
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Makes retries of this request safe"),
+    ),
+    request_body = SendMessageWithOnetimeStampCommandDto,
+    responses((status = 200, body = SendReceipt)),
+)]
 #[axum::debug_handler]
 pub async fn send_onetime(
     Extension(app_state): Extension<AppState>,
     AuthUser(user): AuthUser,
-    Json(command_dto): Json<SendMessageWithOnetimeStampCommandDto>,
-) -> Result<StatusCode, ApiError> {
+    IdempotencyKey(key): IdempotencyKey,
+    body: Bytes,
+) -> Response {
+    let handler = deliver_onetime(app_state.clone(), user.id, body.clone());
+    idempotent(app_state, user.id, key, "send_onetime", body, handler).await
+}
+
+async fn deliver_onetime(
+    app_state: AppState,
+    sender_id: Uuid,
+    body: Bytes,
+) -> Result<Json<SendReceipt>, ApiError> {
+    let Json(command_dto) = Json::<SendMessageWithOnetimeStampCommandDto>::from_bytes(&body)?;
+    let recipient_id = ResolveRecipientQuery {
+        recipient: command_dto.recipient,
+        local_host: app_state.local_host.clone(),
+    }
+    .handle(&app_state.user_repository)
+    .await?;
     let command = SendMessageWithOnetimeStampCommand {
         content: command_dto.content,
         metadata: command_dto.metadata,
         signature: command_dto.signature,
-        recipient_id: command_dto.recipient_id,
+        recipient_id,
         stamp: command_dto.stamp,
-        sender_id: user.id,
+        expires_at: command_dto.expires_at,
+        attachments: command_dto.attachments,
+        sender_copy: command_dto.sender_copy,
+        sender_id,
     };
-    command
+    let receipt = command
         .handle(
             &app_state.user_repository,
             &app_state.cryptography_service,
@@ -30,55 +87,272 @@ pub async fn send_onetime(
             &app_state.tracker_repository,
             &app_state.system_key_repository,
             &app_state.message_repository,
+            &app_state.blob_repository,
+            &app_state.block_list_repository,
+            &app_state.stamp_revocation_repository,
+            &app_state.mailbox_quota,
         )
         .await?;
-    Ok(StatusCode::OK)
+    Ok(Json(receipt))
 }
 
+#[utoipa::path(
+    post,
+    path = "/message/send_periodic",
+    tag = "message",
+    security(("session" = [])),
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Makes retries of this request safe"),
+    ),
+    request_body = SendMessageWithPeriodicStampCommandDto,
+    responses((status = 200, body = SendReceipt)),
+)]
 #[axum::debug_handler]
 pub async fn send_periodic(
     Extension(app_state): Extension<AppState>,
     AuthUser(user): AuthUser,
-    Json(command_dto): Json<SendMessageWithPeriodicStampCommandDto>,
-) -> Result<StatusCode, ApiError> {
+    IdempotencyKey(key): IdempotencyKey,
+    body: Bytes,
+) -> Response {
+    let handler = deliver_periodic(app_state.clone(), user.id, body.clone());
+    idempotent(app_state, user.id, key, "send_periodic", body, handler).await
+}
+
+async fn deliver_periodic(
+    app_state: AppState,
+    sender_id: Uuid,
+    body: Bytes,
+) -> Result<Json<SendReceipt>, ApiError> {
+    let Json(command_dto) = Json::<SendMessageWithPeriodicStampCommandDto>::from_bytes(&body)?;
+    let recipient_id = ResolveRecipientQuery {
+        recipient: command_dto.recipient,
+        local_host: app_state.local_host.clone(),
+    }
+    .handle(&app_state.user_repository)
+    .await?;
     let command = SendMessageWithPeriodicStampCommand {
         content: command_dto.content,
         metadata: command_dto.metadata,
         signature: command_dto.signature,
-        recipient_id: command_dto.recipient_id,
+        recipient_id,
         stamp: command_dto.stamp,
-        sender_id: user.id,
+        expires_at: command_dto.expires_at,
+        attachments: command_dto.attachments,
+        sender_copy: command_dto.sender_copy,
+        sender_id,
     };
-    command
+    let receipt = command
         .handle(
             &app_state.user_repository,
             &app_state.cryptography_service,
             &app_state.serialize_service,
+            &app_state.tracker_repository,
+            &app_state.system_key_repository,
             &app_state.message_repository,
+            &app_state.blob_repository,
+            &app_state.block_list_repository,
+            &app_state.stamp_revocation_repository,
+            &app_state.mailbox_quota,
         )
         .await?;
-    Ok(StatusCode::OK)
+    Ok(Json(receipt))
 }
 
+/// Delivers one copy per recipient, all or nothing. If any copy is rejected, the report lists
+/// every rejected copy and the response is a 422.
+#[utoipa::path(
+    post,
+    path = "/message/send",
+    tag = "message",
+    security(("session" = [])),
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Makes retries of this request safe"),
+    ),
+    request_body = SendMessageToRecipientsCommandDto,
+    responses(
+        (status = 200, body = FanOutReport),
+        (status = 422, body = FanOutReport, description = "Some copies were rejected, none was delivered"),
+    ),
+)]
+#[axum::debug_handler]
+pub async fn send_to_recipients(
+    Extension(app_state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    IdempotencyKey(key): IdempotencyKey,
+    body: Bytes,
+) -> Response {
+    let handler = deliver_to_recipients(app_state.clone(), user.id, body.clone());
+    idempotent(app_state, user.id, key, "send", body, handler).await
+}
+
+async fn deliver_to_recipients(
+    app_state: AppState,
+    sender_id: Uuid,
+    body: Bytes,
+) -> Result<(StatusCode, Json<FanOutReport>), ApiError> {
+    let Json(command_dto) = Json::<SendMessageToRecipientsCommandDto>::from_bytes(&body)?;
+    let command = SendMessageToRecipientsCommand {
+        sender_id,
+        copies: command_dto.copies,
+        sender_copy: command_dto.sender_copy,
+        local_host: app_state.local_host.clone(),
+    };
+    let report = command
+        .handle(
+            &app_state.user_repository,
+            &app_state.cryptography_service,
+            &app_state.serialize_service,
+            &app_state.tracker_repository,
+            &app_state.system_key_repository,
+            &app_state.message_repository,
+            &app_state.blob_repository,
+            &app_state.block_list_repository,
+            &app_state.stamp_revocation_repository,
+            &app_state.mailbox_quota,
+        )
+        .await?;
+    let status = if report.rejected.is_empty() {
+        StatusCode::OK
+    } else {
+        StatusCode::UNPROCESSABLE_ENTITY
+    };
+    Ok((status, Json(report)))
+}
+
+/// Validates the message now and delivers it at `deliver_at`. Until then it is invisible to
+/// the recipient and can be cancelled.
+#[utoipa::path(
+    post,
+    path = "/message/schedule",
+    tag = "message",
+    security(("session" = [])),
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Makes retries of this request safe"),
+    ),
+    request_body = ScheduleMessageCommandDto,
+    responses((status = 200, body = ScheduleReceipt)),
+)]
+#[axum::debug_handler]
+pub async fn schedule_message(
+    Extension(app_state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    IdempotencyKey(key): IdempotencyKey,
+    body: Bytes,
+) -> Response {
+    let handler = submit_scheduled(app_state.clone(), user.id, body.clone());
+    idempotent(app_state, user.id, key, "schedule", body, handler).await
+}
+
+async fn submit_scheduled(
+    app_state: AppState,
+    sender_id: Uuid,
+    body: Bytes,
+) -> Result<Json<ScheduleReceipt>, ApiError> {
+    let Json(command_dto) = Json::<ScheduleMessageCommandDto>::from_bytes(&body)?;
+    let recipient_id = ResolveRecipientQuery {
+        recipient: command_dto.copy.recipient.clone(),
+        local_host: app_state.local_host.clone(),
+    }
+    .handle(&app_state.user_repository)
+    .await?;
+    let command = ScheduleMessageCommand {
+        sender_id,
+        copy: command_dto.copy.into_copy(recipient_id),
+        deliver_at: command_dto.deliver_at,
+        recheck_stamp: command_dto.recheck_stamp,
+        sender_copy: command_dto.sender_copy,
+    };
+    let receipt = command
+        .handle(
+            &app_state.user_repository,
+            &app_state.cryptography_service,
+            &app_state.serialize_service,
+            &app_state.tracker_repository,
+            &app_state.system_key_repository,
+            &app_state.message_repository,
+            &app_state.blob_repository,
+            &app_state.block_list_repository,
+            &app_state.stamp_revocation_repository,
+            &app_state.scheduled_message_repository,
+            &app_state.mailbox_quota,
+        )
+        .await?;
+    Ok(Json(receipt))
+}
+
+#[utoipa::path(
+    get,
+    path = "/message/scheduled",
+    tag = "message",
+    security(("session" = [])),
+    responses((status = 200, body = Vec<ScheduledMessageSummary>)),
+)]
+pub async fn get_scheduled_messages(
+    Extension(app_state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<Vec<ScheduledMessageSummary>>, ApiError> {
+    let scheduled = GetScheduledMessagesQuery { sender_id: user.id }
+        .handle(&app_state.scheduled_message_repository)
+        .await?;
+    Ok(Json(scheduled))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/message/scheduled/{id}",
+    tag = "message",
+    security(("session" = [])),
+    params(("id" = i64, Path, description = "Id of the scheduled message")),
+    responses((status = 204, description = "The message will not be delivered")),
+)]
+pub async fn cancel_scheduled_message(
+    Extension(app_state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Path(scheduled_id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    CancelScheduledMessageCommand {
+        sender_id: user.id,
+        scheduled_id,
+    }
+    .handle(&app_state.scheduled_message_repository)
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/message/get_all",
+    tag = "message",
+    security(("session" = [])),
+    params(GetAllMessagesForUserQueryDto),
+    responses((status = 200, body = MessagePage)),
+)]
 pub async fn get_all_messages(
     Extension(app_state): Extension<AppState>,
     AuthUser(user): AuthUser,
-) -> Result<Json<Vec<(i64, MessageMetadata)>>, ApiError> {
+    Query(query_dto): Query<GetAllMessagesForUserQueryDto>,
+) -> Result<Json<MessagePage>, ApiError> {
     let query = GetAllMessagesForUserQuery {
         recipient_id: user.id,
+        trash: query_dto.trash,
+        after: query_dto.after,
+        before: query_dto.before,
+        limit: query_dto.limit,
     };
 
-    let messages = query.handle(&app_state.message_repository).await?;
+    let page = query.handle(&app_state.message_repository).await?;
 
-    // Convert MessageMetadata to String for JSON serialization
-    let messages = messages
-        .into_iter()
-        .map(|(id, metadata)| (id, metadata.0))
-        .collect();
-
-    Ok(Json(messages))
+    Ok(Json(page))
 }
 
+#[utoipa::path(
+    get,
+    path = "/message/{id}",
+    tag = "message",
+    security(("session" = [])),
+    params(("id" = i64, Path, description = "Id of the message")),
+    responses((status = 200, body = Option<Message>)),
+)]
 pub async fn get_message_by_id(
     Extension(app_state): Extension<AppState>,
     AuthUser(user): AuthUser,
@@ -93,3 +367,283 @@ pub async fn get_message_by_id(
 
     Ok(Json(message))
 }
+
+#[utoipa::path(
+    delete,
+    path = "/message/{id}",
+    tag = "message",
+    security(("session" = [])),
+    params(("id" = i64, Path, description = "Id of the message"), DeleteMessageCommandDto),
+    responses((status = 204, description = "The message is trashed or deleted")),
+)]
+pub async fn delete_message(
+    Extension(app_state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Path(message_id): Path<i64>,
+    Query(command_dto): Query<DeleteMessageCommandDto>,
+) -> Result<StatusCode, ApiError> {
+    let deleted = DeleteMessagesCommand {
+        recipient_id: user.id,
+        message_ids: vec![message_id],
+        permanent: command_dto.permanent,
+    }
+    .handle(&app_state.message_repository)
+    .await?;
+    if deleted.is_empty() {
+        return Err(MessageError::MessageNotFound.into());
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Reports the message as spam or abuse. Reporting it again has no further effect.
+#[utoipa::path(
+    post,
+    path = "/message/{id}/report",
+    tag = "message",
+    security(("session" = [])),
+    params(("id" = i64, Path, description = "Id of the message")),
+    request_body = ReportMessageCommandDto,
+    responses((status = 204, description = "The report is recorded")),
+)]
+pub async fn report_message(
+    Extension(app_state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Path(message_id): Path<i64>,
+    Json(command_dto): Json<ReportMessageCommandDto>,
+) -> Result<StatusCode, ApiError> {
+    ReportMessageCommand {
+        reporter_id: user.id,
+        message_id,
+        reason: command_dto.reason,
+    }
+    .handle(
+        &app_state.message_repository,
+        &app_state.system_key_repository,
+        &app_state.cryptography_service,
+        &app_state.report_repository,
+        &app_state.stamp_revocation_repository,
+        &app_state.report_policy,
+    )
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/message/delete",
+    tag = "message",
+    security(("session" = [])),
+    request_body = DeleteMessagesCommandDto,
+    responses(
+        (status = 200, body = Vec<i64>, description = "Ids of the affected messages"),
+    ),
+)]
+pub async fn delete_messages(
+    Extension(app_state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Json(command_dto): Json<DeleteMessagesCommandDto>,
+) -> Result<Json<Vec<i64>>, ApiError> {
+    let deleted = DeleteMessagesCommand {
+        recipient_id: user.id,
+        message_ids: command_dto.message_ids,
+        permanent: command_dto.permanent,
+    }
+    .handle(&app_state.message_repository)
+    .await?;
+    Ok(Json(deleted))
+}
+
+#[utoipa::path(
+    post,
+    path = "/message/restore",
+    tag = "message",
+    security(("session" = [])),
+    request_body = RestoreMessagesCommandDto,
+    responses(
+        (status = 200, body = Vec<i64>, description = "Ids of the restored messages"),
+    ),
+)]
+pub async fn restore_messages(
+    Extension(app_state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Json(command_dto): Json<RestoreMessagesCommandDto>,
+) -> Result<Json<Vec<i64>>, ApiError> {
+    let restored = RestoreMessagesCommand {
+        recipient_id: user.id,
+        message_ids: command_dto.message_ids,
+    }
+    .handle(&app_state.message_repository)
+    .await?;
+    Ok(Json(restored))
+}
+
+#[utoipa::path(
+    get,
+    path = "/message/changes",
+    tag = "message",
+    security(("session" = [])),
+    params(GetMessageChangesQueryDto),
+    responses((status = 200, body = MessageChangeFeed)),
+)]
+pub async fn get_message_changes(
+    Extension(app_state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Query(query_dto): Query<GetMessageChangesQueryDto>,
+) -> Result<Json<MessageChangeFeed>, ApiError> {
+    let query = GetMessageChangesQuery {
+        recipient_id: user.id,
+        since: query_dto.since,
+        limit: query_dto.limit,
+    };
+
+    let feed = query.handle(&app_state.message_change_repository).await?;
+
+    Ok(Json(feed))
+}
+
+#[utoipa::path(
+    put,
+    path = "/message/{id}/recipient_metadata",
+    tag = "message",
+    security(("session" = [])),
+    params(("id" = i64, Path, description = "Id of the message")),
+    request_body = UpdateRecipientMetadataCommandDto,
+    responses((status = 200, body = i64, description = "The new metadata version")),
+)]
+pub async fn update_recipient_metadata(
+    Extension(app_state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Path(message_id): Path<i64>,
+    Json(command_dto): Json<UpdateRecipientMetadataCommandDto>,
+) -> Result<Json<i64>, ApiError> {
+    let version = UpdateRecipientMetadataCommand {
+        recipient_id: user.id,
+        message_id,
+        recipient_metadata: command_dto.recipient_metadata,
+        expected_version: command_dto.expected_version,
+    }
+    .handle(&app_state.message_repository)
+    .await?;
+    Ok(Json(version))
+}
+
+struct MessageEventStream {
+    app_state: AppState,
+    recipient_id: Uuid,
+    last_seen_id: i64,
+    pending: VecDeque<MessageSummary>,
+    notifications: broadcast::Receiver<NewMessageNotification>,
+    request_id: Option<String>,
+    closed: bool,
+}
+
+/// Streams new mail as server-sent events. Notifications only serve as a wake-up signal: the
+/// mailbox is always re-read past the last sent id, so nothing is lost across lagging or
+/// reconnects, and `Last-Event-ID` resumes the stream where the client left off. If the mailbox
+/// can't be read, the stream ends with an `error` event carrying the usual error envelope.
+#[utoipa::path(
+    get,
+    path = "/message/events",
+    tag = "message",
+    security(("session" = [])),
+    params(
+        GetNewMessagesQueryDto,
+        ("Last-Event-ID" = Option<i64>, Header, description = "Id of the last message received, to resume from"),
+    ),
+    responses(
+        (status = 200, body = MessageSummary, content_type = "text/event-stream", description = "One `message` event per new message, or a final `error` event"),
+    ),
+)]
+pub async fn message_events(
+    Extension(app_state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    headers: HeaderMap,
+    Query(query_dto): Query<GetNewMessagesQueryDto>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    // Subscribe before reading the mailbox so no message can slip in between
+    let notifications = app_state.message_notifier.subscribe();
+
+    let resume_from = headers
+        .get("Last-Event-ID")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.parse::<i64>().ok())
+        .or(query_dto.last_seen_id);
+    let last_seen_id = match resume_from {
+        Some(last_seen_id) => last_seen_id,
+        None => GetLatestMessageIdQuery {
+            recipient_id: user.id,
+        }
+        .handle(&app_state.message_repository)
+        .await?
+        .unwrap_or(0),
+    };
+
+    let events = MessageEventStream {
+        app_state,
+        recipient_id: user.id,
+        last_seen_id,
+        pending: VecDeque::new(),
+        notifications,
+        request_id: request_id::current(),
+        closed: false,
+    };
+    let stream = futures::stream::unfold(events, |mut events| async move {
+        if events.closed {
+            return None;
+        }
+        loop {
+            if let Some(message) = events.pending.pop_front() {
+                events.last_seen_id = message.id;
+                let event = Event::default()
+                    .event("message")
+                    .id(message.id.to_string())
+                    .json_data(&message)
+                    .expect("Message summaries always serialize");
+                return Some((Ok(event), events));
+            }
+
+            let new_messages = match (GetNewMessagesQuery {
+                recipient_id: events.recipient_id,
+                after_id: events.last_seen_id,
+            })
+            .handle(&events.app_state.message_repository)
+            .await
+            {
+                Ok(new_messages) => new_messages,
+                Err(e) => {
+                    // Tell the client why the stream ends, so it knows to reconnect
+                    events.closed = true;
+                    let envelope = ApiError::from(e).into_envelope(events.request_id.clone());
+                    let event = Event::default()
+                        .event("error")
+                        .json_data(&envelope)
+                        .expect("Error envelopes always serialize");
+                    return Some((Ok(event), events));
+                }
+            };
+            if !new_messages.is_empty() {
+                events.pending.extend(new_messages);
+                continue;
+            }
+
+            // Wait for a notification for this recipient, re-checking periodically in case
+            // one was missed while the listener was reconnecting
+            let _ = tokio::time::timeout(EVENT_RECHECK_INTERVAL, async {
+                loop {
+                    match events.notifications.recv().await {
+                        Ok(notification) if notification.recipient_id != events.recipient_id => {
+                            continue
+                        }
+                        Err(broadcast::error::RecvError::Closed) => {
+                            std::future::pending::<()>().await
+                        }
+                        _ => break,
+                    }
+                }
+            })
+            .await;
+        }
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
@@ -0,0 +1,82 @@
+use application::outbox::commands::DeleteOutboxEntryCommand;
+use application::outbox::queries::{
+    GetOutboxEntryQuery, GetOutboxQuery, GetOutboxQueryDto, OutboxPage,
+};
+use axum::{http::StatusCode, Extension};
+use domain::outbox::OutboxEntry;
+
+use crate::{
+    error::ApiError,
+    extractors::{AuthUser, Json, Path, Query},
+    state::AppState,
+};
+
+#[utoipa::path(
+    get,
+    path = "/outbox",
+    tag = "outbox",
+    security(("session" = [])),
+    params(GetOutboxQueryDto),
+    responses((status = 200, body = OutboxPage)),
+)]
+pub async fn get_outbox(
+    Extension(app_state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Query(query_dto): Query<GetOutboxQueryDto>,
+) -> Result<Json<OutboxPage>, ApiError> {
+    let query = GetOutboxQuery {
+        sender_id: user.id,
+        after: query_dto.after,
+        before: query_dto.before,
+        limit: query_dto.limit,
+    };
+
+    let page = query.handle(&app_state.outbox_repository).await?;
+
+    Ok(Json(page))
+}
+
+#[utoipa::path(
+    get,
+    path = "/outbox/{id}",
+    tag = "outbox",
+    security(("session" = [])),
+    params(("id" = i64, Path, description = "Id of the outbox entry")),
+    responses((status = 200, body = OutboxEntry)),
+)]
+pub async fn get_outbox_entry(
+    Extension(app_state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<i64>,
+) -> Result<Json<OutboxEntry>, ApiError> {
+    let query = GetOutboxEntryQuery {
+        sender_id: user.id,
+        id,
+    };
+
+    let entry = query.handle(&app_state.outbox_repository).await?;
+
+    Ok(Json(entry))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/outbox/{id}",
+    tag = "outbox",
+    security(("session" = [])),
+    params(("id" = i64, Path, description = "Id of the outbox entry")),
+    responses((status = 204, description = "The entry is deleted")),
+)]
+pub async fn delete_outbox_entry(
+    Extension(app_state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    DeleteOutboxEntryCommand {
+        sender_id: user.id,
+        id,
+    }
+    .handle(&app_state.outbox_repository)
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,43 +1,168 @@
+use std::net::IpAddr;
+
+use application::rate_limit::commands::ConsumeRateLimitCommand;
 use application::stamp::commands::{
-    IssueSystemStampCommand, IssueSystemStampCommandDto, RequestSystemStampIssueCommand,
+    DifficultyRule, IssueSystemStampCommand, IssueSystemStampCommandDto,
+    RequestSystemStampIssueCommand, RequestSystemStampIssueCommandDto, StampRequestReceipt,
 };
-use axum::{Extension, Json};
-use domain::{stamp::OnetimeStamp, uuid::Uuid};
+use application::user::queries::ResolveRecipientQuery;
+use axum::Extension;
+use domain::rate_limit::{RateLimitKey, RouteClass};
+use domain::stamp::OnetimeStamp;
+use domain::user::User;
+use domain::uuid::Uuid;
 
-use crate::{error::ApiError, extractors::AuthUser, state::AppState};
+use crate::{
+    error::ApiError,
+    extractors::{AuthUser, ClientIp, Json},
+    state::AppState,
+};
 
+/// Answers with the id of the stamp request only. At the base difficulty it is held against
+/// the score of the proof of work as is. Raised difficulties, for senders who have been
+/// reported, are the expected number of hashes as in `/v2/stamp/request_system_issue`.
+#[utoipa::path(
+    post,
+    path = "/stamp/request_system_issue",
+    tag = "stamp",
+    security(("session" = [])),
+    request_body = RequestSystemStampIssueCommandDto,
+    responses((status = 200, body = Uuid)),
+)]
 #[axum::debug_handler]
 pub async fn request_system_issue(
     Extension(state): Extension<AppState>,
-    AuthUser(_user): AuthUser,
-    Json(command): Json<RequestSystemStampIssueCommand>,
+    ClientIp(ip): ClientIp,
+    AuthUser(user): AuthUser,
+    Json(command_dto): Json<RequestSystemStampIssueCommandDto>,
 ) -> Result<Json<Uuid>, ApiError> {
+    let receipt = request_stamp(state, ip, user, command_dto).await?;
+    Ok(Json(receipt.request.stamp_request_id))
+}
+
+/// Answers with the whole stamp request and the recipient it is for. The difficulty is the
+/// expected number of hashes the proof of work takes.
+#[utoipa::path(
+    post,
+    path = "/stamp/request_system_issue",
+    tag = "stamp",
+    security(("session" = [])),
+    request_body = RequestSystemStampIssueCommandDto,
+    responses((status = 200, body = StampRequestReceipt)),
+)]
+#[axum::debug_handler]
+pub async fn request_system_issue_v2(
+    Extension(state): Extension<AppState>,
+    ClientIp(ip): ClientIp,
+    AuthUser(user): AuthUser,
+    Json(command_dto): Json<RequestSystemStampIssueCommandDto>,
+) -> Result<Json<StampRequestReceipt>, ApiError> {
+    let receipt = request_stamp(state, ip, user, command_dto).await?;
+    Ok(Json(receipt))
+}
+
+async fn request_stamp(
+    state: AppState,
+    ip: IpAddr,
+    user: User,
+    command_dto: RequestSystemStampIssueCommandDto,
+) -> Result<StampRequestReceipt, ApiError> {
+    let recipient_id = ResolveRecipientQuery {
+        recipient: command_dto.recipient,
+        local_host: state.local_host.clone(),
+    }
+    .handle(&state.user_repository)
+    .await?;
+    ConsumeRateLimitCommand {
+        class: RouteClass::StampRequest,
+        keys: vec![
+            RateLimitKey::Ip(ip),
+            RateLimitKey::User(user.id.to_string()),
+            RateLimitKey::Recipient(recipient_id),
+        ],
+    }
+    .handle(&state.rate_limiter, &state.rate_limit_policy)
+    .await?;
+    let command = RequestSystemStampIssueCommand {
+        recipient_id,
+        sender_id: user.id,
+    };
     let result = command
-        .handle(&state.user_repository, &state.stamp_request_repository)
+        .handle(
+            &state.user_repository,
+            &state.stamp_request_repository,
+            &state.block_list_repository,
+            &state.report_repository,
+            &state.cryptography_service,
+            &state.report_policy,
+            &state.stamp_policy,
+        )
         .await?;
-    Ok(Json(result))
+    Ok(result)
 }
 
+/// Accepts a proof of work whose score reaches the difficulty of the stamp request, or that took
+/// that many hashes on average if the difficulty was raised above the base.
+#[utoipa::path(
+    post,
+    path = "/stamp/system_issue",
+    tag = "stamp",
+    security(("session" = [])),
+    request_body = IssueSystemStampCommandDto,
+    responses((status = 200, body = OnetimeStamp)),
+)]
 #[axum::debug_handler]
 pub async fn system_issue(
     Extension(state): Extension<AppState>,
     AuthUser(user): AuthUser,
     Json(command_dto): Json<IssueSystemStampCommandDto>,
 ) -> Result<Json<OnetimeStamp>, ApiError> {
+    let stamp = issue_stamp(state, user, command_dto, DifficultyRule::Score).await?;
+    Ok(Json(stamp))
+}
+
+/// Accepts a proof of work that took the difficulty of the stamp request in hashes, on average.
+#[utoipa::path(
+    post,
+    path = "/stamp/system_issue",
+    tag = "stamp",
+    security(("session" = [])),
+    request_body = IssueSystemStampCommandDto,
+    responses((status = 200, body = OnetimeStamp)),
+)]
+#[axum::debug_handler]
+pub async fn system_issue_v2(
+    Extension(state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Json(command_dto): Json<IssueSystemStampCommandDto>,
+) -> Result<Json<OnetimeStamp>, ApiError> {
+    let stamp = issue_stamp(state, user, command_dto, DifficultyRule::ExpectedHashes).await?;
+    Ok(Json(stamp))
+}
+
+async fn issue_stamp(
+    state: AppState,
+    user: User,
+    command_dto: IssueSystemStampCommandDto,
+    difficulty_rule: DifficultyRule,
+) -> Result<OnetimeStamp, ApiError> {
     let command = IssueSystemStampCommand {
         sender_id: user.id,
         stamp_request_id: command_dto.stamp_request_id,
         proof_of_work: command_dto.proof_of_work,
+        difficulty_rule,
     };
     let result = command
         .handle(
             &state.user_repository,
             &state.stamp_request_repository,
+            &state.block_list_repository,
             &state.tracker_repository,
             &state.system_key_repository,
             &state.cryptography_service,
             &state.serialize_service,
+            &state.stamp_policy,
         )
         .await?;
-    Ok(Json(result))
+    Ok(result)
 }
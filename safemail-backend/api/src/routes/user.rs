@@ -1,16 +1,32 @@
+use application::block_list::commands::{
+    BlockSenderCommand, BlockSenderCommandDto, UnblockSenderCommand,
+};
+use application::block_list::queries::GetBlockedSendersQuery;
+use application::message::queries::{GetMailboxUsageQuery, MailboxUsageReport};
+use application::rate_limit::commands::ConsumeRateLimitCommand;
 use application::user::commands::{
     ActivateSessionCommand, RegisterUserCommand, RequestSessionCommand, UserCommandValidator,
 };
 use application::user::queries::GetUserByUsernameQuery;
-use axum::extract::Path;
-use axum::{Extension, Json};
+use axum::{http::StatusCode, Extension};
 use domain::error::UserError;
+use domain::rate_limit::{RateLimitKey, RouteClass};
+use domain::uuid::Uuid;
 use domain::validate::Validate;
-use domain::{session::Session, user::User};
+use domain::{block_list::BlockedSender, session::Session, user::User};
 
-use crate::extractors::AuthUser;
+use crate::extractors::{AuthUser, ClientIp, Json, Path};
 use crate::{error::ApiError, state::AppState};
 
+#[utoipa::path(
+    get,
+    path = "/user/{username}",
+    tag = "user",
+    params(
+        ("username" = String, Path, description = "Username, or username@host of a known remote user"),
+    ),
+    responses((status = 200, body = User)),
+)]
 #[axum::debug_handler]
 pub async fn get_user(
     Extension(state): Extension<AppState>,
@@ -23,32 +39,81 @@ pub async fn get_user(
     Ok(Json(user))
 }
 
+#[utoipa::path(
+    post,
+    path = "/user/register",
+    tag = "user",
+    request_body = RegisterUserCommand,
+    responses((status = 200, body = User)),
+)]
 #[axum::debug_handler]
 pub async fn register_user(
     Extension(state): Extension<AppState>,
+    ClientIp(ip): ClientIp,
     Json(command): Json<RegisterUserCommand>,
 ) -> Result<Json<User>, ApiError> {
+    ConsumeRateLimitCommand {
+        class: RouteClass::Registration,
+        keys: vec![RateLimitKey::Ip(ip)],
+    }
+    .handle(&state.rate_limiter, &state.rate_limit_policy)
+    .await?;
     UserCommandValidator(&state.cryptography_service).validate(&command)?;
     let user = command.handle(&state.user_repository).await?;
     Ok(Json(user))
 }
 
+#[utoipa::path(
+    post,
+    path = "/user/login",
+    tag = "user",
+    request_body = RequestSessionCommand,
+    responses((status = 200, body = Session)),
+)]
 #[axum::debug_handler]
 pub async fn request_session(
     Extension(state): Extension<AppState>,
+    ClientIp(ip): ClientIp,
     Json(command): Json<RequestSessionCommand>,
 ) -> Result<Json<Session>, ApiError> {
+    ConsumeRateLimitCommand {
+        class: RouteClass::Login,
+        keys: vec![
+            RateLimitKey::Ip(ip),
+            RateLimitKey::User(command.username.to_lowercase()),
+        ],
+    }
+    .handle(&state.rate_limiter, &state.rate_limit_policy)
+    .await?;
     let session = command
-        .handle(&state.user_repository, &state.session_repository)
+        .handle(
+            &state.user_repository,
+            &state.session_repository,
+            &state.session_policy,
+        )
         .await?;
     Ok(Json(session))
 }
 
+#[utoipa::path(
+    post,
+    path = "/user/login/confirm",
+    tag = "user",
+    request_body = ActivateSessionCommand,
+    responses((status = 200, description = "The session is active")),
+)]
 #[axum::debug_handler]
 pub async fn activate_session(
     Extension(state): Extension<AppState>,
+    ClientIp(ip): ClientIp,
     Json(command): Json<ActivateSessionCommand>,
 ) -> Result<(), ApiError> {
+    ConsumeRateLimitCommand {
+        class: RouteClass::Login,
+        keys: vec![RateLimitKey::Ip(ip)],
+    }
+    .handle(&state.rate_limiter, &state.rate_limit_policy)
+    .await?;
     command
         .handle(
             &state.user_repository,
@@ -59,7 +124,100 @@ pub async fn activate_session(
     Ok(())
 }
 
+#[utoipa::path(
+    post,
+    path = "/user/whoami",
+    tag = "user",
+    security(("session" = [])),
+    responses((status = 200, body = User)),
+)]
 #[axum::debug_handler]
 pub async fn whoami(AuthUser(user): AuthUser) -> Json<User> {
     Json(user)
 }
+
+/// How much of their mailbox quota the user is using.
+#[utoipa::path(
+    get,
+    path = "/user/usage",
+    tag = "user",
+    security(("session" = [])),
+    responses((status = 200, body = MailboxUsageReport)),
+)]
+#[axum::debug_handler]
+pub async fn get_mailbox_usage(
+    Extension(state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<MailboxUsageReport>, ApiError> {
+    let report = GetMailboxUsageQuery {
+        recipient_id: user.id,
+    }
+    .handle(&state.message_repository, &state.mailbox_quota)
+    .await?;
+    Ok(Json(report))
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/blocked",
+    tag = "user",
+    security(("session" = [])),
+    responses((status = 200, body = Vec<BlockedSender>)),
+)]
+#[axum::debug_handler]
+pub async fn get_blocked_senders(
+    Extension(state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<Vec<BlockedSender>>, ApiError> {
+    let blocked = GetBlockedSendersQuery { user_id: user.id }
+        .handle(&state.block_list_repository)
+        .await?;
+    Ok(Json(blocked))
+}
+
+/// Refuses all further mail from the sender. The sender isn't told.
+#[utoipa::path(
+    post,
+    path = "/user/blocked",
+    tag = "user",
+    security(("session" = [])),
+    request_body = BlockSenderCommandDto,
+    responses((status = 204, description = "The sender is blocked")),
+)]
+#[axum::debug_handler]
+pub async fn block_sender(
+    Extension(state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Json(command_dto): Json<BlockSenderCommandDto>,
+) -> Result<StatusCode, ApiError> {
+    BlockSenderCommand {
+        user_id: user.id,
+        sender_id: command_dto.sender_id,
+    }
+    .handle(&state.user_repository, &state.block_list_repository)
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/user/blocked/{id}",
+    tag = "user",
+    security(("session" = [])),
+    params(("id" = Uuid, Path, description = "Id of the blocked sender")),
+    responses((status = 204, description = "The sender is no longer blocked")),
+)]
+#[axum::debug_handler]
+pub async fn unblock_sender(
+    Extension(state): Extension<AppState>,
+    AuthUser(user): AuthUser,
+    Path(sender_id): Path<Uuid>,
+) -> Result<StatusCode, ApiError> {
+    UnblockSenderCommand {
+        user_id: user.id,
+        sender_id,
+    }
+    .handle(&state.block_list_repository)
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,12 +1,23 @@
-use domain::{message::MessageRepository, onetime_stamp::OneTimeStampTrackerRepository, serialize};
+use application::system_key::commands::InitSystemKeysCommand;
+use domain::{
+    idempotency::IdempotencyPolicy, message::MailboxQuota, outbound_mail::BridgePolicy,
+    rate_limit::RateLimitPolicy, report::ReportPolicy, session::SessionPolicy, stamp::StampPolicy,
+};
 use infrastructure::{
+    config::Config,
     repositories::{
-        PostgresMessageRepository, PostgresOneTimeStampRepository, PostgresSessionRepository,
-        PostgresStampRequestRepository, PostgresSystemKeyRepository, PostgresUserRepository,
+        PostgresBlobRepository, PostgresBlockListRepository, PostgresFederationPeerRepository,
+        PostgresIdempotencyRepository, PostgresMessageChangeRepository, PostgresMessageRepository,
+        PostgresOneTimeStampRepository, PostgresOrphanedContentRepository,
+        PostgresOutboundMailRepository, PostgresOutboxRepository, PostgresRemoteUserRepository,
+        PostgresReportRepository, PostgresScheduledMessageRepository, PostgresSessionRepository,
+        PostgresStampRequestRepository, PostgresStampRevocationRepository,
+        PostgresSystemKeyRepository, PostgresUserRepository,
     },
     services::{
-        cryptography::{BcryptPasswordService, OpensslCryptographyService},
-        serialize::JsonService,
+        content_store::ConfiguredContentStore, cryptography::OpensslCryptographyService,
+        federation::HttpFederationClient, notifications::PostgresMessageNotifier,
+        rate_limiter::ConfiguredRateLimiter, serialize::JsonService,
     },
 };
 
@@ -15,35 +26,117 @@ pub struct AppState {
     pub user_repository: PostgresUserRepository,
     pub session_repository: PostgresSessionRepository,
     pub message_repository: PostgresMessageRepository,
+    pub message_change_repository: PostgresMessageChangeRepository,
     pub tracker_repository: PostgresOneTimeStampRepository,
     pub stamp_request_repository: PostgresStampRequestRepository,
     pub system_key_repository: PostgresSystemKeyRepository,
-    pub password_service: BcryptPasswordService,
+    pub blob_repository: PostgresBlobRepository,
+    pub block_list_repository: PostgresBlockListRepository,
+    pub orphaned_content_repository: PostgresOrphanedContentRepository,
+    pub idempotency_repository: PostgresIdempotencyRepository,
+    pub outbox_repository: PostgresOutboxRepository,
+    pub outbound_mail_repository: PostgresOutboundMailRepository,
+    pub scheduled_message_repository: PostgresScheduledMessageRepository,
+    pub report_repository: PostgresReportRepository,
+    pub stamp_revocation_repository: PostgresStampRevocationRepository,
+    pub remote_user_repository: PostgresRemoteUserRepository,
+    pub federation_peer_repository: PostgresFederationPeerRepository,
+    pub federation_client: HttpFederationClient,
+    /// The host other servers know this one by, the part after the `@` in its users' addresses.
+    pub local_host: String,
+    pub content_store: ConfiguredContentStore,
+    pub message_notifier: PostgresMessageNotifier,
+    pub mailbox_quota: MailboxQuota,
+    pub session_policy: SessionPolicy,
+    pub stamp_policy: StampPolicy,
+    pub report_policy: ReportPolicy,
+    pub bridge_policy: BridgePolicy,
+    pub rate_limiter: ConfiguredRateLimiter,
+    pub rate_limit_policy: RateLimitPolicy,
+    pub idempotency_policy: IdempotencyPolicy,
+    /// Whether the client address is taken from `X-Forwarded-For`, which only a proxy in front of
+    /// the server may be trusted to set.
+    pub trust_forwarded_for: bool,
     pub cryptography_service: OpensslCryptographyService,
     pub serialize_service: JsonService,
 }
 impl AppState {
-    pub async fn new() -> Self {
-        let db = infrastructure::db::get_pool().await;
+    pub async fn new(config: &Config) -> Self {
+        let db = infrastructure::db::get_pool(&config.database.url).await;
+        let content_store = ConfiguredContentStore::new(&config.content_store, db.clone());
+        let rate_limiter = ConfiguredRateLimiter::new(config.rate_limits.backend, db.clone());
+        let local_host = config.server.host.to_ascii_lowercase();
         let user_repository = PostgresUserRepository::new(db.clone());
         let session_repository = PostgresSessionRepository::new(db.clone());
-        let message_repository = PostgresMessageRepository::new(db.clone());
+        let message_repository = PostgresMessageRepository::new(
+            db.clone(),
+            content_store.clone(),
+            config.content_store.out_of_line_threshold,
+        );
+        let message_change_repository = PostgresMessageChangeRepository::new(db.clone());
         let tracker_repository = PostgresOneTimeStampRepository::new(db.clone());
         let stamp_request_repository = PostgresStampRequestRepository::new(db.clone());
         let system_key_repository = PostgresSystemKeyRepository::new(db.clone());
+        let blob_repository = PostgresBlobRepository::new(db.clone(), content_store.clone());
+        let block_list_repository = PostgresBlockListRepository::new(db.clone());
+        let orphaned_content_repository = PostgresOrphanedContentRepository::new(db.clone());
+        let idempotency_repository = PostgresIdempotencyRepository::new(db.clone());
+        let outbox_repository = PostgresOutboxRepository::new(db.clone(), content_store.clone());
+        let outbound_mail_repository = PostgresOutboundMailRepository::new(db.clone());
+        let scheduled_message_repository = PostgresScheduledMessageRepository::new(
+            db.clone(),
+            content_store.clone(),
+            message_repository.clone(),
+        );
+        let report_repository = PostgresReportRepository::new(db.clone());
+        let stamp_revocation_repository = PostgresStampRevocationRepository::new(db.clone());
+        let remote_user_repository = PostgresRemoteUserRepository::new(db.clone());
+        let federation_peer_repository = PostgresFederationPeerRepository::new(db.clone());
+        let federation_client = HttpFederationClient::new(&config.federation);
+        let message_notifier = PostgresMessageNotifier::listen(db.clone())
+            .await
+            .expect("Failed to listen for message notifications");
 
-        let password_service = BcryptPasswordService;
         let cryptography_service = OpensslCryptographyService;
         let serialize_service = JsonService;
 
+        InitSystemKeysCommand
+            .handle(&system_key_repository, &cryptography_service)
+            .await
+            .expect("Failed to initialize system keys");
+
         Self {
             user_repository,
             session_repository,
             message_repository,
+            message_change_repository,
             stamp_request_repository,
             system_key_repository,
+            blob_repository,
+            block_list_repository,
+            orphaned_content_repository,
+            idempotency_repository,
+            outbox_repository,
+            outbound_mail_repository,
+            scheduled_message_repository,
+            report_repository,
+            stamp_revocation_repository,
+            remote_user_repository,
+            federation_peer_repository,
+            federation_client,
+            local_host,
+            content_store,
             tracker_repository,
-            password_service,
+            message_notifier,
+            mailbox_quota: config.mailbox,
+            session_policy: config.session,
+            stamp_policy: config.stamps,
+            report_policy: config.reports,
+            bridge_policy: config.bridge,
+            rate_limiter,
+            rate_limit_policy: config.rate_limits.policy(),
+            idempotency_policy: config.idempotency,
+            trust_forwarded_for: config.server.trust_forwarded_for,
             cryptography_service,
             serialize_service,
         }
@@ -0,0 +1,200 @@
+use std::time::Duration;
+
+use application::blob::commands::CollectBlobGarbageCommand;
+use application::content::commands::CollectOrphanedContentCommand;
+use application::federation::commands::PurgeFederationRequestsCommand;
+use application::idempotency::commands::PurgeIdempotencyKeysCommand;
+use application::message::commands::{
+    DispatchScheduledMessagesCommand, PruneMessageChangesCommand, PurgeExpiredMessagesCommand,
+    PurgeTrashCommand,
+};
+use application::rate_limit::commands::PurgeRateLimitsCommand;
+use domain::chrono;
+
+use crate::state::AppState;
+
+const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const TRASH_RETENTION_DAYS: i64 = 30;
+const EXPIRED_MESSAGE_PURGE_INTERVAL: Duration = Duration::from_secs(60);
+const CHANGE_FEED_PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const BLOB_GARBAGE_COLLECTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const UNREFERENCED_BLOB_GRACE_HOURS: i64 = 24;
+const ABANDONED_UPLOAD_GRACE_HOURS: i64 = 72;
+const ORPHANED_CONTENT_COLLECTION_INTERVAL: Duration = Duration::from_secs(10 * 60);
+const ORPHANED_CONTENT_BATCH_SIZE: i64 = 500;
+const IDEMPOTENCY_KEY_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const SCHEDULED_MESSAGE_DISPATCH_INTERVAL: Duration = Duration::from_secs(15);
+const SCHEDULED_MESSAGE_BATCH_SIZE: i64 = 100;
+const RATE_LIMIT_PURGE_INTERVAL: Duration = Duration::from_secs(60);
+const FEDERATION_REQUEST_PURGE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Permanently deletes messages that have sat in the trash for longer than the retention period.
+pub fn spawn_trash_purge(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TRASH_PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let command = PurgeTrashCommand {
+                retention: chrono::Duration::days(TRASH_RETENTION_DAYS),
+            };
+            if let Err(e) = command.handle(&state.message_repository).await {
+                eprintln!("failed to purge trash: {}", e);
+            }
+        }
+    });
+}
+
+/// Deletes messages whose sender-specified expiry has passed. They are hidden from queries
+/// from the moment they expire; this only reclaims their storage.
+pub fn spawn_expired_message_purge(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(EXPIRED_MESSAGE_PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = PurgeExpiredMessagesCommand
+                .handle(&state.message_repository)
+                .await
+            {
+                eprintln!("failed to purge expired messages: {}", e);
+            }
+        }
+    });
+}
+
+/// Prunes change feed entries, tombstones included, once they are older than `retention_days`.
+/// Devices that were away for longer have to resync from scratch.
+pub fn spawn_change_feed_pruning(state: AppState, retention_days: i64) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHANGE_FEED_PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let command = PruneMessageChangesCommand {
+                retention: chrono::Duration::days(retention_days),
+            };
+            if let Err(e) = command.handle(&state.message_change_repository).await {
+                eprintln!("failed to prune message changes: {}", e);
+            }
+        }
+    });
+}
+
+/// Deletes blobs once the last message referencing them is gone, as well as uploads that were
+/// never finished. Fresh uploads get a grace period to be attached to a message.
+pub fn spawn_blob_garbage_collection(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(BLOB_GARBAGE_COLLECTION_INTERVAL);
+        loop {
+            interval.tick().await;
+            let command = CollectBlobGarbageCommand {
+                unreferenced_grace: chrono::Duration::hours(UNREFERENCED_BLOB_GRACE_HOURS),
+                abandoned_grace: chrono::Duration::hours(ABANDONED_UPLOAD_GRACE_HOURS),
+            };
+            if let Err(e) = command.handle(&state.blob_repository).await {
+                eprintln!("failed to collect blob garbage: {}", e);
+            }
+        }
+    });
+}
+
+/// Deletes objects from the content store once the messages or blob chunks they belonged to
+/// are gone.
+pub fn spawn_orphaned_content_collection(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ORPHANED_CONTENT_COLLECTION_INTERVAL);
+        loop {
+            interval.tick().await;
+            let command = CollectOrphanedContentCommand {
+                batch_size: ORPHANED_CONTENT_BATCH_SIZE,
+            };
+            if let Err(e) = command
+                .handle(&state.orphaned_content_repository, &state.content_store)
+                .await
+            {
+                eprintln!("failed to collect orphaned content: {}", e);
+            }
+        }
+    });
+}
+
+/// Forgets idempotency keys after `retention_hours`. Retries that arrive later are processed as
+/// new requests.
+pub fn spawn_idempotency_key_purge(state: AppState, retention_hours: i64) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(IDEMPOTENCY_KEY_PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let command = PurgeIdempotencyKeysCommand {
+                retention: chrono::Duration::hours(retention_hours),
+            };
+            if let Err(e) = command.handle(&state.idempotency_repository).await {
+                eprintln!("failed to purge idempotency keys: {}", e);
+            }
+        }
+    });
+}
+
+/// Moves scheduled messages into their recipients' mailboxes once they are due. Full batches
+/// are followed up right away, so a backlog doesn't wait for the next tick.
+pub fn spawn_scheduled_message_dispatch(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SCHEDULED_MESSAGE_DISPATCH_INTERVAL);
+        loop {
+            interval.tick().await;
+            loop {
+                let command = DispatchScheduledMessagesCommand {
+                    batch_size: SCHEDULED_MESSAGE_BATCH_SIZE,
+                };
+                let result = command
+                    .handle(
+                        &state.user_repository,
+                        &state.cryptography_service,
+                        &state.serialize_service,
+                        &state.tracker_repository,
+                        &state.system_key_repository,
+                        &state.block_list_repository,
+                        &state.stamp_revocation_repository,
+                        &state.scheduled_message_repository,
+                        &state.mailbox_quota,
+                    )
+                    .await;
+                match result {
+                    Ok(delivered) if delivered as i64 == SCHEDULED_MESSAGE_BATCH_SIZE => continue,
+                    Ok(_) => break,
+                    Err(e) => {
+                        eprintln!("failed to dispatch scheduled messages: {}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Forgets the request counts of rate limit windows that have ended.
+pub fn spawn_rate_limit_purge(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(RATE_LIMIT_PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = PurgeRateLimitsCommand.handle(&state.rate_limiter).await {
+                eprintln!("failed to purge rate limits: {}", e);
+            }
+        }
+    });
+}
+
+/// Forgets the ids of requests from other servers once they are too old to be replayed.
+pub fn spawn_federation_request_purge(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(FEDERATION_REQUEST_PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = PurgeFederationRequestsCommand
+                .handle(&state.federation_peer_repository)
+                .await
+            {
+                eprintln!("failed to purge federation requests: {}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,149 @@
+use domain::blob::BlobRepository;
+use serde_json::json;
+
+use super::{error_code, read, TestServer};
+
+#[tokio::test]
+async fn only_users_who_can_read_a_blob_can_attach_it() {
+    let server = TestServer::start().await;
+    let (alice, bob, carol) = (
+        server.user().await,
+        server.user().await,
+        server.user().await,
+    );
+    let data = vec![7; 2500];
+    let hash = alice.upload(&data).await;
+
+    // Knowing the hash is not enough, neither to download the blob nor to attach it
+    let response = carol.get(&format!("/blob/{}", hash)).send().await.unwrap();
+    assert_eq!(error_code(response).await, "blob_not_found");
+    let message = carol.message("look", &[&hash]);
+    let response = carol.send(&bob, message).send().await.unwrap();
+    assert_eq!(error_code(response).await, "blob_not_found");
+
+    read(alice.send(&bob, alice.message("look", &[&hash]))).await;
+    let response = bob.get(&format!("/blob/{}", hash)).send().await.unwrap();
+    assert_eq!(response.bytes().await.unwrap(), data);
+    // Recipients may pass on what they were sent
+    read(bob.send(&carol, bob.message("look", &[&hash]))).await;
+}
+
+#[tokio::test]
+async fn idle_uploads_can_be_taken_over() {
+    let server = TestServer::start().await;
+    let (alice, carol) = (server.user().await, server.user().await);
+    let data = vec![1; 500];
+    let hash = alice.upload(&data).await;
+    let other = "0".repeat(64);
+    let start = json!({ "hash": other, "size": 500, "chunk_size": 500 });
+
+    read(carol.post("/blob/upload").json(&start)).await;
+    let response = alice
+        .post("/blob/upload")
+        .json(&start)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(error_code(response).await, "upload_mismatch");
+
+    server
+        .database
+        .execute(&format!(
+            "UPDATE sm.blobs SET active_at = NOW() - INTERVAL '11 minutes' WHERE hash = '{}'",
+            other
+        ))
+        .await;
+    read(alice.post("/blob/upload").json(&start)).await;
+    let response = carol
+        .put(&format!("/blob/{}/chunks/0", other))
+        .body(data)
+        .send()
+        .await
+        .unwrap();
+    assert!(!response.status().is_success());
+
+    // A finished upload is never up for grabs
+    server
+        .database
+        .execute(&format!(
+            "UPDATE sm.blobs SET active_at = NOW() - INTERVAL '11 minutes' WHERE hash = '{}'",
+            hash
+        ))
+        .await;
+    let start = json!({ "hash": hash, "size": 500, "chunk_size": 500 });
+    let response = carol
+        .post("/blob/upload")
+        .json(&start)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(error_code(response).await, "upload_mismatch");
+}
+
+#[tokio::test]
+async fn sent_copies_keep_their_attachments_readable_until_deleted() {
+    let server = TestServer::start().await;
+    let (alice, bob, carol) = (
+        server.user().await,
+        server.user().await,
+        server.user().await,
+    );
+    let hash = alice.upload(&[3; 1500]).await;
+    let received = read(alice.send(&bob, alice.message("look", &[&hash]))).await;
+
+    let mut message = bob.message("look", &[&hash]);
+    message["sender_copy"] = json!({
+        "metadata": super::base64("mine"),
+        "content": super::base64("my copy"),
+    });
+    let receipt = read(bob.send(&carol, message)).await;
+    let outbox_id = receipt["outbox_id"].as_i64().unwrap();
+    let entry = read(bob.get(&format!("/outbox/{}", outbox_id))).await;
+    assert_eq!(entry["attachments"], json!([hash]));
+    let response = carol
+        .get(&format!("/outbox/{}", outbox_id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(error_code(response).await, "outbox_entry_not_found");
+
+    let message_id = received["message_id"].as_i64().unwrap();
+    read(bob.delete(&format!("/message/{}?permanent=true", message_id))).await;
+    let blob = format!("/blob/{}", hash);
+    assert!(bob.get(&blob).send().await.unwrap().status().is_success());
+    read(bob.delete(&format!("/outbox/{}", outbox_id))).await;
+    let response = bob.get(&blob).send().await.unwrap();
+    assert_eq!(error_code(response).await, "blob_not_found");
+}
+
+#[tokio::test]
+async fn chunks_only_go_into_pending_uploads_of_their_uploader() {
+    let server = TestServer::start().await;
+    let (alice, carol) = (server.user().await, server.user().await);
+    let data = vec![1; 500];
+    let hash = alice.upload(&data).await;
+    let pending = "0".repeat(64);
+    read(
+        alice
+            .post("/blob/upload")
+            .json(&json!({ "hash": pending, "size": 500, "chunk_size": 500 })),
+    )
+    .await;
+    let blobs = &server.state.blob_repository;
+
+    // As though the upload was completed or taken over after the command looked it up
+    assert!(!blobs
+        .put_chunk(&hash, alice.id, 0, vec![2; 500])
+        .await
+        .unwrap());
+    assert!(!blobs
+        .put_chunk(&pending, carol.id, 0, vec![2; 500])
+        .await
+        .unwrap());
+    assert_eq!(blobs.get_chunk(&hash, 0).await.unwrap(), Some(data));
+    assert_eq!(blobs.get_chunk(&pending, 0).await.unwrap(), None);
+    assert!(blobs
+        .put_chunk(&pending, alice.id, 0, vec![2; 500])
+        .await
+        .unwrap());
+}
@@ -0,0 +1,97 @@
+use application::content::commands::CollectOrphanedContentCommand;
+use domain::content::ContentStore;
+use infrastructure::{
+    services::content_store::{ContentStoreBackend, S3ContentStore},
+    testing::s3_config,
+};
+
+use super::{read, TestServer};
+
+#[tokio::test]
+async fn message_bodies_and_blobs_are_kept_in_s3() {
+    let Some(s3) = s3_config() else {
+        eprintln!("SM_TEST_S3_ENDPOINT is not set, skipping the S3 content store");
+        return;
+    };
+    let server = TestServer::start_with(|config| {
+        config.content_store.backend = ContentStoreBackend::S3;
+        config.content_store.s3 = s3.clone();
+        config.content_store.out_of_line_threshold = 1024;
+    })
+    .await;
+    let (alice, bob) = (server.user().await, server.user().await);
+    let data: Vec<u8> = (0..2500).map(|i| i as u8).collect();
+    let hash = alice.upload(&data).await;
+    let content = "x".repeat(3000);
+    let receipt = read(alice.send(&bob, alice.message(&content, &[&hash]))).await;
+
+    let message = read(bob.get(&format!("/message/{}", receipt["message_id"]))).await;
+    assert_eq!(message["content"], super::base64(&content));
+    let response = bob.get(&format!("/blob/{}", hash)).send().await.unwrap();
+    assert_eq!(response.bytes().await.unwrap(), data);
+
+    let keys = server
+        .database
+        .strings(
+            "SELECT content_key FROM sm.blob_chunks
+            UNION ALL SELECT content_key FROM sm.messages WHERE content_key IS NOT NULL",
+        )
+        .await;
+    assert_eq!(keys.len(), 4);
+    let store = S3ContentStore::new(s3).unwrap();
+    for key in keys {
+        assert!(
+            store.get(&key).await.unwrap().is_some(),
+            "{} is missing",
+            key
+        );
+    }
+}
+
+#[tokio::test]
+async fn objects_never_referenced_are_collected_after_a_while() {
+    let server = TestServer::start_with(|config| {
+        config.content_store.out_of_line_threshold = 1024;
+    })
+    .await;
+    let (alice, bob) = (server.user().await, server.user().await);
+    let data: Vec<u8> = (0..2500).map(|i| i as u8).collect();
+    let hash = alice.upload(&data).await;
+    let content = "x".repeat(3000);
+    let receipt = read(alice.send(&bob, alice.message(&content, &[&hash]))).await;
+    let pending = server
+        .database
+        .strings("SELECT key FROM sm.orphaned_content")
+        .await;
+    assert!(pending.is_empty(), "{:?}", pending);
+
+    // As left behind by a send interrupted between storing the body and inserting the message
+    let store = &server.state.content_store;
+    store.put("messages/interrupted", vec![1]).await.unwrap();
+    server
+        .database
+        .execute(
+            "INSERT INTO sm.orphaned_content (key, orphaned_at)
+            VALUES ('messages/interrupted', NOW() + INTERVAL '1 hour')",
+        )
+        .await;
+    let collect = || async {
+        CollectOrphanedContentCommand { batch_size: 10 }
+            .handle(&server.state.orphaned_content_repository, store)
+            .await
+            .unwrap()
+    };
+
+    assert_eq!(collect().await, 0);
+    assert!(store.get("messages/interrupted").await.unwrap().is_some());
+    server
+        .database
+        .execute("UPDATE sm.orphaned_content SET orphaned_at = NOW()")
+        .await;
+    assert_eq!(collect().await, 1);
+    assert!(store.get("messages/interrupted").await.unwrap().is_none());
+    let message = read(bob.get(&format!("/message/{}", receipt["message_id"]))).await;
+    assert_eq!(message["content"], super::base64(&content));
+    let response = bob.get(&format!("/blob/{}", hash)).send().await.unwrap();
+    assert_eq!(response.bytes().await.unwrap(), data);
+}
@@ -0,0 +1,184 @@
+use application::{
+    federation::commands::{sign_request, FederatedMessage},
+    system_key::commands::InitSystemKeysCommand,
+};
+use domain::{
+    error::{FederationError, SmError},
+    federation::{FederationClient, RemoteDelivery, RemoteUser, SignedRequest},
+    stamp::RecipientStamp,
+    uuid::Uuid,
+};
+use serde_json::json;
+
+use super::{base64, error_code, read, TestServer, TestUser};
+
+/// Looks up a user of another server, returning the id of their stand-in.
+async fn stand_in(user: &TestUser, remote: &TestUser) -> Uuid {
+    let stand_in = read(user.get(&format!("/federation/user/{}", remote.address))).await;
+    stand_in["id"].as_str().unwrap().parse().unwrap()
+}
+
+#[tokio::test]
+async fn messages_are_sent_between_instances() {
+    let a = TestServer::start().await;
+    let b = TestServer::start().await;
+    let alice = a.user().await;
+    let bob = b.user().await;
+
+    let bob_at_a = stand_in(&alice, &bob).await;
+    let mut body = bob.message("hello from b", &[]);
+    body["recipient"] = json!(alice.address);
+    body["stamp"] = json!(RecipientStamp::Periodic(alice.periodic_stamp_for(bob_at_a)));
+    let delivery = read(bob.post("/federation/send").json(&body)).await;
+
+    let message = read(alice.get(&format!("/message/{}", delivery["message_id"]))).await;
+    assert_eq!(message["content"], json!(base64("hello from b")));
+
+    // A stamp alice didn't sign is turned away before it leaves b
+    body["stamp"] = json!(RecipientStamp::Periodic(bob.periodic_stamp_for(bob_at_a)));
+    let response = bob
+        .post("/federation/send")
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(error_code(response).await, "invalid_stamp");
+}
+
+/// A message from bob of b to alice of a, forwarded and signed by b the way it forwards sends,
+/// with `sender` standing for bob.
+async fn forwarded_message(
+    a: &TestServer,
+    b: &TestServer,
+    alice: &TestUser,
+    bob: &TestUser,
+    sender: RemoteUser,
+) -> SignedRequest {
+    let bob_at_a = stand_in(alice, bob).await;
+    let message = bob.message("hello from b", &[]);
+    let body = serde_json::to_string(&FederatedMessage {
+        sender,
+        recipient: alice.username.clone(),
+        content: message["content"].as_str().unwrap().to_string(),
+        metadata: message["metadata"].as_str().unwrap().to_string(),
+        signature: message["signature"].as_str().unwrap().to_string(),
+        stamp: RecipientStamp::Periodic(alice.periodic_stamp_for(bob_at_a)),
+        expires_at: None,
+    })
+    .unwrap();
+    sign_request(
+        &b.host,
+        &a.host,
+        body,
+        &b.state.system_key_repository,
+        &b.state.cryptography_service,
+        &b.state.serialize_service,
+    )
+    .await
+    .unwrap()
+}
+
+async fn remote_user(user: &TestUser) -> RemoteUser {
+    serde_json::from_value(read(user.get(&format!("/user/{}", user.username))).await).unwrap()
+}
+
+/// The code a request delivered by b's client was turned down with.
+fn rejection_code(result: Result<RemoteDelivery, SmError>) -> String {
+    match result {
+        Err(SmError::Federation(FederationError::RemoteRejected {
+            code: Some(code), ..
+        })) => code,
+        other => panic!("{:?}", other.map(|delivery| delivery.message_id)),
+    }
+}
+
+#[tokio::test]
+async fn signed_requests_are_only_accepted_once() {
+    let a = TestServer::start().await;
+    let b = TestServer::start().await;
+    let alice = a.user().await;
+    let bob = b.user().await;
+    let request = forwarded_message(&a, &b, &alice, &bob, remote_user(&bob).await).await;
+
+    let client = &b.state.federation_client;
+    client.deliver_message(&a.host, &request).await.unwrap();
+    let replayed = client.deliver_message(&a.host, &request).await;
+    assert_eq!(rejection_code(replayed), "request_replayed");
+}
+
+#[tokio::test]
+async fn senders_with_unreadable_keys_are_turned_away() {
+    let a = TestServer::start().await;
+    let b = TestServer::start().await;
+    let alice = a.user().await;
+    let bob = b.user().await;
+    let mut sender = remote_user(&bob).await;
+    sender.public_verify_key = "not a key".to_string();
+    let request = forwarded_message(&a, &b, &alice, &bob, sender).await;
+
+    let result = b
+        .state
+        .federation_client
+        .deliver_message(&a.host, &request)
+        .await;
+    assert_eq!(rejection_code(result), "invalid_public_key");
+}
+
+#[tokio::test]
+async fn peers_are_only_reached_by_public_host_name() {
+    let a =
+        TestServer::start_with(|config| config.federation.allow_private_addresses = false).await;
+    let b = TestServer::start().await;
+    let alice = a.user().await;
+    let bob = b.user().await;
+
+    // b's host name resolves to loopback
+    let response = alice
+        .get(&format!("/federation/user/{}", bob.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(error_code(response).await, "peer_unreachable");
+
+    let port = b.host.rsplit_once(':').unwrap().1;
+    for host in ["127.0.0.1", "2130706433", "[::1]"] {
+        let address = format!("{}@{}:{}", bob.username, host, port);
+        let response = alice
+            .get(&format!("/federation/user/{}", address))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(error_code(response).await, "invalid_address", "{}", address);
+    }
+}
+
+#[tokio::test]
+async fn replaced_server_keys_are_fetched_again() {
+    let a = TestServer::start().await;
+    let b = TestServer::start().await;
+    let alice = a.user().await;
+    let bob = b.user().await;
+    let client = &b.state.federation_client;
+    let request = forwarded_message(&a, &b, &alice, &bob, remote_user(&bob).await).await;
+    client.deliver_message(&a.host, &request).await.unwrap();
+
+    b.database.execute("DELETE FROM sm.system_sign_keys").await;
+    InitSystemKeysCommand
+        .handle(
+            &b.state.system_key_repository,
+            &b.state.cryptography_service,
+        )
+        .await
+        .unwrap();
+
+    // The key a has is too fresh to be fetched again for every request that fails
+    let request = forwarded_message(&a, &b, &alice, &bob, remote_user(&bob).await).await;
+    let result = client.deliver_message(&a.host, &request).await;
+    assert_eq!(rejection_code(result), "invalid_server_signature");
+
+    a.database
+        .execute("UPDATE sm.federation_peers SET fetched_at = NOW() - INTERVAL '2 minutes'")
+        .await;
+    let request = forwarded_message(&a, &b, &alice, &bob, remote_user(&bob).await).await;
+    client.deliver_message(&a.host, &request).await.unwrap();
+}
@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+use axum::{body::Bytes, http::StatusCode};
+
+use super::TestServer;
+use crate::idempotency::idempotent;
+
+#[tokio::test]
+async fn requests_still_being_handled_are_not_taken_over() {
+    let server = TestServer::start_with(|config| config.idempotency.stale_claim_seconds = 1).await;
+    let alice = server.user().await;
+    let request = |handling: Duration| {
+        idempotent(
+            server.state.clone(),
+            alice.id,
+            Some("key".to_string()),
+            "test",
+            Bytes::from_static(b"{}"),
+            async move {
+                tokio::time::sleep(handling).await;
+                StatusCode::CREATED
+            },
+        )
+    };
+
+    let first = tokio::spawn(request(Duration::from_secs(3)));
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    let repeat = request(Duration::ZERO).await;
+    assert_eq!(repeat.status(), StatusCode::CONFLICT);
+    assert_eq!(first.await.unwrap().status(), StatusCode::CREATED);
+    let repeat = request(Duration::ZERO).await;
+    assert_eq!(repeat.headers()["Idempotent-Replayed"], "true");
+}
@@ -0,0 +1,142 @@
+use application::message::commands::DispatchScheduledMessagesCommand;
+use domain::chrono::{Duration, Utc};
+use serde_json::json;
+
+use super::{error_code, read, TestServer};
+
+#[tokio::test]
+async fn stamps_only_admit_the_sender_they_were_issued_to() {
+    let server = TestServer::start().await;
+    let (alice, bob, carol) = (
+        server.user().await,
+        server.user().await,
+        server.user().await,
+    );
+
+    // Bob's stamp for Carol, and Bob's stamp for Alice used on Carol
+    let mut message = alice.message("hello", &[]);
+    message["sender_id"] = json!(alice.id);
+    message["recipient_id"] = json!(bob.id);
+    for (path, stamp) in [
+        (
+            "/message/send_periodic",
+            json!(bob.periodic_stamp_for(carol.id)),
+        ),
+        (
+            "/message/send_onetime",
+            json!(bob.onetime_stamp_for(carol.id)),
+        ),
+    ] {
+        message["stamp"] = stamp;
+        let response = alice.post(path).json(&message).send().await.unwrap();
+        assert_eq!(error_code(response).await, "invalid_stamp", "{}", path);
+    }
+    message["recipient_id"] = json!(carol.id);
+    for (path, stamp) in [
+        (
+            "/message/send_periodic",
+            json!(bob.periodic_stamp_for(alice.id)),
+        ),
+        (
+            "/message/send_onetime",
+            json!(bob.onetime_stamp_for(alice.id)),
+        ),
+    ] {
+        message["stamp"] = stamp;
+        let response = alice.post(path).json(&message).send().await.unwrap();
+        assert_eq!(error_code(response).await, "invalid_stamp", "{}", path);
+    }
+
+    message["recipient_id"] = json!(bob.id);
+    message["stamp"] = json!(bob.onetime_stamp_for(alice.id));
+    read(alice.post("/message/send_onetime").json(&message)).await;
+    read(alice.send(&bob, alice.message("hello", &[]))).await;
+}
+
+#[tokio::test]
+async fn onetime_stamps_are_used_up_on_delivery() {
+    let server = TestServer::start().await;
+    let (alice, bob) = (server.user().await, server.user().await);
+    let mut message = alice.message("hello", &[]);
+    message["sender_id"] = json!(alice.id);
+    message["recipient_id"] = json!(bob.id);
+    message["stamp"] = json!(bob.onetime_stamp_for(alice.id));
+
+    read(alice.post("/message/send_onetime").json(&message)).await;
+    let response = alice
+        .post("/message/send_onetime")
+        .json(&message)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(error_code(response).await, "invalid_stamp");
+    let used = server
+        .database
+        .strings("SELECT used_or_revoked::text FROM sm.onetime_stamps")
+        .await;
+    assert_eq!(used, ["true"]);
+}
+
+#[tokio::test]
+async fn unreadable_signatures_do_not_verify() {
+    let server = TestServer::start().await;
+    let (alice, bob) = (server.user().await, server.user().await);
+    for signature in ["not base64!", "bm90IGEgc2lnbmF0dXJl"] {
+        let mut message = alice.message("hello", &[]);
+        message["signature"] = json!(signature);
+        let response = alice.send(&bob, message).send().await.unwrap();
+        assert_eq!(error_code(response).await, "invalid_signature");
+    }
+}
+
+#[tokio::test]
+async fn scheduled_messages_to_a_full_mailbox_are_retried() {
+    let server = TestServer::start_with(|config| config.mailbox.max_messages = 1).await;
+    let (alice, bob) = (server.user().await, server.user().await);
+    let mut message = alice.message("later", &[]);
+    message["recipient_id"] = json!(bob.id);
+    message["stamp"] = json!(bob.periodic_stamp_for(alice.id));
+    message["stamp"]["type"] = json!("periodic");
+    message["deliver_at"] = json!(Utc::now() + Duration::hours(1));
+    read(alice.post("/message/schedule").json(&message)).await;
+    read(alice.send(&bob, alice.message("now", &[]))).await;
+    server
+        .database
+        .execute("UPDATE sm.scheduled_messages SET deliver_at = NOW()")
+        .await;
+    let dispatch = || async {
+        DispatchScheduledMessagesCommand { batch_size: 10 }
+            .handle(
+                &server.state.user_repository,
+                &server.state.cryptography_service,
+                &server.state.serialize_service,
+                &server.state.tracker_repository,
+                &server.state.system_key_repository,
+                &server.state.block_list_repository,
+                &server.state.stamp_revocation_repository,
+                &server.state.scheduled_message_repository,
+                &server.state.mailbox_quota,
+            )
+            .await
+            .unwrap()
+    };
+
+    assert_eq!(dispatch().await, 0);
+    let pending = server
+        .database
+        .strings(
+            "SELECT concat_ws(' ', attempts, failure IS NULL, next_attempt_at > NOW()) \
+             FROM sm.scheduled_messages",
+        )
+        .await;
+    assert_eq!(pending, ["1 t t"]);
+    // Put off until the next attempt is due, however much room there is
+    server.database.execute("DELETE FROM sm.messages").await;
+    assert_eq!(dispatch().await, 0);
+
+    server
+        .database
+        .execute("UPDATE sm.scheduled_messages SET next_attempt_at = NOW()")
+        .await;
+    assert_eq!(dispatch().await, 1);
+}
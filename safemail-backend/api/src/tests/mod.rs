@@ -0,0 +1,304 @@
+//! Tests that drive instances of the server over HTTP, each with a database of its own on the
+//! Postgres server `DATABASE_URL` points at.
+
+use std::net::SocketAddr;
+
+use domain::{
+    chrono::{Duration, DurationRound, Utc},
+    crypto::{ContentHasher, CryptographyService},
+    stamp::{OnetimeStamp, PeriodicStamp},
+    uuid::Uuid,
+};
+use infrastructure::{
+    config::Config,
+    services::{cryptography::OpensslCryptographyService, federation::FederationScheme},
+    testing::TestDatabase,
+};
+use reqwest::{Client, RequestBuilder, Response};
+use serde_json::{json, Value};
+use tokio::{net::TcpListener, task::JoinHandle};
+
+use crate::state::AppState;
+
+mod blob;
+mod content_store;
+mod federation;
+mod idempotency;
+mod message;
+mod stamp;
+mod user;
+
+/// An instance of the server on a port of its own, reachable by other instances under the
+/// host `localhost:<port>`.
+pub struct TestServer {
+    pub host: String,
+    pub state: AppState,
+    client: Client,
+    pub database: TestDatabase,
+    server: JoinHandle<()>,
+}
+
+impl TestServer {
+    pub async fn start() -> Self {
+        Self::start_with(|_| {}).await
+    }
+
+    /// Starts an instance with settings of its own, made over those the tests need.
+    pub async fn start_with(configure: impl FnOnce(&mut Config)) -> Self {
+        dotenvy::dotenv().ok();
+        let database = TestDatabase::create().await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let mut config = Config::default();
+        config.database.url = database.url.clone();
+        config.server.listen_addr = address;
+        config.server.host = format!("localhost:{}", address.port());
+        config.federation.scheme = FederationScheme::Http;
+        config.federation.allow_private_addresses = true;
+        configure(&mut config);
+        let state = AppState::new(&config).await;
+        let app = crate::app(&config, state.clone());
+        let server = tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+            .unwrap();
+        });
+
+        Self {
+            host: config.server.host,
+            state,
+            client: Client::new(),
+            database,
+            server,
+        }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}/v1{}", self.host, path)
+    }
+
+    /// Registers a user with fresh keys and signs them in.
+    pub async fn user(&self) -> TestUser {
+        let cryptography_service = OpensslCryptographyService;
+        let (public_verify_key, verify_key) = cryptography_service.generate_key_pair().unwrap();
+        let (public_encryption_key, _) = cryptography_service.generate_key_pair().unwrap();
+        let username = format!("u{}", &Uuid::new_v4().simple().to_string()[..12]);
+        let user = read(self.client.post(self.url("/user/register")).json(&json!({
+            "username": username,
+            "public_encryption_key": public_encryption_key,
+            "public_verify_key": public_verify_key,
+        })))
+        .await;
+
+        let mut user = TestUser {
+            id: user["id"].as_str().unwrap().parse().unwrap(),
+            username,
+            address: String::new(),
+            verify_key,
+            session: String::new(),
+            base: self.url(""),
+            client: self.client.clone(),
+        };
+        user.address = format!("{}@{}", user.username, self.host);
+        let session = read(
+            self.client
+                .post(self.url("/user/login"))
+                .json(&json!({ "username": user.username })),
+        )
+        .await;
+        let challenge_signature = user.sign(session["challenge_string"].as_str().unwrap());
+        read(
+            self.client
+                .post(self.url("/user/login/confirm"))
+                .json(&json!({
+                    "session_id": session["session_id"],
+                    "challenge_signature": challenge_signature,
+                })),
+        )
+        .await;
+        user.session = session["session_id"].as_str().unwrap().to_string();
+        user
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+/// A signed in user, making requests the way a client would.
+pub struct TestUser {
+    pub id: Uuid,
+    pub username: String,
+    /// `username@host`, for users of other servers.
+    pub address: String,
+    verify_key: String,
+    session: String,
+    base: String,
+    client: Client,
+}
+
+impl TestUser {
+    pub fn sign(&self, plaintext: &str) -> String {
+        OpensslCryptographyService
+            .produce_signature(plaintext, &self.verify_key)
+            .unwrap()
+    }
+
+    pub fn get(&self, path: &str) -> RequestBuilder {
+        self.client
+            .get(format!("{}{}", self.base, path))
+            .bearer_auth(&self.session)
+    }
+
+    pub fn post(&self, path: &str) -> RequestBuilder {
+        self.client
+            .post(format!("{}{}", self.base, path))
+            .bearer_auth(&self.session)
+    }
+
+    /// Like `post`, against v2 of the API.
+    pub fn post_v2(&self, path: &str) -> RequestBuilder {
+        self.client
+            .post(format!("{}{}", self.base.replace("/v1", "/v2"), path))
+            .bearer_auth(&self.session)
+    }
+
+    pub fn put(&self, path: &str) -> RequestBuilder {
+        self.client
+            .put(format!("{}{}", self.base, path))
+            .bearer_auth(&self.session)
+    }
+
+    pub fn delete(&self, path: &str) -> RequestBuilder {
+        self.client
+            .delete(format!("{}{}", self.base, path))
+            .bearer_auth(&self.session)
+    }
+
+    /// Uploads a blob in chunks of a thousand bytes, returning its hash.
+    pub async fn upload(&self, data: &[u8]) -> String {
+        let mut hasher = OpensslCryptographyService.content_hasher();
+        hasher.update(data);
+        let hash = hasher.finish();
+        read(self.post("/blob/upload").json(&json!({
+            "hash": hash,
+            "size": data.len(),
+            "chunk_size": 1000,
+        })))
+        .await;
+        for (index, chunk) in data.chunks(1000).enumerate() {
+            read(
+                self.put(&format!("/blob/{}/chunks/{}", hash, index))
+                    .body(chunk.to_vec()),
+            )
+            .await;
+        }
+        read(self.post(&format!("/blob/{}/upload/complete", hash))).await;
+        hash
+    }
+
+    /// Sends `message` to `recipient` with a periodic stamp from them.
+    pub fn send(&self, recipient: &TestUser, message: Value) -> RequestBuilder {
+        let mut body = message;
+        body["sender_id"] = json!(self.id);
+        body["recipient_id"] = json!(recipient.id);
+        body["stamp"] = json!(recipient.periodic_stamp_for(self.id));
+        self.post("/message/send_periodic").json(&body)
+    }
+
+    /// A periodic stamp from this user for `sender_id` to send them messages with, valid for an
+    /// hour.
+    pub fn periodic_stamp_for(&self, sender_id: Uuid) -> PeriodicStamp {
+        let now = Utc::now().duration_trunc(Duration::seconds(1)).unwrap();
+        let mut stamp = PeriodicStamp {
+            issuer_id: self.id,
+            recipient_id: self.id,
+            sender_id,
+            valid_from: now - Duration::minutes(5),
+            valid_to: now + Duration::hours(1),
+            signature: String::new(),
+        };
+        let plaintext = [
+            json!(stamp.issuer_id),
+            json!(stamp.recipient_id),
+            json!(stamp.sender_id),
+            json!(stamp.valid_from),
+            json!(stamp.valid_to),
+        ]
+        .map(|value| value.to_string())
+        .join("\n");
+        stamp.signature = self.sign(&plaintext);
+        stamp
+    }
+
+    /// A one-time stamp from this user for `sender_id`, valid for an hour.
+    pub fn onetime_stamp_for(&self, sender_id: Uuid) -> OnetimeStamp {
+        let valid_to =
+            Utc::now().duration_trunc(Duration::seconds(1)).unwrap() + Duration::hours(1);
+        let mut stamp = OnetimeStamp {
+            stamp_id: Uuid::new_v4(),
+            issuer_id: self.id,
+            recipient_id: self.id,
+            sender_id,
+            valid_to: Some(valid_to),
+            signature: String::new(),
+        };
+        let plaintext = [
+            json!(stamp.stamp_id),
+            json!(stamp.issuer_id),
+            json!(stamp.recipient_id),
+            json!(stamp.sender_id),
+            json!(stamp.valid_to),
+        ]
+        .map(|value| value.to_string())
+        .join("\n");
+        stamp.signature = self.sign(&plaintext);
+        stamp
+    }
+
+    /// A message with its signature, attaching the given blobs.
+    pub fn message(&self, content: &str, attachments: &[&str]) -> Value {
+        let content = base64(content);
+        let metadata = base64("metadata");
+        let plaintext = if attachments.is_empty() {
+            format!("{}\n{}", metadata, content)
+        } else {
+            format!("{}\n{}\nnull\n{}", metadata, content, json!(attachments))
+        };
+        json!({
+            "content": content,
+            "metadata": metadata,
+            "signature": self.sign(&plaintext),
+            "attachments": attachments,
+        })
+    }
+}
+
+pub fn base64(text: &str) -> String {
+    use base64::Engine;
+    base64::engine::general_purpose::STANDARD.encode(text)
+}
+
+/// Sends the request and returns the JSON it is answered with, failing unless that is a success.
+pub async fn read(request: RequestBuilder) -> Value {
+    let response = request.send().await.unwrap();
+    let status = response.status();
+    let body = response.text().await.unwrap();
+    assert!(status.is_success(), "{}: {}", status, body);
+    serde_json::from_str(&body).unwrap_or(Value::Null)
+}
+
+/// The error code a request failed with.
+pub async fn error_code(response: Response) -> String {
+    let body: Value = response.json().await.unwrap();
+    body["error"]["code"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string()
+}
@@ -0,0 +1,185 @@
+use application::stamp::commands::pow_threshold;
+use domain::{pow::Pow, uuid::Uuid};
+use reqwest::RequestBuilder;
+use serde_json::{json, Value};
+
+use super::{error_code, read, TestServer, TestUser};
+
+const BASE_DIFFICULTY: i64 = 16;
+const DIFFICULTY_FACTOR: i64 = 256;
+
+/// A proof of work for the request scoring at least `score`, but short of `below`.
+fn proof(request_id: Uuid, score: u128, below: u128) -> Value {
+    (0u64..)
+        .map(|nonce| json!({ "proof": nonce }))
+        .find(|proof| {
+            let proof: Pow<Uuid> = serde_json::from_value(proof.clone()).unwrap();
+            (score..below).contains(&proof.score(&request_id).unwrap())
+        })
+        .unwrap()
+}
+
+/// Requests a stamp for mail from `sender` to `recipient`, returning its id and difficulty.
+async fn request(sender: &TestUser, recipient: &TestUser, v2: bool) -> (Uuid, i64) {
+    let body = json!({ "recipient_id": recipient.id });
+    if v2 {
+        let receipt = read(sender.post_v2("/stamp/request_system_issue").json(&body)).await;
+        let id = receipt["stamp_request_id"]
+            .as_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        (id, receipt["difficulty"].as_i64().unwrap())
+    } else {
+        let id = read(sender.post("/stamp/request_system_issue").json(&body)).await;
+        (id.as_str().unwrap().parse().unwrap(), 0)
+    }
+}
+
+fn issue(sender: &TestUser, request_id: Uuid, proof: Value, v2: bool) -> RequestBuilder {
+    let body = json!({ "stamp_request_id": request_id, "proof_of_work": proof });
+    let request = if v2 {
+        sender.post_v2("/stamp/system_issue")
+    } else {
+        sender.post("/stamp/system_issue")
+    };
+    request.json(&body)
+}
+
+#[tokio::test]
+async fn reports_raise_the_difficulty_on_both_api_versions() {
+    let server = TestServer::start_with(|config| {
+        config.stamps.base_difficulty = BASE_DIFFICULTY;
+        config.reports.difficulty_factor = DIFFICULTY_FACTOR;
+    })
+    .await;
+    let (alice, bob) = (server.user().await, server.user().await);
+    let raised = BASE_DIFFICULTY * DIFFICULTY_FACTOR;
+
+    // Unreported, v1 takes the difficulty as a score
+    let (id, _) = request(&alice, &bob, false).await;
+    let weak = proof(id, BASE_DIFFICULTY as u128, pow_threshold(BASE_DIFFICULTY));
+    read(issue(&alice, id, weak, false)).await;
+    let (id, difficulty) = request(&alice, &bob, true).await;
+    assert_eq!(difficulty, BASE_DIFFICULTY);
+    let weak = proof(id, BASE_DIFFICULTY as u128, pow_threshold(BASE_DIFFICULTY));
+    let response = issue(&alice, id, weak, true).send().await.unwrap();
+    assert_eq!(error_code(response).await, "invalid_proof_of_work");
+
+    let receipt = read(alice.send(&bob, alice.message("buy now", &[]))).await;
+    let report = format!("/message/{}/report", receipt["message_id"]);
+    read(bob.post(&report).json(&json!({ "reason": "spam" }))).await;
+
+    for v2 in [false, true] {
+        let (id, difficulty) = request(&alice, &bob, v2).await;
+        if v2 {
+            assert_eq!(difficulty, raised);
+        }
+        let weak = proof(id, raised as u128, pow_threshold(raised));
+        let response = issue(&alice, id, weak, v2).send().await.unwrap();
+        assert_eq!(
+            error_code(response).await,
+            "invalid_proof_of_work",
+            "v2: {}",
+            v2
+        );
+        let strong = json!(Pow::prove_work(&id, pow_threshold(raised)).unwrap());
+        read(issue(&alice, id, strong, v2)).await;
+    }
+}
+
+#[tokio::test]
+async fn stamp_requests_are_solved_once_by_their_sender() {
+    let server = TestServer::start_with(|config| {
+        config.stamps.base_difficulty = BASE_DIFFICULTY;
+    })
+    .await;
+    let (alice, bob, carol) = (
+        server.user().await,
+        server.user().await,
+        server.user().await,
+    );
+    let (id, _) = request(&alice, &bob, true).await;
+    let solution = json!(Pow::prove_work(&id, pow_threshold(BASE_DIFFICULTY)).unwrap());
+
+    let response = issue(&carol, id, solution.clone(), true)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(error_code(response).await, "stamp_request_not_found");
+
+    // Only one of two solutions handed in at once gets a stamp
+    let (first, second) = tokio::join!(
+        issue(&alice, id, solution.clone(), true).send(),
+        issue(&alice, id, solution, true).send(),
+    );
+    let mut codes = vec![
+        error_code(first.unwrap()).await,
+        error_code(second.unwrap()).await,
+    ];
+    codes.sort();
+    assert_eq!(codes, ["", "stamp_request_solved"]);
+}
+
+#[tokio::test]
+async fn missing_system_keys_are_reported_rather_than_panicked_over() {
+    let server = TestServer::start_with(|config| {
+        config.stamps.base_difficulty = BASE_DIFFICULTY;
+    })
+    .await;
+    let (alice, bob) = (server.user().await, server.user().await);
+    let receipt = read(alice.send(&bob, alice.message("hello", &[]))).await;
+    let (id, _) = request(&alice, &bob, true).await;
+    server
+        .database
+        .execute("DELETE FROM sm.system_sign_keys")
+        .await;
+
+    let solution = json!(Pow::prove_work(&id, pow_threshold(BASE_DIFFICULTY)).unwrap());
+    let response = issue(&alice, id, solution, true).send().await.unwrap();
+    assert_eq!(error_code(response).await, "invalid_system_keys");
+    let response = bob
+        .post(&format!("/message/{}/report", receipt["message_id"]))
+        .json(&json!({ "reason": null }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(error_code(response).await, "invalid_system_keys");
+}
+
+#[tokio::test]
+async fn reports_count_once_per_reporter() {
+    let server = TestServer::start_with(|config| {
+        config.stamps.base_difficulty = BASE_DIFFICULTY;
+        config.stamps.max_difficulty = 1 << 40;
+        config.reports.difficulty_factor = DIFFICULTY_FACTOR;
+        // Bob keeps taking alice's mail to report
+        config.reports.revoke_stamps = false;
+    })
+    .await;
+    let (alice, bob, carol) = (
+        server.user().await,
+        server.user().await,
+        server.user().await,
+    );
+    let report = |reporter: &TestUser, receipt: &Value| {
+        reporter
+            .post(&format!("/message/{}/report", receipt["message_id"]))
+            .json(&json!({ "reason": "spam" }))
+    };
+
+    for _ in 0..3 {
+        let receipt = read(alice.send(&bob, alice.message("buy now", &[]))).await;
+        read(report(&bob, &receipt)).await;
+    }
+    let (_, difficulty) = request(&alice, &carol, true).await;
+    assert_eq!(difficulty, BASE_DIFFICULTY * DIFFICULTY_FACTOR);
+
+    let receipt = read(alice.send(&carol, alice.message("buy now", &[]))).await;
+    read(report(&carol, &receipt)).await;
+    let (_, difficulty) = request(&alice, &carol, true).await;
+    assert_eq!(
+        difficulty,
+        BASE_DIFFICULTY * DIFFICULTY_FACTOR * DIFFICULTY_FACTOR
+    );
+}
@@ -0,0 +1,29 @@
+use domain::crypto::CryptographyService;
+use infrastructure::services::cryptography::OpensslCryptographyService;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+
+use super::TestServer;
+
+#[tokio::test]
+async fn taken_usernames_are_reported_as_such() {
+    let server = TestServer::start().await;
+    let alice = server.user().await;
+    let (public_key, _) = OpensslCryptographyService.generate_key_pair().unwrap();
+
+    let response = Client::new()
+        .post(server.url("/user/register"))
+        .json(&json!({
+            "username": alice.username,
+            "public_encryption_key": public_key,
+            "public_verify_key": public_key,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "user_already_exists");
+    // The constraint that caught it stays in the server's logs
+    assert!(!body.to_string().contains("users_username_key"), "{}", body);
+}
@@ -0,0 +1,59 @@
+use axum::{
+    extract::{Request, State},
+    http::{header::LINK, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use domain::chrono::{DateTime, TimeZone, Utc};
+
+pub const DEPRECATION_HEADER: HeaderName = HeaderName::from_static("deprecation");
+pub const SUNSET_HEADER: HeaderName = HeaderName::from_static("sunset");
+
+/// Marks a set of routes as on their way out: since when, until when they are still served, and
+/// where they live on in a newer version.
+#[derive(Clone)]
+pub struct Deprecation {
+    pub since: DateTime<Utc>,
+    pub sunset: Option<DateTime<Utc>>,
+    /// Prefix under which the same path is served by the version replacing them.
+    pub successor: Option<&'static str>,
+}
+
+/// The routes at the root, from before the API was versioned, which are v1 under another name.
+pub fn unversioned(sunset: DateTime<Utc>) -> Deprecation {
+    Deprecation {
+        since: Utc.with_ymd_and_hms(2026, 10, 19, 0, 0, 0).unwrap(),
+        sunset: Some(sunset),
+        successor: Some("/v1"),
+    }
+}
+
+/// Adds the `Deprecation` (RFC 9745), `Sunset` (RFC 8594) and successor `Link` headers to every
+/// response of the routes it is layered on.
+pub async fn deprecated(
+    State(deprecation): State<Deprecation>,
+    request: Request,
+    next: Next,
+) -> Response {
+    // Inside a nested router this is the path below the prefix, as the successor serves it
+    let path = request.uri().path().to_string();
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    let since = format!("@{}", deprecation.since.timestamp());
+    if let Ok(value) = HeaderValue::from_str(&since) {
+        headers.insert(DEPRECATION_HEADER, value);
+    }
+    if let Some(sunset) = deprecation.sunset {
+        let sunset = sunset.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        if let Ok(value) = HeaderValue::from_str(&sunset) {
+            headers.insert(SUNSET_HEADER, value);
+        }
+    }
+    if let Some(successor) = deprecation.successor {
+        let link = format!("<{}{}>; rel=\"successor-version\"", successor, path);
+        if let Ok(value) = HeaderValue::from_str(&link) {
+            headers.append(LINK, value);
+        }
+    }
+    response
+}
@@ -0,0 +1,240 @@
+use domain::{
+    blob::{Blob, BlobRepository},
+    chrono,
+    crypto::{ContentHasher, CryptographyService},
+    error::{BlobError, SmError, ValidationError},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+const MAX_CHUNK_SIZE: i32 = 1024 * 1024;
+const MAX_BLOB_SIZE: i64 = 256 * 1024 * 1024;
+/// How long a pending upload may go without a chunk before someone else may take the hash over.
+/// Until then, a hash can't be claimed by uploading nothing.
+const IDLE_UPLOAD_MINUTES: i64 = 10;
+
+pub(crate) fn validate_blob_hash(hash: &str) -> Result<(), ValidationError> {
+    if hash.len() != 64 || !hash.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')) {
+        return Err(ValidationError(
+            "Blob hash must be a lowercase hex encoded SHA-256 digest".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BlobUploadStatus {
+    pub hash: String,
+    pub size: i64,
+    pub chunk_size: i32,
+    pub chunk_count: i32,
+    pub complete: bool,
+    /// Indices of the chunks received so far; an interrupted upload resumes with the others.
+    pub received_chunks: Vec<i32>,
+}
+
+impl BlobUploadStatus {
+    pub(crate) async fn of(
+        blob: Blob,
+        blob_repository: &impl BlobRepository,
+    ) -> Result<Self, SmError> {
+        let complete = blob.completed_at.is_some();
+        let received_chunks = if complete {
+            (0..blob.chunk_count).collect()
+        } else {
+            blob_repository.list_chunk_indices(&blob.hash).await?
+        };
+        Ok(Self {
+            hash: blob.hash,
+            size: blob.size,
+            chunk_size: blob.chunk_size,
+            chunk_count: blob.chunk_count,
+            complete,
+            received_chunks,
+        })
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct StartBlobUploadCommandDto {
+    pub hash: String,
+    pub size: i64,
+    pub chunk_size: i32,
+}
+pub struct StartBlobUploadCommand {
+    pub uploader_id: Uuid,
+    pub hash: String,
+    pub size: i64,
+    pub chunk_size: i32,
+}
+
+impl StartBlobUploadCommand {
+    /// Registers the upload. If a blob with the same hash the uploader can access is already
+    /// stored, it is reported as complete and nothing needs to be uploaded. Blobs are encrypted
+    /// by the client, so those of different users never coincide: a stored hash the uploader
+    /// has no access to is refused rather than handed out.
+    pub async fn handle(
+        self,
+        blob_repository: &impl BlobRepository,
+    ) -> Result<BlobUploadStatus, SmError> {
+        validate_blob_hash(&self.hash)?;
+        if self.size < 1 || self.size > MAX_BLOB_SIZE {
+            return Err(ValidationError(format!(
+                "Blob size must be between 1 and {} bytes",
+                MAX_BLOB_SIZE
+            ))
+            .into());
+        }
+        if self.chunk_size < 1 || self.chunk_size > MAX_CHUNK_SIZE {
+            return Err(ValidationError(format!(
+                "Chunk size must be between 1 and {} bytes",
+                MAX_CHUNK_SIZE
+            ))
+            .into());
+        }
+        let chunk_count =
+            ((self.size + self.chunk_size as i64 - 1) / self.chunk_size as i64) as i32;
+
+        let blob = blob_repository
+            .create_blob(
+                &self.hash,
+                self.size,
+                self.chunk_size,
+                chunk_count,
+                self.uploader_id,
+            )
+            .await?;
+        if blob.completed_at.is_some() {
+            if !blob_repository
+                .can_access(&self.hash, self.uploader_id)
+                .await?
+            {
+                return Err(BlobError::UploadMismatch.into());
+            }
+            return BlobUploadStatus::of(blob, blob_repository).await;
+        }
+
+        // A pending upload can only be resumed by whoever started it, with the same parameters,
+        // or be taken over once it has been left idle
+        let blob = if blob.uploader_id != self.uploader_id {
+            let idle_before = chrono::Utc::now() - chrono::Duration::minutes(IDLE_UPLOAD_MINUTES);
+            blob_repository
+                .take_over_upload(
+                    &self.hash,
+                    self.size,
+                    self.chunk_size,
+                    chunk_count,
+                    self.uploader_id,
+                    idle_before,
+                )
+                .await?
+                .ok_or(BlobError::UploadMismatch)?
+        } else if blob.size != self.size || blob.chunk_size != self.chunk_size {
+            return Err(BlobError::UploadMismatch.into());
+        } else {
+            blob
+        };
+
+        BlobUploadStatus::of(blob, blob_repository).await
+    }
+}
+
+pub struct UploadBlobChunkCommand {
+    pub uploader_id: Uuid,
+    pub hash: String,
+    pub index: i32,
+    pub data: Vec<u8>,
+}
+
+impl UploadBlobChunkCommand {
+    pub async fn handle(self, blob_repository: &impl BlobRepository) -> Result<(), SmError> {
+        let blob = blob_repository
+            .get_blob(&self.hash)
+            .await?
+            .filter(|blob| blob.uploader_id == self.uploader_id)
+            .ok_or(BlobError::BlobNotFound)?;
+        if blob.completed_at.is_some() {
+            return Ok(());
+        }
+        if self.index < 0
+            || self.index >= blob.chunk_count
+            || self.data.len() as i64 != blob.expected_chunk_size(self.index)
+        {
+            return Err(BlobError::InvalidChunk.into());
+        }
+        // Completed or taken over since it was looked up
+        if !blob_repository
+            .put_chunk(&self.hash, self.uploader_id, self.index, self.data)
+            .await?
+        {
+            return Err(BlobError::BlobNotFound.into());
+        }
+        Ok(())
+    }
+}
+
+pub struct CompleteBlobUploadCommand {
+    pub uploader_id: Uuid,
+    pub hash: String,
+}
+
+impl CompleteBlobUploadCommand {
+    /// Checks the uploaded chunks against the blob hash. On a mismatch, the chunks are
+    /// discarded and the upload has to start over.
+    pub async fn handle(
+        self,
+        blob_repository: &impl BlobRepository,
+        cryptography_service: &impl CryptographyService,
+    ) -> Result<BlobUploadStatus, SmError> {
+        let blob = blob_repository
+            .get_blob(&self.hash)
+            .await?
+            .filter(|blob| blob.uploader_id == self.uploader_id)
+            .ok_or(BlobError::BlobNotFound)?;
+        if blob.completed_at.is_some() {
+            return BlobUploadStatus::of(blob, blob_repository).await;
+        }
+
+        let received_chunks = blob_repository.list_chunk_indices(&self.hash).await?;
+        if received_chunks.len() as i32 != blob.chunk_count {
+            return Err(BlobError::UploadIncomplete.into());
+        }
+
+        let mut hasher = cryptography_service.content_hasher();
+        for index in 0..blob.chunk_count {
+            let chunk = blob_repository
+                .get_chunk(&self.hash, index)
+                .await?
+                .ok_or(BlobError::UploadIncomplete)?;
+            hasher.update(&chunk);
+        }
+        if hasher.finish() != self.hash {
+            blob_repository.discard_chunks(&self.hash).await?;
+            return Err(BlobError::HashMismatch.into());
+        }
+
+        blob_repository.mark_complete(&self.hash).await?;
+        let blob = blob_repository
+            .get_blob(&self.hash)
+            .await?
+            .ok_or(BlobError::BlobNotFound)?;
+        BlobUploadStatus::of(blob, blob_repository).await
+    }
+}
+
+pub struct CollectBlobGarbageCommand {
+    /// How long a complete blob may stay unreferenced, e.g. between upload and sending.
+    pub unreferenced_grace: chrono::Duration,
+    /// How long an incomplete upload may be left unfinished.
+    pub abandoned_grace: chrono::Duration,
+}
+
+impl CollectBlobGarbageCommand {
+    pub async fn handle(self, blob_repository: &impl BlobRepository) -> Result<u64, SmError> {
+        let now = chrono::Utc::now();
+        blob_repository
+            .collect_garbage(now - self.unreferenced_grace, now - self.abandoned_grace)
+            .await
+    }
+}
@@ -0,0 +1,62 @@
+use domain::{
+    blob::{Blob, BlobRepository},
+    error::{BlobError, SmError},
+};
+use uuid::Uuid;
+
+use super::commands::{validate_blob_hash, BlobUploadStatus};
+
+pub struct GetBlobUploadStatusQuery {
+    pub uploader_id: Uuid,
+    pub hash: String,
+}
+
+impl GetBlobUploadStatusQuery {
+    pub async fn handle(
+        self,
+        blob_repository: &impl BlobRepository,
+    ) -> Result<BlobUploadStatus, SmError> {
+        let blob = blob_repository
+            .get_blob(&self.hash)
+            .await?
+            .filter(|blob| blob.uploader_id == self.uploader_id)
+            .ok_or(BlobError::BlobNotFound)?;
+        BlobUploadStatus::of(blob, blob_repository).await
+    }
+}
+
+pub struct GetAccessibleBlobQuery {
+    pub user_id: Uuid,
+    pub hash: String,
+}
+
+impl GetAccessibleBlobQuery {
+    /// Returns a complete blob the user may download. Blobs the user has no access to are
+    /// reported as missing, so their existence isn't revealed.
+    pub async fn handle(self, blob_repository: &impl BlobRepository) -> Result<Blob, SmError> {
+        validate_blob_hash(&self.hash)?;
+        if !blob_repository.can_access(&self.hash, self.user_id).await? {
+            return Err(BlobError::BlobNotFound.into());
+        }
+        blob_repository
+            .get_blob(&self.hash)
+            .await?
+            .filter(|blob| blob.completed_at.is_some())
+            .ok_or(BlobError::BlobNotFound.into())
+    }
+}
+
+pub struct GetBlobChunkQuery {
+    pub hash: String,
+    pub index: i32,
+}
+
+impl GetBlobChunkQuery {
+    /// Reads a chunk of a blob that was already checked with `GetAccessibleBlobQuery`.
+    pub async fn handle(self, blob_repository: &impl BlobRepository) -> Result<Vec<u8>, SmError> {
+        blob_repository
+            .get_chunk(&self.hash, self.index)
+            .await?
+            .ok_or(BlobError::BlobNotFound.into())
+    }
+}
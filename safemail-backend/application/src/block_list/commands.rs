@@ -0,0 +1,56 @@
+use domain::{
+    block_list::BlockListRepository,
+    error::{SmError, UserError, ValidationError},
+    user::UserRepository,
+};
+use serde::Deserialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::user::queries::GetUserByIdQuery;
+
+#[derive(Deserialize, ToSchema)]
+pub struct BlockSenderCommandDto {
+    pub sender_id: Uuid,
+}
+pub struct BlockSenderCommand {
+    pub user_id: Uuid,
+    pub sender_id: Uuid,
+}
+
+impl BlockSenderCommand {
+    pub async fn handle(
+        self,
+        user_repository: &impl UserRepository,
+        block_list_repository: &impl BlockListRepository,
+    ) -> Result<(), SmError> {
+        if self.sender_id == self.user_id {
+            return Err(ValidationError("Users cannot block themselves".to_string()).into());
+        }
+        GetUserByIdQuery {
+            user_id: self.sender_id,
+        }
+        .handle(user_repository)
+        .await?
+        .ok_or(UserError::UserNotFound)?;
+        block_list_repository
+            .block_sender(self.user_id, self.sender_id)
+            .await
+    }
+}
+
+pub struct UnblockSenderCommand {
+    pub user_id: Uuid,
+    pub sender_id: Uuid,
+}
+
+impl UnblockSenderCommand {
+    pub async fn handle(
+        self,
+        block_list_repository: &impl BlockListRepository,
+    ) -> Result<(), SmError> {
+        block_list_repository
+            .unblock_sender(self.user_id, self.sender_id)
+            .await
+    }
+}
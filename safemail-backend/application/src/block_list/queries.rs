@@ -0,0 +1,20 @@
+use domain::{
+    block_list::{BlockListRepository, BlockedSender},
+    error::SmError,
+};
+use uuid::Uuid;
+
+pub struct GetBlockedSendersQuery {
+    pub user_id: Uuid,
+}
+
+impl GetBlockedSendersQuery {
+    pub async fn handle(
+        &self,
+        block_list_repository: &impl BlockListRepository,
+    ) -> Result<Vec<BlockedSender>, SmError> {
+        block_list_repository
+            .list_blocked_senders(self.user_id)
+            .await
+    }
+}
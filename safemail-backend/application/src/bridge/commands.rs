@@ -0,0 +1,253 @@
+use domain::{
+    chrono,
+    crypto::CryptographyService,
+    error::{BridgeError, CryptographyError, SmError, UserError, ValidationError},
+    outbound_mail::{BridgePolicy, NewOutboundMail, OutboundMailRepository, SmtpRelay},
+    serialize::SerializeService,
+    system_key::SystemKeyRepository,
+    user::UserRepository,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{message::commands::is_base64, user::queries::GetUserByIdQuery};
+
+const MAX_ADDRESS_LENGTH: usize = 254;
+const MAX_RELAY_ATTEMPTS: i32 = 6;
+/// How long mail a relay has claimed is left to it, longer than relaying a batch takes.
+const RELAY_CLAIM_MINUTES: i64 = 15;
+
+/// The text a sender signs to have mail relayed, so that the bridge sends exactly this
+/// message to exactly these recipients.
+fn outbound_mail_plaintext(
+    recipients: &[String],
+    content: &str,
+    serialize_service: &impl SerializeService,
+) -> String {
+    format!("{}\n{}", serialize_service.serialize(&recipients), content)
+}
+
+/// Only plain `local@domain` addresses are accepted, anything that could end up as an SMTP
+/// parameter or a second address is not.
+fn is_mail_address(address: &str) -> bool {
+    let Some((local, domain)) = address.rsplit_once('@') else {
+        return false;
+    };
+    address.len() <= MAX_ADDRESS_LENGTH
+        && !local.is_empty()
+        && !local.contains('@')
+        && local
+            .chars()
+            .all(|c| c.is_ascii_graphic() && !"<>()[]\\,;:\"".contains(c))
+        && !domain.is_empty()
+        && domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+}
+
+/// The message with the `From` and `Sender` fields of its header, continuation lines and all,
+/// replaced by a `From` naming `from`.
+fn with_originator(message: &[u8], from: &str) -> Vec<u8> {
+    let mut rewritten = format!("From: <{}>\r\n", from).into_bytes();
+    let mut rest = message;
+    let mut dropping = false;
+    while let Some(end) = rest.iter().position(|&b| b == b'\n') {
+        let (line, tail) = rest.split_at(end + 1);
+        if line == b"\r\n" || line == b"\n" {
+            break;
+        }
+        if !line.starts_with(b" ") && !line.starts_with(b"\t") {
+            let name = line.split(|&b| b == b':').next().unwrap_or_default();
+            let name = name.trim_ascii_end();
+            dropping = name.eq_ignore_ascii_case(b"from") || name.eq_ignore_ascii_case(b"sender");
+        }
+        if !dropping {
+            rewritten.extend_from_slice(line);
+        }
+        rest = tail;
+    }
+    rewritten.extend_from_slice(rest);
+    rewritten
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RelayOutboundMailCommandDto {
+    pub recipients: Vec<String>,
+    pub content: String,
+    pub signature: String,
+}
+
+/// Hands a message to the SMTP bridge. The content is the whole message, headers included,
+/// encrypted to the system key.
+pub struct RelayOutboundMailCommand {
+    pub sender_id: Uuid,
+    pub recipients: Vec<String>,
+    pub content: String,
+    pub signature: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RelayReceipt {
+    pub id: i64,
+}
+
+impl RelayOutboundMailCommand {
+    pub async fn handle(
+        self,
+        user_repository: &impl UserRepository,
+        outbound_mail_repository: &impl OutboundMailRepository,
+        system_key_repository: &impl SystemKeyRepository,
+        cryptography_service: &impl CryptographyService,
+        serialize_service: &impl SerializeService,
+        bridge_policy: &BridgePolicy,
+    ) -> Result<RelayReceipt, SmError> {
+        let sender = GetUserByIdQuery {
+            user_id: self.sender_id,
+        }
+        .handle(user_repository)
+        .await?
+        .ok_or(UserError::UserNotFound)?;
+
+        if self.recipients.is_empty() || self.recipients.len() as i64 > bridge_policy.max_recipients
+        {
+            return Err(ValidationError(format!(
+                "Mail must have between 1 and {} recipients",
+                bridge_policy.max_recipients
+            ))
+            .into());
+        }
+        if let Some(address) = self.recipients.iter().find(|a| !is_mail_address(a)) {
+            return Err(ValidationError(format!("Invalid recipient address: {}", address)).into());
+        }
+        if !is_base64(&self.content) {
+            return Err(ValidationError("Content must be base64 encoded".to_string()).into());
+        }
+        if !cryptography_service.validate_signature(
+            &outbound_mail_plaintext(&self.recipients, &self.content, serialize_service),
+            &self.signature,
+            &sender.public_verify_key,
+        ) {
+            return Err(CryptographyError::InvalidSignature.into());
+        }
+
+        // Caught now rather than when it is due to be relayed
+        let system_keys = system_key_repository
+            .get_system_keys()
+            .await?
+            .ok_or(CryptographyError::InvalidSystemKeys)?;
+        if cryptography_service
+            .decrypt(&self.content, &system_keys.private_key)
+            .is_err()
+        {
+            return Err(
+                ValidationError("Content is not encrypted to the system key".to_string()).into(),
+            );
+        }
+
+        let id = outbound_mail_repository
+            .enqueue_within_limit(
+                NewOutboundMail {
+                    sender_id: sender.id,
+                    recipients: self.recipients,
+                    content: self.content,
+                    signature: self.signature,
+                },
+                chrono::Utc::now() - chrono::Duration::hours(1),
+                bridge_policy.max_recipients_per_hour,
+            )
+            .await?
+            .ok_or(BridgeError::RateLimited)?;
+        Ok(RelayReceipt { id })
+    }
+}
+
+/// Relays pending mail that is due, from `username@domain` of its sender. The sender's
+/// signature is checked again, as the queue is only as trustworthy as the database, and the
+/// message's `From` and `Sender` fields are replaced by one naming them, so that nobody sends
+/// as anyone else. Failures the SMTP server may get over are retried with growing delays.
+/// Several relays may run at once, each sending the mail it claimed. Returns the number of
+/// messages relayed.
+pub struct RelayDueOutboundMailCommand {
+    pub domain: String,
+    pub batch_size: i64,
+}
+
+impl RelayDueOutboundMailCommand {
+    pub async fn handle(
+        self,
+        user_repository: &impl UserRepository,
+        outbound_mail_repository: &impl OutboundMailRepository,
+        system_key_repository: &impl SystemKeyRepository,
+        cryptography_service: &impl CryptographyService,
+        serialize_service: &impl SerializeService,
+        smtp_relay: &impl SmtpRelay,
+    ) -> Result<usize, SmError> {
+        let system_keys = system_key_repository
+            .get_system_keys()
+            .await?
+            .ok_or(CryptographyError::InvalidSystemKeys)?;
+        let mut relayed = 0;
+        for mail in outbound_mail_repository
+            .claim_due_mail(
+                self.batch_size,
+                chrono::Utc::now() + chrono::Duration::minutes(RELAY_CLAIM_MINUTES),
+            )
+            .await?
+        {
+            // Mail of deleted users goes with them
+            let Some(sender) = (GetUserByIdQuery {
+                user_id: mail.sender_id,
+            })
+            .handle(user_repository)
+            .await?
+            else {
+                continue;
+            };
+            if !cryptography_service.validate_signature(
+                &outbound_mail_plaintext(&mail.recipients, &mail.content, serialize_service),
+                &mail.signature,
+                &sender.public_verify_key,
+            ) {
+                outbound_mail_repository
+                    .record_failure(mail.id, "Sender's signature is invalid", None)
+                    .await?;
+                continue;
+            }
+            let Ok(message) = cryptography_service.decrypt(&mail.content, &system_keys.private_key)
+            else {
+                outbound_mail_repository
+                    .record_failure(mail.id, "Content could not be decrypted", None)
+                    .await?;
+                continue;
+            };
+
+            let from = format!("{}@{}", sender.username, self.domain);
+            let relayed_message = with_originator(&message, &from);
+            match smtp_relay
+                .relay(&from, &mail.recipients, &relayed_message)
+                .await
+            {
+                Ok(()) => {
+                    outbound_mail_repository.mark_sent(mail.id).await?;
+                    relayed += 1;
+                }
+                Err(SmError::Bridge(BridgeError::RelayUnavailable(failure)))
+                    if mail.attempts + 1 < MAX_RELAY_ATTEMPTS =>
+                {
+                    let retry_at =
+                        chrono::Utc::now() + chrono::Duration::minutes(1 << mail.attempts);
+                    outbound_mail_repository
+                        .record_failure(mail.id, &failure, Some(retry_at))
+                        .await?;
+                }
+                Err(e) => {
+                    outbound_mail_repository
+                        .record_failure(mail.id, &e.to_string(), None)
+                        .await?;
+                }
+            }
+        }
+        Ok(relayed)
+    }
+}
@@ -0,0 +1,36 @@
+use domain::{
+    error::SmError,
+    outbound_mail::{OutboundMailRepository, OutboundMailSummary},
+};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use uuid::Uuid;
+
+use crate::message::queries::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GetOutboundMailQueryDto {
+    pub limit: Option<i64>,
+}
+
+/// Lists the sender's most recent bridged mail with how relaying it went.
+pub struct GetOutboundMailQuery {
+    pub sender_id: Uuid,
+    pub limit: Option<i64>,
+}
+
+impl GetOutboundMailQuery {
+    pub async fn handle(
+        self,
+        outbound_mail_repository: &impl OutboundMailRepository,
+    ) -> Result<Vec<OutboundMailSummary>, SmError> {
+        let limit = self
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+        outbound_mail_repository
+            .list_outbound_mail(self.sender_id, limit)
+            .await
+    }
+}
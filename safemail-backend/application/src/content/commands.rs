@@ -0,0 +1,35 @@
+use domain::{
+    content::{ContentStore, OrphanedContentRepository},
+    error::SmError,
+};
+
+pub struct CollectOrphanedContentCommand {
+    pub batch_size: i64,
+}
+
+impl CollectOrphanedContentCommand {
+    /// Deletes orphaned objects from the content store, returning how many were deleted. A key
+    /// is only forgotten once its object is gone, so an interrupted run is picked up later.
+    pub async fn handle(
+        self,
+        orphaned_content_repository: &impl OrphanedContentRepository,
+        content_store: &impl ContentStore,
+    ) -> Result<u64, SmError> {
+        let mut deleted = 0;
+        loop {
+            let keys = orphaned_content_repository
+                .list_orphaned_content(self.batch_size)
+                .await?;
+            for key in &keys {
+                content_store.delete(key).await?;
+            }
+            orphaned_content_repository
+                .forget_orphaned_content(&keys)
+                .await?;
+            deleted += keys.len() as u64;
+            if (keys.len() as i64) < self.batch_size {
+                return Ok(deleted);
+            }
+        }
+    }
+}
@@ -0,0 +1,708 @@
+use domain::{
+    blob::BlobRepository,
+    block_list::BlockListRepository,
+    chrono::{self, DateTime, Utc},
+    crypto::CryptographyService,
+    error::{CryptographyError, FederationError, SmError, StampError, UserError, ValidationError},
+    federation::{
+        normalize_host, Address, FederationClient, FederationPeerRepository, RemoteDelivery,
+        RemoteUser, RemoteUserRepository, SignedRequest,
+    },
+    message::{MailboxQuota, MessageRepository},
+    onetime_stamp::OneTimeStampTrackerRepository,
+    pow::Pow,
+    report::{ReportPolicy, ReportRepository},
+    serialize::SerializeService,
+    stamp::{OneTimeStampRequest, OnetimeStamp, RecipientStamp, StampPolicy},
+    stamp_request::StampRequestRepository,
+    stamp_revocation::StampRevocationRepository,
+    system_key::SystemKeyRepository,
+    user::UserRepository,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    message::commands::{
+        message_signature_plaintext, recipient_key_fingerprint, validate_copy, validate_expiry,
+        MessageCopy, SendReceipt,
+    },
+    stamp::commands::{
+        onetime_stamp_plaintext, periodic_stamp_plaintext, DifficultyRule, IssueSystemStampCommand,
+        RequestSystemStampIssueCommand, STAMP_SYSTEM_ISSUED,
+    },
+    user::queries::GetUserByIdQuery,
+};
+
+/// How far the time a server request was signed at may lie from now, either way.
+const MAX_REQUEST_CLOCK_SKEW_SECONDS: i64 = 300;
+/// How long a fetched identity key is used before a failed signature may have it fetched again.
+const PEER_KEY_REFRESH_SECONDS: i64 = 60;
+
+/// The text a server signs for a request to another server.
+fn signed_request_plaintext(
+    origin: &str,
+    destination: &str,
+    id: Uuid,
+    sent_at: &DateTime<Utc>,
+    body: &str,
+    serialize_service: &impl SerializeService,
+) -> String {
+    format!(
+        "{}\n{}\n{}\n{}\n{}",
+        origin,
+        destination,
+        id,
+        serialize_service.serialize(sent_at),
+        body
+    )
+}
+
+/// Signs a request to `destination` with this server's system key, under an id of its own.
+pub async fn sign_request(
+    origin: &str,
+    destination: &str,
+    body: String,
+    system_key_repository: &impl SystemKeyRepository,
+    cryptography_service: &impl CryptographyService,
+    serialize_service: &impl SerializeService,
+) -> Result<SignedRequest, SmError> {
+    let system_keys = system_key_repository
+        .get_system_keys()
+        .await?
+        .ok_or(CryptographyError::InvalidSystemKeys)?;
+    let id = Uuid::new_v4();
+    let sent_at = Utc::now();
+    let signature = cryptography_service
+        .produce_signature(
+            &signed_request_plaintext(origin, destination, id, &sent_at, &body, serialize_service),
+            &system_keys.private_key,
+        )
+        .map_err(|_| CryptographyError::InvalidSystemKeys)?;
+    Ok(SignedRequest {
+        origin: origin.to_string(),
+        id,
+        sent_at,
+        body,
+        signature,
+    })
+}
+
+/// Returns the identity key of another server, from the cache unless `refresh` is set. A cached
+/// key is fetched anew at most once in `PEER_KEY_REFRESH_SECONDS`, however many requests fail
+/// to verify against it.
+async fn peer_key(
+    host: &str,
+    refresh: bool,
+    federation_client: &impl FederationClient,
+    peer_repository: &impl FederationPeerRepository,
+    cryptography_service: &impl CryptographyService,
+) -> Result<String, SmError> {
+    if let Some(cached) = peer_repository.get_peer_key(host).await? {
+        let fresh =
+            Utc::now() - cached.fetched_at < chrono::Duration::seconds(PEER_KEY_REFRESH_SECONDS);
+        if !refresh || fresh {
+            return Ok(cached.public_key);
+        }
+    }
+    let identity = federation_client.fetch_identity(host).await?;
+    // Nothing would ever verify against a key that can't be read, so it isn't cached
+    if !identity.host.eq_ignore_ascii_case(host)
+        || !cryptography_service.validate_public_key(&identity.public_key)
+    {
+        return Err(FederationError::InvalidServerSignature.into());
+    }
+    peer_repository
+        .save_peer_key(host, &identity.public_key)
+        .await?;
+    Ok(identity.public_key)
+}
+
+fn ensure_remote(host: &str, local_host: &str) -> Result<(), ValidationError> {
+    if host.eq_ignore_ascii_case(local_host) {
+        return Err(ValidationError(
+            "Users of this server are addressed by their id".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn parse_remote_address(address: &str, local_host: &str) -> Result<Address, SmError> {
+    let address: Address = address.parse()?;
+    ensure_remote(&address.host, local_host)?;
+    Ok(address)
+}
+
+/// Users of other servers are stored and written to like local users, so their keys have to be
+/// ones that can be read.
+pub(crate) fn ensure_valid_keys(
+    user: &RemoteUser,
+    cryptography_service: &impl CryptographyService,
+) -> Result<(), UserError> {
+    if !cryptography_service.validate_public_key(&user.public_encryption_key)
+        || !cryptography_service.validate_public_key(&user.public_verify_key)
+    {
+        return Err(UserError::InvalidPublicKey);
+    }
+    Ok(())
+}
+
+/// Stands in the sender of a request from another server for a local user. The origin vouches
+/// for its own users, so their keys are taken as given once they are readable.
+async fn save_sender(
+    origin: &str,
+    sender: &RemoteUser,
+    remote_user_repository: &impl RemoteUserRepository,
+    cryptography_service: &impl CryptographyService,
+) -> Result<Uuid, SmError> {
+    format!("{}@{}", sender.username, origin).parse::<Address>()?;
+    ensure_valid_keys(sender, cryptography_service)?;
+    let user = remote_user_repository
+        .save_remote_user(origin, sender)
+        .await?;
+    Ok(user.id)
+}
+
+/// Users of other servers have a local stand-in, but can't be sent to from here.
+async fn find_local_recipient(
+    username: String,
+    user_repository: &impl UserRepository,
+) -> Result<Uuid, SmError> {
+    if username.contains('@') {
+        return Err(UserError::UserNotFound.into());
+    }
+    let recipient = user_repository
+        .find_by_username(username)
+        .await?
+        .ok_or(UserError::UserNotFound)?;
+    Ok(recipient.id)
+}
+
+pub struct VerifySignedRequestCommand {
+    pub request: SignedRequest,
+    pub local_host: String,
+}
+
+impl VerifySignedRequestCommand {
+    /// Checks that the request was signed by its origin for this server, recently, and wasn't
+    /// received before. A cached key that fails to verify is fetched anew, unless it was just
+    /// fetched, in case the origin has replaced it.
+    pub async fn handle(
+        self,
+        federation_client: &impl FederationClient,
+        peer_repository: &impl FederationPeerRepository,
+        cryptography_service: &impl CryptographyService,
+        serialize_service: &impl SerializeService,
+    ) -> Result<(), SmError> {
+        let request = self.request;
+        let skew = (Utc::now() - request.sent_at).num_seconds().abs();
+        if skew > MAX_REQUEST_CLOCK_SKEW_SECONDS {
+            return Err(FederationError::RequestExpired.into());
+        }
+
+        let plaintext = signed_request_plaintext(
+            &request.origin,
+            &self.local_host,
+            request.id,
+            &request.sent_at,
+            &request.body,
+            serialize_service,
+        );
+        let mut signature_valid = false;
+        for refresh in [false, true] {
+            let public_key = peer_key(
+                &request.origin,
+                refresh,
+                federation_client,
+                peer_repository,
+                cryptography_service,
+            )
+            .await?;
+            if cryptography_service.validate_signature(&plaintext, &request.signature, &public_key)
+            {
+                signature_valid = true;
+                break;
+            }
+        }
+        if !signature_valid {
+            return Err(FederationError::InvalidServerSignature.into());
+        }
+
+        // Past this the date check turns the request away, so its id needn't be kept longer
+        let expires_at =
+            request.sent_at + chrono::Duration::seconds(MAX_REQUEST_CLOCK_SKEW_SECONDS);
+        let first_seen = peer_repository
+            .record_request(&request.origin, request.id, expires_at)
+            .await?;
+        if !first_seen {
+            return Err(FederationError::RequestReplayed.into());
+        }
+        Ok(())
+    }
+}
+
+/// Forgets the requests from other servers that are too old to be accepted again anyway.
+pub struct PurgeFederationRequestsCommand;
+
+impl PurgeFederationRequestsCommand {
+    pub async fn handle(
+        self,
+        peer_repository: &impl FederationPeerRepository,
+    ) -> Result<u64, SmError> {
+        peer_repository.purge_requests(Utc::now()).await
+    }
+}
+
+/// Checks a stamp for a user of another server before the message is forwarded, against the
+/// keys of that user or of their server. Only the recipient's server can tell whether a
+/// one-time stamp was used already.
+async fn verify_remote_stamp(
+    stamp: &RecipientStamp,
+    recipient: &Address,
+    remote_recipient: &RemoteUser,
+    cryptography_service: &impl CryptographyService,
+    serialize_service: &impl SerializeService,
+    federation_client: &impl FederationClient,
+    peer_repository: &impl FederationPeerRepository,
+) -> Result<bool, SmError> {
+    let (issuer_id, recipient_id, plaintext, signature) = match stamp {
+        RecipientStamp::Periodic(stamp) => (
+            stamp.issuer_id,
+            stamp.recipient_id,
+            periodic_stamp_plaintext(stamp, serialize_service),
+            &stamp.signature,
+        ),
+        RecipientStamp::Onetime(stamp) => {
+            if stamp
+                .valid_to
+                .is_some_and(|valid_to| valid_to < chrono::Utc::now())
+            {
+                return Err(StampError::InvalidTimePeriod.into());
+            }
+            (
+                stamp.issuer_id,
+                stamp.recipient_id,
+                onetime_stamp_plaintext(stamp, serialize_service),
+                &stamp.signature,
+            )
+        }
+    };
+    if recipient_id != remote_recipient.id {
+        return Ok(false);
+    }
+    let issuer_key = if issuer_id == remote_recipient.id {
+        remote_recipient.public_verify_key.clone()
+    } else if issuer_id == STAMP_SYSTEM_ISSUED {
+        peer_key(
+            &recipient.host,
+            false,
+            federation_client,
+            peer_repository,
+            cryptography_service,
+        )
+        .await?
+    } else {
+        return Ok(false);
+    };
+    Ok(cryptography_service.validate_signature(&plaintext, signature, &issuer_key))
+}
+
+/// A message forwarded from the sender's server to the recipient's.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct FederatedMessage {
+    pub sender: RemoteUser,
+    /// The recipient's username on the receiving server.
+    pub recipient: String,
+    pub content: String,
+    pub metadata: String,
+    pub signature: String,
+    pub stamp: RecipientStamp,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SendRemoteMessageCommandDto {
+    /// The recipient's address, `username@host`.
+    pub recipient: String,
+    pub content: String,
+    pub metadata: String,
+    pub signature: String,
+    pub stamp: RecipientStamp,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+pub struct SendRemoteMessageCommand {
+    pub sender_id: Uuid,
+    pub recipient: String,
+    pub content: String,
+    pub metadata: String,
+    pub signature: String,
+    pub stamp: RecipientStamp,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl SendRemoteMessageCommand {
+    /// Forwards the message to the recipient's server, which delivers it as it would a local
+    /// one. The stamp has to be addressed to the sender's stand-in on that server.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn handle(
+        self,
+        local_host: &str,
+        user_repository: &impl UserRepository,
+        cryptography_service: &impl CryptographyService,
+        serialize_service: &impl SerializeService,
+        system_key_repository: &impl SystemKeyRepository,
+        federation_client: &impl FederationClient,
+        peer_repository: &impl FederationPeerRepository,
+    ) -> Result<RemoteDelivery, SmError> {
+        let recipient = parse_remote_address(&self.recipient, local_host)?;
+        let sender = GetUserByIdQuery {
+            user_id: self.sender_id,
+        }
+        .handle(user_repository)
+        .await?
+        .ok_or(UserError::UserNotFound)?;
+        validate_expiry(self.expires_at)?;
+
+        let signature_valid = cryptography_service.validate_signature(
+            &message_signature_plaintext(
+                &self.metadata,
+                &self.content,
+                self.expires_at,
+                &[],
+                serialize_service,
+            ),
+            &self.signature,
+            &sender.public_verify_key,
+        );
+        if !signature_valid {
+            return Err(CryptographyError::InvalidSignature.into());
+        }
+
+        let remote_recipient = federation_client
+            .fetch_user(&recipient)
+            .await?
+            .ok_or(UserError::UserNotFound)?;
+        let stamp_valid = verify_remote_stamp(
+            &self.stamp,
+            &recipient,
+            &remote_recipient,
+            cryptography_service,
+            serialize_service,
+            federation_client,
+            peer_repository,
+        )
+        .await?;
+        if !stamp_valid {
+            return Err(StampError::InvalidStamp.into());
+        }
+
+        let body = serialize_service.serialize(&FederatedMessage {
+            sender: sender.into(),
+            recipient: recipient.username,
+            content: self.content,
+            metadata: self.metadata,
+            signature: self.signature,
+            stamp: self.stamp,
+            expires_at: self.expires_at,
+        });
+        let request = sign_request(
+            local_host,
+            &recipient.host,
+            body,
+            system_key_repository,
+            cryptography_service,
+            serialize_service,
+        )
+        .await?;
+        federation_client
+            .deliver_message(&recipient.host, &request)
+            .await
+    }
+}
+
+pub struct DeliverFederatedMessageCommand {
+    /// The server the message was forwarded by, verified beforehand.
+    pub origin: String,
+    pub message: FederatedMessage,
+}
+
+impl DeliverFederatedMessageCommand {
+    /// Delivers a message from a user of another server like any other, with the sender's
+    /// local stand-in as its sender.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn handle(
+        self,
+        user_repository: &impl UserRepository,
+        cryptography_service: &impl CryptographyService,
+        serialize_service: &impl SerializeService,
+        tracker_repository: &impl OneTimeStampTrackerRepository,
+        system_key_repository: &impl SystemKeyRepository,
+        message_repository: &impl MessageRepository,
+        blob_repository: &impl BlobRepository,
+        block_list_repository: &impl BlockListRepository,
+        stamp_revocation_repository: &impl StampRevocationRepository,
+        remote_user_repository: &impl RemoteUserRepository,
+        mailbox_quota: &MailboxQuota,
+    ) -> Result<SendReceipt, SmError> {
+        let message = self.message;
+        let recipient_id = find_local_recipient(message.recipient, user_repository).await?;
+        let sender_id = save_sender(
+            &self.origin,
+            &message.sender,
+            remote_user_repository,
+            cryptography_service,
+        )
+        .await?;
+
+        let new_message = validate_copy(
+            MessageCopy {
+                recipient_id,
+                content: message.content,
+                metadata: message.metadata,
+                signature: message.signature,
+                stamp: message.stamp,
+                expires_at: message.expires_at,
+                attachments: Vec::new(),
+            },
+            sender_id,
+            &message.sender.public_verify_key,
+            user_repository,
+            cryptography_service,
+            serialize_service,
+            tracker_repository,
+            system_key_repository,
+            message_repository,
+            blob_repository,
+            block_list_repository,
+            stamp_revocation_repository,
+            mailbox_quota,
+        )
+        .await?;
+        let delivery = message_repository
+            .create_message(new_message, None, mailbox_quota)
+            .await?;
+
+        let message = &delivery.messages[0];
+        Ok(SendReceipt {
+            recipient_id: message.recipient_id,
+            recipient_key_fingerprint: recipient_key_fingerprint(
+                message.recipient_id,
+                user_repository,
+                cryptography_service,
+            )
+            .await?,
+            message_id: message.id,
+            outbox_id: None,
+        })
+    }
+}
+
+/// A request for a system stamp, forwarded on behalf of a user of another server.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct FederatedStampRequest {
+    pub sender: RemoteUser,
+    /// The recipient's username on the receiving server.
+    pub recipient: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RequestRemoteStampIssueCommandDto {
+    /// The recipient's address, `username@host`.
+    pub recipient: String,
+}
+pub struct RequestRemoteStampIssueCommand {
+    pub sender_id: Uuid,
+    pub recipient: String,
+}
+
+impl RequestRemoteStampIssueCommand {
+    /// Asks the recipient's server for a system stamp request, whose proof of work is handed
+    /// back to that server through `IssueRemoteStampCommand`.
+    pub async fn handle(
+        self,
+        local_host: &str,
+        user_repository: &impl UserRepository,
+        cryptography_service: &impl CryptographyService,
+        serialize_service: &impl SerializeService,
+        system_key_repository: &impl SystemKeyRepository,
+        federation_client: &impl FederationClient,
+    ) -> Result<OneTimeStampRequest, SmError> {
+        let recipient = parse_remote_address(&self.recipient, local_host)?;
+        let sender = GetUserByIdQuery {
+            user_id: self.sender_id,
+        }
+        .handle(user_repository)
+        .await?
+        .ok_or(UserError::UserNotFound)?;
+
+        let body = serialize_service.serialize(&FederatedStampRequest {
+            sender: sender.into(),
+            recipient: recipient.username,
+        });
+        let request = sign_request(
+            local_host,
+            &recipient.host,
+            body,
+            system_key_repository,
+            cryptography_service,
+            serialize_service,
+        )
+        .await?;
+        federation_client
+            .request_system_stamp(&recipient.host, &request)
+            .await
+    }
+}
+
+pub struct HandleFederatedStampRequestCommand {
+    pub origin: String,
+    pub request: FederatedStampRequest,
+}
+
+impl HandleFederatedStampRequestCommand {
+    #[allow(clippy::too_many_arguments)]
+    pub async fn handle(
+        self,
+        user_repository: &impl UserRepository,
+        stamp_request_repository: &impl StampRequestRepository,
+        block_list_repository: &impl BlockListRepository,
+        report_repository: &impl ReportRepository,
+        remote_user_repository: &impl RemoteUserRepository,
+        cryptography_service: &impl CryptographyService,
+        report_policy: &ReportPolicy,
+        stamp_policy: &StampPolicy,
+    ) -> Result<OneTimeStampRequest, SmError> {
+        let recipient_id = find_local_recipient(self.request.recipient, user_repository).await?;
+        let sender_id = save_sender(
+            &self.origin,
+            &self.request.sender,
+            remote_user_repository,
+            cryptography_service,
+        )
+        .await?;
+        RequestSystemStampIssueCommand {
+            recipient_id,
+            sender_id,
+        }
+        .handle(
+            user_repository,
+            stamp_request_repository,
+            block_list_repository,
+            report_repository,
+            cryptography_service,
+            report_policy,
+            stamp_policy,
+        )
+        .await
+        .map(|receipt| receipt.request)
+    }
+}
+
+/// A solved system stamp request, forwarded on behalf of a user of another server.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct FederatedStampIssue {
+    pub sender: RemoteUser,
+    pub stamp_request_id: Uuid,
+    #[schema(value_type = domain::stamp::ProofOfWork)]
+    pub proof_of_work: Pow<Uuid>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct IssueRemoteStampCommandDto {
+    /// The server the stamp request was made to.
+    pub host: String,
+    pub stamp_request_id: Uuid,
+    #[schema(value_type = domain::stamp::ProofOfWork)]
+    pub proof_of_work: Pow<Uuid>,
+}
+pub struct IssueRemoteStampCommand {
+    pub sender_id: Uuid,
+    pub host: String,
+    pub stamp_request_id: Uuid,
+    pub proof_of_work: Pow<Uuid>,
+}
+
+impl IssueRemoteStampCommand {
+    pub async fn handle(
+        self,
+        local_host: &str,
+        user_repository: &impl UserRepository,
+        cryptography_service: &impl CryptographyService,
+        serialize_service: &impl SerializeService,
+        system_key_repository: &impl SystemKeyRepository,
+        federation_client: &impl FederationClient,
+    ) -> Result<OnetimeStamp, SmError> {
+        let host = normalize_host(&self.host)?;
+        ensure_remote(&host, local_host)?;
+        let sender = GetUserByIdQuery {
+            user_id: self.sender_id,
+        }
+        .handle(user_repository)
+        .await?
+        .ok_or(UserError::UserNotFound)?;
+
+        let body = serialize_service.serialize(&FederatedStampIssue {
+            sender: sender.into(),
+            stamp_request_id: self.stamp_request_id,
+            proof_of_work: self.proof_of_work,
+        });
+        let request = sign_request(
+            local_host,
+            &host,
+            body,
+            system_key_repository,
+            cryptography_service,
+            serialize_service,
+        )
+        .await?;
+        federation_client.issue_system_stamp(&host, &request).await
+    }
+}
+
+pub struct HandleFederatedStampIssueCommand {
+    pub origin: String,
+    pub issue: FederatedStampIssue,
+}
+
+impl HandleFederatedStampIssueCommand {
+    #[allow(clippy::too_many_arguments)]
+    pub async fn handle(
+        self,
+        user_repository: &impl UserRepository,
+        stamp_request_repository: &impl StampRequestRepository,
+        block_list_repository: &impl BlockListRepository,
+        tracker_repository: &impl OneTimeStampTrackerRepository,
+        system_key_repository: &impl SystemKeyRepository,
+        remote_user_repository: &impl RemoteUserRepository,
+        cryptography_service: &impl CryptographyService,
+        serialize_service: &impl SerializeService,
+        stamp_policy: &StampPolicy,
+    ) -> Result<OnetimeStamp, SmError> {
+        let sender_id = save_sender(
+            &self.origin,
+            &self.issue.sender,
+            remote_user_repository,
+            cryptography_service,
+        )
+        .await?;
+        IssueSystemStampCommand {
+            stamp_request_id: self.issue.stamp_request_id,
+            sender_id,
+            proof_of_work: self.issue.proof_of_work,
+            // Federation is younger than the score rule, so no peer relies on it
+            difficulty_rule: DifficultyRule::ExpectedHashes,
+        }
+        .handle(
+            user_repository,
+            stamp_request_repository,
+            block_list_repository,
+            tracker_repository,
+            system_key_repository,
+            cryptography_service,
+            serialize_service,
+            stamp_policy,
+        )
+        .await
+    }
+}
@@ -0,0 +1,63 @@
+use domain::{
+    crypto::CryptographyService,
+    error::{CryptographyError, SmError, UserError},
+    federation::{Address, FederationClient, RemoteUserRepository, ServerIdentity},
+    system_key::SystemKeyRepository,
+    user::{User, UserRepository},
+};
+
+use crate::federation::commands::ensure_valid_keys;
+
+pub struct GetServerIdentityQuery {
+    pub local_host: String,
+}
+
+impl GetServerIdentityQuery {
+    pub async fn handle(
+        self,
+        system_key_repository: &impl SystemKeyRepository,
+    ) -> Result<ServerIdentity, SmError> {
+        let system_keys = system_key_repository
+            .get_system_keys()
+            .await?
+            .ok_or(CryptographyError::InvalidSystemKeys)?;
+        Ok(ServerIdentity {
+            host: self.local_host,
+            public_key: system_keys.public_key,
+        })
+    }
+}
+
+pub struct ResolveAddressQuery {
+    pub address: String,
+    pub local_host: String,
+}
+
+impl ResolveAddressQuery {
+    /// Looks up the user with the address, fetching users of other servers from their home
+    /// server. They are returned as their local stand-in, whose id is the one stamps for them
+    /// are issued to.
+    pub async fn handle(
+        self,
+        user_repository: &impl UserRepository,
+        federation_client: &impl FederationClient,
+        remote_user_repository: &impl RemoteUserRepository,
+        cryptography_service: &impl CryptographyService,
+    ) -> Result<User, SmError> {
+        let address: Address = self.address.parse()?;
+        if address.host.eq_ignore_ascii_case(&self.local_host) {
+            return user_repository
+                .find_by_username(address.username)
+                .await?
+                .ok_or(UserError::UserNotFound.into());
+        }
+        let remote_user = federation_client
+            .fetch_user(&address)
+            .await?
+            .ok_or(UserError::UserNotFound)?;
+        ensure_valid_keys(&remote_user, cryptography_service)?;
+        remote_user_repository
+            .save_remote_user(&address.host, &remote_user)
+            .await
+    }
+}
@@ -0,0 +1,159 @@
+use domain::{
+    blob::BlobRepository,
+    block_list::BlockListRepository,
+    chrono,
+    crypto::CryptographyService,
+    error::{CryptographyError, SmError, UserError},
+    message::{MailboxQuota, MessageRepository},
+    onetime_stamp::OneTimeStampTrackerRepository,
+    serialize::SerializeService,
+    stamp::OnetimeStamp,
+    stamp_revocation::StampRevocationRepository,
+    system_key::SystemKeyRepository,
+    user::{User, UserRepository},
+};
+use uuid::Uuid;
+
+use crate::{
+    message::commands::{
+        message_signature_plaintext, SendMessageWithOnetimeStampCommand, SendReceipt,
+    },
+    stamp::commands::{onetime_stamp_plaintext, STAMP_SYSTEM_ISSUED},
+    user::queries::GetUserByIdQuery,
+};
+
+/// The gateway uses its stamps right away, so they only need to outlive the delivery.
+const GATEWAY_STAMP_VALIDITY_MINUTES: i64 = 1;
+
+/// Sets up the account mail received by the SMTP gateway is sent from. Both of its keys are
+/// the system key, so only the server can sign for it. Fails if an ordinary user already holds
+/// the username.
+pub struct InitGatewayAccountCommand {
+    pub username: String,
+}
+
+impl InitGatewayAccountCommand {
+    pub async fn handle(
+        self,
+        user_repository: &impl UserRepository,
+        system_key_repository: &impl SystemKeyRepository,
+    ) -> Result<User, SmError> {
+        let system_keys = system_key_repository
+            .get_system_keys()
+            .await?
+            .ok_or(CryptographyError::InvalidSystemKeys)?;
+        if let Some(user) = user_repository
+            .find_by_username(self.username.clone())
+            .await?
+        {
+            if user.public_verify_key != system_keys.public_key {
+                return Err(UserError::UserAlreadyExists.into());
+            }
+            return Ok(user);
+        }
+        user_repository
+            .create(
+                self.username,
+                system_keys.public_key.clone(),
+                system_keys.public_key,
+            )
+            .await
+    }
+}
+
+/// Delivers mail received by the SMTP gateway. The message and its headers are encrypted to
+/// the recipient and sent from the gateway account under a system stamp the gateway issues
+/// itself, so whatever an SMTP sender is charged has to be paid before this is called. The
+/// recipient's block list and mailbox quota apply as they do for any other sender.
+pub struct DeliverGatewayMailCommand {
+    pub gateway_id: Uuid,
+    pub recipient_id: Uuid,
+    pub content: Vec<u8>,
+    pub metadata: Vec<u8>,
+}
+
+impl DeliverGatewayMailCommand {
+    #[allow(clippy::too_many_arguments)]
+    pub async fn handle(
+        self,
+        user_repository: &impl UserRepository,
+        cryptography_service: &impl CryptographyService,
+        serialize_service: &impl SerializeService,
+        tracker_repository: &impl OneTimeStampTrackerRepository,
+        system_key_repository: &impl SystemKeyRepository,
+        message_repository: &impl MessageRepository,
+        blob_repository: &impl BlobRepository,
+        block_list_repository: &impl BlockListRepository,
+        stamp_revocation_repository: &impl StampRevocationRepository,
+        mailbox_quota: &MailboxQuota,
+    ) -> Result<SendReceipt, SmError> {
+        let recipient = GetUserByIdQuery {
+            user_id: self.recipient_id,
+        }
+        .handle(user_repository)
+        .await?
+        .ok_or(UserError::UserNotFound)?;
+        let content = cryptography_service
+            .encrypt(&self.content, &recipient.public_encryption_key)
+            .map_err(|_| UserError::InvalidPublicKey)?;
+        let metadata = cryptography_service
+            .encrypt(&self.metadata, &recipient.public_encryption_key)
+            .map_err(|_| UserError::InvalidPublicKey)?;
+
+        let system_keys = system_key_repository
+            .get_system_keys()
+            .await?
+            .ok_or(CryptographyError::InvalidSystemKeys)?;
+        let stamp = OnetimeStamp {
+            stamp_id: Uuid::new_v4(),
+            issuer_id: STAMP_SYSTEM_ISSUED,
+            recipient_id: recipient.id,
+            sender_id: self.gateway_id,
+            valid_to: Some(
+                chrono::Utc::now() + chrono::Duration::minutes(GATEWAY_STAMP_VALIDITY_MINUTES),
+            ),
+            signature: String::new(),
+        };
+        let signature = cryptography_service
+            .produce_signature(
+                &onetime_stamp_plaintext(&stamp, serialize_service),
+                &system_keys.private_key,
+            )
+            .map_err(|_| CryptographyError::InvalidSystemKeys)?;
+        let stamp = OnetimeStamp { signature, ..stamp };
+        tracker_repository
+            .insert(stamp.stamp_id, stamp.recipient_id, stamp.sender_id)
+            .await?;
+
+        let signature = cryptography_service
+            .produce_signature(
+                &message_signature_plaintext(&metadata, &content, None, &[], serialize_service),
+                &system_keys.private_key,
+            )
+            .map_err(|_| CryptographyError::InvalidSystemKeys)?;
+        SendMessageWithOnetimeStampCommand {
+            sender_id: self.gateway_id,
+            recipient_id: recipient.id,
+            content,
+            metadata,
+            signature,
+            stamp,
+            expires_at: None,
+            attachments: Vec::new(),
+            sender_copy: None,
+        }
+        .handle(
+            user_repository,
+            cryptography_service,
+            serialize_service,
+            tracker_repository,
+            system_key_repository,
+            message_repository,
+            blob_repository,
+            block_list_repository,
+            stamp_revocation_repository,
+            mailbox_quota,
+        )
+        .await
+    }
+}
@@ -0,0 +1,125 @@
+use domain::{
+    chrono,
+    crypto::{ContentHasher, CryptographyService},
+    error::{IdempotencyError, SmError, ValidationError},
+    idempotency::{IdempotencyClaim, IdempotencyPolicy, IdempotencyRepository, IdempotentOutcome},
+};
+use uuid::Uuid;
+
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+fn validate_idempotency_key(key: &str) -> Result<(), ValidationError> {
+    if key.is_empty()
+        || key.len() > MAX_IDEMPOTENCY_KEY_LENGTH
+        || !key.chars().all(|c| c.is_ascii_graphic())
+    {
+        return Err(ValidationError(format!(
+            "Idempotency key must be 1 to {} printable ASCII characters",
+            MAX_IDEMPOTENCY_KEY_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+pub struct ClaimIdempotencyKeyCommand<'a> {
+    pub sender_id: Uuid,
+    pub key: String,
+    /// Identifies the endpoint, so that a key can't be reused across endpoints.
+    pub scope: &'a str,
+    pub request_body: &'a [u8],
+}
+
+impl ClaimIdempotencyKeyCommand<'_> {
+    /// Returns the stored outcome if the request was already processed, or `None` if it should
+    /// be processed now, in which case it must be completed or released afterwards.
+    pub async fn handle(
+        self,
+        idempotency_repository: &impl IdempotencyRepository,
+        cryptography_service: &impl CryptographyService,
+        idempotency_policy: &IdempotencyPolicy,
+    ) -> Result<Option<IdempotentOutcome>, SmError> {
+        validate_idempotency_key(&self.key)?;
+
+        let mut hasher = cryptography_service.content_hasher();
+        hasher.update(self.scope.as_bytes());
+        hasher.update(b"\n");
+        hasher.update(self.request_body);
+        let fingerprint = hasher.finish();
+
+        let stale_before =
+            chrono::Utc::now() - chrono::Duration::seconds(idempotency_policy.stale_claim_seconds);
+        match idempotency_repository
+            .claim(self.sender_id, &self.key, &fingerprint, stale_before)
+            .await?
+        {
+            IdempotencyClaim::Claimed => Ok(None),
+            IdempotencyClaim::Completed(outcome) => Ok(Some(outcome)),
+            IdempotencyClaim::InProgress => Err(IdempotencyError::RequestInProgress.into()),
+            IdempotencyClaim::Mismatch => Err(IdempotencyError::KeyReused.into()),
+        }
+    }
+}
+
+pub struct CompleteIdempotencyKeyCommand {
+    pub sender_id: Uuid,
+    pub key: String,
+    pub outcome: IdempotentOutcome,
+}
+
+impl CompleteIdempotencyKeyCommand {
+    pub async fn handle(
+        self,
+        idempotency_repository: &impl IdempotencyRepository,
+    ) -> Result<(), SmError> {
+        idempotency_repository
+            .complete(self.sender_id, &self.key, self.outcome)
+            .await
+    }
+}
+
+pub struct RenewIdempotencyKeyCommand {
+    pub sender_id: Uuid,
+    pub key: String,
+}
+
+impl RenewIdempotencyKeyCommand {
+    pub async fn handle(
+        self,
+        idempotency_repository: &impl IdempotencyRepository,
+    ) -> Result<(), SmError> {
+        idempotency_repository
+            .renew(self.sender_id, &self.key)
+            .await
+    }
+}
+
+pub struct ReleaseIdempotencyKeyCommand {
+    pub sender_id: Uuid,
+    pub key: String,
+}
+
+impl ReleaseIdempotencyKeyCommand {
+    pub async fn handle(
+        self,
+        idempotency_repository: &impl IdempotencyRepository,
+    ) -> Result<(), SmError> {
+        idempotency_repository
+            .release(self.sender_id, &self.key)
+            .await
+    }
+}
+
+pub struct PurgeIdempotencyKeysCommand {
+    pub retention: chrono::Duration,
+}
+
+impl PurgeIdempotencyKeysCommand {
+    pub async fn handle(
+        self,
+        idempotency_repository: &impl IdempotencyRepository,
+    ) -> Result<u64, SmError> {
+        idempotency_repository
+            .purge(chrono::Utc::now() - self.retention)
+            .await
+    }
+}
@@ -9,3 +9,42 @@ pub mod message {
 pub mod stamp {
     pub mod commands;
 }
+pub mod bridge {
+    pub mod commands;
+    pub mod queries;
+}
+pub mod block_list {
+    pub mod commands;
+    pub mod queries;
+}
+pub mod blob {
+    pub mod commands;
+    pub mod queries;
+}
+pub mod content {
+    pub mod commands;
+}
+pub mod federation {
+    pub mod commands;
+    pub mod queries;
+}
+pub mod gateway {
+    pub mod commands;
+}
+pub mod idempotency {
+    pub mod commands;
+}
+pub mod outbox {
+    pub mod commands;
+    pub mod queries;
+}
+pub mod rate_limit {
+    pub mod commands;
+}
+pub mod report {
+    pub mod commands;
+    pub mod queries;
+}
+pub mod system_key {
+    pub mod commands;
+}
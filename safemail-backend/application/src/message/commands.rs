@@ -1,29 +1,192 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use domain::{
+    blob::BlobRepository,
+    block_list::BlockListRepository,
+    chrono::{self, DateTime, Utc},
     crypto::CryptographyService,
-    error::{CryptographyError, SmError, StampError, UserError},
-    message::{MessageMetadata, MessageRepository},
+    error::{
+        BlobError, CryptographyError, DatabaseError, MessageError, SmError, StampError, UserError,
+        ValidationError,
+    },
+    message::{MailboxQuota, MessageMetadata, MessageRepository, NewMessage},
+    message_change::MessageChangeRepository,
     onetime_stamp::OneTimeStampTrackerRepository,
+    outbox::NewOutboxEntry,
+    scheduled_message::{NewScheduledMessage, ScheduledMessageRepository},
     serialize::SerializeService,
-    stamp::PeriodicStamp,
+    stamp::{OnetimeStamp, PeriodicStamp, RecipientStamp},
+    stamp_revocation::StampRevocationRepository,
     system_key::SystemKeyRepository,
     user::UserRepository,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
 use crate::{
+    blob::commands::validate_blob_hash,
     stamp::commands::{VerifyOnetimeStampCommand, VerifyPeriodicStampCommand},
-    user::queries::GetUserByIdQuery,
+    user::queries::{GetUserByIdQuery, RecipientRef, ResolveRecipientQuery},
 };
 
-#[derive(Deserialize)]
+const MAX_ATTACHMENTS: usize = 32;
+
+/// The text a sender signs. An expiry and attachments are part of it, so the server can
+/// neither extend the lifetime of a message nor swap out its attachments. Messages with
+/// neither keep the original two-line format, and the expiry line is `null` for messages that
+/// only carry attachments.
+pub(crate) fn message_signature_plaintext(
+    metadata: &str,
+    content: &str,
+    expires_at: Option<DateTime<Utc>>,
+    attachments: &[String],
+    serialize_service: &impl SerializeService,
+) -> String {
+    match (expires_at, attachments.is_empty()) {
+        (None, true) => format!("{}\n{}", metadata, content),
+        (Some(expires_at), true) => format!(
+            "{}\n{}\n{}",
+            metadata,
+            content,
+            serialize_service.serialize(&expires_at)
+        ),
+        (expires_at, false) => format!(
+            "{}\n{}\n{}\n{}",
+            metadata,
+            content,
+            serialize_service.serialize(&expires_at),
+            serialize_service.serialize(&attachments)
+        ),
+    }
+}
+
+/// Whether `value` is non-empty, padded, standard base64.
+pub(crate) fn is_base64(value: &str) -> bool {
+    !value.is_empty() && BASE64.decode(value).is_ok()
+}
+
+/// Seals the sender's id to the system key, so that it can be recovered if the recipient
+/// reports the message but isn't otherwise kept.
+async fn seal_sender(
+    sender_id: Uuid,
+    system_key_repository: &impl SystemKeyRepository,
+    cryptography_service: &impl CryptographyService,
+) -> Result<String, SmError> {
+    let system_keys = system_key_repository
+        .get_system_keys()
+        .await?
+        .ok_or(CryptographyError::InvalidSystemKeys)?;
+    let seal = cryptography_service
+        .seal(sender_id.as_bytes(), &system_keys.public_key)
+        .map_err(|_| CryptographyError::InvalidSystemKeys)?;
+    Ok(seal)
+}
+
+/// Echoed back to senders, so they can make sure they encrypted to the key the recipient has.
+pub(crate) async fn recipient_key_fingerprint(
+    recipient_id: Uuid,
+    user_repository: &impl UserRepository,
+    cryptography_service: &impl CryptographyService,
+) -> Result<String, SmError> {
+    let recipient = GetUserByIdQuery {
+        user_id: recipient_id,
+    }
+    .handle(user_repository)
+    .await?
+    .ok_or(UserError::UserNotFound)?;
+    let fingerprint = cryptography_service
+        .key_fingerprint(&recipient.public_encryption_key)
+        .map_err(|_| UserError::InvalidPublicKey)?;
+    Ok(fingerprint)
+}
+
+/// A copy of the message encrypted to the sender's own key, kept in the sender's outbox.
+#[derive(Deserialize, ToSchema)]
+pub struct SenderCopy {
+    pub metadata: String,
+    pub content: String,
+}
+
+impl SenderCopy {
+    fn into_outbox_entry(self, sender_id: Uuid) -> Result<NewOutboxEntry, ValidationError> {
+        if !is_base64(&self.metadata) || !is_base64(&self.content) {
+            return Err(ValidationError(
+                "Sender copy must be base64 encoded".to_string(),
+            ));
+        }
+        Ok(NewOutboxEntry {
+            sender_id,
+            metadata: self.metadata,
+            content: self.content,
+        })
+    }
+}
+
+/// Returned for a delivered message.
+#[derive(Serialize, ToSchema)]
+pub struct SendReceipt {
+    pub recipient_id: Uuid,
+    pub recipient_key_fingerprint: String,
+    pub message_id: i64,
+    /// The sender's outbox entry, if a sender copy was given.
+    pub outbox_id: Option<i64>,
+}
+
+pub(crate) fn validate_expiry(expires_at: Option<DateTime<Utc>>) -> Result<(), ValidationError> {
+    if expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+        return Err(ValidationError(
+            "Message expiry must lie in the future".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the combined size of the attachments. Senders can only attach blobs they could
+/// download themselves, so knowing a hash is not enough to pass a blob on.
+async fn validate_attachments(
+    attachments: &[String],
+    sender_id: Uuid,
+    blob_repository: &impl BlobRepository,
+) -> Result<i64, SmError> {
+    if attachments.len() > MAX_ATTACHMENTS {
+        return Err(ValidationError(format!(
+            "A message can have at most {} attachments",
+            MAX_ATTACHMENTS
+        ))
+        .into());
+    }
+    let mut size = 0;
+    for (i, hash) in attachments.iter().enumerate() {
+        validate_blob_hash(hash)?;
+        if attachments[..i].contains(hash) {
+            return Err(ValidationError("Attachments must not repeat".to_string()).into());
+        }
+        let blob = blob_repository
+            .get_blob(hash)
+            .await?
+            .filter(|blob| blob.completed_at.is_some())
+            .ok_or(BlobError::BlobNotFound)?;
+        if !blob_repository.can_access(hash, sender_id).await? {
+            return Err(BlobError::BlobNotFound.into());
+        }
+        size += blob.size;
+    }
+    Ok(size)
+}
+
+#[derive(Deserialize, ToSchema)]
 pub struct SendMessageWithPeriodicStampCommandDto {
     pub sender_id: Uuid,
-    pub recipient_id: Uuid,
+    #[serde(flatten)]
+    pub recipient: RecipientRef,
     pub content: String,
     pub metadata: String,
     pub signature: String,
     pub stamp: PeriodicStamp,
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub attachments: Vec<String>,
+    pub sender_copy: Option<SenderCopy>,
 }
 pub struct SendMessageWithPeriodicStampCommand {
     pub sender_id: Uuid,
@@ -32,16 +195,26 @@ pub struct SendMessageWithPeriodicStampCommand {
     pub metadata: String,
     pub signature: String,
     pub stamp: PeriodicStamp,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub attachments: Vec<String>,
+    pub sender_copy: Option<SenderCopy>,
 }
 
 impl SendMessageWithPeriodicStampCommand {
+    #[allow(clippy::too_many_arguments)]
     pub async fn handle(
         self,
         user_repository: &impl UserRepository,
         cryptography_service: &impl CryptographyService,
         serialize_service: &impl SerializeService,
+        tracker_repository: &impl OneTimeStampTrackerRepository,
+        system_key_repository: &impl SystemKeyRepository,
         message_repository: &impl MessageRepository,
-    ) -> Result<(), SmError> {
+        blob_repository: &impl BlobRepository,
+        block_list_repository: &impl BlockListRepository,
+        stamp_revocation_repository: &impl StampRevocationRepository,
+        mailbox_quota: &MailboxQuota,
+    ) -> Result<SendReceipt, SmError> {
         let sender = match (GetUserByIdQuery {
             user_id: self.sender_id,
         })
@@ -51,16 +224,38 @@ impl SendMessageWithPeriodicStampCommand {
             Some(sender) => sender,
             None => return Err(UserError::UserNotFound.into()),
         };
+        validate_expiry(self.expires_at)?;
+        validate_attachments(&self.attachments, sender.id, blob_repository).await?;
+        let outbox_entry = self
+            .sender_copy
+            .map(|sender_copy| sender_copy.into_outbox_entry(sender.id))
+            .transpose()?;
 
-        let stamp_valid = VerifyPeriodicStampCommand(self.stamp)
-            .handle(user_repository, cryptography_service, serialize_service)
-            .await?;
+        ensure_not_blocked(self.recipient_id, sender.id, block_list_repository).await?;
+        let stamp_valid = verify_recipient_stamp(
+            RecipientStamp::Periodic(self.stamp),
+            sender.id,
+            self.recipient_id,
+            user_repository,
+            cryptography_service,
+            serialize_service,
+            tracker_repository,
+            system_key_repository,
+            stamp_revocation_repository,
+        )
+        .await?;
         if !stamp_valid {
             return Err(StampError::InvalidStamp.into());
         }
 
         let signature_valid = cryptography_service.validate_signature(
-            &format!("{}\n{}", self.metadata, self.content),
+            &message_signature_plaintext(
+                &self.metadata,
+                &self.content,
+                self.expires_at,
+                &self.attachments,
+                serialize_service,
+            ),
             &self.signature,
             &sender.public_verify_key,
         );
@@ -68,27 +263,51 @@ impl SendMessageWithPeriodicStampCommand {
             return Err(CryptographyError::InvalidSignature.into());
         }
 
-        message_repository
+        let delivery = message_repository
             .create_message(
-                self.recipient_id,
-                domain::message::MessageMetadata(self.metadata),
-                self.content,
+                NewMessage {
+                    recipient_id: self.recipient_id,
+                    metadata: MessageMetadata(self.metadata),
+                    content: self.content,
+                    expires_at: self.expires_at,
+                    attachments: self.attachments,
+                    sender_seal: Some(
+                        seal_sender(sender.id, system_key_repository, cryptography_service).await?,
+                    ),
+                    onetime_stamp_id: None,
+                },
+                outbox_entry,
+                mailbox_quota,
             )
             .await?;
 
-        Ok(())
+        let message = &delivery.messages[0];
+        Ok(SendReceipt {
+            recipient_id: message.recipient_id,
+            recipient_key_fingerprint: recipient_key_fingerprint(
+                message.recipient_id,
+                user_repository,
+                cryptography_service,
+            )
+            .await?,
+            message_id: message.id,
+            outbox_id: delivery.outbox_id,
+        })
     }
 }
 
-use domain::stamp::OnetimeStamp;
-
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct SendMessageWithOnetimeStampCommandDto {
-    pub recipient_id: Uuid,
+    #[serde(flatten)]
+    pub recipient: RecipientRef,
     pub content: String,
     pub metadata: String,
     pub signature: String,
     pub stamp: OnetimeStamp,
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub attachments: Vec<String>,
+    pub sender_copy: Option<SenderCopy>,
 }
 pub struct SendMessageWithOnetimeStampCommand {
     pub sender_id: Uuid,
@@ -97,9 +316,13 @@ pub struct SendMessageWithOnetimeStampCommand {
     pub metadata: String,
     pub signature: String,
     pub stamp: OnetimeStamp,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub attachments: Vec<String>,
+    pub sender_copy: Option<SenderCopy>,
 }
 
 impl SendMessageWithOnetimeStampCommand {
+    #[allow(clippy::too_many_arguments)]
     pub async fn handle(
         self,
         user_repository: &impl UserRepository,
@@ -108,7 +331,11 @@ impl SendMessageWithOnetimeStampCommand {
         tracker_repository: &impl OneTimeStampTrackerRepository,
         system_key_repository: &impl SystemKeyRepository,
         message_repository: &impl MessageRepository,
-    ) -> Result<(), SmError> {
+        blob_repository: &impl BlobRepository,
+        block_list_repository: &impl BlockListRepository,
+        stamp_revocation_repository: &impl StampRevocationRepository,
+        mailbox_quota: &MailboxQuota,
+    ) -> Result<SendReceipt, SmError> {
         let sender = match (GetUserByIdQuery {
             user_id: self.sender_id,
         })
@@ -118,22 +345,39 @@ impl SendMessageWithOnetimeStampCommand {
             Some(sender) => sender,
             None => return Err(UserError::UserNotFound.into()),
         };
+        validate_expiry(self.expires_at)?;
+        validate_attachments(&self.attachments, sender.id, blob_repository).await?;
+        let outbox_entry = self
+            .sender_copy
+            .map(|sender_copy| sender_copy.into_outbox_entry(sender.id))
+            .transpose()?;
 
-        let stamp_valid = VerifyOnetimeStampCommand(self.stamp)
-            .handle(
-                user_repository,
-                cryptography_service,
-                serialize_service,
-                tracker_repository,
-                system_key_repository,
-            )
-            .await?;
+        ensure_not_blocked(self.recipient_id, sender.id, block_list_repository).await?;
+        let stamp_id = self.stamp.stamp_id;
+        let stamp_valid = verify_recipient_stamp(
+            RecipientStamp::Onetime(self.stamp),
+            sender.id,
+            self.recipient_id,
+            user_repository,
+            cryptography_service,
+            serialize_service,
+            tracker_repository,
+            system_key_repository,
+            stamp_revocation_repository,
+        )
+        .await?;
         if !stamp_valid {
             return Err(StampError::InvalidStamp.into());
         }
 
         let signature_valid = cryptography_service.validate_signature(
-            &format!("{}\n{}", self.metadata, self.content),
+            &message_signature_plaintext(
+                &self.metadata,
+                &self.content,
+                self.expires_at,
+                &self.attachments,
+                serialize_service,
+            ),
             &self.signature,
             &sender.public_verify_key,
         );
@@ -141,13 +385,773 @@ impl SendMessageWithOnetimeStampCommand {
             return Err(CryptographyError::InvalidSignature.into());
         }
 
-        message_repository
+        let delivery = message_repository
             .create_message(
-                self.recipient_id,
-                MessageMetadata(self.metadata),
-                self.content,
+                NewMessage {
+                    recipient_id: self.recipient_id,
+                    metadata: MessageMetadata(self.metadata),
+                    content: self.content,
+                    expires_at: self.expires_at,
+                    attachments: self.attachments,
+                    sender_seal: Some(
+                        seal_sender(sender.id, system_key_repository, cryptography_service).await?,
+                    ),
+                    onetime_stamp_id: Some(stamp_id),
+                },
+                outbox_entry,
+                mailbox_quota,
             )
             .await?;
+
+        let message = &delivery.messages[0];
+        Ok(SendReceipt {
+            recipient_id: message.recipient_id,
+            recipient_key_fingerprint: recipient_key_fingerprint(
+                message.recipient_id,
+                user_repository,
+                cryptography_service,
+            )
+            .await?,
+            message_id: message.id,
+            outbox_id: delivery.outbox_id,
+        })
+    }
+}
+
+const MAX_FAN_OUT_RECIPIENTS: usize = 100;
+
+/// One recipient's copy of a message, encrypted, signed and stamped for that recipient alone.
+/// The stamp may be of either kind.
+#[derive(Deserialize, ToSchema)]
+pub struct MessageCopyDto {
+    #[serde(flatten)]
+    pub recipient: RecipientRef,
+    pub content: String,
+    pub metadata: String,
+    pub signature: String,
+    pub stamp: RecipientStamp,
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub attachments: Vec<String>,
+}
+impl MessageCopyDto {
+    pub fn into_copy(self, recipient_id: Uuid) -> MessageCopy {
+        MessageCopy {
+            recipient_id,
+            content: self.content,
+            metadata: self.metadata,
+            signature: self.signature,
+            stamp: self.stamp,
+            expires_at: self.expires_at,
+            attachments: self.attachments,
+        }
+    }
+}
+pub struct MessageCopy {
+    pub recipient_id: Uuid,
+    pub content: String,
+    pub metadata: String,
+    pub signature: String,
+    pub stamp: RecipientStamp,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub attachments: Vec<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SendMessageToRecipientsCommandDto {
+    pub copies: Vec<MessageCopyDto>,
+    pub sender_copy: Option<SenderCopy>,
+}
+pub struct SendMessageToRecipientsCommand {
+    pub sender_id: Uuid,
+    pub copies: Vec<MessageCopyDto>,
+    pub sender_copy: Option<SenderCopy>,
+    /// The host of this server, which recipient addresses must name.
+    pub local_host: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DeliveredCopy {
+    pub recipient_id: Uuid,
+    pub recipient_key_fingerprint: String,
+    pub message_id: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RejectedCopy {
+    /// Position of the copy in the request.
+    pub index: usize,
+    /// Left out when the recipient could not be found.
+    pub recipient_id: Option<Uuid>,
+    pub error: String,
+}
+
+/// Either every copy was delivered, or none was and `rejected` says why.
+#[derive(Serialize, ToSchema)]
+pub struct FanOutReport {
+    pub delivered: Vec<DeliveredCopy>,
+    pub rejected: Vec<RejectedCopy>,
+    /// The sender's outbox entry, if a sender copy was given and the copies were delivered.
+    pub outbox_id: Option<i64>,
+}
+
+impl SendMessageToRecipientsCommand {
+    /// Validates every copy before delivering any. Problems with individual copies are
+    /// collected into the report instead of failing the command, so the sender learns about
+    /// all of them at once.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn handle(
+        self,
+        user_repository: &impl UserRepository,
+        cryptography_service: &impl CryptographyService,
+        serialize_service: &impl SerializeService,
+        tracker_repository: &impl OneTimeStampTrackerRepository,
+        system_key_repository: &impl SystemKeyRepository,
+        message_repository: &impl MessageRepository,
+        blob_repository: &impl BlobRepository,
+        block_list_repository: &impl BlockListRepository,
+        stamp_revocation_repository: &impl StampRevocationRepository,
+        mailbox_quota: &MailboxQuota,
+    ) -> Result<FanOutReport, SmError> {
+        if self.copies.is_empty() || self.copies.len() > MAX_FAN_OUT_RECIPIENTS {
+            return Err(ValidationError(format!(
+                "A message must have between 1 and {} recipients",
+                MAX_FAN_OUT_RECIPIENTS
+            ))
+            .into());
+        }
+        let sender = GetUserByIdQuery {
+            user_id: self.sender_id,
+        }
+        .handle(user_repository)
+        .await?
+        .ok_or(UserError::UserNotFound)?;
+        let outbox_entry = self
+            .sender_copy
+            .map(|sender_copy| sender_copy.into_outbox_entry(sender.id))
+            .transpose()?;
+
+        let mut recipient_ids = Vec::with_capacity(self.copies.len());
+        let mut messages = Vec::with_capacity(self.copies.len());
+        let mut rejected = Vec::new();
+        for (index, copy) in self.copies.into_iter().enumerate() {
+            let resolved = ResolveRecipientQuery {
+                recipient: copy.recipient.clone(),
+                local_host: self.local_host.clone(),
+            }
+            .handle(user_repository)
+            .await;
+            let recipient_id = match resolved {
+                Ok(recipient_id) => recipient_id,
+                Err(e @ SmError::Database(_)) => return Err(e),
+                Err(e) => {
+                    rejected.push(RejectedCopy {
+                        index,
+                        recipient_id: None,
+                        error: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+            let result = if recipient_ids.contains(&recipient_id) {
+                Err(ValidationError("Recipients must not repeat".to_string()).into())
+            } else {
+                validate_copy(
+                    copy.into_copy(recipient_id),
+                    sender.id,
+                    &sender.public_verify_key,
+                    user_repository,
+                    cryptography_service,
+                    serialize_service,
+                    tracker_repository,
+                    system_key_repository,
+                    message_repository,
+                    blob_repository,
+                    block_list_repository,
+                    stamp_revocation_repository,
+                    mailbox_quota,
+                )
+                .await
+            };
+            recipient_ids.push(recipient_id);
+            match result {
+                Ok(message) => messages.push(message),
+                // Failures of the server itself are not the fault of any one copy
+                Err(e @ (SmError::Database(_) | SmError::Storage(_))) => return Err(e),
+                Err(e) => rejected.push(RejectedCopy {
+                    index,
+                    recipient_id: Some(recipient_id),
+                    error: e.to_string(),
+                }),
+            }
+        }
+        if !rejected.is_empty() {
+            return Ok(FanOutReport {
+                delivered: Vec::new(),
+                rejected,
+                outbox_id: None,
+            });
+        }
+
+        let delivery = message_repository
+            .create_messages(messages, outbox_entry, mailbox_quota)
+            .await?;
+        let mut delivered = Vec::with_capacity(delivery.messages.len());
+        for message in delivery.messages {
+            delivered.push(DeliveredCopy {
+                recipient_id: message.recipient_id,
+                recipient_key_fingerprint: recipient_key_fingerprint(
+                    message.recipient_id,
+                    user_repository,
+                    cryptography_service,
+                )
+                .await?,
+                message_id: message.id,
+            });
+        }
+        Ok(FanOutReport {
+            delivered,
+            rejected,
+            outbox_id: delivery.outbox_id,
+        })
+    }
+}
+
+/// Blocked senders are turned away as though their stamp was invalid, so they can't tell
+/// they have been blocked.
+async fn ensure_not_blocked(
+    recipient_id: Uuid,
+    sender_id: Uuid,
+    block_list_repository: &impl BlockListRepository,
+) -> Result<(), SmError> {
+    if block_list_repository
+        .is_blocked(recipient_id, sender_id)
+        .await?
+    {
+        return Err(StampError::InvalidStamp.into());
+    }
+    Ok(())
+}
+
+/// Each stamp has to authorize this sender to write to this recipient, so that one valid
+/// stamp cannot carry the others.
+#[allow(clippy::too_many_arguments)]
+async fn verify_recipient_stamp(
+    stamp: RecipientStamp,
+    sender_id: Uuid,
+    recipient_id: Uuid,
+    user_repository: &impl UserRepository,
+    cryptography_service: &impl CryptographyService,
+    serialize_service: &impl SerializeService,
+    tracker_repository: &impl OneTimeStampTrackerRepository,
+    system_key_repository: &impl SystemKeyRepository,
+    stamp_revocation_repository: &impl StampRevocationRepository,
+) -> Result<bool, SmError> {
+    let valid = match stamp {
+        RecipientStamp::Periodic(stamp) => {
+            stamp.sender_id == sender_id
+                && stamp.recipient_id == recipient_id
+                && VerifyPeriodicStampCommand(stamp)
+                    .handle(
+                        user_repository,
+                        cryptography_service,
+                        serialize_service,
+                        stamp_revocation_repository,
+                    )
+                    .await?
+        }
+        RecipientStamp::Onetime(stamp) => {
+            stamp.sender_id == sender_id
+                && stamp.recipient_id == recipient_id
+                && VerifyOnetimeStampCommand(stamp)
+                    .handle(
+                        user_repository,
+                        cryptography_service,
+                        serialize_service,
+                        tracker_repository,
+                        system_key_repository,
+                    )
+                    .await?
+        }
+    };
+    Ok(valid)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn validate_copy(
+    copy: MessageCopy,
+    sender_id: Uuid,
+    sender_verify_key: &str,
+    user_repository: &impl UserRepository,
+    cryptography_service: &impl CryptographyService,
+    serialize_service: &impl SerializeService,
+    tracker_repository: &impl OneTimeStampTrackerRepository,
+    system_key_repository: &impl SystemKeyRepository,
+    message_repository: &impl MessageRepository,
+    blob_repository: &impl BlobRepository,
+    block_list_repository: &impl BlockListRepository,
+    stamp_revocation_repository: &impl StampRevocationRepository,
+    mailbox_quota: &MailboxQuota,
+) -> Result<NewMessage, SmError> {
+    validate_expiry(copy.expires_at)?;
+    let attachments_size =
+        validate_attachments(&copy.attachments, sender_id, blob_repository).await?;
+
+    ensure_not_blocked(copy.recipient_id, sender_id, block_list_repository).await?;
+    let onetime_stamp_id = match &copy.stamp {
+        RecipientStamp::Onetime(stamp) => Some(stamp.stamp_id),
+        RecipientStamp::Periodic(_) => None,
+    };
+    let stamp_valid = verify_recipient_stamp(
+        copy.stamp,
+        sender_id,
+        copy.recipient_id,
+        user_repository,
+        cryptography_service,
+        serialize_service,
+        tracker_repository,
+        system_key_repository,
+        stamp_revocation_repository,
+    )
+    .await?;
+    if !stamp_valid {
+        return Err(StampError::InvalidStamp.into());
+    }
+
+    let signature_valid = cryptography_service.validate_signature(
+        &message_signature_plaintext(
+            &copy.metadata,
+            &copy.content,
+            copy.expires_at,
+            &copy.attachments,
+            serialize_service,
+        ),
+        &copy.signature,
+        sender_verify_key,
+    );
+    if !signature_valid {
+        return Err(CryptographyError::InvalidSignature.into());
+    }
+
+    // Checked again on delivery; this is only to report which recipients are over quota. Only
+    // senders the recipient accepts mail from get to learn whether their mailbox is full.
+    let size = (copy.metadata.len() + copy.content.len()) as i64 + attachments_size;
+    let usage = message_repository
+        .get_mailbox_usage(copy.recipient_id)
+        .await?;
+    if !mailbox_quota.admits(&usage, 1, size) {
+        return Err(MessageError::MailboxFull.into());
+    }
+
+    Ok(NewMessage {
+        recipient_id: copy.recipient_id,
+        metadata: MessageMetadata(copy.metadata),
+        content: copy.content,
+        expires_at: copy.expires_at,
+        attachments: copy.attachments,
+        sender_seal: Some(
+            seal_sender(sender_id, system_key_repository, cryptography_service).await?,
+        ),
+        onetime_stamp_id,
+    })
+}
+
+const MAX_SCHEDULED_MESSAGES: i64 = 100;
+const MAX_SCHEDULE_AHEAD_DAYS: i64 = 365;
+/// Deliveries tried before a message is given up on, the last about four hours after the first.
+const MAX_DISPATCH_ATTEMPTS: i32 = 9;
+
+#[derive(Deserialize, ToSchema)]
+pub struct ScheduleMessageCommandDto {
+    #[serde(flatten)]
+    pub copy: MessageCopyDto,
+    pub deliver_at: DateTime<Utc>,
+    /// Verify the stamp once more on delivery, so that a stamp revoked in the meantime keeps
+    /// the message from being delivered.
+    #[serde(default)]
+    pub recheck_stamp: bool,
+    pub sender_copy: Option<SenderCopy>,
+}
+pub struct ScheduleMessageCommand {
+    pub sender_id: Uuid,
+    pub copy: MessageCopy,
+    pub deliver_at: DateTime<Utc>,
+    pub recheck_stamp: bool,
+    pub sender_copy: Option<SenderCopy>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ScheduleReceipt {
+    pub scheduled_id: i64,
+    pub recipient_id: Uuid,
+    pub recipient_key_fingerprint: String,
+    pub deliver_at: DateTime<Utc>,
+}
+
+impl ScheduleMessageCommand {
+    /// Validates the message as though it was sent right away, and keeps it back until
+    /// `deliver_at`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn handle(
+        self,
+        user_repository: &impl UserRepository,
+        cryptography_service: &impl CryptographyService,
+        serialize_service: &impl SerializeService,
+        tracker_repository: &impl OneTimeStampTrackerRepository,
+        system_key_repository: &impl SystemKeyRepository,
+        message_repository: &impl MessageRepository,
+        blob_repository: &impl BlobRepository,
+        block_list_repository: &impl BlockListRepository,
+        stamp_revocation_repository: &impl StampRevocationRepository,
+        scheduled_message_repository: &impl ScheduledMessageRepository,
+        mailbox_quota: &MailboxQuota,
+    ) -> Result<ScheduleReceipt, SmError> {
+        let now = Utc::now();
+        if self.deliver_at <= now
+            || self.deliver_at > now + chrono::Duration::days(MAX_SCHEDULE_AHEAD_DAYS)
+        {
+            return Err(ValidationError(format!(
+                "Delivery time must lie in the future, at most {} days ahead",
+                MAX_SCHEDULE_AHEAD_DAYS
+            ))
+            .into());
+        }
+        if self
+            .copy
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= self.deliver_at)
+        {
+            return Err(ValidationError(
+                "Message expiry must lie after the delivery time".to_string(),
+            )
+            .into());
+        }
+        let scheduled = scheduled_message_repository
+            .count_scheduled_messages(self.sender_id)
+            .await?;
+        if scheduled >= MAX_SCHEDULED_MESSAGES {
+            return Err(ValidationError(format!(
+                "A sender can have at most {} scheduled messages",
+                MAX_SCHEDULED_MESSAGES
+            ))
+            .into());
+        }
+
+        let sender = GetUserByIdQuery {
+            user_id: self.sender_id,
+        }
+        .handle(user_repository)
+        .await?
+        .ok_or(UserError::UserNotFound)?;
+        let outbox_entry = self
+            .sender_copy
+            .map(|sender_copy| sender_copy.into_outbox_entry(sender.id))
+            .transpose()?;
+        let stamp = self.recheck_stamp.then(|| self.copy.stamp.clone());
+        let recipient_id = self.copy.recipient_id;
+
+        let message = validate_copy(
+            self.copy,
+            sender.id,
+            &sender.public_verify_key,
+            user_repository,
+            cryptography_service,
+            serialize_service,
+            tracker_repository,
+            system_key_repository,
+            message_repository,
+            blob_repository,
+            block_list_repository,
+            stamp_revocation_repository,
+            mailbox_quota,
+        )
+        .await?;
+
+        let scheduled_id = scheduled_message_repository
+            .schedule_message(NewScheduledMessage {
+                sender_id: sender.id,
+                message,
+                outbox_entry,
+                stamp,
+                deliver_at: self.deliver_at,
+            })
+            .await?;
+        Ok(ScheduleReceipt {
+            scheduled_id,
+            recipient_id,
+            recipient_key_fingerprint: recipient_key_fingerprint(
+                recipient_id,
+                user_repository,
+                cryptography_service,
+            )
+            .await?,
+            deliver_at: self.deliver_at,
+        })
+    }
+}
+
+/// Cancels a message that hasn't been delivered yet, or discards one whose delivery failed.
+pub struct CancelScheduledMessageCommand {
+    pub sender_id: Uuid,
+    pub scheduled_id: i64,
+}
+
+impl CancelScheduledMessageCommand {
+    pub async fn handle(
+        self,
+        scheduled_message_repository: &impl ScheduledMessageRepository,
+    ) -> Result<(), SmError> {
+        if !scheduled_message_repository
+            .cancel_scheduled_message(self.sender_id, self.scheduled_id)
+            .await?
+        {
+            return Err(MessageError::ScheduledMessageNotFound.into());
+        }
         Ok(())
     }
 }
+
+/// Whether a scheduled message turned away with `error` may yet be delivered later. Stamps
+/// don't become valid again, and recipients who are gone don't come back.
+fn is_transient(error: &SmError) -> bool {
+    !matches!(
+        error,
+        SmError::Stamp(_)
+            | SmError::User(UserError::UserNotFound)
+            | SmError::Database(DatabaseError::ForeignKeyViolation { .. })
+    )
+}
+
+pub struct DispatchScheduledMessagesCommand {
+    pub batch_size: i64,
+}
+
+impl DispatchScheduledMessagesCommand {
+    /// Delivers up to a batch of due messages, returning how many were delivered. Messages that
+    /// can never be delivered, with a stamp that no longer holds or to a recipient who is gone,
+    /// are marked as failed. Those turned away for reasons that may pass, such as a full
+    /// mailbox, are retried with growing delays before they are given up on.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn handle(
+        self,
+        user_repository: &impl UserRepository,
+        cryptography_service: &impl CryptographyService,
+        serialize_service: &impl SerializeService,
+        tracker_repository: &impl OneTimeStampTrackerRepository,
+        system_key_repository: &impl SystemKeyRepository,
+        block_list_repository: &impl BlockListRepository,
+        stamp_revocation_repository: &impl StampRevocationRepository,
+        scheduled_message_repository: &impl ScheduledMessageRepository,
+        mailbox_quota: &MailboxQuota,
+    ) -> Result<usize, SmError> {
+        let due = scheduled_message_repository
+            .list_due_messages(self.batch_size)
+            .await?;
+        let mut delivered = 0;
+        for message in due {
+            let result = async {
+                // Senders blocked after scheduling are turned away all the same
+                ensure_not_blocked(
+                    message.recipient_id,
+                    message.sender_id,
+                    block_list_repository,
+                )
+                .await?;
+                if let Some(stamp) = message.stamp {
+                    let stamp_valid = verify_recipient_stamp(
+                        stamp,
+                        message.sender_id,
+                        message.recipient_id,
+                        user_repository,
+                        cryptography_service,
+                        serialize_service,
+                        tracker_repository,
+                        system_key_repository,
+                        stamp_revocation_repository,
+                    )
+                    .await?;
+                    if !stamp_valid {
+                        return Err(StampError::InvalidStamp.into());
+                    }
+                }
+                scheduled_message_repository
+                    .deliver_scheduled_message(message.id, mailbox_quota)
+                    .await
+            }
+            .await;
+            match result {
+                Ok(Some(_)) => delivered += 1,
+                Ok(None) => {}
+                Err(e) if is_transient(&e) && message.attempts + 1 < MAX_DISPATCH_ATTEMPTS => {
+                    let retry_at =
+                        chrono::Utc::now() + chrono::Duration::minutes(1 << message.attempts);
+                    scheduled_message_repository
+                        .defer_scheduled_message(message.id, retry_at)
+                        .await?
+                }
+                Err(e) => {
+                    scheduled_message_repository
+                        .fail_scheduled_message(message.id, &e.to_string())
+                        .await?
+                }
+            }
+        }
+        Ok(delivered)
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateRecipientMetadataCommandDto {
+    pub recipient_metadata: Option<String>,
+    pub expected_version: i64,
+}
+pub struct UpdateRecipientMetadataCommand {
+    pub recipient_id: Uuid,
+    pub message_id: i64,
+    pub recipient_metadata: Option<String>,
+    pub expected_version: i64,
+}
+
+impl UpdateRecipientMetadataCommand {
+    /// Returns the new version of the recipient metadata.
+    pub async fn handle(self, message_repository: &impl MessageRepository) -> Result<i64, SmError> {
+        if let Some(recipient_metadata) = &self.recipient_metadata {
+            if !is_base64(recipient_metadata) {
+                return Err(ValidationError(
+                    "Recipient metadata must be base64 encoded".to_string(),
+                )
+                .into());
+            }
+        }
+        message_repository
+            .update_recipient_metadata(
+                self.recipient_id,
+                self.message_id,
+                self.recipient_metadata,
+                self.expected_version,
+            )
+            .await
+    }
+}
+
+const MAX_BULK_MESSAGE_IDS: usize = 500;
+
+fn validate_message_ids(message_ids: &[i64]) -> Result<(), ValidationError> {
+    if message_ids.is_empty() || message_ids.len() > MAX_BULK_MESSAGE_IDS {
+        return Err(ValidationError(format!(
+            "Between 1 and {} message ids must be given",
+            MAX_BULK_MESSAGE_IDS
+        )));
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeleteMessageCommandDto {
+    #[serde(default)]
+    pub permanent: bool,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct DeleteMessagesCommandDto {
+    pub message_ids: Vec<i64>,
+    #[serde(default)]
+    pub permanent: bool,
+}
+pub struct DeleteMessagesCommand {
+    pub recipient_id: Uuid,
+    pub message_ids: Vec<i64>,
+    pub permanent: bool,
+}
+
+impl DeleteMessagesCommand {
+    /// Returns the ids of the recipient's messages that were actually deleted or trashed.
+    pub async fn handle(
+        self,
+        message_repository: &impl MessageRepository,
+    ) -> Result<Vec<i64>, SmError> {
+        validate_message_ids(&self.message_ids)?;
+        if self.permanent {
+            message_repository
+                .delete_messages(self.recipient_id, &self.message_ids)
+                .await
+        } else {
+            message_repository
+                .trash_messages(self.recipient_id, &self.message_ids)
+                .await
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RestoreMessagesCommandDto {
+    pub message_ids: Vec<i64>,
+}
+pub struct RestoreMessagesCommand {
+    pub recipient_id: Uuid,
+    pub message_ids: Vec<i64>,
+}
+
+impl RestoreMessagesCommand {
+    /// Returns the ids of the recipient's messages that were taken out of the trash.
+    pub async fn handle(
+        self,
+        message_repository: &impl MessageRepository,
+    ) -> Result<Vec<i64>, SmError> {
+        validate_message_ids(&self.message_ids)?;
+        message_repository
+            .restore_messages(self.recipient_id, &self.message_ids)
+            .await
+    }
+}
+
+pub struct PurgeTrashCommand {
+    pub retention: chrono::Duration,
+}
+
+impl PurgeTrashCommand {
+    pub async fn handle(self, message_repository: &impl MessageRepository) -> Result<u64, SmError> {
+        message_repository
+            .purge_trash(chrono::Utc::now() - self.retention)
+            .await
+    }
+}
+
+pub struct PruneMessageChangesCommand {
+    pub retention: chrono::Duration,
+}
+
+impl PruneMessageChangesCommand {
+    pub async fn handle(
+        self,
+        change_repository: &impl MessageChangeRepository,
+    ) -> Result<u64, SmError> {
+        change_repository
+            .prune_changes(chrono::Utc::now() - self.retention)
+            .await
+    }
+}
+
+pub struct PurgeExpiredMessagesCommand;
+
+impl PurgeExpiredMessagesCommand {
+    pub async fn handle(self, message_repository: &impl MessageRepository) -> Result<u64, SmError> {
+        message_repository.purge_expired().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_base64;
+
+    #[test]
+    fn is_base64_only_accepts_padded_standard_base64() {
+        assert!(is_base64("aGVsbG8="));
+        assert!(is_base64("aGk+Lw=="));
+        assert!(!is_base64(""));
+        assert!(!is_base64("aGVs=bG8"));
+        assert!(!is_base64("aGVsbG8"));
+        assert!(!is_base64("aGk-_w=="));
+    }
+}
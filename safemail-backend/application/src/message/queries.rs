@@ -1,22 +1,124 @@
 use domain::error::SmError;
-use domain::message::{Message, MessageMetadata, MessageRepository};
+use domain::message::{MailboxQuota, Message, MessageRepository, MessageSummary};
+use domain::message_change::{MessageChange, MessageChangeRepository};
+use domain::scheduled_message::{ScheduledMessageRepository, ScheduledMessageSummary};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
+pub(crate) const DEFAULT_PAGE_SIZE: i64 = 50;
+pub(crate) const MAX_PAGE_SIZE: i64 = 200;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GetAllMessagesForUserQueryDto {
+    #[serde(default)]
+    pub trash: bool,
+    pub after: Option<i64>,
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
 pub struct GetAllMessagesForUserQuery {
     pub recipient_id: Uuid,
+    pub trash: bool,
+    pub after: Option<i64>,
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MessagePage {
+    pub messages: Vec<MessageSummary>,
+    /// Total number of messages in the mailbox or trash, regardless of the cursors.
+    pub total: i64,
+    /// Whether more messages exist past this page in the direction being paged.
+    pub has_more: bool,
 }
 
 impl GetAllMessagesForUserQuery {
     pub async fn handle(
         &self,
         message_repository: &impl MessageRepository,
-    ) -> Result<Vec<(i64, MessageMetadata)>, SmError> {
+    ) -> Result<MessagePage, SmError> {
+        let limit = self
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+
+        // Fetch one extra message to find out whether another page follows
+        let mut messages = message_repository
+            .list_messages(
+                self.recipient_id,
+                self.trash,
+                self.after,
+                self.before,
+                limit + 1,
+            )
+            .await?;
+        let has_more = messages.len() as i64 > limit;
+        if has_more {
+            if self.after.is_none() && self.before.is_some() {
+                messages.remove(0);
+            } else {
+                messages.pop();
+            }
+        }
+
+        let total = message_repository
+            .count_messages(self.recipient_id, self.trash)
+            .await?;
+
+        Ok(MessagePage {
+            messages,
+            total,
+            has_more,
+        })
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GetNewMessagesQueryDto {
+    pub last_seen_id: Option<i64>,
+}
+pub struct GetNewMessagesQuery {
+    pub recipient_id: Uuid,
+    pub after_id: i64,
+}
+
+impl GetNewMessagesQuery {
+    pub async fn handle(
+        &self,
+        message_repository: &impl MessageRepository,
+    ) -> Result<Vec<MessageSummary>, SmError> {
         message_repository
-            .list_messages(self.recipient_id, None)
+            .list_messages(
+                self.recipient_id,
+                false,
+                Some(self.after_id),
+                None,
+                MAX_PAGE_SIZE,
+            )
             .await
     }
 }
 
+pub struct GetLatestMessageIdQuery {
+    pub recipient_id: Uuid,
+}
+
+impl GetLatestMessageIdQuery {
+    pub async fn handle(
+        &self,
+        message_repository: &impl MessageRepository,
+    ) -> Result<Option<i64>, SmError> {
+        let latest = message_repository
+            .list_messages(self.recipient_id, false, None, Some(i64::MAX), 1)
+            .await?;
+        Ok(latest.last().map(|message| message.id))
+    }
+}
+
 pub struct GetMessageByIdQuery {
     pub recipient_id: Uuid,
     pub message_id: i64,
@@ -32,3 +134,108 @@ impl GetMessageByIdQuery {
             .await
     }
 }
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GetMessageChangesQueryDto {
+    pub since: i64,
+    pub limit: Option<i64>,
+}
+pub struct GetMessageChangesQuery {
+    pub recipient_id: Uuid,
+    pub since: i64,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MessageChangeFeed {
+    pub changes: Vec<MessageChange>,
+    /// The sequence number to pass as `since` on the next sync.
+    pub latest_seq: i64,
+    pub has_more: bool,
+    /// Set when changes past `since` have already been pruned. The device has to drop its
+    /// local state and list the mailbox from scratch, then sync from `latest_seq`.
+    pub resync_required: bool,
+}
+
+impl GetMessageChangesQuery {
+    pub async fn handle(
+        &self,
+        change_repository: &impl MessageChangeRepository,
+    ) -> Result<MessageChangeFeed, SmError> {
+        let limit = self
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+
+        let mut changes = change_repository
+            .list_changes(self.recipient_id, self.since, limit + 1)
+            .await?;
+
+        // Read the horizon only after listing, so that a prune racing with the listing is
+        // noticed rather than leaving a silent gap in the feed
+        let horizon = change_repository.get_horizon(self.recipient_id).await?;
+        if self.since < horizon.pruned_through_seq {
+            return Ok(MessageChangeFeed {
+                changes: Vec::new(),
+                latest_seq: horizon.last_seq,
+                has_more: false,
+                resync_required: true,
+            });
+        }
+
+        let has_more = changes.len() as i64 > limit;
+        changes.truncate(limit as usize);
+        let latest_seq = changes.last().map_or(self.since, |change| change.seq);
+
+        Ok(MessageChangeFeed {
+            changes,
+            latest_seq,
+            has_more,
+            resync_required: false,
+        })
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MailboxUsageReport {
+    pub message_count: i64,
+    pub stored_bytes: i64,
+    pub quota: MailboxQuota,
+}
+
+pub struct GetMailboxUsageQuery {
+    pub recipient_id: Uuid,
+}
+
+impl GetMailboxUsageQuery {
+    pub async fn handle(
+        &self,
+        message_repository: &impl MessageRepository,
+        mailbox_quota: &MailboxQuota,
+    ) -> Result<MailboxUsageReport, SmError> {
+        let usage = message_repository
+            .get_mailbox_usage(self.recipient_id)
+            .await?;
+        Ok(MailboxUsageReport {
+            message_count: usage.message_count,
+            stored_bytes: usage.stored_bytes,
+            quota: *mailbox_quota,
+        })
+    }
+}
+
+pub struct GetScheduledMessagesQuery {
+    pub sender_id: Uuid,
+}
+
+impl GetScheduledMessagesQuery {
+    pub async fn handle(
+        &self,
+        scheduled_message_repository: &impl ScheduledMessageRepository,
+    ) -> Result<Vec<ScheduledMessageSummary>, SmError> {
+        scheduled_message_repository
+            .list_scheduled_messages(self.sender_id)
+            .await
+    }
+}
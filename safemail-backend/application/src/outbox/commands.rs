@@ -0,0 +1,21 @@
+use domain::error::{MessageError, SmError};
+use domain::outbox::OutboxRepository;
+use uuid::Uuid;
+
+/// Deletes the sender's copy only; the delivered messages are left in the recipients' mailboxes.
+pub struct DeleteOutboxEntryCommand {
+    pub sender_id: Uuid,
+    pub id: i64,
+}
+
+impl DeleteOutboxEntryCommand {
+    pub async fn handle(self, outbox_repository: &impl OutboxRepository) -> Result<(), SmError> {
+        if !outbox_repository
+            .delete_entry(self.sender_id, self.id)
+            .await?
+        {
+            return Err(MessageError::OutboxEntryNotFound.into());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use domain::error::{MessageError, SmError};
+use domain::outbox::{OutboxEntry, OutboxRepository, OutboxSummary};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::message::queries::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GetOutboxQueryDto {
+    pub after: Option<i64>,
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
+pub struct GetOutboxQuery {
+    pub sender_id: Uuid,
+    pub after: Option<i64>,
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OutboxPage {
+    pub entries: Vec<OutboxSummary>,
+    /// Total number of entries in the outbox, regardless of the cursors.
+    pub total: i64,
+    /// Whether more entries exist past this page in the direction being paged.
+    pub has_more: bool,
+}
+
+impl GetOutboxQuery {
+    pub async fn handle(
+        &self,
+        outbox_repository: &impl OutboxRepository,
+    ) -> Result<OutboxPage, SmError> {
+        let limit = self
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+
+        // Fetch one extra entry to find out whether another page follows
+        let mut entries = outbox_repository
+            .list_entries(self.sender_id, self.after, self.before, limit + 1)
+            .await?;
+        let has_more = entries.len() as i64 > limit;
+        if has_more {
+            if self.after.is_none() && self.before.is_some() {
+                entries.remove(0);
+            } else {
+                entries.pop();
+            }
+        }
+
+        let total = outbox_repository.count_entries(self.sender_id).await?;
+
+        Ok(OutboxPage {
+            entries,
+            total,
+            has_more,
+        })
+    }
+}
+
+pub struct GetOutboxEntryQuery {
+    pub sender_id: Uuid,
+    pub id: i64,
+}
+
+impl GetOutboxEntryQuery {
+    pub async fn handle(
+        &self,
+        outbox_repository: &impl OutboxRepository,
+    ) -> Result<OutboxEntry, SmError> {
+        outbox_repository
+            .get_entry(self.sender_id, self.id)
+            .await?
+            .ok_or_else(|| MessageError::OutboxEntryNotFound.into())
+    }
+}
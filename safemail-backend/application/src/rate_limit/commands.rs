@@ -0,0 +1,52 @@
+use domain::{
+    chrono,
+    error::{RateLimitError, SmError},
+    rate_limit::{RateLimitKey, RateLimitPolicy, RateLimiter, RouteClass},
+};
+
+/// Counts a request against each of its keys' budgets for its route class.
+pub struct ConsumeRateLimitCommand {
+    pub class: RouteClass,
+    pub keys: Vec<RateLimitKey>,
+}
+
+impl ConsumeRateLimitCommand {
+    /// Fails with the longest wait among the budgets the request exceeds. It is counted against
+    /// all of them either way, so that retrying too early doesn't come for free.
+    pub async fn handle(
+        self,
+        rate_limiter: &impl RateLimiter,
+        rate_limit_policy: &RateLimitPolicy,
+    ) -> Result<(), SmError> {
+        let now = chrono::Utc::now();
+        let budget = rate_limit_policy.budget(self.class);
+        let mut retry_after_seconds = None;
+        for key in &self.keys {
+            let Some(limit) = budget.limit(key) else {
+                continue;
+            };
+            let window_end = limit.window_end(now);
+            let hits = rate_limiter.hit(&key.name(self.class), window_end).await?;
+            if hits > limit.requests {
+                let wait = (window_end - now).num_seconds().max(1);
+                retry_after_seconds = retry_after_seconds.max(Some(wait));
+            }
+        }
+        match retry_after_seconds {
+            Some(retry_after_seconds) => Err(RateLimitError::Exceeded {
+                retry_after_seconds,
+            }
+            .into()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Forgets the counts of windows that have ended.
+pub struct PurgeRateLimitsCommand;
+
+impl PurgeRateLimitsCommand {
+    pub async fn handle(self, rate_limiter: &impl RateLimiter) -> Result<u64, SmError> {
+        rate_limiter.purge(chrono::Utc::now()).await
+    }
+}
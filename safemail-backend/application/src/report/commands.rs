@@ -0,0 +1,90 @@
+use domain::{
+    crypto::CryptographyService,
+    error::{CryptographyError, MessageError, SmError, ValidationError},
+    message::MessageRepository,
+    report::{NewReport, ReportPolicy, ReportRepository},
+    stamp_revocation::StampRevocationRepository,
+    system_key::SystemKeyRepository,
+};
+use serde::Deserialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+const MAX_REASON_LENGTH: usize = 1000;
+
+#[derive(Deserialize, ToSchema)]
+pub struct ReportMessageCommandDto {
+    pub reason: Option<String>,
+}
+pub struct ReportMessageCommand {
+    pub reporter_id: Uuid,
+    pub message_id: i64,
+    pub reason: Option<String>,
+}
+
+impl ReportMessageCommand {
+    /// Unseals the sender of one of the reporter's messages and records the report, applying
+    /// the policy's consequences to the sender. Reporting a message again changes nothing.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn handle(
+        self,
+        message_repository: &impl MessageRepository,
+        system_key_repository: &impl SystemKeyRepository,
+        cryptography_service: &impl CryptographyService,
+        report_repository: &impl ReportRepository,
+        stamp_revocation_repository: &impl StampRevocationRepository,
+        report_policy: &ReportPolicy,
+    ) -> Result<(), SmError> {
+        if self
+            .reason
+            .as_ref()
+            .is_some_and(|reason| reason.chars().count() > MAX_REASON_LENGTH)
+        {
+            return Err(ValidationError(format!(
+                "A report reason can be at most {} characters long",
+                MAX_REASON_LENGTH
+            ))
+            .into());
+        }
+
+        let seal = message_repository
+            .get_sender_seal(self.reporter_id, self.message_id)
+            .await?
+            .ok_or(MessageError::SenderNotAttributable)?;
+        let system_keys = system_key_repository
+            .get_system_keys()
+            .await?
+            .ok_or(CryptographyError::InvalidSystemKeys)?;
+        let sender_id = cryptography_service
+            .unseal(&seal, &system_keys.private_key)
+            .ok()
+            .and_then(|sender_id| Uuid::from_slice(&sender_id).ok())
+            .ok_or(MessageError::SenderNotAttributable)?;
+
+        let report_id = report_repository
+            .create_report(NewReport {
+                reporter_id: self.reporter_id,
+                message_id: self.message_id,
+                sender_id,
+                reason: self.reason,
+            })
+            .await?;
+        if report_id.is_none() {
+            return Ok(());
+        }
+
+        if report_policy.revoke_stamps {
+            stamp_revocation_repository
+                .revoke_stamps(self.reporter_id, sender_id)
+                .await?;
+        }
+        if report_policy.review_threshold > 0 {
+            let reports = report_repository.count_reports_against(sender_id).await?;
+            if reports >= report_policy.review_threshold {
+                report_repository.flag_for_review(sender_id).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,48 @@
+use domain::error::SmError;
+use domain::report::{Report, ReportRepository};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::message::queries::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GetReportsQueryDto {
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
+pub struct GetReportsQuery {
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReportPage {
+    /// Newest first.
+    pub reports: Vec<Report>,
+    /// Whether older reports exist past this page.
+    pub has_more: bool,
+}
+
+impl GetReportsQuery {
+    pub async fn handle(
+        &self,
+        report_repository: &impl ReportRepository,
+    ) -> Result<ReportPage, SmError> {
+        let limit = self
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+
+        // Fetch one extra report to find out whether another page follows
+        let mut reports = report_repository
+            .list_reports(self.before, limit + 1)
+            .await?;
+        let has_more = reports.len() as i64 > limit;
+        if has_more {
+            reports.pop();
+        }
+
+        Ok(ReportPage { reports, has_more })
+    }
+}
@@ -1,66 +1,129 @@
 use domain::{
+    block_list::BlockListRepository,
     chrono,
     crypto::CryptographyService,
-    error::{SmError, StampError, UserError},
+    error::{CryptographyError, SmError, StampError, UserError},
     onetime_stamp::OneTimeStampTrackerRepository,
     pow::Pow,
+    report::{ReportPolicy, ReportRepository},
     serialize::SerializeService,
-    stamp::{OnetimeStamp, PeriodicStamp},
+    stamp::{OneTimeStampRequest, OnetimeStamp, PeriodicStamp, StampPolicy},
     stamp_request::StampRequestRepository,
+    stamp_revocation::StampRevocationRepository,
     system_key::SystemKeyRepository,
     user::UserRepository,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::{uuid, Uuid};
 
-use crate::user::queries::GetUserByIdQuery;
+use crate::{
+    message::commands::recipient_key_fingerprint,
+    user::queries::{GetUserByIdQuery, RecipientRef},
+};
+
+pub(crate) const STAMP_SYSTEM_ISSUED: Uuid = uuid!("00000000-0000-0000-0000-000000000000");
+
+/// The lowest proof of work score accepted at the given difficulty, so that the expected
+/// number of hashes grows linearly with it.
+pub fn pow_threshold(difficulty: i64) -> u128 {
+    u128::MAX - u128::MAX / difficulty.max(1) as u128
+}
+
+/// How the score of a proof of work is held against the difficulty of its stamp request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DifficultyRule {
+    /// The score only has to reach the difficulty itself, as v1 of the API has always done.
+    /// Nearly every proof does, so this only holds for requests at the base difficulty; raised
+    /// ones are held to [`DifficultyRule::ExpectedHashes`] all the same.
+    Score,
+    /// The score has to reach [`pow_threshold`], so that the difficulty is the expected number
+    /// of hashes. Used from v2 of the API on.
+    ExpectedHashes,
+}
+
+impl DifficultyRule {
+    pub fn threshold(self, difficulty: i64, base_difficulty: i64) -> u128 {
+        match self {
+            DifficultyRule::Score if difficulty <= base_difficulty => difficulty.max(0) as u128,
+            _ => pow_threshold(difficulty),
+        }
+    }
+}
 
-const STAMP_SYSTEM_ISSUED: Uuid = uuid!("00000000-0000-0000-0000-000000000000");
-const BASE_STAMP_DIFFICULTY: i64 = 50_000;
+/// The text the issuer of a periodic stamp signs.
+pub(crate) fn periodic_stamp_plaintext(
+    stamp: &PeriodicStamp,
+    serialize_service: &impl SerializeService,
+) -> String {
+    let issuer_id = serialize_service.serialize(&stamp.issuer_id);
+    let recipient_id = serialize_service.serialize(&stamp.recipient_id);
+    let sender_id = serialize_service.serialize(&stamp.sender_id);
+    let valid_from = serialize_service.serialize(&stamp.valid_from);
+    let valid_to = serialize_service.serialize(&stamp.valid_to);
+    format!(
+        "{}\n{}\n{}\n{}\n{}",
+        issuer_id, recipient_id, sender_id, valid_from, valid_to
+    )
+}
+
+/// The text the issuer of a one-time stamp signs.
+pub(crate) fn onetime_stamp_plaintext(
+    stamp: &OnetimeStamp,
+    serialize_service: &impl SerializeService,
+) -> String {
+    let stamp_id = serialize_service.serialize(&stamp.stamp_id);
+    let issuer_id = serialize_service.serialize(&stamp.issuer_id);
+    let recipient_id = serialize_service.serialize(&stamp.recipient_id);
+    let sender_id = serialize_service.serialize(&stamp.sender_id);
+    let valid_to = serialize_service.serialize(&stamp.valid_to);
+    format!(
+        "{}\n{}\n{}\n{}\n{}",
+        stamp_id, issuer_id, recipient_id, sender_id, valid_to
+    )
+}
 
 pub struct VerifyPeriodicStampCommand(pub PeriodicStamp);
 impl VerifyPeriodicStampCommand {
+    /// Stamps valid from before the recipient last revoked the sender's stamps are refused.
     pub async fn handle(
         self,
         user_repository: &impl UserRepository,
         cryptography_service: &impl CryptographyService,
         serialize_service: &impl SerializeService,
+        stamp_revocation_repository: &impl StampRevocationRepository,
     ) -> Result<bool, SmError> {
         let stamp = self.0;
 
         let issuer = GetUserByIdQuery {
-            user_id: stamp.issuer_id.clone(),
+            user_id: stamp.issuer_id,
         }
         .handle(user_repository)
         .await?
         .ok_or(StampError::InvalidStamp)?;
 
         let recipient = GetUserByIdQuery {
-            user_id: stamp.recipient_id.clone(),
+            user_id: stamp.recipient_id,
         }
         .handle(user_repository)
         .await?
         .ok_or(StampError::InvalidStamp)?;
 
-        let signature_plaintext = {
-            let issuer_id = serialize_service.serialize(&issuer.id);
-            let recipient_id = serialize_service.serialize(&recipient.id);
-            let sender_id = serialize_service.serialize(&stamp.sender_id);
-            let valid_from = serialize_service.serialize(&stamp.valid_from);
-            let valid_to = serialize_service.serialize(&stamp.valid_to);
-            format!(
-                "{}\n{}\n{}\n{}\n{}",
-                issuer_id, recipient_id, sender_id, valid_from, valid_to
-            )
-        };
-
         let validation = cryptography_service.validate_signature(
-            &signature_plaintext,
+            &periodic_stamp_plaintext(&stamp, serialize_service),
             &stamp.signature,
             &issuer.public_verify_key,
         ) && (issuer.id == recipient.id || issuer.id == STAMP_SYSTEM_ISSUED);
+        if !validation {
+            return Ok(false);
+        }
 
-        Ok(validation)
+        let revoked = stamp_revocation_repository
+            .get_periodic_revocation(recipient.id, stamp.sender_id)
+            .await?
+            .is_some_and(|revoked_at| stamp.valid_from <= revoked_at);
+
+        Ok(!revoked)
     }
 }
 pub struct VerifyOnetimeStampCommand(pub OnetimeStamp);
@@ -85,18 +148,19 @@ impl VerifyOnetimeStampCommand {
 
         // Verify issuer and recipient exist
         let issuer_key = if stamp.issuer_id == STAMP_SYSTEM_ISSUED {
-            let system_keys = system_key_repository.get_system_keys().await?.unwrap();
+            let system_keys = system_key_repository
+                .get_system_keys()
+                .await?
+                .ok_or(CryptographyError::InvalidSystemKeys)?;
             system_keys.public_key
         } else {
-            let issuer = GetUserByIdQuery {
+            GetUserByIdQuery {
                 user_id: stamp.issuer_id,
             }
             .handle(user_repository)
-            .await?;
-            if issuer.is_none() {
-                return Err(UserError::UserNotFound)?;
-            }
-            issuer.unwrap().public_verify_key
+            .await?
+            .ok_or(UserError::UserNotFound)?
+            .public_verify_key
         };
 
         let recipient = GetUserByIdQuery {
@@ -113,22 +177,9 @@ impl VerifyOnetimeStampCommand {
             }
         }
 
-        // Prepare signature plaintext
-        let signature_plaintext = {
-            let stamp_id = serialize_service.serialize(&stamp.stamp_id);
-            let issuer_id = serialize_service.serialize(&stamp.issuer_id);
-            let recipient_id = serialize_service.serialize(&stamp.recipient_id);
-            let sender_id = serialize_service.serialize(&stamp.sender_id);
-            let valid_to = serialize_service.serialize(&stamp.valid_to);
-            format!(
-                "{}\n{}\n{}\n{}\n{}",
-                stamp_id, issuer_id, recipient_id, sender_id, valid_to
-            )
-        };
-
         // Validate signature
         let validation = cryptography_service.validate_signature(
-            &signature_plaintext,
+            &onetime_stamp_plaintext(&stamp, serialize_service),
             &stamp.signature,
             &issuer_key,
         ) && (stamp.issuer_id == recipient.id
@@ -137,17 +188,41 @@ impl VerifyOnetimeStampCommand {
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
+pub struct RequestSystemStampIssueCommandDto {
+    #[serde(flatten)]
+    pub recipient: RecipientRef,
+}
 pub struct RequestSystemStampIssueCommand {
     pub recipient_id: Uuid,
     pub sender_id: Uuid,
 }
+
+/// A stamp request along with whom it is for.
+#[derive(Serialize, ToSchema)]
+pub struct StampRequestReceipt {
+    #[serde(flatten)]
+    pub request: OneTimeStampRequest,
+    pub recipient_id: Uuid,
+    pub recipient_key_fingerprint: String,
+}
 impl RequestSystemStampIssueCommand {
+    /// Senders the recipient has blocked are handed a request id that was never stored, so
+    /// they only find out once the solved request is turned down like any unknown one.
+    ///
+    /// The difficulty is multiplied by the report policy's factor for every user who reported
+    /// the sender, so that one recipient can't price a sender out on their own.
+    #[allow(clippy::too_many_arguments)]
     pub async fn handle(
         self,
         user_repository: &impl UserRepository,
         stamp_request_repository: &impl StampRequestRepository,
-    ) -> Result<Uuid, SmError> {
+        block_list_repository: &impl BlockListRepository,
+        report_repository: &impl ReportRepository,
+        cryptography_service: &impl CryptographyService,
+        report_policy: &ReportPolicy,
+        stamp_policy: &StampPolicy,
+    ) -> Result<StampRequestReceipt, SmError> {
         // ensure sender and recipient exist
         let recipient = GetUserByIdQuery {
             user_id: self.recipient_id,
@@ -162,17 +237,57 @@ impl RequestSystemStampIssueCommand {
         .await?
         .ok_or(SmError::from(UserError::UserNotFound))?;
 
+        let reports = report_repository
+            .count_reports_against(self.sender_id)
+            .await?;
+        let difficulty = stamp_policy.difficulty(reports, report_policy.difficulty_factor);
+        let valid_to =
+            chrono::Utc::now() + chrono::Duration::minutes(stamp_policy.request_validity_minutes);
+
+        let recipient_key_fingerprint =
+            recipient_key_fingerprint(recipient.id, user_repository, cryptography_service).await?;
+
+        if block_list_repository
+            .is_blocked(recipient.id, self.sender_id)
+            .await?
+        {
+            return Ok(StampRequestReceipt {
+                request: OneTimeStampRequest {
+                    stamp_request_id: Uuid::new_v4(),
+                    difficulty,
+                    valid_to,
+                    solved_at: None,
+                },
+                recipient_id: recipient.id,
+                recipient_key_fingerprint,
+            });
+        }
+
         let id = stamp_request_repository
-            .create_stamp_request(BASE_STAMP_DIFFICULTY, recipient.id)
+            .create_stamp_request(difficulty, recipient.id, self.sender_id, valid_to)
             .await?;
+        let request = stamp_request_repository
+            .get_stamp_request(id)
+            .await?
+            .ok_or(StampError::StampRequestNotFound)?;
 
-        Ok(id)
+        Ok(StampRequestReceipt {
+            request: OneTimeStampRequest {
+                stamp_request_id: request.stamp_request_id,
+                difficulty: request.difficulty,
+                valid_to: request.valid_to,
+                solved_at: request.solved_at,
+            },
+            recipient_id: recipient.id,
+            recipient_key_fingerprint,
+        })
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct IssueSystemStampCommandDto {
     pub stamp_request_id: Uuid,
+    #[schema(value_type = domain::stamp::ProofOfWork)]
     pub proof_of_work: Pow<Uuid>,
 }
 
@@ -180,23 +295,41 @@ pub struct IssueSystemStampCommand {
     pub stamp_request_id: Uuid,
     pub sender_id: Uuid,
     pub proof_of_work: Pow<Uuid>,
+    pub difficulty_rule: DifficultyRule,
 }
 
 impl IssueSystemStampCommand {
+    #[allow(clippy::too_many_arguments)]
     pub async fn handle(
         self,
         user_repository: &impl UserRepository,
         stamp_request_repo: &impl StampRequestRepository,
+        block_list_repository: &impl BlockListRepository,
         tracker_repo: &impl OneTimeStampTrackerRepository,
         system_key_repo: &impl SystemKeyRepository,
         crypto_service: &impl CryptographyService,
         serialize_service: &impl SerializeService,
+        stamp_policy: &StampPolicy,
     ) -> Result<OnetimeStamp, SmError> {
         // Retrieve the stamp request
         let stamp_request = stamp_request_repo
             .get_stamp_request(self.stamp_request_id)
             .await?
             .ok_or(StampError::StampRequestNotFound)?;
+        // Requests made for other senders are none of this sender's business
+        if stamp_request.sender_id != self.sender_id {
+            return Err(StampError::StampRequestNotFound.into());
+        }
+        if stamp_request.solved_at.is_some() {
+            return Err(StampError::StampRequestSolved.into());
+        }
+        // The sender may have been blocked since requesting the stamp
+        if block_list_repository
+            .is_blocked(stamp_request.recipient_id, self.sender_id)
+            .await?
+        {
+            return Err(StampError::StampRequestNotFound.into());
+        }
 
         // Check if the stamp request has expired
         let current_time = chrono::Utc::now();
@@ -205,11 +338,13 @@ impl IssueSystemStampCommand {
         }
 
         // Verify the proof of work
-        if !(self
+        if self
             .proof_of_work
             .score(&self.stamp_request_id)
             .unwrap_or(0)
-            >= stamp_request.difficulty as u128)
+            < self
+                .difficulty_rule
+                .threshold(stamp_request.difficulty, stamp_policy.base_difficulty)
         {
             return Err(StampError::InvalidProofOfWork.into());
         }
@@ -217,7 +352,7 @@ impl IssueSystemStampCommand {
         let system_keys = system_key_repo
             .get_system_keys()
             .await?
-            .expect("System keys have not been set");
+            .ok_or(CryptographyError::InvalidSystemKeys)?;
 
         // Get the recipient
         let recipient = GetUserByIdQuery {
@@ -233,37 +368,37 @@ impl IssueSystemStampCommand {
             issuer_id: STAMP_SYSTEM_ISSUED,
             recipient_id: recipient.id,
             sender_id: self.sender_id,
-            valid_to: Some(chrono::Utc::now() + chrono::Duration::minutes(15)),
+            valid_to: Some(
+                chrono::Utc::now() + chrono::Duration::minutes(stamp_policy.validity_minutes),
+            ),
             signature: String::new(), // This will be filled in later
         };
 
         // Create the signature
-        let signature = {
-            let signature_stamp = {
-                let stamp_id = serialize_service.serialize(&stamp.stamp_id);
-                let issuer_id = serialize_service.serialize(&stamp.issuer_id);
-                let recipient_id = serialize_service.serialize(&stamp.recipient_id);
-                let sender_id = serialize_service.serialize(&stamp.sender_id);
-                let valid_to = serialize_service.serialize(&stamp.valid_to);
-                format!(
-                    "{}\n{}\n{}\n{}\n{}",
-                    stamp_id, issuer_id, recipient_id, sender_id, valid_to
-                )
-            };
-            crypto_service.produce_signature(&signature_stamp, &system_keys.private_key)
-        }
-        .expect("System keys were invalid for signing");
+        let signature = crypto_service
+            .produce_signature(
+                &onetime_stamp_plaintext(&stamp, serialize_service),
+                &system_keys.private_key,
+            )
+            .map_err(|_| CryptographyError::InvalidSystemKeys)?;
 
         // Create the final stamp with the signature
         let final_stamp = OnetimeStamp { signature, ..stamp };
 
-        // Set stamp request as completed
-        stamp_request_repo
+        // Set stamp request as completed, unless another solution beat this one to it
+        if !stamp_request_repo
             .mark_solved(self.stamp_request_id)
-            .await?;
+            .await?
+        {
+            return Err(StampError::StampRequestSolved.into());
+        }
 
         tracker_repo
-            .insert(final_stamp.stamp_id, final_stamp.recipient_id)
+            .insert(
+                final_stamp.stamp_id,
+                final_stamp.recipient_id,
+                final_stamp.sender_id,
+            )
             .await?;
 
         Ok(final_stamp)
@@ -0,0 +1,37 @@
+use domain::{
+    crypto::CryptographyService,
+    error::{CryptographyError, SmError},
+    system_key::{SystemKeyPair, SystemKeyRepository},
+};
+
+/// Generates the system keys that sign system issued stamps and seal message senders, unless
+/// they already exist. Public keys kept in PEM form, as keys generated before all public keys
+/// were DER were, are converted, since stamp verification and sealing only read DER.
+pub struct InitSystemKeysCommand;
+
+impl InitSystemKeysCommand {
+    pub async fn handle(
+        self,
+        system_key_repository: &impl SystemKeyRepository,
+        cryptography_service: &impl CryptographyService,
+    ) -> Result<(), SmError> {
+        if let Some(system_keys) = system_key_repository.get_system_keys().await? {
+            if !cryptography_service.validate_public_key(&system_keys.public_key) {
+                let public_key = cryptography_service
+                    .public_key_of(&system_keys.private_key)
+                    .map_err(|_| CryptographyError::InvalidSystemKeys)?;
+                system_key_repository.update_public_key(public_key).await?;
+            }
+            return Ok(());
+        }
+        let (public_key, private_key) = cryptography_service
+            .generate_key_pair()
+            .expect("System key generation failed");
+        system_key_repository
+            .init_system_keys(SystemKeyPair {
+                private_key,
+                public_key,
+            })
+            .await
+    }
+}
@@ -1,14 +1,16 @@
 use domain::{
+    chrono,
     crypto::CryptographyService,
     error::{CryptographyError, SessionError, SmError, UserError, ValidationError},
-    session::{Session, SessionRepository},
+    session::{Session, SessionPolicy, SessionRepository},
     user::{User, UserRepository},
     validate::Validate,
 };
 use serde::Deserialize;
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct RegisterUserCommand {
     pub username: String,
     pub public_encryption_key: String,
@@ -26,7 +28,7 @@ impl RegisterUserCommand {
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct RequestSessionCommand {
     pub username: String,
 }
@@ -35,17 +37,26 @@ impl RequestSessionCommand {
         self,
         user_repository: &UR,
         session_repository: &SR,
+        session_policy: &SessionPolicy,
     ) -> Result<Session, SmError> {
+        // Users of other servers are only represented here, they sign in at their own server
+        if self.username.contains('@') {
+            return Err(UserError::InvalidCredentials.into());
+        }
         let user = user_repository
             .find_by_username(self.username)
             .await?
             .ok_or(UserError::InvalidCredentials)?;
-        let session = session_repository.request_session(user.id).await?;
+        let expires_at =
+            chrono::Utc::now() + chrono::Duration::minutes(session_policy.length_minutes);
+        let session = session_repository
+            .request_session(user.id, expires_at)
+            .await?;
         Ok(session)
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct ActivateSessionCommand {
     pub session_id: Uuid,
     pub challenge_signature: String,
@@ -1,9 +1,11 @@
 use domain::{
-    error::{SessionError, SmError},
+    error::{SessionError, SmError, UserError, ValidationError},
+    federation::Address,
     session::SessionRepository,
     user::{User, UserRepository},
 };
 use serde::Deserialize;
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 #[derive(Deserialize)]
@@ -58,3 +60,49 @@ impl GetUserBySessionQuery {
         Ok(user)
     }
 }
+
+/// Names who a message or stamp request is for, either by id or by address.
+#[derive(Clone, Deserialize, ToSchema)]
+pub struct RecipientRef {
+    pub recipient_id: Option<Uuid>,
+    /// A username, optionally followed by `@` and the host of this server.
+    pub recipient: Option<String>,
+}
+
+/// Finds the id of the user a recipient reference names. Users of other servers are written
+/// to through federation, so their addresses are turned away.
+pub struct ResolveRecipientQuery {
+    pub recipient: RecipientRef,
+    pub local_host: String,
+}
+
+impl ResolveRecipientQuery {
+    pub async fn handle<UR: UserRepository>(self, user_repository: &UR) -> Result<Uuid, SmError> {
+        let username = match (self.recipient.recipient_id, self.recipient.recipient) {
+            (Some(recipient_id), None) => return Ok(recipient_id),
+            (None, Some(address)) if !address.contains('@') => address,
+            (None, Some(address)) => {
+                let address: Address = address.parse()?;
+                if !address.host.eq_ignore_ascii_case(&self.local_host) {
+                    return Err(ValidationError(format!(
+                        "{} is a user of another server, write to them through federation",
+                        address
+                    ))
+                    .into());
+                }
+                address.username
+            }
+            _ => {
+                return Err(ValidationError(
+                    "Either recipient_id or recipient must be given".to_string(),
+                )
+                .into())
+            }
+        };
+        let user = user_repository
+            .find_by_username(username)
+            .await?
+            .ok_or(UserError::UserNotFound)?;
+        Ok(user.id)
+    }
+}
@@ -0,0 +1,76 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::SmError;
+
+/// Client-encrypted data addressed by the hex encoded SHA-256 of its complete contents and
+/// uploaded in fixed-size chunks, the last of which may be shorter.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Blob {
+    pub hash: String,
+    pub size: i64,
+    pub chunk_size: i32,
+    pub chunk_count: i32,
+    pub uploader_id: Uuid,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+impl Blob {
+    pub fn expected_chunk_size(&self, index: i32) -> i64 {
+        if index == self.chunk_count - 1 {
+            self.size - self.chunk_size as i64 * index as i64
+        } else {
+            self.chunk_size as i64
+        }
+    }
+}
+
+#[async_trait]
+pub trait BlobRepository {
+    /// Registers an upload, or returns the blob already registered under the same hash.
+    async fn create_blob(
+        &self,
+        hash: &str,
+        size: i64,
+        chunk_size: i32,
+        chunk_count: i32,
+        uploader_id: Uuid,
+    ) -> Result<Blob, SmError>;
+    async fn get_blob(&self, hash: &str) -> Result<Option<Blob>, SmError>;
+    /// Hands a pending upload that has received no chunk since `idle_before` over to another
+    /// uploader, discarding what was uploaded so far. Returns `None` if it wasn't idle.
+    async fn take_over_upload(
+        &self,
+        hash: &str,
+        size: i64,
+        chunk_size: i32,
+        chunk_count: i32,
+        uploader_id: Uuid,
+        idle_before: DateTime<Utc>,
+    ) -> Result<Option<Blob>, SmError>;
+    /// Stores a chunk of a pending upload by `uploader_id`. Returns `false`, storing nothing, if
+    /// the upload has been completed or taken over by someone else.
+    async fn put_chunk(
+        &self,
+        hash: &str,
+        uploader_id: Uuid,
+        index: i32,
+        data: Vec<u8>,
+    ) -> Result<bool, SmError>;
+    async fn get_chunk(&self, hash: &str, index: i32) -> Result<Option<Vec<u8>>, SmError>;
+    async fn list_chunk_indices(&self, hash: &str) -> Result<Vec<i32>, SmError>;
+    async fn discard_chunks(&self, hash: &str) -> Result<(), SmError>;
+    async fn mark_complete(&self, hash: &str) -> Result<(), SmError>;
+    /// Whether the user uploaded the blob, or received, kept a sent copy of, or scheduled a
+    /// message that references it.
+    async fn can_access(&self, hash: &str, user_id: Uuid) -> Result<bool, SmError>;
+    /// Deletes complete blobs unreferenced since before `unreferenced_before` and uploads
+    /// abandoned since before `abandoned_before`, returning how many were deleted.
+    async fn collect_garbage(
+        &self,
+        unreferenced_before: DateTime<Utc>,
+        abandoned_before: DateTime<Utc>,
+    ) -> Result<u64, SmError>;
+}
@@ -0,0 +1,23 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::error::SmError;
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BlockedSender {
+    pub sender_id: Uuid,
+    pub blocked_at: DateTime<Utc>,
+}
+
+/// Each user's list of senders whose messages they refuse.
+#[async_trait]
+pub trait BlockListRepository {
+    /// Blocking an already blocked sender is a no-op, as is unblocking one that isn't.
+    async fn block_sender(&self, user_id: Uuid, sender_id: Uuid) -> Result<(), SmError>;
+    async fn unblock_sender(&self, user_id: Uuid, sender_id: Uuid) -> Result<(), SmError>;
+    async fn list_blocked_senders(&self, user_id: Uuid) -> Result<Vec<BlockedSender>, SmError>;
+    async fn is_blocked(&self, user_id: Uuid, sender_id: Uuid) -> Result<bool, SmError>;
+}
@@ -0,0 +1,21 @@
+use async_trait::async_trait;
+
+use crate::error::SmError;
+
+/// Storage for opaque objects kept outside the database rows that reference them, such as
+/// blob chunks and message bodies above the out-of-line threshold. Keys are never reused:
+/// replacing an object writes it under a new key and orphans the old one.
+#[async_trait]
+pub trait ContentStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), SmError>;
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, SmError>;
+    /// Deleting a key that does not exist is not an error.
+    async fn delete(&self, key: &str) -> Result<(), SmError>;
+}
+
+/// Keys of stored objects whose referencing rows have been deleted or replaced.
+#[async_trait]
+pub trait OrphanedContentRepository {
+    async fn list_orphaned_content(&self, limit: i64) -> Result<Vec<String>, SmError>;
+    async fn forget_orphaned_content(&self, keys: &[String]) -> Result<(), SmError>;
+}
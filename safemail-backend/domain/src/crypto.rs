@@ -1,11 +1,53 @@
+pub trait ContentHasher {
+    fn update(&mut self, data: &[u8]);
+    /// Returns the hex encoded digest of everything passed to `update`.
+    fn finish(self) -> String;
+}
+
 pub trait CryptographyService {
+    type ContentHasher: ContentHasher + Send;
+
     fn validate_public_key(&self, public_key: &str) -> bool;
     fn validate_signature(&self, plaintext: &str, signature_base64: &str, public_key: &str)
         -> bool;
+    /// Returns a base64 encoded public key in DER form, and private key in PEM form.
     fn generate_key_pair(&self) -> Result<(String, String), Box<dyn std::error::Error>>;
+    /// Returns the base64 encoded public key, in DER form, of a base64 encoded PEM private key.
+    fn public_key_of(&self, private_key: &str) -> Result<String, Box<dyn std::error::Error>>;
     fn produce_signature(
         &self,
         plaintext: &str,
         private_key: &str,
     ) -> Result<String, Box<dyn std::error::Error>>;
+    /// Encrypts a short plaintext to the holder of the private key, returning it base64
+    /// encoded. Sealing the same plaintext twice gives different results.
+    fn seal(
+        &self,
+        plaintext: &[u8],
+        public_key: &str,
+    ) -> Result<String, Box<dyn std::error::Error>>;
+    fn unseal(
+        &self,
+        sealed_base64: &str,
+        private_key: &str,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>>;
+    /// Encrypts a plaintext of any length to the holder of the private key, returning it base64
+    /// encoded. A fresh AES-256-GCM key is sealed to the public key and prepended, the layout
+    /// being the sealed key's length as two big-endian bytes, the sealed key, the 12 byte nonce,
+    /// the ciphertext and the 16 byte tag.
+    fn encrypt(
+        &self,
+        plaintext: &[u8],
+        public_key: &str,
+    ) -> Result<String, Box<dyn std::error::Error>>;
+    fn decrypt(
+        &self,
+        encrypted_base64: &str,
+        private_key: &str,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>>;
+    /// Returns the hex encoded SHA-256 digest of a public key's DER form, for users to compare
+    /// keys by.
+    fn key_fingerprint(&self, public_key: &str) -> Result<String, Box<dyn std::error::Error>>;
+    /// Creates an incremental SHA-256 hasher.
+    fn content_hasher(&self) -> Self::ContentHasher;
 }
@@ -14,16 +14,79 @@ pub enum SmError {
     Cryptography(#[from] CryptographyError),
     #[error("Stamp error: {0}")]
     Stamp(#[from] StampError),
+    #[error("Message error: {0}")]
+    Message(#[from] MessageError),
+    #[error("Blob error: {0}")]
+    Blob(#[from] BlobError),
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+    #[error("Idempotency error: {0}")]
+    Idempotency(#[from] IdempotencyError),
+    #[error("Federation error: {0}")]
+    Federation(#[from] FederationError),
+    #[error("Bridge error: {0}")]
+    Bridge(#[from] BridgeError),
+    #[error("Rate limit error: {0}")]
+    RateLimit(#[from] RateLimitError),
+}
+
+impl SmError {
+    /// Whether the operation may succeed if simply tried again.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            SmError::Database(e) => e.is_retryable(),
+            SmError::RateLimit(_) => true,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Error, Debug)]
 #[error("{0}")]
 pub struct ValidationError(pub String);
 
+/// The underlying error of a failed database operation, kept for logging.
+pub type DatabaseErrorSource = Box<dyn std::error::Error + Send + Sync>;
+
+/// Constraint names are kept for the server's log, and never shown to clients.
 #[derive(Error, Debug)]
 pub enum DatabaseError {
     #[error("Database error")]
     Arbitrary,
+    #[error("Database query failed")]
+    Query(#[source] DatabaseErrorSource),
+    #[error("Conflicts with an existing record")]
+    UniqueViolation {
+        constraint: String,
+        #[source]
+        source: DatabaseErrorSource,
+    },
+    #[error("Refers to a record that does not exist")]
+    ForeignKeyViolation {
+        constraint: String,
+        #[source]
+        source: DatabaseErrorSource,
+    },
+    #[error("Transaction conflicted with a concurrent one")]
+    SerializationFailure(#[source] DatabaseErrorSource),
+    #[error("Database operation timed out")]
+    Timeout(#[source] DatabaseErrorSource),
+}
+
+impl DatabaseError {
+    /// Whether the operation may succeed if simply tried again.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            DatabaseError::SerializationFailure(_) | DatabaseError::Timeout(_)
+        )
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error("Content storage error")]
+    Arbitrary,
 }
 
 #[derive(Error, Debug)]
@@ -52,6 +115,8 @@ pub enum SessionError {
 pub enum CryptographyError {
     #[error("Invalid signature")]
     InvalidSignature,
+    #[error("System keys are missing or unusable")]
+    InvalidSystemKeys,
 }
 
 #[derive(Error, Debug)]
@@ -66,4 +131,82 @@ pub enum StampError {
     StampRequestNotFound,
     #[error("Stamp request expired")]
     StampRequestExpired,
+    #[error("Stamp request has already been solved")]
+    StampRequestSolved,
+}
+
+#[derive(Error, Debug)]
+pub enum MessageError {
+    #[error("Message not found")]
+    MessageNotFound,
+    #[error("Recipient metadata has been modified, current version is {current_version}")]
+    RecipientMetadataConflict { current_version: i64 },
+    #[error("Recipient's mailbox is full")]
+    MailboxFull,
+    #[error("Sender's mailbox is full, the sent copy cannot be kept")]
+    OutboxFull,
+    #[error("Outbox entry not found")]
+    OutboxEntryNotFound,
+    #[error("Scheduled message not found")]
+    ScheduledMessageNotFound,
+    #[error("The sender of this message is not known")]
+    SenderNotAttributable,
+}
+
+#[derive(Error, Debug)]
+pub enum BlobError {
+    #[error("Blob not found")]
+    BlobNotFound,
+    #[error("Blob upload parameters do not match the pending upload")]
+    UploadMismatch,
+    #[error("Chunk index or size is invalid")]
+    InvalidChunk,
+    #[error("Blob upload is missing chunks")]
+    UploadIncomplete,
+    #[error("Uploaded data does not match the blob hash")]
+    HashMismatch,
+}
+
+#[derive(Error, Debug)]
+pub enum IdempotencyError {
+    #[error("A request with this idempotency key is still being processed")]
+    RequestInProgress,
+    #[error("Idempotency key was already used for a different request")]
+    KeyReused,
+}
+
+#[derive(Error, Debug)]
+pub enum FederationError {
+    #[error("Address must have the form username@host")]
+    InvalidAddress,
+    #[error("Remote server could not be reached")]
+    PeerUnreachable,
+    #[error("Remote server rejected the request: {message}")]
+    RemoteRejected {
+        status: u16,
+        code: Option<String>,
+        message: String,
+    },
+    #[error("Server request is not signed by its origin")]
+    InvalidServerSignature,
+    #[error("Server request was sent too long ago")]
+    RequestExpired,
+    #[error("Server request was received before")]
+    RequestReplayed,
+}
+
+#[derive(Error, Debug)]
+pub enum BridgeError {
+    #[error("Too much mail was relayed recently")]
+    RateLimited,
+    #[error("SMTP server refused the message: {0}")]
+    RelayRejected(String),
+    #[error("SMTP server could not be reached: {0}")]
+    RelayUnavailable(String),
+}
+
+#[derive(Error, Debug)]
+pub enum RateLimitError {
+    #[error("Too many requests, try again in {retry_after_seconds} seconds")]
+    Exceeded { retry_after_seconds: i64 },
 }
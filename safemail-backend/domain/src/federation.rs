@@ -0,0 +1,204 @@
+use std::{fmt, str::FromStr};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::error::{FederationError, SmError};
+use crate::stamp::{OneTimeStampRequest, OnetimeStamp};
+use crate::user::User;
+
+/// A user on any safemail server, written `username@host`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Address {
+    pub username: String,
+    pub host: String,
+}
+
+/// Checks that `host` is a host name, optionally followed by a port, and lowercases it. Servers
+/// are known by name, so IP addresses are refused, as are names whose last label doesn't start
+/// with a letter, which URL parsers may read as one.
+pub fn normalize_host(host: &str) -> Result<String, FederationError> {
+    let (name, port) = match host.split_once(':') {
+        Some((name, port)) => (name, Some(port)),
+        None => (host, None),
+    };
+    let valid_name = name.split('.').all(|label| {
+        !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    }) && name
+        .rsplit('.')
+        .next()
+        .is_some_and(|label| label.starts_with(|c: char| c.is_ascii_alphabetic()));
+    let valid_port = port.is_none_or(|port| {
+        port.chars().all(|c| c.is_ascii_digit()) && port.parse::<u16>().is_ok_and(|port| port > 0)
+    });
+    if !valid_name || !valid_port {
+        return Err(FederationError::InvalidAddress);
+    }
+    Ok(host.to_ascii_lowercase())
+}
+
+impl FromStr for Address {
+    type Err = FederationError;
+
+    fn from_str(address: &str) -> Result<Self, Self::Err> {
+        let (username, host) = address
+            .rsplit_once('@')
+            .ok_or(FederationError::InvalidAddress)?;
+        let valid_username = !username.is_empty()
+            && username
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid_username {
+            return Err(FederationError::InvalidAddress);
+        }
+        Ok(Self {
+            username: username.to_string(),
+            host: normalize_host(host)?,
+        })
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.username, self.host)
+    }
+}
+
+/// What a server publishes about itself at `/.well-known/safemail`. The key is the server's
+/// system key, which signs its system issued stamps as well as its requests to other servers.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ServerIdentity {
+    pub host: String,
+    pub public_key: String,
+}
+
+/// A user as their home server publishes them.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RemoteUser {
+    pub id: Uuid,
+    pub username: String,
+    pub public_encryption_key: String,
+    pub public_verify_key: String,
+}
+
+impl From<User> for RemoteUser {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            public_encryption_key: user.public_encryption_key,
+            public_verify_key: user.public_verify_key,
+        }
+    }
+}
+
+/// A request from one server to another, signed with the origin's system key. The signature
+/// covers the origin, the destination, the request's id, the time and the body, so a request
+/// can neither be redirected to another server nor replayed, the time bounding how long its id
+/// has to be remembered.
+#[derive(Debug)]
+pub struct SignedRequest {
+    pub origin: String,
+    pub id: Uuid,
+    pub sent_at: DateTime<Utc>,
+    pub body: String,
+    pub signature: String,
+}
+
+/// Where a message forwarded to another server ended up.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RemoteDelivery {
+    pub recipient_id: Uuid,
+    pub message_id: i64,
+}
+
+/// Talks to other safemail servers.
+#[async_trait]
+pub trait FederationClient {
+    async fn fetch_identity(&self, host: &str) -> Result<ServerIdentity, SmError>;
+    async fn fetch_user(&self, address: &Address) -> Result<Option<RemoteUser>, SmError>;
+    async fn deliver_message(
+        &self,
+        host: &str,
+        request: &SignedRequest,
+    ) -> Result<RemoteDelivery, SmError>;
+    async fn request_system_stamp(
+        &self,
+        host: &str,
+        request: &SignedRequest,
+    ) -> Result<OneTimeStampRequest, SmError>;
+    async fn issue_system_stamp(
+        &self,
+        host: &str,
+        request: &SignedRequest,
+    ) -> Result<OnetimeStamp, SmError>;
+}
+
+/// Another server's identity key, as last fetched.
+#[derive(Debug, Clone)]
+pub struct PeerKey {
+    pub public_key: String,
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// The identity keys of other servers, cached so they needn't be fetched for every request, and
+/// the requests received from them.
+#[async_trait]
+pub trait FederationPeerRepository {
+    async fn get_peer_key(&self, host: &str) -> Result<Option<PeerKey>, SmError>;
+    async fn save_peer_key(&self, host: &str, public_key: &str) -> Result<(), SmError>;
+    /// Remembers a request received from another server until `expires_at`, returning false if
+    /// it was received before.
+    async fn record_request(
+        &self,
+        origin: &str,
+        request_id: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, SmError>;
+    /// Forgets requests remembered until before the given time, returning how many.
+    async fn purge_requests(&self, expired_before: DateTime<Utc>) -> Result<u64, SmError>;
+}
+
+/// Local stand-ins for users of other servers.
+#[async_trait]
+pub trait RemoteUserRepository {
+    /// Creates the local user standing in for the remote one, or brings its username and keys
+    /// up to date. The local user is named after the remote user's address.
+    async fn save_remote_user(&self, host: &str, user: &RemoteUser) -> Result<User, SmError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize_host;
+
+    #[test]
+    fn normalize_host_only_accepts_host_names() {
+        assert_eq!(
+            normalize_host("Mail.Example.com").unwrap(),
+            "mail.example.com"
+        );
+        assert_eq!(normalize_host("localhost:3001").unwrap(), "localhost:3001");
+        for host in [
+            "",
+            "127.0.0.1",
+            "127.1",
+            "2130706433",
+            "0x7f.1",
+            "::1",
+            "[::1]:80",
+            "10.0.0.1:8080",
+            "example.com.",
+            "example.com:",
+            "example.com:0",
+            "example.com:65536",
+            "example.com:+80",
+            "a..example.com",
+            "user@example.com",
+        ] {
+            assert!(normalize_host(host).is_err(), "{} was accepted", host);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::SmError;
+
+/// How requests made with an idempotency key are kept apart.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IdempotencyPolicy {
+    /// How long a claimed key may go without an outcome or a renewal before a retry may take it
+    /// over, e.g. after the server was restarted mid-request. Claims are renewed well within
+    /// this while their request is being handled.
+    pub stale_claim_seconds: i64,
+}
+
+/// The response stored for a request made with an idempotency key, replayed for repeats.
+pub struct IdempotentOutcome {
+    pub status: i32,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+pub enum IdempotencyClaim {
+    /// The key is new, or its earlier claim went stale; the request should be processed.
+    Claimed,
+    /// Another request with the key is still being processed.
+    InProgress,
+    Completed(IdempotentOutcome),
+    /// The key was first used for a different request.
+    Mismatch,
+}
+
+/// Idempotency keys, scoped to the sender that chose them.
+#[async_trait]
+pub trait IdempotencyRepository {
+    /// Claims the key for a request, taking over claims made before `stale_before` that were
+    /// never completed or released.
+    async fn claim(
+        &self,
+        sender_id: Uuid,
+        key: &str,
+        fingerprint: &str,
+        stale_before: DateTime<Utc>,
+    ) -> Result<IdempotencyClaim, SmError>;
+    async fn complete(
+        &self,
+        sender_id: Uuid,
+        key: &str,
+        outcome: IdempotentOutcome,
+    ) -> Result<(), SmError>;
+    /// Keeps a claim from going stale while its request is still being handled.
+    async fn renew(&self, sender_id: Uuid, key: &str) -> Result<(), SmError>;
+    /// Gives up a claim without storing an outcome, so that the request may be retried.
+    async fn release(&self, sender_id: Uuid, key: &str) -> Result<(), SmError>;
+    /// Forgets keys first used before the given time, returning how many were forgotten.
+    async fn purge(&self, created_before: DateTime<Utc>) -> Result<u64, SmError>;
+}
@@ -1,11 +1,23 @@
+pub mod blob;
+pub mod block_list;
+pub mod content;
 pub mod crypto;
 pub mod error;
+pub mod federation;
+pub mod idempotency;
 pub mod message;
+pub mod message_change;
 pub mod onetime_stamp;
+pub mod outbound_mail;
+pub mod outbox;
+pub mod rate_limit;
+pub mod report;
+pub mod scheduled_message;
 pub mod serialize;
 pub mod session;
 pub mod stamp;
 pub mod stamp_request;
+pub mod stamp_revocation;
 pub mod system_key;
 pub mod user;
 pub mod validate;
@@ -1,37 +1,134 @@
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 use crate::error::SmError;
+use crate::outbox::NewOutboxEntry;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct Message {
     pub id: i64,
     pub recipient_id: Uuid,
     pub metadata: String,
     pub recipient_metadata: Option<String>,
+    pub recipient_metadata_version: i64,
     pub content: String,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Hashes of the blobs attached to the message.
+    pub attachments: Vec<String>,
 }
 pub struct MessageMetadata(pub String);
 
+pub struct NewMessage {
+    pub recipient_id: Uuid,
+    pub metadata: MessageMetadata,
+    pub content: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub attachments: Vec<String>,
+    /// The sender's id, sealed to the system key. Messages scheduled before senders were
+    /// sealed have none.
+    pub sender_seal: Option<String>,
+    /// The one-time stamp the message was sent with, used up as the message is delivered.
+    pub onetime_stamp_id: Option<Uuid>,
+}
+
+/// The messages created by a send, and the id of the sender's outbox entry if one was kept.
+pub struct Delivery {
+    pub messages: Vec<Message>,
+    pub outbox_id: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MessageSummary {
+    pub id: i64,
+    pub metadata: String,
+}
+
+/// How much a single user's mailbox may hold, outbox included. Trashed messages count until
+/// purged.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MailboxQuota {
+    pub max_messages: i64,
+    pub max_bytes: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MailboxUsage {
+    pub message_count: i64,
+    /// Metadata, content and attachments of every received message and outbox entry,
+    /// attachments counted once per message even when shared.
+    pub stored_bytes: i64,
+}
+
+impl MailboxQuota {
+    pub fn admits(&self, usage: &MailboxUsage, messages: i64, bytes: i64) -> bool {
+        usage.message_count + messages <= self.max_messages
+            && usage.stored_bytes + bytes <= self.max_bytes
+    }
+}
+
+/// Published whenever a message is committed to a recipient's mailbox.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewMessageNotification {
+    pub id: i64,
+    pub recipient_id: Uuid,
+}
+
 #[async_trait]
 pub trait MessageRepository {
+    /// Fails with `MessageError::MailboxFull` rather than take the recipient over the quota.
     async fn create_message(
         &self,
-        recipient_id: Uuid,
-        metadata: MessageMetadata,
-        content: String,
-    ) -> Result<Message, SmError>;
+        message: NewMessage,
+        outbox_entry: Option<NewOutboxEntry>,
+        quota: &MailboxQuota,
+    ) -> Result<Delivery, SmError>;
+    /// Creates all of the messages, and the sender's outbox entry if given, or none of them.
+    async fn create_messages(
+        &self,
+        messages: Vec<NewMessage>,
+        outbox_entry: Option<NewOutboxEntry>,
+        quota: &MailboxQuota,
+    ) -> Result<Delivery, SmError>;
+    async fn get_mailbox_usage(&self, recipient_id: Uuid) -> Result<MailboxUsage, SmError>;
+    /// Fails with `MessageError::MessageNotFound` if the recipient has no such message. Messages
+    /// sent before senders were sealed have no seal.
+    async fn get_sender_seal(&self, recipient_id: Uuid, id: i64)
+        -> Result<Option<String>, SmError>;
+    /// Expired messages are treated as though they had already been deleted.
     async fn get_message(&self, recipient_id: Uuid, id: i64) -> Result<Option<Message>, SmError>;
+    /// Replaces the recipient's metadata if it is still at `expected_version`, returning the
+    /// new version.
     async fn update_recipient_metadata(
         &self,
+        recipient_id: Uuid,
         id: i64,
-        recipient_metadata: String,
-    ) -> Result<(), SmError>;
-    async fn delete_message(&self, id: i64) -> Result<(), SmError>;
+        recipient_metadata: Option<String>,
+        expected_version: i64,
+    ) -> Result<i64, SmError>;
+    /// Moves the recipient's messages to the trash, returning the ids that were moved.
+    async fn trash_messages(&self, recipient_id: Uuid, ids: &[i64]) -> Result<Vec<i64>, SmError>;
+    /// Takes the recipient's messages out of the trash, returning the ids that were restored.
+    async fn restore_messages(&self, recipient_id: Uuid, ids: &[i64]) -> Result<Vec<i64>, SmError>;
+    /// Permanently deletes the recipient's messages, returning the ids that were deleted.
+    async fn delete_messages(&self, recipient_id: Uuid, ids: &[i64]) -> Result<Vec<i64>, SmError>;
+    /// Permanently deletes every message that was trashed before the given time.
+    async fn purge_trash(&self, trashed_before: DateTime<Utc>) -> Result<u64, SmError>;
+    /// Permanently deletes every message whose sender-specified expiry has passed.
+    async fn purge_expired(&self) -> Result<u64, SmError>;
+    /// Lists up to `limit` messages in ascending id order. When only `before_id` is given,
+    /// the page ends right below it; otherwise it starts right above `after_id`.
     async fn list_messages(
         &self,
         recipient_id: Uuid,
-        above_id: Option<i64>,
-    ) -> Result<Vec<(i64, MessageMetadata)>, SmError>;
+        trashed: bool,
+        after_id: Option<i64>,
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<MessageSummary>, SmError>;
+    async fn count_messages(&self, recipient_id: Uuid, trashed: bool) -> Result<i64, SmError>;
 }
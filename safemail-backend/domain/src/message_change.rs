@@ -0,0 +1,46 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::error::SmError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageChangeKind {
+    Created,
+    MetadataUpdated,
+    Trashed,
+    Restored,
+    /// A tombstone left behind by a permanently deleted message.
+    Deleted,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MessageChange {
+    pub seq: i64,
+    pub message_id: i64,
+    pub kind: MessageChangeKind,
+    pub changed_at: DateTime<Utc>,
+}
+
+/// Where a recipient's change feed currently stands.
+pub struct MessageChangeHorizon {
+    pub last_seq: i64,
+    /// Changes up to and including this sequence number have been pruned.
+    pub pruned_through_seq: i64,
+}
+
+#[async_trait]
+pub trait MessageChangeRepository {
+    async fn list_changes(
+        &self,
+        recipient_id: Uuid,
+        since_seq: i64,
+        limit: i64,
+    ) -> Result<Vec<MessageChange>, SmError>;
+    async fn get_horizon(&self, recipient_id: Uuid) -> Result<MessageChangeHorizon, SmError>;
+    /// Deletes changes recorded before the given time, returning how many were deleted.
+    async fn prune_changes(&self, changed_before: DateTime<Utc>) -> Result<u64, SmError>;
+}
@@ -10,7 +10,11 @@ pub struct OneTimeStampTracker {
 
 #[async_trait]
 pub trait OneTimeStampTrackerRepository {
-    async fn insert(&self, stamp_id: Uuid, recipient_id: Uuid) -> Result<(), SmError>;
+    async fn insert(
+        &self,
+        stamp_id: Uuid,
+        recipient_id: Uuid,
+        sender_id: Uuid,
+    ) -> Result<(), SmError>;
     async fn get_by_id(&self, stamp_id: Uuid) -> Result<Option<OneTimeStampTracker>, SmError>;
-    async fn set_used_or_revoked(&self, stamp_id: Uuid) -> Result<(), SmError>;
 }
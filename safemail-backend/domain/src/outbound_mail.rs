@@ -0,0 +1,88 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::error::SmError;
+
+/// How much mail a single user may relay to SMTP addresses.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BridgePolicy {
+    pub max_recipients: i64,
+    /// Counted over every message handed to the bridge in the last hour, sent or not.
+    pub max_recipients_per_hour: i64,
+}
+
+/// Mail handed to the bridge, encrypted to the system key and signed by its sender.
+pub struct NewOutboundMail {
+    pub sender_id: Uuid,
+    pub recipients: Vec<String>,
+    pub content: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct OutboundMailSummary {
+    pub id: i64,
+    pub recipients: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub attempts: i32,
+    pub sent_at: Option<DateTime<Utc>>,
+    /// Why relaying failed for good. Mail that is pending or sent has none.
+    pub failure: Option<String>,
+}
+
+/// Pending mail whose next attempt is due.
+pub struct DueOutboundMail {
+    pub id: i64,
+    pub sender_id: Uuid,
+    pub recipients: Vec<String>,
+    pub content: String,
+    pub signature: String,
+    pub attempts: i32,
+}
+
+#[async_trait]
+pub trait OutboundMailRepository {
+    /// Queues mail unless its recipients, together with those of all mail the sender handed to
+    /// the bridge since `since`, come to more than `max_recipients`. Returns its id, or `None`
+    /// if it was over the limit. Mail of the same sender is counted and queued one at a time.
+    async fn enqueue_within_limit(
+        &self,
+        mail: NewOutboundMail,
+        since: DateTime<Utc>,
+        max_recipients: i64,
+    ) -> Result<Option<i64>, SmError>;
+    /// Lists up to `limit` of the sender's most recent mail, newest first.
+    async fn list_outbound_mail(
+        &self,
+        sender_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<OutboundMailSummary>, SmError>;
+    /// Claims up to `limit` pending mail that is due, oldest first, putting its next attempt off
+    /// until `claimed_until` so that other relays pass it over while it is being sent.
+    async fn claim_due_mail(
+        &self,
+        limit: i64,
+        claimed_until: DateTime<Utc>,
+    ) -> Result<Vec<DueOutboundMail>, SmError>;
+    async fn mark_sent(&self, id: i64) -> Result<(), SmError>;
+    /// Counts a failed attempt. The mail is tried again at `retry_at`, or given up on if there
+    /// is none.
+    async fn record_failure(
+        &self,
+        id: i64,
+        failure: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<(), SmError>;
+}
+
+/// Hands mail to an SMTP server. Fails with `BridgeError::RelayRejected` if the server refused
+/// it for good, and `BridgeError::RelayUnavailable` if it may be worth trying again.
+#[async_trait]
+pub trait SmtpRelay {
+    async fn relay(&self, from: &str, recipients: &[String], message: &[u8])
+        -> Result<(), SmError>;
+}
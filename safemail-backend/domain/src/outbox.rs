@@ -0,0 +1,60 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::error::SmError;
+
+/// The sender's own copy of a message being sent, encrypted to the sender's key. It carries the
+/// attachments of the delivered messages.
+pub struct NewOutboxEntry {
+    pub sender_id: Uuid,
+    pub metadata: String,
+    pub content: String,
+}
+
+/// Receipt of one delivery of an outbox entry.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct OutboxDelivery {
+    pub recipient_id: Uuid,
+    pub message_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct OutboxEntry {
+    pub id: i64,
+    pub sender_id: Uuid,
+    pub metadata: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    /// Hashes of the blobs attached to the delivered messages.
+    pub attachments: Vec<String>,
+    pub deliveries: Vec<OutboxDelivery>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct OutboxSummary {
+    pub id: i64,
+    pub metadata: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Outbox entries are created together with the messages they were sent as, see
+/// `MessageRepository::create_messages`.
+#[async_trait]
+pub trait OutboxRepository {
+    async fn get_entry(&self, sender_id: Uuid, id: i64) -> Result<Option<OutboxEntry>, SmError>;
+    /// Lists up to `limit` entries in ascending id order, paging like
+    /// `MessageRepository::list_messages`.
+    async fn list_entries(
+        &self,
+        sender_id: Uuid,
+        after_id: Option<i64>,
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<OutboxSummary>, SmError>;
+    async fn count_entries(&self, sender_id: Uuid) -> Result<i64, SmError>;
+    /// Returns whether the entry existed.
+    async fn delete_entry(&self, sender_id: Uuid, id: i64) -> Result<bool, SmError>;
+}
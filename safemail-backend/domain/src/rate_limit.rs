@@ -0,0 +1,192 @@
+use std::{fmt, net::IpAddr, str::FromStr};
+
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::SmError;
+
+/// How many requests fit in a window of so many seconds. Windows are fixed, starting at
+/// multiples of their length since the epoch, so every instance agrees on them.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct RateLimit {
+    pub requests: i64,
+    pub window_seconds: i64,
+}
+
+impl RateLimit {
+    /// The end of the window `now` falls into.
+    pub fn window_end(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let start = now.timestamp() - now.timestamp().rem_euclid(self.window_seconds);
+        Utc.timestamp_opt(start + self.window_seconds, 0).unwrap()
+    }
+}
+
+/// Written as `requests/seconds`, such as `10/60` for ten requests a minute.
+impl FromStr for RateLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (requests, window_seconds) = s
+            .split_once('/')
+            .ok_or_else(|| format!("{} is not of the form requests/seconds", s))?;
+        let limit = RateLimit {
+            requests: requests
+                .trim()
+                .parse()
+                .map_err(|_| format!("{} is not a number", requests))?,
+            window_seconds: window_seconds
+                .trim()
+                .parse()
+                .map_err(|_| format!("{} is not a number", window_seconds))?,
+        };
+        if limit.requests < 1 || limit.window_seconds < 1 {
+            return Err(format!("{} needs at least one request and one second", s));
+        }
+        Ok(limit)
+    }
+}
+
+impl fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.requests, self.window_seconds)
+    }
+}
+
+impl TryFrom<String> for RateLimit {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<RateLimit> for String {
+    fn from(limit: RateLimit) -> Self {
+        limit.to_string()
+    }
+}
+
+/// Writes a missing limit as `off`.
+mod off_or_limit {
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::RateLimit;
+
+    pub fn serialize<S: Serializer>(
+        limit: &Option<RateLimit>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match limit {
+            Some(limit) => limit.serialize(serializer),
+            None => serializer.serialize_str("off"),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<RateLimit>, D::Error> {
+        match String::deserialize(deserializer)? {
+            off if off == "off" => Ok(None),
+            limit => limit.parse().map(Some).map_err(D::Error::custom),
+        }
+    }
+}
+
+/// Routes that share a budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteClass {
+    /// Requesting and activating sessions.
+    Login,
+    Registration,
+    /// Asking for system stamp requests, locally or through federation.
+    StampRequest,
+    /// Requests from other servers, counted before their signature is checked, as checking it
+    /// may have the server fetch the key of whichever origin they name.
+    Federation,
+}
+
+impl fmt::Display for RouteClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RouteClass::Login => "login",
+            RouteClass::Registration => "registration",
+            RouteClass::StampRequest => "stamp_request",
+            RouteClass::Federation => "federation",
+        })
+    }
+}
+
+/// Who a request is counted against.
+#[derive(Debug, Clone)]
+pub enum RateLimitKey {
+    Ip(IpAddr),
+    /// The user making the request, or, before they have signed in, the one named in it.
+    User(String),
+    Recipient(Uuid),
+}
+
+impl RateLimitKey {
+    /// The name the key is counted under within `class`.
+    pub fn name(&self, class: RouteClass) -> String {
+        match self {
+            RateLimitKey::Ip(ip) => format!("{}:ip:{}", class, ip),
+            RateLimitKey::User(user) => format!("{}:user:{}", class, user),
+            RateLimitKey::Recipient(id) => format!("{}:recipient:{}", class, id),
+        }
+    }
+}
+
+/// The limits of one route class, for each kind of key. Kinds without a limit are not counted.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteBudget {
+    #[serde(with = "off_or_limit")]
+    pub per_ip: Option<RateLimit>,
+    #[serde(with = "off_or_limit")]
+    pub per_user: Option<RateLimit>,
+    #[serde(with = "off_or_limit")]
+    pub per_recipient: Option<RateLimit>,
+}
+
+impl RouteBudget {
+    pub fn limit(&self, key: &RateLimitKey) -> Option<RateLimit> {
+        match key {
+            RateLimitKey::Ip(_) => self.per_ip,
+            RateLimitKey::User(_) => self.per_user,
+            RateLimitKey::Recipient(_) => self.per_recipient,
+        }
+    }
+}
+
+/// Budgets of the routes that create rows for unauthenticated or cheap requests.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RateLimitPolicy {
+    pub login: RouteBudget,
+    pub registration: RouteBudget,
+    pub stamp_request: RouteBudget,
+    pub federation: RouteBudget,
+}
+
+impl RateLimitPolicy {
+    pub fn budget(&self, class: RouteClass) -> &RouteBudget {
+        match class {
+            RouteClass::Login => &self.login,
+            RouteClass::Registration => &self.registration,
+            RouteClass::StampRequest => &self.stamp_request,
+            RouteClass::Federation => &self.federation,
+        }
+    }
+}
+
+/// Counts requests per key and window.
+#[async_trait]
+pub trait RateLimiter {
+    /// Counts a request against `key` in the window ending at `window_end`, returning how many
+    /// have been counted in it, this one included.
+    async fn hit(&self, key: &str, window_end: DateTime<Utc>) -> Result<i64, SmError>;
+    /// Forgets windows that ended before the given time, returning how many were forgotten.
+    async fn purge(&self, ended_before: DateTime<Utc>) -> Result<u64, SmError>;
+}
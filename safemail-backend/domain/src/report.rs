@@ -0,0 +1,54 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::error::SmError;
+
+/// What happens automatically when a message is reported.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReportPolicy {
+    /// Revoke the sender's outstanding stamps to the reporter.
+    pub revoke_stamps: bool,
+    /// Each user who reported a sender multiplies the difficulty of their system stamps by this.
+    pub difficulty_factor: i64,
+    /// Flag senders for admin review once this many users reported them. Zero never flags.
+    pub review_threshold: i64,
+}
+
+pub struct NewReport {
+    pub reporter_id: Uuid,
+    pub message_id: i64,
+    pub sender_id: Uuid,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Report {
+    pub id: i64,
+    pub reporter_id: Uuid,
+    pub message_id: i64,
+    pub sender_id: Uuid,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// When the sender was flagged for review, if they are.
+    pub sender_flagged_at: Option<DateTime<Utc>>,
+}
+
+#[async_trait]
+pub trait ReportRepository {
+    /// Returns the id of the new report, or `None` if the reporter already reported the message.
+    async fn create_report(&self, report: NewReport) -> Result<Option<i64>, SmError>;
+    /// Counts the users who reported the sender, however many messages each of them reported.
+    async fn count_reports_against(&self, sender_id: Uuid) -> Result<i64, SmError>;
+    /// Flagging a sender that is already flagged keeps the original time.
+    async fn flag_for_review(&self, sender_id: Uuid) -> Result<(), SmError>;
+    /// Lists up to `limit` reports, newest first, starting right below `before_id`.
+    async fn list_reports(
+        &self,
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Report>, SmError>;
+}
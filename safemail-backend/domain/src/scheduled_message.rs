@@ -0,0 +1,71 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::error::SmError;
+use crate::message::{Delivery, MailboxQuota, NewMessage};
+use crate::outbox::NewOutboxEntry;
+use crate::stamp::RecipientStamp;
+
+pub struct NewScheduledMessage {
+    pub sender_id: Uuid,
+    pub message: NewMessage,
+    /// Moved to the sender's outbox on delivery.
+    pub outbox_entry: Option<NewOutboxEntry>,
+    /// Only kept when the stamp is to be re-checked on delivery.
+    pub stamp: Option<RecipientStamp>,
+    pub deliver_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ScheduledMessageSummary {
+    pub id: i64,
+    pub recipient_id: Uuid,
+    pub deliver_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    /// Why the message could not be delivered. Pending messages have none.
+    pub failure: Option<String>,
+}
+
+/// A pending message whose delivery time has come.
+pub struct DueMessage {
+    pub id: i64,
+    pub sender_id: Uuid,
+    pub recipient_id: Uuid,
+    pub stamp: Option<RecipientStamp>,
+    /// The number of deliveries tried before, that failed for reasons that may pass.
+    pub attempts: i32,
+}
+
+#[async_trait]
+pub trait ScheduledMessageRepository {
+    async fn schedule_message(&self, message: NewScheduledMessage) -> Result<i64, SmError>;
+    /// Lists the sender's pending and failed messages in ascending id order.
+    async fn list_scheduled_messages(
+        &self,
+        sender_id: Uuid,
+    ) -> Result<Vec<ScheduledMessageSummary>, SmError>;
+    async fn count_scheduled_messages(&self, sender_id: Uuid) -> Result<i64, SmError>;
+    /// Returns whether the message was still scheduled, i.e. not yet delivered.
+    async fn cancel_scheduled_message(&self, sender_id: Uuid, id: i64) -> Result<bool, SmError>;
+    /// Lists up to `limit` pending messages that are due, and not put off to a later attempt,
+    /// oldest delivery time first.
+    async fn list_due_messages(&self, limit: i64) -> Result<Vec<DueMessage>, SmError>;
+    /// Moves a pending message into the recipient's mailbox, and its sender copy into the
+    /// outbox, in one go. Returns `None` if the message was cancelled in the meantime.
+    async fn deliver_scheduled_message(
+        &self,
+        id: i64,
+        quota: &MailboxQuota,
+    ) -> Result<Option<Delivery>, SmError>;
+    /// Keeps the message out of further dispatches, for the sender to see why.
+    async fn fail_scheduled_message(&self, id: i64, failure: &str) -> Result<(), SmError>;
+    /// Counts a failed attempt and puts the next one off until `retry_at`.
+    async fn defer_scheduled_message(
+        &self,
+        id: i64,
+        retry_at: DateTime<Utc>,
+    ) -> Result<(), SmError>;
+}
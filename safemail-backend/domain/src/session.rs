@@ -1,11 +1,12 @@
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 use crate::error::SmError;
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct Session {
     pub session_id: Uuid,
     pub user_id: Uuid,
@@ -16,9 +17,21 @@ pub struct Session {
     pub expires_at_utc: DateTime<Utc>,
 }
 
+/// How long sessions last.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SessionPolicy {
+    /// Counted from when the session is requested, not from when it is activated.
+    pub length_minutes: i64,
+}
+
 #[async_trait]
 pub trait SessionRepository {
-    async fn request_session(&self, user_id: Uuid) -> Result<Session, SmError>;
+    async fn request_session(
+        &self,
+        user_id: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Session, SmError>;
     async fn activate_session(&self, session_id: Uuid) -> Result<(), SmError>;
     async fn get_session(
         &self,
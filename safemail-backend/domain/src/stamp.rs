@@ -1,8 +1,9 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
 pub struct PeriodicStamp {
     pub issuer_id: Uuid,
     pub recipient_id: Uuid,
@@ -12,7 +13,7 @@ pub struct PeriodicStamp {
     pub signature: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
 pub struct OnetimeStamp {
     pub stamp_id: Uuid,
     pub issuer_id: Uuid,
@@ -22,10 +23,57 @@ pub struct OnetimeStamp {
     pub signature: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// The stamp authorizing a message to one recipient, of either kind.
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RecipientStamp {
+    Periodic(PeriodicStamp),
+    Onetime(OnetimeStamp),
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct OneTimeStampRequest {
     pub stamp_request_id: Uuid,
+    /// The average number of hashes a proof of work takes. A proof has to score at least
+    /// `u128::MAX - u128::MAX / difficulty`, except in v1 of the API, where a score of
+    /// `difficulty` does as long as it is the base difficulty.
     pub difficulty: i64,
     pub valid_to: DateTime<Utc>,
     pub solved_at: Option<DateTime<Utc>>,
 }
+
+/// A solved stamp request, in the form `pow::Pow` is serialized in.
+#[allow(dead_code)]
+#[derive(ToSchema)]
+pub struct ProofOfWork {
+    /// Nonce that brings the hash of the request id above the requested difficulty.
+    proof: u128,
+}
+
+/// What system stamps cost and how long they are good for.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StampPolicy {
+    /// Difficulty of a stamp request for a sender nobody has reported.
+    pub base_difficulty: i64,
+    /// Reports never raise the difficulty past this.
+    pub max_difficulty: i64,
+    /// How long a sender has to solve a stamp request.
+    pub request_validity_minutes: i64,
+    /// How long an issued stamp can be sent with.
+    pub validity_minutes: i64,
+}
+
+impl StampPolicy {
+    /// The difficulty for a sender with `reports` reports against them, each multiplying it by
+    /// `factor`.
+    pub fn difficulty(&self, reports: i64, factor: i64) -> i64 {
+        (0..reports)
+            .try_fold(self.base_difficulty, |difficulty, _| {
+                difficulty
+                    .checked_mul(factor.max(1))
+                    .filter(|difficulty| *difficulty < self.max_difficulty)
+            })
+            .unwrap_or(self.max_difficulty)
+    }
+}
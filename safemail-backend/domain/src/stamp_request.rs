@@ -6,6 +6,7 @@ use uuid::Uuid;
 pub struct OnetimeStampRequest {
     pub stamp_request_id: Uuid,
     pub recipient_id: Uuid,
+    pub sender_id: Uuid,
     pub difficulty: i64,
     pub valid_to: DateTime<Utc>,
     pub solved_at: Option<DateTime<Utc>>,
@@ -17,10 +18,13 @@ pub trait StampRequestRepository {
         &self,
         difficulty: i64,
         recipient_id: Uuid,
+        sender_id: Uuid,
+        valid_to: DateTime<Utc>,
     ) -> Result<Uuid, SmError>;
     async fn get_stamp_request(
         &self,
         stamp_request_id: Uuid,
     ) -> Result<Option<OnetimeStampRequest>, SmError>;
-    async fn mark_solved(&self, stamp_request_id: Uuid) -> Result<(), SmError>;
+    /// Marks the request solved unless it already was, returning whether it was marked.
+    async fn mark_solved(&self, stamp_request_id: Uuid) -> Result<bool, SmError>;
 }
@@ -0,0 +1,18 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::error::SmError;
+
+#[async_trait]
+pub trait StampRevocationRepository {
+    /// Revokes the sender's unused one-time stamps to the recipient, along with every periodic
+    /// stamp valid from before now.
+    async fn revoke_stamps(&self, recipient_id: Uuid, sender_id: Uuid) -> Result<(), SmError>;
+    /// When the sender's periodic stamps to the recipient were last revoked.
+    async fn get_periodic_revocation(
+        &self,
+        recipient_id: Uuid,
+        sender_id: Uuid,
+    ) -> Result<Option<DateTime<Utc>>, SmError>;
+}
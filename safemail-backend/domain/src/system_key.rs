@@ -10,4 +10,5 @@ pub struct SystemKeyPair {
 pub trait SystemKeyRepository {
     async fn init_system_keys(&self, system_key: SystemKeyPair) -> Result<(), SmError>;
     async fn get_system_keys(&self) -> Result<Option<SystemKeyPair>, SmError>;
+    async fn update_public_key(&self, public_key: String) -> Result<(), SmError>;
 }
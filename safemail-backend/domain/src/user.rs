@@ -1,10 +1,11 @@
 use async_trait::async_trait;
 use serde::Serialize;
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 use crate::error::SmError;
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct User {
     pub id: Uuid,
     pub username: String,
@@ -22,6 +23,7 @@ pub trait UserRepository {
     ) -> Result<User, SmError>;
     async fn find_by_username(&self, username: String) -> Result<Option<User>, SmError>;
     async fn find_by_id(&self, id: Uuid) -> Result<Option<User>, SmError>;
+    async fn is_admin(&self, id: Uuid) -> Result<bool, SmError>;
 }
 
 pub trait PasswordService {
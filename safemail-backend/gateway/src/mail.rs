@@ -0,0 +1,33 @@
+use domain::chrono;
+use mail_parser::{Address, MessageParser};
+use serde_json::json;
+
+/// The metadata of received mail: its SMTP envelope and the headers a mail client lists
+/// messages by. The whole message, headers included, becomes the content.
+pub fn metadata(mail_from: &str, rcpt_to: &str, message: &[u8]) -> Vec<u8> {
+    let parsed = MessageParser::default().parse(message);
+    let headers = parsed.as_ref();
+    let metadata = json!({
+        "source": "smtp",
+        "mail_from": mail_from,
+        "rcpt_to": rcpt_to,
+        "received_at": chrono::Utc::now(),
+        "from": headers.and_then(|message| message.from()).map(addresses),
+        "to": headers.and_then(|message| message.to()).map(addresses),
+        "cc": headers.and_then(|message| message.cc()).map(addresses),
+        "subject": headers.and_then(|message| message.subject()),
+        "date": headers.and_then(|message| message.date()).map(|date| date.to_rfc3339()),
+        "message_id": headers.and_then(|message| message.message_id()),
+    });
+    serde_json::to_vec(&metadata).expect("Mail metadata failed to serialize")
+}
+
+fn addresses(address: &Address) -> Vec<String> {
+    address
+        .iter()
+        .map(|addr| match (addr.name(), addr.address()) {
+            (Some(name), Some(address)) => format!("{} <{}>", name, address),
+            (name, address) => name.or(address).unwrap_or_default().to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,33 @@
+use std::sync::Arc;
+
+use infrastructure::config::Config;
+use tokio::net::TcpListener;
+
+use crate::state::GatewayState;
+
+mod mail;
+mod smtp;
+mod state;
+mod tasks;
+#[cfg(test)]
+mod tests;
+
+/// Receives mail over SMTP and delivers it, encrypted, to the mailboxes of safemail users, and
+/// relays the mail they hand to the bridge. There is no TLS or authentication, so it is meant to
+/// listen locally behind an MTA that handles those.
+#[tokio::main]
+async fn main() {
+    // load env vars
+    dotenvy::dotenv().ok();
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let state = Arc::new(GatewayState::new(&config).await);
+    tasks::spawn_outbound_relay(state.clone());
+
+    let addr = config.gateway.listen_addr;
+    let listener = TcpListener::bind(addr).await.unwrap();
+    println!("listening on smtp://{}", addr);
+    smtp::listen(listener, state).await;
+}
@@ -0,0 +1,395 @@
+use std::{io, net::IpAddr, sync::Arc, time::Duration};
+
+use application::{
+    gateway::commands::DeliverGatewayMailCommand, user::queries::GetUserByUsernameQuery,
+};
+use domain::{
+    error::{MessageError, SmError, StampError, UserError},
+    uuid::Uuid,
+};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    time::{sleep, timeout},
+};
+
+use crate::{mail, state::GatewayState};
+
+const IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// RFC 5321 allows 512 octets per command line, some room is left for extensions.
+const MAX_COMMAND_LENGTH: usize = 1024;
+
+struct Recipient {
+    id: Uuid,
+    address: String,
+}
+
+/// The state of one mail transaction, from `MAIL` to the end of `DATA` or a reset.
+#[derive(Default)]
+struct Transaction {
+    mail_from: Option<String>,
+    recipients: Vec<Recipient>,
+}
+
+/// Accepts SMTP sessions for as long as the listener is open, turning away clients that already
+/// have as many sessions open as they are allowed.
+pub async fn listen(listener: TcpListener, state: Arc<GatewayState>) {
+    loop {
+        let (mut stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                eprintln!("failed to accept connection: {}", e);
+                continue;
+            }
+        };
+        let state = state.clone();
+        tokio::spawn(async move {
+            let Some(_session) = Session::open(&state, peer.ip()) else {
+                let _ = reply(
+                    &mut stream,
+                    421,
+                    "4.7.0 Too many connections, try again later",
+                )
+                .await;
+                return;
+            };
+            if let Err(e) = serve(stream, peer.ip(), &state).await {
+                eprintln!("smtp session with {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+/// What sessions are counted against: the client address, or for IPv6 the /64 network, which
+/// a single client commonly has all of.
+pub(crate) fn session_key(ip: IpAddr) -> IpAddr {
+    match ip.to_canonical() {
+        IpAddr::V6(ip) => IpAddr::V6((u128::from(ip) & !(u64::MAX as u128)).into()),
+        ip => ip,
+    }
+}
+
+/// An open session, counted against its client's [`session_key`] until it is dropped.
+struct Session<'a> {
+    state: &'a GatewayState,
+    ip: IpAddr,
+}
+
+impl<'a> Session<'a> {
+    /// Counts a session from `ip`, or gives `None` if it already has as many as allowed.
+    fn open(state: &'a GatewayState, ip: IpAddr) -> Option<Self> {
+        let ip = session_key(ip);
+        let mut sessions = state.sessions.lock().unwrap();
+        let open = sessions.entry(ip).or_default();
+        if *open >= state.max_sessions_per_ip {
+            return None;
+        }
+        *open += 1;
+        Some(Self { state, ip })
+    }
+}
+
+impl Drop for Session<'_> {
+    fn drop(&mut self) {
+        let mut sessions = self.state.sessions.lock().unwrap();
+        if let Some(open) = sessions.get_mut(&self.ip) {
+            *open -= 1;
+            if *open == 0 {
+                sessions.remove(&self.ip);
+            }
+        }
+    }
+}
+
+/// Runs an SMTP session until the client quits, disconnects or idles for too long.
+pub async fn serve(stream: TcpStream, client: IpAddr, state: &GatewayState) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let hostname = state
+        .domains
+        .first()
+        .map(String::as_str)
+        .unwrap_or("localhost");
+    let Some(cost) = state.sender_policy.cost(client) else {
+        return reply(&mut writer, 554, "5.7.1 Client not allowed").await;
+    };
+    reply(
+        &mut writer,
+        220,
+        &format!("{} Safemail gateway ready", hostname),
+    )
+    .await?;
+
+    let mut greeted = false;
+    let mut transaction = Transaction::default();
+    loop {
+        let line = match timeout(IDLE_TIMEOUT, read_line(&mut reader, MAX_COMMAND_LENGTH)).await {
+            Ok(Ok(Some(line))) => line,
+            Ok(Ok(None)) => return Ok(()),
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return reply(&mut writer, 421, "4.4.2 Idle for too long").await,
+        };
+        if !line.ends_with(b"\n") {
+            discard_line(&mut reader).await?;
+            reply(&mut writer, 500, "5.5.2 Line too long").await?;
+            continue;
+        }
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim_end();
+        let (verb, argument) = line.split_once(' ').unwrap_or((line, ""));
+        match verb.to_ascii_uppercase().as_str() {
+            "HELO" => {
+                greeted = true;
+                transaction = Transaction::default();
+                reply(&mut writer, 250, hostname).await?;
+            }
+            "EHLO" => {
+                greeted = true;
+                transaction = Transaction::default();
+                let greeting = format!(
+                    "250-{}\r\n250-SIZE {}\r\n250-8BITMIME\r\n250 SMTPUTF8\r\n",
+                    hostname, state.max_message_bytes
+                );
+                writer.write_all(greeting.as_bytes()).await?;
+            }
+            "MAIL" => {
+                if !greeted {
+                    reply(&mut writer, 503, "5.5.1 Send HELO or EHLO first").await?;
+                    continue;
+                }
+                if transaction.mail_from.is_some() {
+                    reply(&mut writer, 503, "5.5.1 Sender already given").await?;
+                    continue;
+                }
+                let Some((mail_from, parameters)) = parse_path(argument, "FROM:") else {
+                    reply(&mut writer, 501, "5.5.4 Syntax: MAIL FROM:<address>").await?;
+                    continue;
+                };
+                if declared_size(parameters).is_some_and(|size| size > state.max_message_bytes) {
+                    reply(&mut writer, 552, "5.3.4 Message too big").await?;
+                    continue;
+                }
+                transaction.mail_from = Some(mail_from);
+                reply(&mut writer, 250, "2.1.0 OK").await?;
+            }
+            "RCPT" => {
+                if transaction.mail_from.is_none() {
+                    reply(&mut writer, 503, "5.5.1 Need MAIL before RCPT").await?;
+                    continue;
+                }
+                let Some((address, _)) = parse_path(argument, "TO:") else {
+                    reply(&mut writer, 501, "5.5.4 Syntax: RCPT TO:<address>").await?;
+                    continue;
+                };
+                if transaction.recipients.len() >= state.max_recipients {
+                    reply(&mut writer, 452, "4.5.3 Too many recipients").await?;
+                    continue;
+                }
+                let (code, text) = match find_recipient(&address, state).await {
+                    Ok(recipient) => {
+                        // What a stranger pays for the recipient: the session is held up, and
+                        // there are only a few of those to go around
+                        sleep(cost).await;
+                        transaction.recipients.push(recipient);
+                        (250, "2.1.5 OK")
+                    }
+                    Err(rejection) => rejection,
+                };
+                reply(&mut writer, code, text).await?;
+            }
+            "DATA" => {
+                let Some(mail_from) = transaction.mail_from.take() else {
+                    reply(&mut writer, 503, "5.5.1 Need MAIL before DATA").await?;
+                    continue;
+                };
+                if transaction.recipients.is_empty() {
+                    transaction = Transaction::default();
+                    reply(&mut writer, 554, "5.5.1 No valid recipients").await?;
+                    continue;
+                }
+                reply(&mut writer, 354, "End data with <CR><LF>.<CR><LF>").await?;
+                let Some(message) = read_message(&mut reader, state.max_message_bytes).await?
+                else {
+                    return Ok(());
+                };
+                let transaction = std::mem::take(&mut transaction);
+                let (code, text) = match message {
+                    Some(message) => deliver(&mail_from, transaction, message, state).await,
+                    None => (552, "5.3.4 Message too big"),
+                };
+                reply(&mut writer, code, text).await?;
+            }
+            "RSET" => {
+                transaction = Transaction::default();
+                reply(&mut writer, 250, "2.0.0 OK").await?;
+            }
+            "NOOP" => reply(&mut writer, 250, "2.0.0 OK").await?,
+            "VRFY" => reply(&mut writer, 252, "2.5.0 Cannot VRFY user").await?,
+            "QUIT" => return reply(&mut writer, 221, "2.0.0 Bye").await,
+            _ => reply(&mut writer, 502, "5.5.2 Command not recognized").await?,
+        }
+    }
+}
+
+async fn reply(writer: &mut (impl AsyncWrite + Unpin), code: u16, text: &str) -> io::Result<()> {
+    writer
+        .write_all(format!("{} {}\r\n", code, text).as_bytes())
+        .await
+}
+
+/// Reads a line of at most `limit` bytes, newline included, or `None` at the end of the
+/// stream. A line that doesn't end in a newline was cut off at the limit.
+async fn read_line(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    limit: usize,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    reader
+        .take(limit as u64)
+        .read_until(b'\n', &mut line)
+        .await?;
+    Ok((!line.is_empty()).then_some(line))
+}
+
+async fn discard_line(reader: &mut (impl AsyncBufRead + Unpin)) -> io::Result<()> {
+    while let Some(line) = read_line(reader, MAX_COMMAND_LENGTH).await? {
+        if line.ends_with(b"\n") {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the message following `DATA` up to the terminating dot, undoing dot stuffing.
+/// Gives `Some(None)` for a message over `max_bytes`, which is read to its end and dropped,
+/// and `None` if the client disconnected or went idle first.
+async fn read_message(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    max_bytes: usize,
+) -> io::Result<Option<Option<Vec<u8>>>> {
+    let mut message = Vec::new();
+    let mut too_big = false;
+    loop {
+        let line = match timeout(IDLE_TIMEOUT, read_line(reader, max_bytes + 3)).await {
+            Ok(Ok(Some(line))) => line,
+            Ok(Ok(None)) | Err(_) => return Ok(None),
+            Ok(Err(e)) => return Err(e),
+        };
+        if line == b".\r\n" || line == b".\n" {
+            break;
+        }
+        let line = line.strip_prefix(b".").unwrap_or(&line);
+        if message.len() + line.len() > max_bytes {
+            too_big = true;
+        }
+        if !too_big {
+            message.extend_from_slice(line);
+        }
+    }
+    Ok(Some((!too_big).then_some(message)))
+}
+
+/// Parses the `<address>` of a `MAIL FROM:` or `RCPT TO:` argument, returning it with the
+/// parameters after it.
+fn parse_path<'a>(argument: &'a str, prefix: &str) -> Option<(String, &'a str)> {
+    let head = argument.get(..prefix.len())?;
+    if !head.eq_ignore_ascii_case(prefix) {
+        return None;
+    }
+    let path = argument[prefix.len()..].trim_start().strip_prefix('<')?;
+    let (address, parameters) = path.split_once('>')?;
+    Some((address.to_string(), parameters))
+}
+
+/// The `SIZE=` a client declared with `MAIL FROM`.
+fn declared_size(parameters: &str) -> Option<usize> {
+    parameters.split_whitespace().find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+        name.eq_ignore_ascii_case("SIZE")
+            .then(|| value.parse().ok())
+            .flatten()
+    })
+}
+
+async fn find_recipient(
+    address: &str,
+    state: &GatewayState,
+) -> Result<Recipient, (u16, &'static str)> {
+    let Some((username, domain)) = address.rsplit_once('@') else {
+        return Err((550, "5.1.3 Recipient must be a full address"));
+    };
+    if !state.domains.contains(&domain.to_ascii_lowercase()) {
+        return Err((550, "5.7.1 Relaying not permitted"));
+    }
+    let user = GetUserByUsernameQuery {
+        username: username.to_string(),
+    }
+    .handle(&state.user_repository)
+    .await
+    .map_err(|e| {
+        eprintln!("failed to look up recipient {}: {}", address, e);
+        (451, "4.3.0 Temporary failure, try again later")
+    })?;
+    match user {
+        Some(user) if user.id != state.gateway.id => Ok(Recipient {
+            id: user.id,
+            address: address.to_string(),
+        }),
+        _ => Err((550, "5.1.1 No such user")),
+    }
+}
+
+/// Delivers a message to every recipient of the transaction. There is only one reply to the
+/// whole message, so recipients that can't take it are merely logged once any other did.
+async fn deliver(
+    mail_from: &str,
+    transaction: Transaction,
+    message: Vec<u8>,
+    state: &GatewayState,
+) -> (u16, &'static str) {
+    let mut delivered = false;
+    let mut failure = None;
+    for recipient in transaction.recipients {
+        let result = DeliverGatewayMailCommand {
+            gateway_id: state.gateway.id,
+            recipient_id: recipient.id,
+            content: message.clone(),
+            metadata: mail::metadata(mail_from, &recipient.address, &message),
+        }
+        .handle(
+            &state.user_repository,
+            &state.cryptography_service,
+            &state.serialize_service,
+            &state.tracker_repository,
+            &state.system_key_repository,
+            &state.message_repository,
+            &state.blob_repository,
+            &state.block_list_repository,
+            &state.stamp_revocation_repository,
+            &state.mailbox_quota,
+        )
+        .await;
+        match result {
+            Ok(_) => delivered = true,
+            Err(e) => {
+                eprintln!("failed to deliver mail to {}: {}", recipient.address, e);
+                failure.get_or_insert(e);
+            }
+        }
+    }
+    match failure {
+        Some(e) if !delivered => rejection(&e),
+        _ => (250, "2.0.0 Delivered"),
+    }
+}
+
+fn rejection(error: &SmError) -> (u16, &'static str) {
+    match error {
+        SmError::Message(MessageError::MailboxFull) => (552, "5.2.2 Mailbox full"),
+        // Blocked senders are told no more than that
+        SmError::Stamp(StampError::InvalidStamp) => (550, "5.7.1 Message refused"),
+        SmError::User(UserError::UserNotFound) => (550, "5.1.1 No such user"),
+        SmError::User(UserError::InvalidPublicKey) => (550, "5.1.1 Recipient can't receive mail"),
+        _ => (451, "4.3.0 Temporary failure, try again later"),
+    }
+}
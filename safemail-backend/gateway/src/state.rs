@@ -0,0 +1,137 @@
+use std::{collections::HashMap, net::IpAddr, sync::Mutex, time::Duration};
+
+use application::{
+    gateway::commands::InitGatewayAccountCommand, system_key::commands::InitSystemKeysCommand,
+};
+use domain::{message::MailboxQuota, user::User};
+use infrastructure::{
+    config::Config,
+    repositories::{
+        PostgresBlobRepository, PostgresBlockListRepository, PostgresMessageRepository,
+        PostgresOneTimeStampRepository, PostgresOutboundMailRepository,
+        PostgresStampRevocationRepository, PostgresSystemKeyRepository, PostgresUserRepository,
+    },
+    services::{
+        content_store::ConfiguredContentStore, cryptography::OpensslCryptographyService,
+        serialize::JsonService, smtp::LettreSmtpRelay,
+    },
+};
+use ipnet::IpNet;
+
+/// What SMTP clients are charged for getting mail into a mailbox. Strangers pay in time: the
+/// gateway holds up the acceptance of each of their recipients, which keeps their connection,
+/// one of the few they are allowed, busy all the while.
+pub struct SenderPolicy {
+    /// Client networks whose mail is delivered for free.
+    pub allowlist: Vec<IpNet>,
+    /// Refuse connections from anyone not on the allowlist.
+    pub allowlist_only: bool,
+    /// How long the acceptance of every recipient of mail from anyone else is held up.
+    pub tarpit: Duration,
+}
+
+impl SenderPolicy {
+    /// The delay to charge each recipient of mail from a client at `ip`, or `None` if it is
+    /// refused.
+    pub fn cost(&self, ip: IpAddr) -> Option<Duration> {
+        let ip = ip.to_canonical();
+        let allowed = self.allowlist.iter().any(|network| network.contains(&ip));
+        match (allowed, self.allowlist_only) {
+            (true, _) => Some(Duration::ZERO),
+            (false, true) => None,
+            (false, false) => Some(self.tarpit),
+        }
+    }
+}
+
+pub struct GatewayState {
+    pub user_repository: PostgresUserRepository,
+    pub message_repository: PostgresMessageRepository,
+    pub tracker_repository: PostgresOneTimeStampRepository,
+    pub stamp_revocation_repository: PostgresStampRevocationRepository,
+    pub system_key_repository: PostgresSystemKeyRepository,
+    pub blob_repository: PostgresBlobRepository,
+    pub block_list_repository: PostgresBlockListRepository,
+    pub outbound_mail_repository: PostgresOutboundMailRepository,
+    pub smtp_relay: LettreSmtpRelay,
+    pub mailbox_quota: MailboxQuota,
+    pub cryptography_service: OpensslCryptographyService,
+    pub serialize_service: JsonService,
+    /// The account gateway mail is sent from.
+    pub gateway: User,
+    /// Mail domains whose local parts are looked up as usernames. Bridged mail is sent from
+    /// the first.
+    pub domains: Vec<String>,
+    pub sender_policy: SenderPolicy,
+    pub max_message_bytes: usize,
+    pub max_recipients: usize,
+    pub max_sessions_per_ip: usize,
+    /// The number of open SMTP sessions from each client address, or /64 network for IPv6.
+    pub sessions: Mutex<HashMap<IpAddr, usize>>,
+}
+
+impl GatewayState {
+    pub async fn new(config: &Config) -> Self {
+        let settings = &config.gateway;
+        let db = infrastructure::db::get_pool(&config.database.url).await;
+        let content_store = ConfiguredContentStore::new(&config.content_store, db.clone());
+        let sender_policy = SenderPolicy {
+            allowlist: settings.allowlist.clone(),
+            allowlist_only: settings.allowlist_only,
+            tarpit: Duration::from_secs(settings.tarpit_seconds),
+        };
+        let user_repository = PostgresUserRepository::new(db.clone());
+        let message_repository = PostgresMessageRepository::new(
+            db.clone(),
+            content_store.clone(),
+            config.content_store.out_of_line_threshold,
+        );
+        let tracker_repository = PostgresOneTimeStampRepository::new(db.clone());
+        let stamp_revocation_repository = PostgresStampRevocationRepository::new(db.clone());
+        let system_key_repository = PostgresSystemKeyRepository::new(db.clone());
+        let blob_repository = PostgresBlobRepository::new(db.clone(), content_store);
+        let block_list_repository = PostgresBlockListRepository::new(db.clone());
+        let outbound_mail_repository = PostgresOutboundMailRepository::new(db.clone());
+        let smtp_relay = LettreSmtpRelay::new(&config.smtp_relay);
+
+        let cryptography_service = OpensslCryptographyService;
+        let serialize_service = JsonService;
+
+        InitSystemKeysCommand
+            .handle(&system_key_repository, &cryptography_service)
+            .await
+            .expect("Failed to initialize system keys");
+        let gateway = InitGatewayAccountCommand {
+            username: settings.username.clone(),
+        }
+        .handle(&user_repository, &system_key_repository)
+        .await
+        .expect("Failed to set up the gateway account");
+
+        Self {
+            user_repository,
+            message_repository,
+            tracker_repository,
+            stamp_revocation_repository,
+            system_key_repository,
+            blob_repository,
+            block_list_repository,
+            outbound_mail_repository,
+            smtp_relay,
+            mailbox_quota: config.mailbox,
+            cryptography_service,
+            serialize_service,
+            gateway,
+            domains: settings
+                .domains
+                .iter()
+                .map(|domain| domain.to_ascii_lowercase())
+                .collect(),
+            sender_policy,
+            max_message_bytes: settings.max_message_bytes,
+            max_recipients: settings.max_recipients,
+            max_sessions_per_ip: settings.max_sessions_per_ip,
+            sessions: Mutex::default(),
+        }
+    }
+}
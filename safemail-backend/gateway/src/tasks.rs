@@ -0,0 +1,40 @@
+use std::{sync::Arc, time::Duration};
+
+use application::bridge::commands::RelayDueOutboundMailCommand;
+
+use crate::state::GatewayState;
+
+const OUTBOUND_RELAY_INTERVAL: Duration = Duration::from_secs(10);
+const OUTBOUND_RELAY_BATCH_SIZE: i64 = 50;
+
+/// Relays the mail users handed to the bridge to its SMTP recipients.
+pub fn spawn_outbound_relay(state: Arc<GatewayState>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(OUTBOUND_RELAY_INTERVAL);
+        let domain = state
+            .domains
+            .first()
+            .cloned()
+            .expect("SM_GATEWAY_DOMAINS names no domain to send bridged mail from");
+        loop {
+            interval.tick().await;
+            let command = RelayDueOutboundMailCommand {
+                domain: domain.clone(),
+                batch_size: OUTBOUND_RELAY_BATCH_SIZE,
+            };
+            if let Err(e) = command
+                .handle(
+                    &state.user_repository,
+                    &state.outbound_mail_repository,
+                    &state.system_key_repository,
+                    &state.cryptography_service,
+                    &state.serialize_service,
+                    &state.smtp_relay,
+                )
+                .await
+            {
+                eprintln!("failed to relay outbound mail: {}", e);
+            }
+        }
+    });
+}
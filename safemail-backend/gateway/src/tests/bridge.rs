@@ -0,0 +1,140 @@
+use application::bridge::commands::{
+    RelayDueOutboundMailCommand, RelayOutboundMailCommand, RelayReceipt,
+};
+use domain::{
+    crypto::CryptographyService,
+    error::{BridgeError, SmError},
+    outbound_mail::BridgePolicy,
+    serialize::SerializeService,
+    system_key::SystemKeyRepository,
+};
+use futures::future::join_all;
+
+use super::{SmtpSink, TestGateway, TestUser};
+
+const POLICY: BridgePolicy = BridgePolicy {
+    max_recipients: 20,
+    max_recipients_per_hour: 3,
+};
+
+/// Starts a gateway relaying bridged mail through the sink.
+async fn gateway(sink: &SmtpSink) -> TestGateway {
+    let (host, port) = (sink.address.ip().to_string(), sink.address.port());
+    TestGateway::start_with(|config| {
+        config.smtp_relay.host = host;
+        config.smtp_relay.port = port;
+    })
+    .await
+}
+
+/// Hands the message to the bridge the way the API does for `sender`.
+async fn hand_over(
+    gateway: &TestGateway,
+    sender: &TestUser,
+    recipients: &[&str],
+    message: &str,
+) -> Result<RelayReceipt, SmError> {
+    let state = &gateway.state;
+    let system_keys = state
+        .system_key_repository
+        .get_system_keys()
+        .await
+        .unwrap()
+        .unwrap();
+    let recipients: Vec<String> = recipients.iter().map(|r| r.to_string()).collect();
+    let content = state
+        .cryptography_service
+        .encrypt(message.as_bytes(), &system_keys.public_key)
+        .unwrap();
+    let plaintext = format!(
+        "{}\n{}",
+        state.serialize_service.serialize(&recipients),
+        content
+    );
+    let signature = state
+        .cryptography_service
+        .produce_signature(&plaintext, &sender.verify_key)
+        .unwrap();
+    RelayOutboundMailCommand {
+        sender_id: sender.id,
+        recipients,
+        content,
+        signature,
+    }
+    .handle(
+        &state.user_repository,
+        &state.outbound_mail_repository,
+        &state.system_key_repository,
+        &state.cryptography_service,
+        &state.serialize_service,
+        &POLICY,
+    )
+    .await
+}
+
+async fn relay_due_mail(gateway: &TestGateway) -> usize {
+    let state = &gateway.state;
+    RelayDueOutboundMailCommand {
+        domain: "localhost".to_string(),
+        batch_size: 50,
+    }
+    .handle(
+        &state.user_repository,
+        &state.outbound_mail_repository,
+        &state.system_key_repository,
+        &state.cryptography_service,
+        &state.serialize_service,
+        &state.smtp_relay,
+    )
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn bridged_mail_is_sent_once_under_its_senders_address() {
+    let sink = SmtpSink::start().await;
+    let gateway = gateway(&sink).await;
+    let alice = gateway.user("alice").await;
+    let message = "From: Bob <bob@localhost>\r\nSender: bob@localhost,\r\n\tbob@example.com\r\n\
+                   To: carol@example.com\r\nSubject: Hi\r\n\r\nFrom: the body stays\r\n";
+    hand_over(&gateway, &alice, &["carol@example.com"], message)
+        .await
+        .unwrap();
+
+    // Two relays at once, as two gateways on the same database would run them
+    let (first, second) = tokio::join!(relay_due_mail(&gateway), relay_due_mail(&gateway));
+    assert_eq!(first + second, 1);
+    let received = sink.received();
+    assert_eq!(received.len(), 1);
+    let mail = &received[0];
+    assert_eq!(mail.mail_from, alice.address);
+    assert_eq!(mail.recipients, ["carol@example.com"]);
+    assert!(!mail.data.contains("bob@"), "{}", mail.data);
+    assert!(
+        mail.data
+            .starts_with("From: <alice@localhost>\r\nTo: carol@example.com\r\n"),
+        "{}",
+        mail.data
+    );
+    assert!(mail.data.contains("\r\n\r\nFrom: the body stays\r\n"));
+
+    assert_eq!(relay_due_mail(&gateway).await, 0);
+    assert_eq!(sink.received().len(), 1);
+}
+
+#[tokio::test]
+async fn the_hourly_limit_holds_for_mail_handed_over_at_once() {
+    let sink = SmtpSink::start().await;
+    let gateway = gateway(&sink).await;
+    let alice = gateway.user("alice").await;
+    let message = "Subject: Hi\r\n\r\nHello.\r\n";
+
+    let attempts = (0..8).map(|_| hand_over(&gateway, &alice, &["carol@example.com"], message));
+    let results = join_all(attempts).await;
+    let accepted = results.iter().filter(|result| result.is_ok()).count();
+    assert_eq!(accepted, POLICY.max_recipients_per_hour as usize);
+    assert!(results.iter().all(|result| matches!(
+        result,
+        Ok(_) | Err(SmError::Bridge(BridgeError::RateLimited))
+    )));
+}
@@ -0,0 +1,123 @@
+use std::{
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use super::TestGateway;
+use crate::smtp::session_key;
+
+#[tokio::test]
+async fn mail_from_allowlisted_networks_is_delivered_at_once() {
+    let gateway = TestGateway::start_with(|config| {
+        config.gateway.allowlist = vec!["127.0.0.0/8".parse().unwrap()];
+        config.gateway.tarpit_seconds = 60;
+    })
+    .await;
+    let alice = gateway.user("alice").await;
+    let bob = gateway.user("bob").await;
+
+    let started = Instant::now();
+    gateway
+        .send("someone@example.com", &[&alice.address, &bob.address])
+        .await
+        .unwrap();
+    assert!(started.elapsed() < Duration::from_secs(60));
+    assert_eq!(gateway.inbox_size(&alice).await, 1);
+    assert_eq!(gateway.inbox_size(&bob).await, 1);
+
+    let error = gateway
+        .send("someone@example.com", &["nobody@localhost"])
+        .await;
+    assert!(error.unwrap_err().is_permanent());
+}
+
+#[tokio::test]
+async fn strangers_are_held_up_for_every_recipient_whoever_they_claim_to_be() {
+    let gateway = TestGateway::start_with(|config| {
+        config.gateway.allowlist = vec!["192.0.2.0/24".parse().unwrap()];
+        config.gateway.tarpit_seconds = 1;
+    })
+    .await;
+    let alice = gateway.user("alice").await;
+    let bob = gateway.user("bob").await;
+
+    let started = Instant::now();
+    gateway
+        .send("postmaster@localhost", &[&alice.address, &bob.address])
+        .await
+        .unwrap();
+    assert!(started.elapsed() >= Duration::from_secs(2));
+    assert_eq!(gateway.inbox_size(&alice).await, 1);
+    assert_eq!(gateway.inbox_size(&bob).await, 1);
+}
+
+#[tokio::test]
+async fn strangers_are_refused_when_only_the_allowlist_may_send() {
+    let gateway = TestGateway::start_with(|config| {
+        config.gateway.allowlist = vec!["192.0.2.0/24".parse().unwrap()];
+        config.gateway.allowlist_only = true;
+    })
+    .await;
+    let alice = gateway.user("alice").await;
+
+    let (_, greeting) = gateway.connect().await;
+    assert!(greeting.starts_with("554 "), "{}", greeting);
+    let error = gateway.send("someone@example.com", &[&alice.address]).await;
+    assert!(error.unwrap_err().is_permanent());
+    assert_eq!(gateway.inbox_size(&alice).await, 0);
+}
+
+#[test]
+fn ipv6_clients_are_counted_by_network() {
+    let key = |ip: &str| session_key(ip.parse::<IpAddr>().unwrap());
+    assert_eq!(key("2001:db8::1"), key("2001:db8::ffff:1:2"));
+    assert_eq!(key("2001:db8::1"), "2001:db8::".parse::<IpAddr>().unwrap());
+    assert_ne!(key("2001:db8::1"), key("2001:db8:0:1::1"));
+    assert_eq!(
+        key("::ffff:192.0.2.1"),
+        "192.0.2.1".parse::<IpAddr>().unwrap()
+    );
+    assert_ne!(key("192.0.2.1"), key("192.0.2.2"));
+}
+
+#[tokio::test]
+async fn clients_get_a_limited_number_of_sessions() {
+    let gateway = TestGateway::start_with(|config| config.gateway.max_sessions_per_ip = 2).await;
+
+    let (first, greeting) = gateway.connect().await;
+    assert!(greeting.starts_with("220 "), "{}", greeting);
+    let (_second, greeting) = gateway.connect().await;
+    assert!(greeting.starts_with("220 "), "{}", greeting);
+    let (_, greeting) = gateway.connect().await;
+    assert!(greeting.starts_with("421 "), "{}", greeting);
+
+    drop(first);
+    // The session ends once the gateway sees the connection close
+    for _ in 0..50 {
+        if gateway.open_sessions() < 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let (_, greeting) = gateway.connect().await;
+    assert!(greeting.starts_with("220 "), "{}", greeting);
+}
+
+#[tokio::test]
+async fn mail_is_deferred_while_the_system_keys_are_missing() {
+    let gateway = TestGateway::start_with(|config| {
+        config.gateway.allowlist = vec!["127.0.0.0/8".parse().unwrap()];
+    })
+    .await;
+    let alice = gateway.user("alice").await;
+    gateway
+        .database
+        .execute("DELETE FROM sm.system_sign_keys")
+        .await;
+
+    let error = gateway.send("someone@example.com", &[&alice.address]).await;
+    assert!(error.unwrap_err().is_transient());
+    assert_eq!(gateway.inbox_size(&alice).await, 0);
+    // The gateway is still there to take the retry
+    gateway.connect().await;
+}
@@ -0,0 +1,232 @@
+//! Tests that hand a gateway mail with an SMTP client and have it relay bridged mail to an SMTP
+//! sink, each gateway with a database of its own on the Postgres server `DATABASE_URL` points
+//! at.
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use domain::{crypto::CryptographyService, message::MessageRepository, user::UserRepository};
+use infrastructure::{
+    config::Config, services::cryptography::OpensslCryptographyService, testing::TestDatabase,
+};
+use lettre::{
+    address::Envelope, transport::smtp::Error, AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+use crate::{smtp, state::GatewayState};
+
+mod bridge;
+mod inbound;
+
+pub struct TestGateway {
+    pub address: SocketAddr,
+    pub state: Arc<GatewayState>,
+    listener: JoinHandle<()>,
+    pub database: TestDatabase,
+}
+
+impl TestGateway {
+    /// Starts a gateway for the domain `localhost` with settings of its own.
+    pub async fn start_with(configure: impl FnOnce(&mut Config)) -> Self {
+        dotenvy::dotenv().ok();
+        let database = TestDatabase::create().await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let mut config = Config::default();
+        config.database.url = database.url.clone();
+        config.gateway.listen_addr = address;
+        configure(&mut config);
+        let state = Arc::new(GatewayState::new(&config).await);
+        let listener = tokio::spawn(smtp::listen(listener, state.clone()));
+
+        Self {
+            address,
+            state,
+            listener,
+            database,
+        }
+    }
+
+    /// Registers a user with fresh keys.
+    pub async fn user(&self, username: &str) -> TestUser {
+        let (public_verify_key, verify_key) =
+            OpensslCryptographyService.generate_key_pair().unwrap();
+        let (public_encryption_key, _) = OpensslCryptographyService.generate_key_pair().unwrap();
+        let user = self
+            .state
+            .user_repository
+            .create(
+                username.to_string(),
+                public_encryption_key,
+                public_verify_key,
+            )
+            .await
+            .unwrap();
+        TestUser {
+            id: user.id,
+            address: format!("{}@localhost", username),
+            verify_key,
+        }
+    }
+
+    pub async fn inbox_size(&self, user: &TestUser) -> i64 {
+        self.state
+            .message_repository
+            .count_messages(user.id, false)
+            .await
+            .unwrap()
+    }
+
+    /// Sends a message over SMTP the way an MTA would.
+    pub async fn send(&self, from: &str, to: &[&str]) -> Result<(), Error> {
+        let envelope = Envelope::new(
+            Some(from.parse().unwrap()),
+            to.iter().map(|address| address.parse().unwrap()).collect(),
+        )
+        .unwrap();
+        let message = format!(
+            "From: {}\r\nTo: {}\r\nSubject: Hello\r\n\r\nHello there.\r\n",
+            from,
+            to.join(", ")
+        );
+        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(self.address.ip().to_string())
+            .port(self.address.port())
+            .build()
+            .send_raw(&envelope, message.as_bytes())
+            .await
+            .map(|_| ())
+    }
+
+    pub fn open_sessions(&self) -> usize {
+        self.state.sessions.lock().unwrap().values().sum()
+    }
+
+    /// Opens a connection and returns it with the first line it is greeted with.
+    pub async fn connect(&self) -> (BufReader<TcpStream>, String) {
+        let mut stream = BufReader::new(TcpStream::connect(self.address).await.unwrap());
+        let mut greeting = String::new();
+        stream.read_line(&mut greeting).await.unwrap();
+        (stream, greeting)
+    }
+}
+
+impl Drop for TestGateway {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+pub struct TestUser {
+    pub id: domain::uuid::Uuid,
+    /// `username@localhost`, where the gateway takes mail for them.
+    pub address: String,
+    pub verify_key: String,
+}
+
+/// Mail as an SMTP server received it.
+#[derive(Debug, Clone)]
+pub struct ReceivedMail {
+    pub mail_from: String,
+    pub recipients: Vec<String>,
+    pub data: String,
+}
+
+/// An SMTP server that accepts any mail and keeps it, standing in for the one bridged mail is
+/// relayed through.
+pub struct SmtpSink {
+    pub address: SocketAddr,
+    received: Arc<Mutex<Vec<ReceivedMail>>>,
+    listener: JoinHandle<()>,
+}
+
+impl SmtpSink {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let listener = tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(receive(stream, sink.clone()));
+            }
+        });
+        Self {
+            address,
+            received,
+            listener,
+        }
+    }
+
+    pub fn received(&self) -> Vec<ReceivedMail> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+impl Drop for SmtpSink {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+/// Runs one session of the sink, taking every command at its word.
+async fn receive(stream: TcpStream, received: Arc<Mutex<Vec<ReceivedMail>>>) {
+    let mut stream = BufReader::new(stream);
+    let mut mail_from = String::new();
+    let mut recipients = Vec::new();
+    stream.write_all(b"220 sink\r\n").await.unwrap();
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await.unwrap() == 0 {
+            return;
+        }
+        let path = || {
+            let start = line.find('<').map_or(0, |i| i + 1);
+            let end = line.rfind('>').unwrap_or(line.len());
+            line[start..end].to_string()
+        };
+        let verb = line.get(..4).unwrap_or_default().to_ascii_uppercase();
+        let reply: &[u8] = match verb.as_str() {
+            "MAIL" => {
+                mail_from = path();
+                b"250 OK\r\n"
+            }
+            "RCPT" => {
+                recipients.push(path());
+                b"250 OK\r\n"
+            }
+            "DATA" => {
+                stream.write_all(b"354 Go ahead\r\n").await.unwrap();
+                let mut data = String::new();
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).await.unwrap();
+                    if line == ".\r\n" {
+                        break;
+                    }
+                    data.push_str(&line);
+                }
+                received.lock().unwrap().push(ReceivedMail {
+                    mail_from: std::mem::take(&mut mail_from),
+                    recipients: std::mem::take(&mut recipients),
+                    data,
+                });
+                b"250 Queued\r\n"
+            }
+            "QUIT" => {
+                let _ = stream.write_all(b"221 Bye\r\n").await;
+                return;
+            }
+            _ => b"250 sink\r\n",
+        };
+        stream.write_all(reply).await.unwrap();
+    }
+}
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TRIGGER messages_notify_new_message ON sm.messages;
+
+DROP FUNCTION sm.notify_new_message();
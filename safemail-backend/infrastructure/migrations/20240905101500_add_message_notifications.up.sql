@@ -0,0 +1,14 @@
+-- Add up migration script here
+CREATE FUNCTION sm.notify_new_message() RETURNS TRIGGER AS $$
+BEGIN
+    PERFORM pg_notify(
+        'sm_new_message',
+        json_build_object('id', NEW.id, 'recipient_id', NEW.recipient_id)::text
+    );
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER messages_notify_new_message
+AFTER INSERT ON sm.messages
+FOR EACH ROW EXECUTE FUNCTION sm.notify_new_message();
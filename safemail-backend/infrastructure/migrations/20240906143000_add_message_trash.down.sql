@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX sm.idx_messages_deleted_at;
+
+ALTER TABLE sm.messages DROP COLUMN deleted_at;
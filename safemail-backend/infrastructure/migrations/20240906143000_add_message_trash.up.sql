@@ -0,0 +1,6 @@
+-- Add up migration script here
+ALTER TABLE sm.messages ADD COLUMN deleted_at TIMESTAMPTZ NULL;
+
+CREATE INDEX idx_messages_deleted_at ON sm.messages (deleted_at)
+WHERE
+    deleted_at IS NOT NULL;
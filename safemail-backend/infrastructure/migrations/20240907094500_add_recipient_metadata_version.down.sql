@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE sm.messages DROP COLUMN recipient_metadata_version;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE sm.messages
+ADD COLUMN recipient_metadata_version BIGINT NOT NULL DEFAULT 0;
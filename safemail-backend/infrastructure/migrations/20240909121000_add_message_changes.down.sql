@@ -0,0 +1,10 @@
+-- Add down migration script here
+DROP TRIGGER messages_record_change ON sm.messages;
+
+DROP FUNCTION sm.record_message_change();
+
+DROP FUNCTION sm.append_message_change(UUID, BIGINT, VARCHAR);
+
+DROP TABLE sm.message_changes;
+
+DROP TABLE sm.message_change_counters;
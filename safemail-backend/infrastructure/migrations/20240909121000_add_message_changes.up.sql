@@ -0,0 +1,65 @@
+-- Add up migration script here
+CREATE TABLE sm.message_change_counters (
+    recipient_id UUID PRIMARY KEY REFERENCES sm.users (id),
+    last_seq BIGINT NOT NULL DEFAULT 0,
+    pruned_through_seq BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE TABLE sm.message_changes (
+    recipient_id UUID NOT NULL REFERENCES sm.users (id),
+    seq BIGINT NOT NULL,
+    message_id BIGINT NOT NULL,
+    kind VARCHAR(32) NOT NULL,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (recipient_id, seq)
+);
+
+CREATE INDEX idx_message_changes_changed_at ON sm.message_changes (changed_at);
+
+-- Sequence numbers are handed out from a per-recipient counter row. The row stays locked until
+-- the changing transaction commits, so a recipient's changes become visible in sequence order.
+CREATE FUNCTION sm.append_message_change(
+    change_recipient_id UUID, change_message_id BIGINT, change_kind VARCHAR
+) RETURNS VOID AS $$
+DECLARE
+    next_seq BIGINT;
+BEGIN
+    INSERT INTO sm.message_change_counters (recipient_id, last_seq)
+    VALUES (change_recipient_id, 1)
+    ON CONFLICT (recipient_id) DO UPDATE
+    SET last_seq = sm.message_change_counters.last_seq + 1
+    RETURNING last_seq INTO next_seq;
+
+    INSERT INTO sm.message_changes (recipient_id, seq, message_id, kind)
+    VALUES (change_recipient_id, next_seq, change_message_id, change_kind);
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE FUNCTION sm.record_message_change() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        PERFORM sm.append_message_change(NEW.recipient_id, NEW.id, 'created');
+    ELSIF TG_OP = 'DELETE' THEN
+        PERFORM sm.append_message_change(OLD.recipient_id, OLD.id, 'deleted');
+    ELSE
+        IF OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL THEN
+            PERFORM sm.append_message_change(NEW.recipient_id, NEW.id, 'trashed');
+        ELSIF OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL THEN
+            PERFORM sm.append_message_change(NEW.recipient_id, NEW.id, 'restored');
+        END IF;
+        IF OLD.recipient_metadata_version <> NEW.recipient_metadata_version THEN
+            PERFORM sm.append_message_change(NEW.recipient_id, NEW.id, 'metadata_updated');
+        END IF;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER messages_record_change
+AFTER INSERT OR UPDATE OR DELETE ON sm.messages
+FOR EACH ROW EXECUTE FUNCTION sm.record_message_change();
+
+-- Start every existing mailbox's feed with the messages already in it
+SELECT sm.append_message_change(recipient_id, id, 'created')
+FROM sm.messages
+ORDER BY id;
@@ -0,0 +1,6 @@
+-- Add up migration script here
+ALTER TABLE sm.messages ADD COLUMN expires_at TIMESTAMPTZ NULL;
+
+CREATE INDEX idx_messages_expires_at ON sm.messages (expires_at)
+WHERE
+    expires_at IS NOT NULL;
@@ -0,0 +1,10 @@
+-- Add down migration script here
+DROP TRIGGER message_blobs_count_references ON sm.message_blobs;
+
+DROP FUNCTION sm.count_blob_references();
+
+DROP TABLE sm.message_blobs;
+
+DROP TABLE sm.blob_chunks;
+
+DROP TABLE sm.blobs;
@@ -0,0 +1,49 @@
+-- Add up migration script here
+CREATE TABLE sm.blobs (
+    -- Hex encoded SHA-256 of the complete, client-encrypted data
+    hash CHAR(64) PRIMARY KEY,
+    size BIGINT NOT NULL,
+    chunk_size INTEGER NOT NULL,
+    chunk_count INTEGER NOT NULL,
+    uploader_id UUID NOT NULL REFERENCES sm.users (id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ NULL,
+    ref_count BIGINT NOT NULL DEFAULT 0,
+    unreferenced_since TIMESTAMPTZ NULL DEFAULT NOW(),
+    CONSTRAINT valid_hex_hash CHECK (hash ~ '^[0-9a-f]{64}$')
+);
+
+CREATE TABLE sm.blob_chunks (
+    blob_hash CHAR(64) NOT NULL REFERENCES sm.blobs (hash) ON DELETE CASCADE,
+    chunk_index INTEGER NOT NULL,
+    data BYTEA NOT NULL,
+    PRIMARY KEY (blob_hash, chunk_index)
+);
+
+CREATE TABLE sm.message_blobs (
+    message_id BIGINT NOT NULL REFERENCES sm.messages (id) ON DELETE CASCADE,
+    blob_hash CHAR(64) NOT NULL REFERENCES sm.blobs (hash),
+    PRIMARY KEY (message_id, blob_hash)
+);
+
+CREATE INDEX idx_message_blobs_blob_hash ON sm.message_blobs (blob_hash);
+
+CREATE FUNCTION sm.count_blob_references() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        UPDATE sm.blobs
+        SET ref_count = ref_count + 1, unreferenced_since = NULL
+        WHERE hash = NEW.blob_hash;
+    ELSE
+        UPDATE sm.blobs
+        SET ref_count = ref_count - 1,
+            unreferenced_since = CASE WHEN ref_count = 1 THEN NOW() ELSE NULL END
+        WHERE hash = OLD.blob_hash;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER message_blobs_count_references
+AFTER INSERT OR DELETE ON sm.message_blobs
+FOR EACH ROW EXECUTE FUNCTION sm.count_blob_references();
@@ -0,0 +1,24 @@
+-- Add down migration script here
+DROP TRIGGER messages_orphan_content ON sm.messages;
+DROP TRIGGER blob_chunks_orphan_content ON sm.blob_chunks;
+DROP FUNCTION sm.orphan_content();
+
+-- Only content kept by the default backend can be moved back inline
+ALTER TABLE sm.blob_chunks ADD COLUMN data BYTEA NULL;
+UPDATE sm.blob_chunks
+SET data = content_objects.data
+FROM sm.content_objects
+WHERE content_objects.key = blob_chunks.content_key;
+DELETE FROM sm.blob_chunks WHERE data IS NULL;
+ALTER TABLE sm.blob_chunks ALTER COLUMN data SET NOT NULL;
+ALTER TABLE sm.blob_chunks DROP COLUMN size;
+ALTER TABLE sm.blob_chunks DROP COLUMN content_key;
+
+UPDATE sm.messages
+SET content = convert_from(content_objects.data, 'UTF8')
+FROM sm.content_objects
+WHERE content_objects.key = messages.content_key;
+ALTER TABLE sm.messages DROP COLUMN content_key;
+
+DROP TABLE sm.orphaned_content;
+DROP TABLE sm.content_objects;
@@ -0,0 +1,50 @@
+-- Add up migration script here
+-- Objects of the default content store backend
+CREATE TABLE sm.content_objects (
+    key TEXT PRIMARY KEY,
+    data BYTEA NOT NULL
+);
+
+-- Keys of stored objects no longer referenced by any row, awaiting deletion from the store
+CREATE TABLE sm.orphaned_content (
+    key TEXT PRIMARY KEY,
+    orphaned_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Move chunk data out of line
+ALTER TABLE sm.blob_chunks ADD COLUMN content_key TEXT NULL;
+ALTER TABLE sm.blob_chunks ADD COLUMN size INTEGER NULL;
+
+INSERT INTO sm.content_objects (key, data)
+SELECT 'blobs/' || blob_hash || '/' || chunk_index, data
+FROM sm.blob_chunks;
+
+UPDATE sm.blob_chunks
+SET content_key = 'blobs/' || blob_hash || '/' || chunk_index, size = length(data);
+
+ALTER TABLE sm.blob_chunks ALTER COLUMN content_key SET NOT NULL;
+ALTER TABLE sm.blob_chunks ALTER COLUMN size SET NOT NULL;
+ALTER TABLE sm.blob_chunks DROP COLUMN data;
+
+-- Message bodies above the out-of-line threshold are stored under this key, leaving content empty
+ALTER TABLE sm.messages ADD COLUMN content_key TEXT NULL;
+
+CREATE FUNCTION sm.orphan_content() RETURNS TRIGGER AS $$
+BEGIN
+    IF OLD.content_key IS NOT NULL
+        AND (TG_OP = 'DELETE' OR NEW.content_key IS DISTINCT FROM OLD.content_key) THEN
+        INSERT INTO sm.orphaned_content (key)
+        VALUES (OLD.content_key)
+        ON CONFLICT (key) DO NOTHING;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER blob_chunks_orphan_content
+AFTER UPDATE OF content_key OR DELETE ON sm.blob_chunks
+FOR EACH ROW EXECUTE FUNCTION sm.orphan_content();
+
+CREATE TRIGGER messages_orphan_content
+AFTER UPDATE OF content_key OR DELETE ON sm.messages
+FOR EACH ROW EXECUTE FUNCTION sm.orphan_content();
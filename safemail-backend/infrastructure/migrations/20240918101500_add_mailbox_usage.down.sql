@@ -0,0 +1,8 @@
+-- Add down migration script here
+DROP TRIGGER messages_account_mailbox_usage ON sm.messages;
+
+DROP FUNCTION sm.account_mailbox_usage();
+
+DROP TABLE sm.mailbox_usage;
+
+ALTER TABLE sm.messages DROP COLUMN size;
@@ -0,0 +1,48 @@
+-- Add up migration script here
+-- Bytes a message takes up in its recipient's mailbox: metadata, content and attachments
+ALTER TABLE sm.messages ADD COLUMN size BIGINT NOT NULL DEFAULT 0;
+
+UPDATE sm.messages
+SET size = octet_length(metadata)
+    + octet_length(content)
+    + COALESCE((
+        SELECT length(data) FROM sm.content_objects WHERE key = messages.content_key
+    ), 0)
+    + COALESCE((
+        SELECT SUM(blobs.size)
+        FROM sm.message_blobs
+        JOIN sm.blobs ON blobs.hash = message_blobs.blob_hash
+        WHERE message_blobs.message_id = messages.id
+    ), 0);
+
+CREATE TABLE sm.mailbox_usage (
+    user_id UUID PRIMARY KEY REFERENCES sm.users (id) ON DELETE CASCADE,
+    message_count BIGINT NOT NULL DEFAULT 0,
+    stored_bytes BIGINT NOT NULL DEFAULT 0
+);
+
+INSERT INTO sm.mailbox_usage (user_id, message_count, stored_bytes)
+SELECT recipient_id, COUNT(*), SUM(size)
+FROM sm.messages
+GROUP BY recipient_id;
+
+CREATE FUNCTION sm.account_mailbox_usage() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        INSERT INTO sm.mailbox_usage (user_id, message_count, stored_bytes)
+        VALUES (NEW.recipient_id, 1, NEW.size)
+        ON CONFLICT (user_id) DO UPDATE
+        SET message_count = mailbox_usage.message_count + 1,
+            stored_bytes = mailbox_usage.stored_bytes + EXCLUDED.stored_bytes;
+    ELSE
+        UPDATE sm.mailbox_usage
+        SET message_count = message_count - 1, stored_bytes = stored_bytes - OLD.size
+        WHERE user_id = OLD.recipient_id;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER messages_account_mailbox_usage
+AFTER INSERT OR DELETE ON sm.messages
+FOR EACH ROW EXECUTE FUNCTION sm.account_mailbox_usage();
@@ -0,0 +1,17 @@
+-- Add up migration script here
+CREATE TABLE sm.idempotency_keys (
+    sender_id UUID NOT NULL REFERENCES sm.users (id) ON DELETE CASCADE,
+    key TEXT NOT NULL,
+    -- SHA-256 of the endpoint and request body the key was first used with
+    fingerprint CHAR(64) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    claimed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- The stored response, once the request has been processed
+    completed_at TIMESTAMPTZ NULL,
+    status INTEGER NULL,
+    content_type TEXT NULL,
+    body BYTEA NULL,
+    PRIMARY KEY (sender_id, key)
+);
+
+CREATE INDEX idx_idempotency_keys_created_at ON sm.idempotency_keys (created_at);
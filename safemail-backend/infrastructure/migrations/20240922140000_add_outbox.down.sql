@@ -0,0 +1,14 @@
+-- Add down migration script here
+DROP TRIGGER outbox_account_mailbox_usage ON sm.outbox;
+
+DROP FUNCTION sm.account_outbox_usage();
+
+DROP TRIGGER outbox_orphan_content ON sm.outbox;
+
+DROP TRIGGER outbox_blobs_count_references ON sm.outbox_blobs;
+
+DROP TABLE sm.outbox_blobs;
+
+DROP TABLE sm.outbox_deliveries;
+
+DROP TABLE sm.outbox;
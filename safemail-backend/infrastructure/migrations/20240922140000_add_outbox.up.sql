@@ -0,0 +1,66 @@
+-- Add up migration script here
+-- Copies of sent messages, encrypted by the sender to their own key
+CREATE TABLE sm.outbox (
+    id BIGSERIAL PRIMARY KEY,
+    sender_id UUID NOT NULL REFERENCES sm.users (id) ON DELETE CASCADE,
+    metadata TEXT NOT NULL,
+    content TEXT NOT NULL,
+    content_key TEXT NULL,
+    size BIGINT NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CONSTRAINT valid_base64_metadata CHECK (
+        metadata ~ '^[A-Za-z0-9+/]*={0,2}$'
+    ),
+    CONSTRAINT valid_base64_content CHECK (
+        content ~ '^[A-Za-z0-9+/]*={0,2}$'
+    )
+);
+
+CREATE INDEX idx_outbox_sender ON sm.outbox (sender_id, id);
+
+-- The messages an outbox entry was delivered as. Recipients may delete their copies, so
+-- message ids are kept as plain receipts rather than references.
+CREATE TABLE sm.outbox_deliveries (
+    outbox_id BIGINT NOT NULL REFERENCES sm.outbox (id) ON DELETE CASCADE,
+    recipient_id UUID NOT NULL,
+    message_id BIGINT NOT NULL,
+    PRIMARY KEY (outbox_id, recipient_id)
+);
+
+CREATE TABLE sm.outbox_blobs (
+    outbox_id BIGINT NOT NULL REFERENCES sm.outbox (id) ON DELETE CASCADE,
+    blob_hash CHAR(64) NOT NULL REFERENCES sm.blobs (hash),
+    PRIMARY KEY (outbox_id, blob_hash)
+);
+
+CREATE INDEX idx_outbox_blobs_blob_hash ON sm.outbox_blobs (blob_hash);
+
+CREATE TRIGGER outbox_blobs_count_references
+AFTER INSERT OR DELETE ON sm.outbox_blobs
+FOR EACH ROW EXECUTE FUNCTION sm.count_blob_references();
+
+CREATE TRIGGER outbox_orphan_content
+AFTER UPDATE OF content_key OR DELETE ON sm.outbox
+FOR EACH ROW EXECUTE FUNCTION sm.orphan_content();
+
+-- Sent copies count towards the sender's mailbox usage
+CREATE FUNCTION sm.account_outbox_usage() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        INSERT INTO sm.mailbox_usage (user_id, message_count, stored_bytes)
+        VALUES (NEW.sender_id, 1, NEW.size)
+        ON CONFLICT (user_id) DO UPDATE
+        SET message_count = mailbox_usage.message_count + 1,
+            stored_bytes = mailbox_usage.stored_bytes + EXCLUDED.stored_bytes;
+    ELSE
+        UPDATE sm.mailbox_usage
+        SET message_count = message_count - 1, stored_bytes = stored_bytes - OLD.size
+        WHERE user_id = OLD.sender_id;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER outbox_account_mailbox_usage
+AFTER INSERT OR DELETE ON sm.outbox
+FOR EACH ROW EXECUTE FUNCTION sm.account_outbox_usage();
@@ -0,0 +1,12 @@
+-- Add down migration script here
+DROP TRIGGER scheduled_sender_copies_orphan_content ON sm.scheduled_sender_copies;
+
+DROP TRIGGER scheduled_messages_orphan_content ON sm.scheduled_messages;
+
+DROP TRIGGER scheduled_message_blobs_count_references ON sm.scheduled_message_blobs;
+
+DROP TABLE sm.scheduled_message_blobs;
+
+DROP TABLE sm.scheduled_sender_copies;
+
+DROP TABLE sm.scheduled_messages;
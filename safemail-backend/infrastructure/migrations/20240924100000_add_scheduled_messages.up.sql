@@ -0,0 +1,61 @@
+-- Add up migration script here
+-- Messages submitted for delivery at a later time. They stay out of the recipient's mailbox
+-- until the dispatcher moves them there.
+CREATE TABLE sm.scheduled_messages (
+    id BIGSERIAL PRIMARY KEY,
+    sender_id UUID NOT NULL REFERENCES sm.users (id) ON DELETE CASCADE,
+    recipient_id UUID NOT NULL REFERENCES sm.users (id) ON DELETE CASCADE,
+    metadata TEXT NOT NULL,
+    content TEXT NOT NULL,
+    content_key TEXT NULL,
+    expires_at TIMESTAMPTZ NULL,
+    -- Only kept when the stamp is to be re-checked on delivery
+    stamp TEXT NULL,
+    deliver_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    failure TEXT NULL,
+    CONSTRAINT valid_base64_metadata CHECK (
+        metadata ~ '^[A-Za-z0-9+/]*={0,2}$'
+    ),
+    CONSTRAINT valid_base64_content CHECK (
+        content ~ '^[A-Za-z0-9+/]*={0,2}$'
+    )
+);
+
+CREATE INDEX idx_scheduled_messages_sender ON sm.scheduled_messages (sender_id, id);
+CREATE INDEX idx_scheduled_messages_due ON sm.scheduled_messages (deliver_at)
+WHERE failure IS NULL;
+
+-- The sender's copy, moved to the outbox on delivery
+CREATE TABLE sm.scheduled_sender_copies (
+    scheduled_id BIGINT PRIMARY KEY REFERENCES sm.scheduled_messages (id) ON DELETE CASCADE,
+    metadata TEXT NOT NULL,
+    content TEXT NOT NULL,
+    content_key TEXT NULL,
+    CONSTRAINT valid_base64_metadata CHECK (
+        metadata ~ '^[A-Za-z0-9+/]*={0,2}$'
+    ),
+    CONSTRAINT valid_base64_content CHECK (
+        content ~ '^[A-Za-z0-9+/]*={0,2}$'
+    )
+);
+
+CREATE TABLE sm.scheduled_message_blobs (
+    scheduled_id BIGINT NOT NULL REFERENCES sm.scheduled_messages (id) ON DELETE CASCADE,
+    blob_hash CHAR(64) NOT NULL REFERENCES sm.blobs (hash),
+    PRIMARY KEY (scheduled_id, blob_hash)
+);
+
+CREATE INDEX idx_scheduled_message_blobs_blob_hash ON sm.scheduled_message_blobs (blob_hash);
+
+CREATE TRIGGER scheduled_message_blobs_count_references
+AFTER INSERT OR DELETE ON sm.scheduled_message_blobs
+FOR EACH ROW EXECUTE FUNCTION sm.count_blob_references();
+
+CREATE TRIGGER scheduled_messages_orphan_content
+AFTER UPDATE OF content_key OR DELETE ON sm.scheduled_messages
+FOR EACH ROW EXECUTE FUNCTION sm.orphan_content();
+
+CREATE TRIGGER scheduled_sender_copies_orphan_content
+AFTER UPDATE OF content_key OR DELETE ON sm.scheduled_sender_copies
+FOR EACH ROW EXECUTE FUNCTION sm.orphan_content();
@@ -0,0 +1,8 @@
+-- Add up migration script here
+-- Senders each user refuses mail from
+CREATE TABLE sm.blocked_senders (
+    user_id UUID NOT NULL REFERENCES sm.users (id) ON DELETE CASCADE,
+    sender_id UUID NOT NULL REFERENCES sm.users (id) ON DELETE CASCADE,
+    blocked_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, sender_id)
+);
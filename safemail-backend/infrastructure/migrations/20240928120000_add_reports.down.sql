@@ -0,0 +1,16 @@
+-- Add down migration script here
+DROP TABLE sm.admins;
+
+DROP TABLE sm.review_flags;
+
+DROP TABLE sm.reports;
+
+DROP TABLE sm.periodic_stamp_revocations;
+
+DROP INDEX sm.idx_onetime_stamps_recipient_sender;
+
+ALTER TABLE sm.onetime_stamps DROP COLUMN sender_id;
+
+ALTER TABLE sm.scheduled_messages DROP COLUMN sender_seal;
+
+ALTER TABLE sm.messages DROP COLUMN sender_seal;
@@ -0,0 +1,42 @@
+-- Add up migration script here
+-- The sender's id encrypted to the system key, only opened when the message is reported.
+-- Messages sent before this was introduced have none.
+ALTER TABLE sm.messages ADD COLUMN sender_seal TEXT NULL;
+ALTER TABLE sm.scheduled_messages ADD COLUMN sender_seal TEXT NULL;
+
+-- Lets a recipient's outstanding stamps to one sender be revoked
+ALTER TABLE sm.onetime_stamps ADD COLUMN sender_id UUID NULL;
+
+CREATE INDEX idx_onetime_stamps_recipient_sender ON sm.onetime_stamps (recipient_id, sender_id)
+WHERE NOT used_or_revoked;
+
+-- Periodic stamps aren't tracked, so those valid from before the revocation are refused
+CREATE TABLE sm.periodic_stamp_revocations (
+    recipient_id UUID NOT NULL REFERENCES sm.users (id) ON DELETE CASCADE,
+    sender_id UUID NOT NULL REFERENCES sm.users (id) ON DELETE CASCADE,
+    revoked_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (recipient_id, sender_id)
+);
+
+-- Reported messages are kept as plain ids, recipients may still delete them
+CREATE TABLE sm.reports (
+    id BIGSERIAL PRIMARY KEY,
+    reporter_id UUID NOT NULL REFERENCES sm.users (id) ON DELETE CASCADE,
+    message_id BIGINT NOT NULL,
+    sender_id UUID NOT NULL REFERENCES sm.users (id) ON DELETE CASCADE,
+    reason TEXT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (reporter_id, message_id)
+);
+
+CREATE INDEX idx_reports_sender ON sm.reports (sender_id);
+
+-- Senders awaiting admin review
+CREATE TABLE sm.review_flags (
+    user_id UUID PRIMARY KEY REFERENCES sm.users (id) ON DELETE CASCADE,
+    flagged_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE sm.admins (
+    user_id UUID PRIMARY KEY REFERENCES sm.users (id) ON DELETE CASCADE
+);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE sm.federation_peers;
+DROP TABLE sm.remote_users;
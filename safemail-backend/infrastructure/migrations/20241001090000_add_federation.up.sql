@@ -0,0 +1,17 @@
+-- Add up migration script here
+-- Users of other servers, each represented by a local user so that stamps, block lists and
+-- reports work for them as they do for local users
+CREATE TABLE sm.remote_users (
+    user_id UUID PRIMARY KEY REFERENCES sm.users (id) ON DELETE CASCADE,
+    host TEXT NOT NULL,
+    remote_id UUID NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (host, remote_id)
+);
+
+-- Identity keys of other servers, as published at their well-known endpoint
+CREATE TABLE sm.federation_peers (
+    host TEXT PRIMARY KEY,
+    public_key TEXT NOT NULL,
+    fetched_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
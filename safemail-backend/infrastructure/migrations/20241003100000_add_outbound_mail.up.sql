@@ -0,0 +1,22 @@
+-- Add up migration script here
+-- Mail users handed to the SMTP bridge, encrypted to the system key, until it is relayed
+CREATE TABLE sm.outbound_mail (
+    id BIGSERIAL PRIMARY KEY,
+    sender_id UUID NOT NULL REFERENCES sm.users (id) ON DELETE CASCADE,
+    recipients TEXT[] NOT NULL,
+    content TEXT NOT NULL,
+    -- The sender's signature over the recipients and content, checked again before relaying
+    signature TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    attempts INT NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    sent_at TIMESTAMPTZ NULL,
+    failure TEXT NULL,
+    CONSTRAINT valid_base64_content CHECK (
+        content ~ '^[A-Za-z0-9+/]*={0,2}$'
+    )
+);
+
+CREATE INDEX idx_outbound_mail_sender ON sm.outbound_mail (sender_id, created_at);
+CREATE INDEX idx_outbound_mail_due ON sm.outbound_mail (next_attempt_at)
+WHERE sent_at IS NULL AND failure IS NULL;
@@ -0,0 +1,11 @@
+-- Add up migration script here
+-- Requests counted per rate limit key in fixed windows, shared by every instance. Unlogged, as
+-- losing the counts in a crash only resets them
+CREATE UNLOGGED TABLE sm.rate_limit_windows (
+    key TEXT NOT NULL,
+    window_end TIMESTAMPTZ NOT NULL,
+    hits BIGINT NOT NULL DEFAULT 1,
+    PRIMARY KEY (key, window_end)
+);
+
+CREATE INDEX idx_rate_limit_windows_end ON sm.rate_limit_windows (window_end);
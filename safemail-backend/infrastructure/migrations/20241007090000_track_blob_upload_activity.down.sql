@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE sm.blobs DROP COLUMN active_at;
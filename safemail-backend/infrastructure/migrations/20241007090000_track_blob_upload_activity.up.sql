@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- When a pending upload last received a chunk, so that an idle one can be taken over
+ALTER TABLE sm.blobs ADD COLUMN active_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
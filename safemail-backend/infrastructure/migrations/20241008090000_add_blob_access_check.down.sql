@@ -0,0 +1,2 @@
+-- Add down migration script here
+DROP FUNCTION sm.can_access_blob(TEXT, UUID);
@@ -0,0 +1,26 @@
+-- Add up migration script here
+-- Whether a user may read a blob: they uploaded it, received a message it is attached to that
+-- hasn't expired, or sent or scheduled a message carrying it
+CREATE FUNCTION sm.can_access_blob(TEXT, UUID) RETURNS BOOLEAN AS $$
+    SELECT EXISTS (
+        SELECT 1 FROM sm.blobs
+        WHERE hash = $1 AND uploader_id = $2
+    ) OR EXISTS (
+        SELECT 1 FROM sm.message_blobs
+        JOIN sm.messages ON messages.id = message_blobs.message_id
+        WHERE message_blobs.blob_hash = $1
+        AND messages.recipient_id = $2
+        AND (messages.expires_at IS NULL OR messages.expires_at > NOW())
+    ) OR EXISTS (
+        SELECT 1 FROM sm.outbox_blobs
+        JOIN sm.outbox ON outbox.id = outbox_blobs.outbox_id
+        WHERE outbox_blobs.blob_hash = $1
+        AND outbox.sender_id = $2
+    ) OR EXISTS (
+        SELECT 1 FROM sm.scheduled_message_blobs
+        JOIN sm.scheduled_messages
+            ON scheduled_messages.id = scheduled_message_blobs.scheduled_id
+        WHERE scheduled_message_blobs.blob_hash = $1
+        AND scheduled_messages.sender_id = $2
+    );
+$$ LANGUAGE sql STABLE;
@@ -0,0 +1,11 @@
+-- Add up migration script here
+-- Requests received from other servers, remembered until their date no longer passes the
+-- clock skew check so that none is accepted twice
+CREATE TABLE sm.federation_requests (
+    origin TEXT NOT NULL,
+    request_id UUID NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (origin, request_id)
+);
+
+CREATE INDEX idx_federation_requests_expires_at ON sm.federation_requests (expires_at);
@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE sm.onetime_stamp_requests DROP COLUMN sender_id;
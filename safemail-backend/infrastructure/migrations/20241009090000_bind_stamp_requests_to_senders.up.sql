@@ -0,0 +1,7 @@
+-- Add up migration script here
+-- A stamp request can only be solved by the sender it was made for. Requests are only valid
+-- for minutes, so those made before are dropped rather than given a sender.
+DELETE FROM sm.onetime_stamp_requests;
+
+ALTER TABLE sm.onetime_stamp_requests
+ADD COLUMN sender_id UUID NOT NULL REFERENCES sm.users (id) ON DELETE CASCADE;
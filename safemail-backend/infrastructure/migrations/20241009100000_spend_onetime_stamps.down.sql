@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE sm.scheduled_messages DROP COLUMN onetime_stamp_id;
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- One-time stamps are used up when the message they came with is delivered, which for a
+-- scheduled message is only once it is due
+ALTER TABLE sm.scheduled_messages ADD COLUMN onetime_stamp_id UUID NULL;
@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE sm.scheduled_messages
+DROP COLUMN next_attempt_at,
+DROP COLUMN attempts;
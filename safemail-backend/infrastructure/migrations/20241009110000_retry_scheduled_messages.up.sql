@@ -0,0 +1,6 @@
+-- Add up migration script here
+-- Deliveries that fail for reasons that may pass, such as a full mailbox, are tried again
+-- with growing delays rather than given up on
+ALTER TABLE sm.scheduled_messages
+ADD COLUMN attempts INT NOT NULL DEFAULT 0,
+ADD COLUMN next_attempt_at TIMESTAMPTZ NULL;
@@ -0,0 +1,646 @@
+use std::{fmt, net::SocketAddr, path::PathBuf};
+
+use chrono::{DateTime, TimeZone, Utc};
+use domain::{
+    idempotency::IdempotencyPolicy,
+    message::MailboxQuota,
+    outbound_mail::BridgePolicy,
+    rate_limit::{RateLimit, RateLimitPolicy, RouteBudget},
+    report::ReportPolicy,
+    session::SessionPolicy,
+    stamp::StampPolicy,
+};
+use ipnet::IpNet;
+use reqwest::header::HeaderValue;
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use crate::services::{
+    content_store::{ContentStoreBackend, ContentStoreConfig, S3Config},
+    federation::FederationScheme,
+    rate_limiter::RateLimiterBackend,
+    smtp::SmtpRelayConfig,
+};
+
+/// The file read when `SM_CONFIG` names none, if it exists.
+const DEFAULT_CONFIG_FILE: &str = "safemail.toml";
+
+/// Environment variables overriding a setting, with the path of the setting. Lists are written
+/// comma separated.
+const ENV_OVERRIDES: &[(&str, &str)] = &[
+    ("DATABASE_URL", "database.url"),
+    ("SM_LISTEN_ADDR", "server.listen_addr"),
+    ("SM_HOST", "server.host"),
+    ("SM_CORS_ORIGINS", "server.cors_origins"),
+    ("SM_TRUST_FORWARDED_FOR", "server.trust_forwarded_for"),
+    ("SM_UNVERSIONED_SUNSET", "server.unversioned_sunset"),
+    ("SM_CONTENT_STORE", "content_store.backend"),
+    ("SM_CONTENT_STORE_PATH", "content_store.path"),
+    (
+        "SM_CONTENT_OUT_OF_LINE_THRESHOLD",
+        "content_store.out_of_line_threshold",
+    ),
+    ("SM_S3_ENDPOINT", "content_store.s3.endpoint"),
+    ("SM_S3_BUCKET", "content_store.s3.bucket"),
+    ("SM_S3_REGION", "content_store.s3.region"),
+    ("SM_S3_ACCESS_KEY_ID", "content_store.s3.access_key_id"),
+    (
+        "SM_S3_SECRET_ACCESS_KEY",
+        "content_store.s3.secret_access_key",
+    ),
+    ("SM_FEDERATION_SCHEME", "federation.scheme"),
+    (
+        "SM_FEDERATION_ALLOW_PRIVATE_ADDRESSES",
+        "federation.allow_private_addresses",
+    ),
+    ("SM_SESSION_LENGTH_MINUTES", "session.length_minutes"),
+    ("SM_STAMP_BASE_DIFFICULTY", "stamps.base_difficulty"),
+    ("SM_STAMP_MAX_DIFFICULTY", "stamps.max_difficulty"),
+    (
+        "SM_STAMP_REQUEST_VALIDITY_MINUTES",
+        "stamps.request_validity_minutes",
+    ),
+    ("SM_STAMP_VALIDITY_MINUTES", "stamps.validity_minutes"),
+    ("SM_MAILBOX_MAX_MESSAGES", "mailbox.max_messages"),
+    ("SM_MAILBOX_MAX_BYTES", "mailbox.max_bytes"),
+    ("SM_REPORT_REVOKE_STAMPS", "reports.revoke_stamps"),
+    ("SM_REPORT_DIFFICULTY_FACTOR", "reports.difficulty_factor"),
+    ("SM_REPORT_REVIEW_THRESHOLD", "reports.review_threshold"),
+    ("SM_BRIDGE_MAX_RECIPIENTS", "bridge.max_recipients"),
+    (
+        "SM_BRIDGE_MAX_RECIPIENTS_PER_HOUR",
+        "bridge.max_recipients_per_hour",
+    ),
+    ("SM_BRIDGE_SMTP_HOST", "smtp_relay.host"),
+    ("SM_BRIDGE_SMTP_PORT", "smtp_relay.port"),
+    ("SM_BRIDGE_SMTP_STARTTLS", "smtp_relay.starttls"),
+    ("SM_BRIDGE_SMTP_USERNAME", "smtp_relay.username"),
+    ("SM_BRIDGE_SMTP_PASSWORD", "smtp_relay.password"),
+    ("SM_RATE_LIMITER", "rate_limits.backend"),
+    ("SM_RATE_LIMIT_LOGIN_PER_IP", "rate_limits.login.per_ip"),
+    ("SM_RATE_LIMIT_LOGIN_PER_USER", "rate_limits.login.per_user"),
+    (
+        "SM_RATE_LIMIT_LOGIN_PER_RECIPIENT",
+        "rate_limits.login.per_recipient",
+    ),
+    (
+        "SM_RATE_LIMIT_REGISTRATION_PER_IP",
+        "rate_limits.registration.per_ip",
+    ),
+    (
+        "SM_RATE_LIMIT_REGISTRATION_PER_USER",
+        "rate_limits.registration.per_user",
+    ),
+    (
+        "SM_RATE_LIMIT_REGISTRATION_PER_RECIPIENT",
+        "rate_limits.registration.per_recipient",
+    ),
+    (
+        "SM_RATE_LIMIT_STAMP_REQUEST_PER_IP",
+        "rate_limits.stamp_request.per_ip",
+    ),
+    (
+        "SM_RATE_LIMIT_STAMP_REQUEST_PER_USER",
+        "rate_limits.stamp_request.per_user",
+    ),
+    (
+        "SM_RATE_LIMIT_STAMP_REQUEST_PER_RECIPIENT",
+        "rate_limits.stamp_request.per_recipient",
+    ),
+    (
+        "SM_RATE_LIMIT_FEDERATION_PER_IP",
+        "rate_limits.federation.per_ip",
+    ),
+    (
+        "SM_RATE_LIMIT_FEDERATION_PER_USER",
+        "rate_limits.federation.per_user",
+    ),
+    (
+        "SM_RATE_LIMIT_FEDERATION_PER_RECIPIENT",
+        "rate_limits.federation.per_recipient",
+    ),
+    (
+        "SM_IDEMPOTENCY_STALE_CLAIM_SECONDS",
+        "idempotency.stale_claim_seconds",
+    ),
+    ("SM_CHANGE_RETENTION_DAYS", "retention.change_days"),
+    (
+        "SM_IDEMPOTENCY_KEY_RETENTION_HOURS",
+        "retention.idempotency_key_hours",
+    ),
+    ("SM_GATEWAY_LISTEN_ADDR", "gateway.listen_addr"),
+    ("SM_GATEWAY_USERNAME", "gateway.username"),
+    ("SM_GATEWAY_DOMAINS", "gateway.domains"),
+    ("SM_GATEWAY_ALLOWLIST", "gateway.allowlist"),
+    ("SM_GATEWAY_ALLOWLIST_ONLY", "gateway.allowlist_only"),
+    ("SM_GATEWAY_TARPIT_SECONDS", "gateway.tarpit_seconds"),
+    (
+        "SM_GATEWAY_MAX_SESSIONS_PER_IP",
+        "gateway.max_sessions_per_ip",
+    ),
+    ("SM_GATEWAY_MAX_MESSAGE_BYTES", "gateway.max_message_bytes"),
+    ("SM_GATEWAY_MAX_RECIPIENTS", "gateway.max_recipients"),
+];
+
+/// Settings of the server and the SMTP gateway. The defaults are overridden by the TOML file
+/// named by `SM_CONFIG`, or `safemail.toml` if there is one, and those by the environment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub content_store: ContentStoreConfig,
+    pub federation: FederationConfig,
+    pub session: SessionPolicy,
+    pub stamps: StampPolicy,
+    pub mailbox: MailboxQuota,
+    pub reports: ReportPolicy,
+    pub bridge: BridgePolicy,
+    pub smtp_relay: SmtpRelayConfig,
+    pub rate_limits: RateLimitConfig,
+    pub idempotency: IdempotencyPolicy,
+    pub retention: RetentionConfig,
+    pub gateway: GatewayConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    pub listen_addr: SocketAddr,
+    /// The host other servers know this one by, the part after the `@` in its users' addresses.
+    pub host: String,
+    /// Origins of the web clients allowed to call the API from a browser.
+    pub cors_origins: Vec<String>,
+    /// Whether the client address is taken from `X-Forwarded-For`, which only a proxy in front of
+    /// the server may be trusted to set.
+    pub trust_forwarded_for: bool,
+    /// Until when the routes from before the API was versioned are served.
+    pub unversioned_sunset: DateTime<Utc>,
+}
+
+impl ServerConfig {
+    /// The CORS origins as header values. [`Config::problems`] rejects any that aren't one.
+    pub fn cors_origin_headers(&self) -> Vec<HeaderValue> {
+        self.cors_origins
+            .iter()
+            .filter_map(|origin| origin_header(origin))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FederationConfig {
+    pub scheme: FederationScheme,
+    /// Lets other servers resolve to loopback and private addresses, which is only meant for
+    /// running several instances locally. Otherwise users could have the server reach into its
+    /// own network.
+    pub allow_private_addresses: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub backend: RateLimiterBackend,
+    pub login: RouteBudget,
+    pub registration: RouteBudget,
+    pub stamp_request: RouteBudget,
+    pub federation: RouteBudget,
+}
+
+impl RateLimitConfig {
+    pub fn policy(&self) -> RateLimitPolicy {
+        RateLimitPolicy {
+            login: self.login,
+            registration: self.registration,
+            stamp_request: self.stamp_request,
+            federation: self.federation,
+        }
+    }
+}
+
+/// How long records kept for clients that come back later are kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// Devices that were away for longer have to resync from scratch.
+    pub change_days: i64,
+    /// Retries that arrive later are processed as new requests.
+    pub idempotency_key_hours: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GatewayConfig {
+    pub listen_addr: SocketAddr,
+    /// The account gateway mail is sent from, created if it doesn't exist.
+    pub username: String,
+    /// Mail domains whose local parts are looked up as usernames. Bridged mail is sent from
+    /// the first.
+    pub domains: Vec<String>,
+    /// Client networks, such as the MTA in front, whose mail is delivered for free. The sender
+    /// addresses clients give are not trusted for this, as anyone can give any.
+    pub allowlist: Vec<IpNet>,
+    /// Refuse connections from anyone not on the allowlist.
+    pub allowlist_only: bool,
+    /// How long the acceptance of every recipient of mail from anyone else is held up.
+    pub tarpit_seconds: u64,
+    /// Concurrent SMTP sessions allowed from one client address.
+    pub max_sessions_per_ip: usize,
+    pub max_message_bytes: usize,
+    pub max_recipients: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let limit = |requests, window_seconds| {
+            Some(RateLimit {
+                requests,
+                window_seconds,
+            })
+        };
+        Self {
+            server: ServerConfig {
+                listen_addr: SocketAddr::from(([127, 0, 0, 1], 3000)),
+                host: "localhost:3000".to_string(),
+                cors_origins: vec!["http://localhost:4200".to_string()],
+                trust_forwarded_for: false,
+                unversioned_sunset: Utc.with_ymd_and_hms(2027, 4, 19, 0, 0, 0).unwrap(),
+            },
+            database: DatabaseConfig { url: String::new() },
+            content_store: ContentStoreConfig {
+                backend: ContentStoreBackend::Postgres,
+                path: None,
+                s3: S3Config {
+                    endpoint: None,
+                    bucket: String::new(),
+                    region: "us-east-1".to_string(),
+                    access_key_id: String::new(),
+                    secret_access_key: String::new(),
+                },
+                out_of_line_threshold: 64 * 1024,
+            },
+            federation: FederationConfig {
+                scheme: FederationScheme::Https,
+                allow_private_addresses: false,
+            },
+            session: SessionPolicy {
+                length_minutes: 2 * 60,
+            },
+            stamps: StampPolicy {
+                base_difficulty: 50_000,
+                max_difficulty: 1 << 30,
+                request_validity_minutes: 15,
+                validity_minutes: 15,
+            },
+            mailbox: MailboxQuota {
+                max_messages: 100_000,
+                max_bytes: 1024 * 1024 * 1024,
+            },
+            reports: ReportPolicy {
+                revoke_stamps: true,
+                difficulty_factor: 2,
+                review_threshold: 3,
+            },
+            bridge: BridgePolicy {
+                max_recipients: 20,
+                max_recipients_per_hour: 50,
+            },
+            smtp_relay: SmtpRelayConfig {
+                host: "localhost".to_string(),
+                port: 25,
+                starttls: false,
+                username: None,
+                password: None,
+            },
+            rate_limits: RateLimitConfig {
+                backend: RateLimiterBackend::Memory,
+                login: RouteBudget {
+                    per_ip: limit(60, 60),
+                    per_user: limit(10, 60),
+                    per_recipient: None,
+                },
+                registration: RouteBudget {
+                    per_ip: limit(10, 60 * 60),
+                    per_user: None,
+                    per_recipient: None,
+                },
+                stamp_request: RouteBudget {
+                    per_ip: limit(300, 60),
+                    per_user: limit(60, 60),
+                    per_recipient: limit(120, 60),
+                },
+                federation: RouteBudget {
+                    per_ip: limit(600, 60),
+                    per_user: None,
+                    per_recipient: None,
+                },
+            },
+            idempotency: IdempotencyPolicy {
+                stale_claim_seconds: 60,
+            },
+            retention: RetentionConfig {
+                change_days: 30,
+                idempotency_key_hours: 24,
+            },
+            gateway: GatewayConfig {
+                listen_addr: SocketAddr::from(([127, 0, 0, 1], 2525)),
+                username: "smtp-gateway".to_string(),
+                domains: vec!["localhost".to_string()],
+                allowlist: Vec::new(),
+                allowlist_only: false,
+                tarpit_seconds: 10,
+                max_sessions_per_ip: 4,
+                max_message_bytes: 10 * 1024 * 1024,
+                max_recipients: 100,
+            },
+        }
+    }
+}
+
+/// Everything found wrong with the configuration, so it can be fixed in one go.
+#[derive(Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid configuration:")?;
+        for problem in &self.0 {
+            write!(f, "\n  {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Reads the configuration file and the environment over the defaults, and checks the result.
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut settings = match Value::try_from(Config::default()) {
+            Ok(Value::Table(settings)) => settings,
+            _ => unreachable!("the defaults serialize to a table"),
+        };
+
+        let file = match std::env::var_os("SM_CONFIG") {
+            Some(path) => Some(PathBuf::from(path)),
+            None => Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|path| path.exists()),
+        };
+        if let Some(path) = file {
+            let file_error = |e: &dyn fmt::Display| {
+                ConfigError(vec![format!(
+                    "{}: {}",
+                    path.display(),
+                    e.to_string().trim_end()
+                )])
+            };
+            let text = std::fs::read_to_string(&path).map_err(|e| file_error(&e))?;
+            let overrides = text.parse::<Table>().map_err(|e| file_error(&e))?;
+            merge(&mut settings, overrides);
+        }
+
+        let problems = ENV_OVERRIDES
+            .iter()
+            .filter_map(|(name, path)| {
+                let text = std::env::var(name).ok()?;
+                set(&mut settings, path, &text)
+                    .err()
+                    .map(|e| format!("{}: {}", name, e))
+            })
+            .collect::<Vec<_>>();
+        if !problems.is_empty() {
+            return Err(ConfigError(problems));
+        }
+
+        let config: Config = serde_path_to_error::deserialize(Value::Table(settings))
+            .map_err(|e| ConfigError(vec![format!("{}: {}", e.path(), e.inner().message())]))?;
+        let problems = config.problems();
+        if !problems.is_empty() {
+            return Err(ConfigError(problems));
+        }
+        Ok(config)
+    }
+
+    /// What is wrong with settings that are each of the right type.
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut check = |ok: bool, problem: &str| {
+            if !ok {
+                problems.push(problem.to_string());
+            }
+        };
+        check(
+            !self.database.url.is_empty(),
+            "database.url, or DATABASE_URL, must be set",
+        );
+        check(
+            !self.server.host.is_empty() && !self.server.host.contains('/'),
+            "server.host must be a host name, optionally followed by a port",
+        );
+        for origin in &self.server.cors_origins {
+            check(
+                origin_header(origin).is_some(),
+                &format!(
+                    "server.cors_origins: {} is not an origin such as https://mail.example.com",
+                    origin
+                ),
+            );
+        }
+        match self.content_store.backend {
+            ContentStoreBackend::Postgres => {}
+            ContentStoreBackend::Filesystem => check(
+                self.content_store.path.is_some(),
+                "content_store.path must be set for the filesystem backend",
+            ),
+            ContentStoreBackend::S3 => {
+                let s3 = &self.content_store.s3;
+                for (name, value) in [
+                    ("bucket", &s3.bucket),
+                    ("access_key_id", &s3.access_key_id),
+                    ("secret_access_key", &s3.secret_access_key),
+                ] {
+                    check(
+                        !value.is_empty(),
+                        &format!("content_store.s3.{} must be set for the s3 backend", name),
+                    );
+                }
+            }
+        }
+        check(
+            self.session.length_minutes > 0,
+            "session.length_minutes must be positive",
+        );
+        check(
+            self.stamps.base_difficulty > 0,
+            "stamps.base_difficulty must be positive",
+        );
+        check(
+            self.stamps.max_difficulty >= self.stamps.base_difficulty,
+            "stamps.max_difficulty must be at least stamps.base_difficulty",
+        );
+        check(
+            self.stamps.request_validity_minutes > 0,
+            "stamps.request_validity_minutes must be positive",
+        );
+        check(
+            self.stamps.validity_minutes > 0,
+            "stamps.validity_minutes must be positive",
+        );
+        check(
+            self.mailbox.max_messages > 0,
+            "mailbox.max_messages must be positive",
+        );
+        check(
+            self.mailbox.max_bytes > 0,
+            "mailbox.max_bytes must be positive",
+        );
+        check(
+            self.reports.difficulty_factor > 0,
+            "reports.difficulty_factor must be positive",
+        );
+        check(
+            self.reports.review_threshold >= 0,
+            "reports.review_threshold must not be negative",
+        );
+        check(
+            self.bridge.max_recipients > 0,
+            "bridge.max_recipients must be positive",
+        );
+        check(
+            self.bridge.max_recipients_per_hour > 0,
+            "bridge.max_recipients_per_hour must be positive",
+        );
+        check(
+            !self.smtp_relay.host.is_empty(),
+            "smtp_relay.host must be set",
+        );
+        check(
+            self.idempotency.stale_claim_seconds > 0,
+            "idempotency.stale_claim_seconds must be positive",
+        );
+        check(
+            self.retention.change_days > 0,
+            "retention.change_days must be positive",
+        );
+        check(
+            self.retention.idempotency_key_hours > 0,
+            "retention.idempotency_key_hours must be positive",
+        );
+        check(
+            !self.gateway.username.is_empty(),
+            "gateway.username must be set",
+        );
+        check(
+            !self.gateway.domains.is_empty(),
+            "gateway.domains must name at least one domain",
+        );
+        check(
+            self.gateway.max_sessions_per_ip > 0,
+            "gateway.max_sessions_per_ip must be positive",
+        );
+        check(
+            self.gateway.max_message_bytes > 0,
+            "gateway.max_message_bytes must be positive",
+        );
+        check(
+            self.gateway.max_recipients > 0,
+            "gateway.max_recipients must be positive",
+        );
+        problems
+    }
+}
+
+/// Whether `origin` is a scheme and host, as browsers send in `Origin`.
+fn origin_header(origin: &str) -> Option<HeaderValue> {
+    origin
+        .strip_prefix("https://")
+        .or_else(|| origin.strip_prefix("http://"))
+        .filter(|host| {
+            !host.is_empty()
+                && host
+                    .bytes()
+                    .all(|byte| byte.is_ascii_graphic() && byte != b'/')
+        })
+        .and_then(|_| HeaderValue::from_str(origin).ok())
+}
+
+/// Lays `overrides` over `settings`, merging the tables both have.
+fn merge(settings: &mut Table, overrides: Table) {
+    for (key, value) in overrides {
+        match (settings.get_mut(&key), value) {
+            (Some(Value::Table(table)), Value::Table(overrides)) => merge(table, overrides),
+            (_, value) => {
+                settings.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Sets the setting at the dotted `path` from the text of an environment variable, read as the
+/// type of the value it replaces. Settings without a value, which are all optional text, are
+/// taken as text.
+fn set(settings: &mut Table, path: &str, text: &str) -> Result<(), String> {
+    let (sections, key) = path.rsplit_once('.').unwrap_or(("", path));
+    let mut table = settings;
+    for section in sections.split('.').filter(|section| !section.is_empty()) {
+        table = match table.get_mut(section) {
+            Some(Value::Table(table)) => table,
+            _ => {
+                return Err(format!(
+                    "{} is not a table in the configuration file",
+                    section
+                ))
+            }
+        };
+    }
+    let value = match table.get(key) {
+        Some(Value::Integer(_)) => Value::Integer(
+            text.trim()
+                .parse()
+                .map_err(|_| format!("{} is not a whole number", text))?,
+        ),
+        Some(Value::Boolean(_)) => Value::Boolean(
+            text.trim()
+                .parse()
+                .map_err(|_| format!("{} is neither true nor false", text))?,
+        ),
+        Some(Value::Array(_)) => Value::Array(
+            text.split(',')
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty())
+                .map(|entry| Value::String(entry.to_string()))
+                .collect(),
+        ),
+        _ => Value::String(text.to_string()),
+    };
+    table.insert(key.to_string(), value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::Value;
+
+    use super::Config;
+
+    #[test]
+    fn example_file_lists_the_defaults() {
+        let example = concat!(env!("CARGO_MANIFEST_DIR"), "/../safemail.example.toml");
+        std::env::set_var("SM_CONFIG", example);
+        std::env::set_var("DATABASE_URL", "postgres://localhost/safemail");
+        let config = Config::load().unwrap_or_else(|e| panic!("{}", e));
+
+        let mut defaults = Config::default();
+        defaults.database.url = config.database.url.clone();
+        assert_eq!(
+            Value::try_from(config).unwrap(),
+            Value::try_from(defaults).unwrap()
+        );
+    }
+}
@@ -1,10 +1,36 @@
-use sqlx::PgPool;
+use domain::error::{DatabaseError, SmError};
+use sqlx::{error::ErrorKind, PgPool};
 use std::sync::Arc;
 
-pub async fn get_pool() -> Arc<PgPool> {
+pub async fn get_pool(url: &str) -> Arc<PgPool> {
     
-    sqlx::PgPool::connect(&std::env::var("DATABASE_URL").expect("DATABASE_URL must be set"))
+    sqlx::PgPool::connect(url)
             .await
             .map(Arc::new)
             .unwrap()
 }
+
+/// Classifies a failed database operation into a domain error, keeping the original as its
+/// source.
+pub fn database_error(e: sqlx::Error) -> SmError {
+    let error = match &e {
+        sqlx::Error::PoolTimedOut => DatabaseError::Timeout(Box::new(e)),
+        sqlx::Error::Database(db) => match (db.code().as_deref(), db.kind()) {
+            // serialization_failure and deadlock_detected
+            (Some("40001" | "40P01"), _) => DatabaseError::SerializationFailure(Box::new(e)),
+            // query_canceled, which statement_timeout raises, and lock_not_available
+            (Some("57014" | "55P03"), _) => DatabaseError::Timeout(Box::new(e)),
+            (_, ErrorKind::UniqueViolation) => DatabaseError::UniqueViolation {
+                constraint: db.constraint().unwrap_or_default().to_string(),
+                source: Box::new(e),
+            },
+            (_, ErrorKind::ForeignKeyViolation) => DatabaseError::ForeignKeyViolation {
+                constraint: db.constraint().unwrap_or_default().to_string(),
+                source: Box::new(e),
+            },
+            _ => DatabaseError::Query(Box::new(e)),
+        },
+        _ => DatabaseError::Query(Box::new(e)),
+    };
+    error.into()
+}
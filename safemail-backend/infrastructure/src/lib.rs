@@ -1,19 +1,51 @@
+pub mod config;
 pub mod db;
 pub mod repositories {
+    mod blob;
+    pub use blob::*;
+    mod block_list;
+    pub use block_list::*;
+    mod content;
+    pub use content::*;
+    mod federation_peer;
+    pub use federation_peer::*;
+    mod idempotency;
+    pub use idempotency::*;
     mod user;
     pub use user::*;
     mod session;
     pub use session::*;
     mod message;
     pub use message::*;
+    mod message_change;
+    pub use message_change::*;
     mod onetime_stamp;
+    mod outbound_mail;
+    pub use outbound_mail::*;
+    mod outbox;
+    pub use outbox::*;
+    mod remote_user;
+    pub use remote_user::*;
+    mod report;
+    pub use report::*;
+    mod scheduled_message;
+    pub use scheduled_message::*;
     mod system_key;
     pub use onetime_stamp::*;
     pub use system_key::*;
     mod stamp_request;
     pub use stamp_request::*;
+    mod stamp_revocation;
+    pub use stamp_revocation::*;
 }
 pub mod services {
+    pub mod content_store;
     pub mod cryptography;
+    pub mod federation;
+    pub mod notifications;
+    pub mod rate_limiter;
     pub mod serialize;
+    pub mod smtp;
 }
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
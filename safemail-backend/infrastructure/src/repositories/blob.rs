@@ -0,0 +1,293 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use domain::{
+    blob::{Blob, BlobRepository},
+    content::ContentStore,
+    error::{DatabaseError, SmError, StorageError},
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::content::record_pending_content;
+use crate::db::database_error;
+use crate::services::content_store::ConfiguredContentStore;
+
+/// Keeps blob metadata in the database and chunk data in the content store.
+#[derive(Clone)]
+pub struct PostgresBlobRepository {
+    pool: Arc<PgPool>,
+    content_store: ConfiguredContentStore,
+}
+
+impl PostgresBlobRepository {
+    pub fn new(pool: Arc<PgPool>, content_store: ConfiguredContentStore) -> Self {
+        Self {
+            pool,
+            content_store,
+        }
+    }
+}
+
+#[async_trait]
+impl BlobRepository for PostgresBlobRepository {
+    async fn create_blob(
+        &self,
+        hash: &str,
+        size: i64,
+        chunk_size: i32,
+        chunk_count: i32,
+        uploader_id: Uuid,
+    ) -> Result<Blob, SmError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO sm.blobs (hash, size, chunk_size, chunk_count, uploader_id)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (hash) DO NOTHING
+            "#,
+            hash,
+            size,
+            chunk_size,
+            chunk_count,
+            uploader_id
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        self.get_blob(hash)
+            .await?
+            .ok_or(SmError::from(DatabaseError::Arbitrary))
+    }
+
+    async fn get_blob(&self, hash: &str) -> Result<Option<Blob>, SmError> {
+        let result = sqlx::query_as!(
+            Blob,
+            r#"
+            SELECT hash, size, chunk_size, chunk_count, uploader_id, completed_at
+            FROM sm.blobs
+            WHERE hash = $1
+            "#,
+            hash
+        )
+        .fetch_optional(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(result)
+    }
+
+    async fn take_over_upload(
+        &self,
+        hash: &str,
+        size: i64,
+        chunk_size: i32,
+        chunk_count: i32,
+        uploader_id: Uuid,
+        idle_before: DateTime<Utc>,
+    ) -> Result<Option<Blob>, SmError> {
+        let mut transaction = self.pool.begin().await.map_err(database_error)?;
+
+        let blob = sqlx::query_as!(
+            Blob,
+            r#"
+            UPDATE sm.blobs
+            SET size = $2, chunk_size = $3, chunk_count = $4, uploader_id = $5,
+                created_at = NOW(), active_at = NOW()
+            WHERE hash = $1 AND completed_at IS NULL AND active_at < $6
+            RETURNING hash, size, chunk_size, chunk_count, uploader_id, completed_at
+            "#,
+            hash,
+            size,
+            chunk_size,
+            chunk_count,
+            uploader_id,
+            idle_before
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .map_err(database_error)?;
+
+        if blob.is_some() {
+            sqlx::query!(
+                r#"
+                DELETE FROM sm.blob_chunks
+                WHERE blob_hash = $1
+                "#,
+                hash
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(database_error)?;
+        }
+
+        transaction.commit().await.map_err(database_error)?;
+        Ok(blob)
+    }
+
+    async fn put_chunk(
+        &self,
+        hash: &str,
+        uploader_id: Uuid,
+        index: i32,
+        data: Vec<u8>,
+    ) -> Result<bool, SmError> {
+        // A re-uploaded chunk gets a fresh key; the replaced one is orphaned by a trigger
+        let content_key = format!("blobs/{}/{}", hash, Uuid::new_v4());
+        let size = data.len() as i32;
+        record_pending_content(&self.pool, std::slice::from_ref(&content_key)).await?;
+        self.content_store.put(&content_key, data).await?;
+
+        // Locking the blob row keeps the upload from being completed or taken over meanwhile
+        let result = sqlx::query!(
+            r#"
+            WITH blob AS (
+                UPDATE sm.blobs
+                SET active_at = NOW()
+                WHERE hash = $1 AND uploader_id = $5 AND completed_at IS NULL
+                RETURNING hash
+            ),
+            settled AS (
+                DELETE FROM sm.orphaned_content
+                WHERE key = $3 AND EXISTS (SELECT 1 FROM blob)
+            )
+            INSERT INTO sm.blob_chunks (blob_hash, chunk_index, content_key, size)
+            SELECT hash, $2, $3, $4
+            FROM blob
+            ON CONFLICT (blob_hash, chunk_index)
+            DO UPDATE SET content_key = EXCLUDED.content_key, size = EXCLUDED.size
+            RETURNING chunk_index
+            "#,
+            hash,
+            index,
+            content_key,
+            size,
+            uploader_id
+        )
+        .fetch_optional(&*self.pool)
+        .await;
+
+        match result {
+            Ok(Some(_)) => Ok(true),
+            Ok(None) => {
+                let _ = self.content_store.delete(&content_key).await;
+                Ok(false)
+            }
+            Err(e) => {
+                let _ = self.content_store.delete(&content_key).await;
+                Err(database_error(e))
+            }
+        }
+    }
+
+    async fn get_chunk(&self, hash: &str, index: i32) -> Result<Option<Vec<u8>>, SmError> {
+        let result = sqlx::query!(
+            r#"
+            SELECT content_key
+            FROM sm.blob_chunks
+            WHERE blob_hash = $1 AND chunk_index = $2
+            "#,
+            hash,
+            index
+        )
+        .fetch_optional(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        match result {
+            Some(record) => self
+                .content_store
+                .get(&record.content_key)
+                .await?
+                .ok_or(StorageError::Arbitrary.into())
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    async fn list_chunk_indices(&self, hash: &str) -> Result<Vec<i32>, SmError> {
+        let records = sqlx::query!(
+            r#"
+            SELECT chunk_index
+            FROM sm.blob_chunks
+            WHERE blob_hash = $1
+            ORDER BY chunk_index
+            "#,
+            hash
+        )
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(records.into_iter().map(|r| r.chunk_index).collect())
+    }
+
+    async fn discard_chunks(&self, hash: &str) -> Result<(), SmError> {
+        sqlx::query!(
+            r#"
+            DELETE FROM sm.blob_chunks
+            WHERE blob_hash = $1
+            "#,
+            hash
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(())
+    }
+
+    async fn mark_complete(&self, hash: &str) -> Result<(), SmError> {
+        sqlx::query!(
+            r#"
+            UPDATE sm.blobs
+            SET completed_at = NOW(),
+                unreferenced_since = CASE WHEN ref_count = 0 THEN NOW() ELSE NULL END
+            WHERE hash = $1 AND completed_at IS NULL
+            "#,
+            hash
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(())
+    }
+
+    async fn can_access(&self, hash: &str, user_id: Uuid) -> Result<bool, SmError> {
+        let result = sqlx::query!(
+            r#"
+            SELECT sm.can_access_blob($1, $2) AS "accessible!"
+            "#,
+            hash,
+            user_id
+        )
+        .fetch_one(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(result.accessible)
+    }
+
+    async fn collect_garbage(
+        &self,
+        unreferenced_before: DateTime<Utc>,
+        abandoned_before: DateTime<Utc>,
+    ) -> Result<u64, SmError> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM sm.blobs
+            WHERE (completed_at IS NOT NULL AND ref_count = 0 AND unreferenced_since < $1)
+            OR (completed_at IS NULL AND created_at < $2)
+            "#,
+            unreferenced_before,
+            abandoned_before
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(result.rows_affected())
+    }
+}
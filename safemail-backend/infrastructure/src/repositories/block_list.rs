@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use domain::{
+    block_list::{BlockListRepository, BlockedSender},
+    error::SmError,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::db::database_error;
+
+#[derive(Clone)]
+pub struct PostgresBlockListRepository {
+    pool: Arc<PgPool>,
+}
+
+impl PostgresBlockListRepository {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl BlockListRepository for PostgresBlockListRepository {
+    async fn block_sender(&self, user_id: Uuid, sender_id: Uuid) -> Result<(), SmError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO sm.blocked_senders (user_id, sender_id)
+            VALUES ($1, $2)
+            ON CONFLICT (user_id, sender_id) DO NOTHING
+            "#,
+            user_id,
+            sender_id
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(())
+    }
+
+    async fn unblock_sender(&self, user_id: Uuid, sender_id: Uuid) -> Result<(), SmError> {
+        sqlx::query!(
+            r#"
+            DELETE FROM sm.blocked_senders
+            WHERE user_id = $1 AND sender_id = $2
+            "#,
+            user_id,
+            sender_id
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(())
+    }
+
+    async fn list_blocked_senders(&self, user_id: Uuid) -> Result<Vec<BlockedSender>, SmError> {
+        sqlx::query_as!(
+            BlockedSender,
+            r#"
+            SELECT sender_id, blocked_at
+            FROM sm.blocked_senders
+            WHERE user_id = $1
+            ORDER BY blocked_at, sender_id
+            "#,
+            user_id
+        )
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(database_error)
+    }
+
+    async fn is_blocked(&self, user_id: Uuid, sender_id: Uuid) -> Result<bool, SmError> {
+        let record = sqlx::query!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM sm.blocked_senders
+                WHERE user_id = $1 AND sender_id = $2
+            ) AS "blocked!"
+            "#,
+            user_id,
+            sender_id
+        )
+        .fetch_one(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(record.blocked)
+    }
+}
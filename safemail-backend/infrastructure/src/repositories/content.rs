@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use domain::{content::OrphanedContentRepository, error::SmError};
+use sqlx::{PgConnection, PgPool};
+
+use crate::db::database_error;
+
+/// How long an object may take from being stored to being referenced, longer than any request.
+const PENDING_CONTENT_MINUTES: i64 = 60;
+
+/// Records keys as orphaned from a while ahead, before their objects are stored, so that
+/// objects stored by requests that never got to reference them are collected as well.
+pub(crate) async fn record_pending_content(pool: &PgPool, keys: &[String]) -> Result<(), SmError> {
+    sqlx::query!(
+        r#"
+        INSERT INTO sm.orphaned_content (key, orphaned_at)
+        SELECT key, $2 FROM UNNEST($1::text[]) AS key
+        "#,
+        keys,
+        Utc::now() + Duration::minutes(PENDING_CONTENT_MINUTES)
+    )
+    .execute(pool)
+    .await
+    .map_err(database_error)?;
+
+    Ok(())
+}
+
+/// Takes back the keys recorded by `record_pending_content`, as part of the transaction that
+/// comes to reference them.
+pub(crate) async fn settle_pending_content(
+    connection: &mut PgConnection,
+    keys: &[String],
+) -> Result<(), SmError> {
+    sqlx::query!(
+        r#"
+        DELETE FROM sm.orphaned_content
+        WHERE key = ANY($1)
+        "#,
+        keys
+    )
+    .execute(connection)
+    .await
+    .map_err(database_error)?;
+
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct PostgresOrphanedContentRepository {
+    pool: Arc<PgPool>,
+}
+
+impl PostgresOrphanedContentRepository {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl OrphanedContentRepository for PostgresOrphanedContentRepository {
+    async fn list_orphaned_content(&self, limit: i64) -> Result<Vec<String>, SmError> {
+        let records = sqlx::query!(
+            r#"
+            SELECT key
+            FROM sm.orphaned_content
+            WHERE orphaned_at <= NOW()
+            ORDER BY orphaned_at
+            LIMIT $1
+            "#,
+            limit
+        )
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(records.into_iter().map(|r| r.key).collect())
+    }
+
+    async fn forget_orphaned_content(&self, keys: &[String]) -> Result<(), SmError> {
+        sqlx::query!(
+            r#"
+            DELETE FROM sm.orphaned_content
+            WHERE key = ANY($1)
+            "#,
+            keys
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use domain::{
+    error::SmError,
+    federation::{FederationPeerRepository, PeerKey},
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::db::database_error;
+
+#[derive(Clone)]
+pub struct PostgresFederationPeerRepository {
+    pool: Arc<PgPool>,
+}
+
+impl PostgresFederationPeerRepository {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl FederationPeerRepository for PostgresFederationPeerRepository {
+    async fn get_peer_key(&self, host: &str) -> Result<Option<PeerKey>, SmError> {
+        let result = sqlx::query_as!(
+            PeerKey,
+            r#"
+            SELECT public_key, fetched_at
+            FROM sm.federation_peers
+            WHERE host = $1
+            "#,
+            host
+        )
+        .fetch_optional(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(result)
+    }
+
+    async fn save_peer_key(&self, host: &str, public_key: &str) -> Result<(), SmError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO sm.federation_peers (host, public_key)
+            VALUES ($1, $2)
+            ON CONFLICT (host) DO UPDATE SET public_key = $2, fetched_at = NOW()
+            "#,
+            host,
+            public_key
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(())
+    }
+
+    async fn record_request(
+        &self,
+        origin: &str,
+        request_id: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, SmError> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO sm.federation_requests (origin, request_id, expires_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING
+            "#,
+            origin,
+            request_id,
+            expires_at
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn purge_requests(&self, expired_before: DateTime<Utc>) -> Result<u64, SmError> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM sm.federation_requests
+            WHERE expires_at < $1
+            "#,
+            expired_before
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(result.rows_affected())
+    }
+}
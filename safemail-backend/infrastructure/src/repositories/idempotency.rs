@@ -0,0 +1,160 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use domain::{
+    error::SmError,
+    idempotency::{IdempotencyClaim, IdempotencyRepository, IdempotentOutcome},
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::db::database_error;
+
+#[derive(Clone)]
+pub struct PostgresIdempotencyRepository {
+    pool: Arc<PgPool>,
+}
+
+impl PostgresIdempotencyRepository {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl IdempotencyRepository for PostgresIdempotencyRepository {
+    async fn claim(
+        &self,
+        sender_id: Uuid,
+        key: &str,
+        fingerprint: &str,
+        stale_before: DateTime<Utc>,
+    ) -> Result<IdempotencyClaim, SmError> {
+        let claimed = sqlx::query!(
+            r#"
+            INSERT INTO sm.idempotency_keys (sender_id, key, fingerprint)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (sender_id, key) DO UPDATE SET claimed_at = NOW()
+            WHERE idempotency_keys.fingerprint = EXCLUDED.fingerprint
+            AND idempotency_keys.completed_at IS NULL
+            AND idempotency_keys.claimed_at < $4
+            RETURNING key
+            "#,
+            sender_id,
+            key,
+            fingerprint,
+            stale_before
+        )
+        .fetch_optional(&*self.pool)
+        .await
+        .map_err(database_error)?;
+        if claimed.is_some() {
+            return Ok(IdempotencyClaim::Claimed);
+        }
+
+        let existing = sqlx::query!(
+            r#"
+            SELECT fingerprint, completed_at, status, content_type, body
+            FROM sm.idempotency_keys
+            WHERE sender_id = $1 AND key = $2
+            "#,
+            sender_id,
+            key
+        )
+        .fetch_optional(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        // The claim may have been released in between, in which case the key is free again
+        let Some(existing) = existing else {
+            return self.claim(sender_id, key, fingerprint, stale_before).await;
+        };
+        if existing.fingerprint != fingerprint {
+            return Ok(IdempotencyClaim::Mismatch);
+        }
+        match (existing.completed_at, existing.status, existing.body) {
+            (Some(_), Some(status), Some(body)) => {
+                Ok(IdempotencyClaim::Completed(IdempotentOutcome {
+                    status,
+                    content_type: existing.content_type,
+                    body,
+                }))
+            }
+            _ => Ok(IdempotencyClaim::InProgress),
+        }
+    }
+
+    async fn complete(
+        &self,
+        sender_id: Uuid,
+        key: &str,
+        outcome: IdempotentOutcome,
+    ) -> Result<(), SmError> {
+        sqlx::query!(
+            r#"
+            UPDATE sm.idempotency_keys
+            SET completed_at = NOW(), status = $3, content_type = $4, body = $5
+            WHERE sender_id = $1 AND key = $2
+            "#,
+            sender_id,
+            key,
+            outcome.status,
+            outcome.content_type,
+            outcome.body
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(())
+    }
+
+    async fn renew(&self, sender_id: Uuid, key: &str) -> Result<(), SmError> {
+        sqlx::query!(
+            r#"
+            UPDATE sm.idempotency_keys
+            SET claimed_at = NOW()
+            WHERE sender_id = $1 AND key = $2 AND completed_at IS NULL
+            "#,
+            sender_id,
+            key
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(())
+    }
+
+    async fn release(&self, sender_id: Uuid, key: &str) -> Result<(), SmError> {
+        sqlx::query!(
+            r#"
+            DELETE FROM sm.idempotency_keys
+            WHERE sender_id = $1 AND key = $2 AND completed_at IS NULL
+            "#,
+            sender_id,
+            key
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(())
+    }
+
+    async fn purge(&self, created_before: DateTime<Utc>) -> Result<u64, SmError> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM sm.idempotency_keys
+            WHERE created_at < $1
+            "#,
+            created_before
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(result.rows_affected())
+    }
+}
@@ -1,126 +1,682 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use sqlx::PgPool;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
-use domain::error::{DatabaseError, SmError};
-use domain::message::{Message, MessageMetadata, MessageRepository};
+use domain::content::ContentStore;
+use domain::error::{MessageError, SmError, StampError, StorageError};
+use domain::message::{
+    Delivery, MailboxQuota, MailboxUsage, Message, MessageRepository, MessageSummary, NewMessage,
+};
+use domain::outbox::NewOutboxEntry;
 
+use super::content::{record_pending_content, settle_pending_content};
+use crate::db::database_error;
+use crate::services::content_store::ConfiguredContentStore;
+
+/// Keeps messages in the database. Bodies longer than `out_of_line_threshold` bytes are moved
+/// to the content store and only referenced by key.
 #[derive(Clone)]
 pub struct PostgresMessageRepository {
     pool: Arc<PgPool>,
+    content_store: ConfiguredContentStore,
+    out_of_line_threshold: usize,
 }
 
 impl PostgresMessageRepository {
-    pub fn new(pool: Arc<PgPool>) -> Self {
-        Self { pool }
+    pub fn new(
+        pool: Arc<PgPool>,
+        content_store: ConfiguredContentStore,
+        out_of_line_threshold: usize,
+    ) -> Self {
+        Self {
+            pool,
+            content_store,
+            out_of_line_threshold,
+        }
+    }
+
+    /// Moves bodies above the threshold to the content store, returning their keys. The keys
+    /// count as orphaned after a while unless the rows referencing them are inserted with
+    /// `insert_messages` or settled otherwise.
+    pub(crate) async fn store_out_of_line(
+        &self,
+        contents: &[&str],
+    ) -> Result<Vec<Option<String>>, SmError> {
+        let keys: Vec<Option<String>> = contents
+            .iter()
+            .map(|content| {
+                (content.len() > self.out_of_line_threshold)
+                    .then(|| format!("messages/{}", Uuid::new_v4()))
+            })
+            .collect();
+        let pending: Vec<String> = keys.iter().flatten().cloned().collect();
+        if pending.is_empty() {
+            return Ok(keys);
+        }
+        record_pending_content(&self.pool, &pending).await?;
+
+        let mut content_keys = Vec::with_capacity(contents.len());
+        for (content, content_key) in contents.iter().zip(keys) {
+            let Some(content_key) = content_key else {
+                content_keys.push(None);
+                continue;
+            };
+            let stored = self
+                .content_store
+                .put(&content_key, content.as_bytes().to_vec())
+                .await;
+            if let Err(e) = stored {
+                self.discard_out_of_line(&content_keys).await;
+                return Err(e);
+            }
+            content_keys.push(Some(content_key));
+        }
+        Ok(content_keys)
+    }
+
+    pub(crate) async fn discard_out_of_line(&self, content_keys: &[Option<String>]) {
+        for content_key in content_keys.iter().flatten() {
+            let _ = self.content_store.delete(content_key).await;
+        }
+    }
+
+    /// Inserts the messages and outbox entry as part of the given transaction. `content_keys`
+    /// are those returned by `store_out_of_line` for the messages followed by the outbox entry.
+    pub(crate) async fn insert_messages(
+        transaction: &mut Transaction<'_, Postgres>,
+        messages: &[NewMessage],
+        outbox_entry: Option<&NewOutboxEntry>,
+        content_keys: &[Option<String>],
+        quota: &MailboxQuota,
+    ) -> Result<Delivery, SmError> {
+        let mut sizes = Vec::with_capacity(messages.len());
+        let mut additions: BTreeMap<Uuid, (i64, i64)> = BTreeMap::new();
+        for message in messages {
+            let size = (message.metadata.0.len() + message.content.len()) as i64
+                + attachments_size(transaction, &message.attachments).await?;
+            sizes.push(size);
+            let addition = additions.entry(message.recipient_id).or_default();
+            addition.0 += 1;
+            addition.1 += size;
+        }
+
+        // The sent copy keeps every attachment of the delivered messages the sender could read
+        // already, listing a hash must not be enough to get at the blob
+        let outbox_attachments: Vec<String> = messages
+            .iter()
+            .flat_map(|message| message.attachments.iter().cloned())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let outbox_attachments = match outbox_entry {
+            Some(entry) => {
+                accessible_blobs(transaction, &outbox_attachments, entry.sender_id).await?
+            }
+            None => Vec::new(),
+        };
+        let outbox_size = match outbox_entry {
+            Some(entry) => {
+                let size = (entry.metadata.len() + entry.content.len()) as i64
+                    + attachments_size(transaction, &outbox_attachments).await?;
+                let addition = additions.entry(entry.sender_id).or_default();
+                addition.0 += 1;
+                addition.1 += size;
+                size
+            }
+            None => 0,
+        };
+
+        // Lock the usage rows, in a consistent order, so concurrent deliveries to the same
+        // mailbox can't both squeeze in under the quota
+        for (user_id, (count, bytes)) in additions {
+            sqlx::query!(
+                r#"
+                INSERT INTO sm.mailbox_usage (user_id)
+                VALUES ($1)
+                ON CONFLICT (user_id) DO NOTHING
+                "#,
+                user_id
+            )
+            .execute(&mut **transaction)
+            .await
+            .map_err(database_error)?;
+
+            let usage = sqlx::query_as!(
+                MailboxUsage,
+                r#"
+                SELECT message_count, stored_bytes
+                FROM sm.mailbox_usage
+                WHERE user_id = $1
+                FOR UPDATE
+                "#,
+                user_id
+            )
+            .fetch_one(&mut **transaction)
+            .await
+            .map_err(database_error)?;
+
+            if !quota.admits(&usage, count, bytes) {
+                let is_recipient = messages
+                    .iter()
+                    .any(|message| message.recipient_id == user_id);
+                return Err(match is_recipient {
+                    true => MessageError::MailboxFull,
+                    false => MessageError::OutboxFull,
+                }
+                .into());
+            }
+        }
+
+        // One-time stamps are used up with the delivery. Stamps users issued themselves are
+        // only tracked from here on.
+        for message in messages {
+            let Some(stamp_id) = message.onetime_stamp_id else {
+                continue;
+            };
+            let spent = sqlx::query!(
+                r#"
+                INSERT INTO sm.onetime_stamps (stamp_id, recipient_id, used_or_revoked)
+                VALUES ($1, $2, true)
+                ON CONFLICT (stamp_id) DO UPDATE SET used_or_revoked = true
+                WHERE NOT onetime_stamps.used_or_revoked
+                AND onetime_stamps.recipient_id = EXCLUDED.recipient_id
+                RETURNING stamp_id
+                "#,
+                stamp_id,
+                message.recipient_id
+            )
+            .fetch_optional(&mut **transaction)
+            .await
+            .map_err(database_error)?;
+            if spent.is_none() {
+                return Err(StampError::InvalidStamp.into());
+            }
+        }
+
+        let mut created = Vec::with_capacity(messages.len());
+        for ((message, content_key), size) in messages.iter().zip(content_keys).zip(sizes) {
+            let content = match content_key {
+                Some(_) => "",
+                None => &message.content,
+            };
+            let record = sqlx::query!(
+                r#"
+                INSERT INTO sm.messages
+                    (recipient_id, metadata, content, content_key, expires_at, size, sender_seal)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                RETURNING id, recipient_id, metadata, recipient_metadata,
+                    recipient_metadata_version, deleted_at, expires_at
+                "#,
+                message.recipient_id,
+                message.metadata.0,
+                content,
+                content_key.as_deref(),
+                message.expires_at,
+                size,
+                message.sender_seal
+            )
+            .fetch_one(&mut **transaction)
+            .await
+            .map_err(database_error)?;
+
+            sqlx::query!(
+                r#"
+                INSERT INTO sm.message_blobs (message_id, blob_hash)
+                SELECT $1, blob_hash FROM UNNEST($2::text[]) AS blob_hash
+                "#,
+                record.id,
+                &message.attachments
+            )
+            .execute(&mut **transaction)
+            .await
+            .map_err(database_error)?;
+
+            created.push(Message {
+                id: record.id,
+                recipient_id: record.recipient_id,
+                metadata: record.metadata,
+                recipient_metadata: record.recipient_metadata,
+                recipient_metadata_version: record.recipient_metadata_version,
+                content: message.content.clone(),
+                deleted_at: record.deleted_at,
+                expires_at: record.expires_at,
+                attachments: message.attachments.clone(),
+            });
+        }
+
+        let outbox_id = match outbox_entry {
+            Some(entry) => {
+                let content_key = content_keys.get(messages.len()).cloned().flatten();
+                let content = match content_key {
+                    Some(_) => "",
+                    None => &entry.content,
+                };
+                let outbox_id = sqlx::query!(
+                    r#"
+                    INSERT INTO sm.outbox (sender_id, metadata, content, content_key, size)
+                    VALUES ($1, $2, $3, $4, $5)
+                    RETURNING id
+                    "#,
+                    entry.sender_id,
+                    entry.metadata,
+                    content,
+                    content_key,
+                    outbox_size
+                )
+                .fetch_one(&mut **transaction)
+                .await
+                .map_err(database_error)?
+                .id;
+
+                let recipient_ids: Vec<Uuid> =
+                    created.iter().map(|message| message.recipient_id).collect();
+                let message_ids: Vec<i64> = created.iter().map(|message| message.id).collect();
+                sqlx::query!(
+                    r#"
+                    INSERT INTO sm.outbox_deliveries (outbox_id, recipient_id, message_id)
+                    SELECT $1, recipient_id, message_id
+                    FROM UNNEST($2::uuid[], $3::bigint[]) AS deliveries (recipient_id, message_id)
+                    "#,
+                    outbox_id,
+                    &recipient_ids,
+                    &message_ids
+                )
+                .execute(&mut **transaction)
+                .await
+                .map_err(database_error)?;
+
+                sqlx::query!(
+                    r#"
+                    INSERT INTO sm.outbox_blobs (outbox_id, blob_hash)
+                    SELECT $1, blob_hash FROM UNNEST($2::text[]) AS blob_hash
+                    "#,
+                    outbox_id,
+                    &outbox_attachments
+                )
+                .execute(&mut **transaction)
+                .await
+                .map_err(database_error)?;
+
+                Some(outbox_id)
+            }
+            None => None,
+        };
+
+        let stored: Vec<String> = content_keys.iter().flatten().cloned().collect();
+        settle_pending_content(transaction, &stored).await?;
+
+        Ok(Delivery {
+            messages: created,
+            outbox_id,
+        })
     }
 }
 
+async fn attachments_size(
+    transaction: &mut Transaction<'_, Postgres>,
+    attachments: &[String],
+) -> Result<i64, SmError> {
+    let record = sqlx::query!(
+        r#"
+        SELECT COALESCE(SUM(size), 0)::bigint AS "size!"
+        FROM sm.blobs
+        WHERE hash = ANY($1)
+        "#,
+        attachments
+    )
+    .fetch_one(&mut **transaction)
+    .await
+    .map_err(database_error)?;
+
+    Ok(record.size)
+}
+
+async fn accessible_blobs(
+    transaction: &mut Transaction<'_, Postgres>,
+    hashes: &[String],
+    user_id: Uuid,
+) -> Result<Vec<String>, SmError> {
+    let records = sqlx::query!(
+        r#"
+        SELECT blob_hash AS "blob_hash!"
+        FROM UNNEST($1::text[]) AS blob_hash
+        WHERE sm.can_access_blob(blob_hash, $2)
+        "#,
+        hashes,
+        user_id
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .map_err(database_error)?;
+
+    Ok(records.into_iter().map(|r| r.blob_hash).collect())
+}
+
 #[async_trait]
 impl MessageRepository for PostgresMessageRepository {
     async fn create_message(
+        &self,
+        message: NewMessage,
+        outbox_entry: Option<NewOutboxEntry>,
+        quota: &MailboxQuota,
+    ) -> Result<Delivery, SmError> {
+        self.create_messages(vec![message], outbox_entry, quota)
+            .await
+    }
+
+    async fn create_messages(
+        &self,
+        messages: Vec<NewMessage>,
+        outbox_entry: Option<NewOutboxEntry>,
+        quota: &MailboxQuota,
+    ) -> Result<Delivery, SmError> {
+        let contents: Vec<&str> = messages
+            .iter()
+            .map(|message| message.content.as_str())
+            .chain(outbox_entry.iter().map(|entry| entry.content.as_str()))
+            .collect();
+        let content_keys = self.store_out_of_line(&contents).await?;
+        let result = async {
+            let mut transaction = self.pool.begin().await.map_err(database_error)?;
+            let delivery = Self::insert_messages(
+                &mut transaction,
+                &messages,
+                outbox_entry.as_ref(),
+                &content_keys,
+                quota,
+            )
+            .await?;
+            transaction.commit().await.map_err(database_error)?;
+            Ok(delivery)
+        }
+        .await;
+        if result.is_err() {
+            self.discard_out_of_line(&content_keys).await;
+        }
+        result
+    }
+
+    async fn get_sender_seal(
         &self,
         recipient_id: Uuid,
-        metadata: MessageMetadata,
-        content: String,
-    ) -> Result<Message, SmError> {
-        let record = sqlx::query_as!(
-            Message,
+        id: i64,
+    ) -> Result<Option<String>, SmError> {
+        let record = sqlx::query!(
             r#"
-            INSERT INTO sm.messages (recipient_id, metadata, content)
-            VALUES ($1, $2, $3)
-            RETURNING id, recipient_id, metadata, recipient_metadata, content
+            SELECT sender_seal
+            FROM sm.messages
+            WHERE recipient_id = $1 AND id = $2
+            AND (expires_at IS NULL OR expires_at > NOW())
             "#,
             recipient_id,
-            metadata.0,
-            content
+            id
         )
-        .fetch_one(&*self.pool)
+        .fetch_optional(&*self.pool)
         .await
-        .map_err(|_| SmError::Database(DatabaseError::Arbitrary))?;
+        .map_err(database_error)?
+        .ok_or(SmError::Message(MessageError::MessageNotFound))?;
 
-        Ok(record)
+        Ok(record.sender_seal)
     }
 
     async fn get_message(&self, recipient_id: Uuid, id: i64) -> Result<Option<Message>, SmError> {
-        let record = sqlx::query_as!(
-            Message,
+        let record = sqlx::query!(
             r#"
-            SELECT id, recipient_id, metadata, recipient_metadata, content
+            SELECT id, recipient_id, metadata, recipient_metadata, recipient_metadata_version,
+                content, content_key, deleted_at, expires_at,
+                ARRAY(
+                    SELECT blob_hash::text FROM sm.message_blobs
+                    WHERE message_id = messages.id
+                    ORDER BY blob_hash
+                ) AS "attachments!"
             FROM sm.messages
             WHERE recipient_id = $1 AND id = $2
+            AND (expires_at IS NULL OR expires_at > NOW())
             "#,
             recipient_id,
             id
         )
         .fetch_optional(&*self.pool)
         .await
-        .map_err(|_| SmError::Database(DatabaseError::Arbitrary))?;
+        .map_err(database_error)?;
 
-        Ok(record)
+        let Some(record) = record else {
+            return Ok(None);
+        };
+        let content = match record.content_key {
+            Some(content_key) => self
+                .content_store
+                .get(&content_key)
+                .await?
+                .and_then(|content| String::from_utf8(content).ok())
+                .ok_or(SmError::Storage(StorageError::Arbitrary))?,
+            None => record.content,
+        };
+
+        Ok(Some(Message {
+            id: record.id,
+            recipient_id: record.recipient_id,
+            metadata: record.metadata,
+            recipient_metadata: record.recipient_metadata,
+            recipient_metadata_version: record.recipient_metadata_version,
+            content,
+            deleted_at: record.deleted_at,
+            expires_at: record.expires_at,
+            attachments: record.attachments,
+        }))
     }
 
     async fn update_recipient_metadata(
         &self,
+        recipient_id: Uuid,
         id: i64,
-        recipient_metadata: String,
-    ) -> Result<(), SmError> {
-        sqlx::query!(
+        recipient_metadata: Option<String>,
+        expected_version: i64,
+    ) -> Result<i64, SmError> {
+        let updated = sqlx::query!(
             r#"
             UPDATE sm.messages
-            SET recipient_metadata = $1
-            WHERE id = $2
+            SET recipient_metadata = $1, recipient_metadata_version = recipient_metadata_version + 1
+            WHERE recipient_id = $2 AND id = $3 AND recipient_metadata_version = $4
+            AND (expires_at IS NULL OR expires_at > NOW())
+            RETURNING recipient_metadata_version
             "#,
             recipient_metadata,
+            recipient_id,
+            id,
+            expected_version
+        )
+        .fetch_optional(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        if let Some(updated) = updated {
+            return Ok(updated.recipient_metadata_version);
+        }
+
+        // Tell a stale version apart from a message that isn't there
+        let current = sqlx::query!(
+            r#"
+            SELECT recipient_metadata_version
+            FROM sm.messages
+            WHERE recipient_id = $1 AND id = $2
+            AND (expires_at IS NULL OR expires_at > NOW())
+            "#,
+            recipient_id,
             id
         )
-        .execute(&*self.pool)
+        .fetch_optional(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        match current {
+            Some(current) => Err(MessageError::RecipientMetadataConflict {
+                current_version: current.recipient_metadata_version,
+            }
+            .into()),
+            None => Err(MessageError::MessageNotFound.into()),
+        }
+    }
+
+    async fn trash_messages(&self, recipient_id: Uuid, ids: &[i64]) -> Result<Vec<i64>, SmError> {
+        let records = sqlx::query!(
+            r#"
+            UPDATE sm.messages
+            SET deleted_at = NOW()
+            WHERE recipient_id = $1 AND id = ANY($2) AND deleted_at IS NULL
+            RETURNING id
+            "#,
+            recipient_id,
+            ids
+        )
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(records.into_iter().map(|r| r.id).collect())
+    }
+
+    async fn restore_messages(&self, recipient_id: Uuid, ids: &[i64]) -> Result<Vec<i64>, SmError> {
+        let records = sqlx::query!(
+            r#"
+            UPDATE sm.messages
+            SET deleted_at = NULL
+            WHERE recipient_id = $1 AND id = ANY($2) AND deleted_at IS NOT NULL
+            RETURNING id
+            "#,
+            recipient_id,
+            ids
+        )
+        .fetch_all(&*self.pool)
         .await
-        .map_err(|_| SmError::Database(DatabaseError::Arbitrary))?;
+        .map_err(database_error)?;
 
-        Ok(())
+        Ok(records.into_iter().map(|r| r.id).collect())
     }
 
-    async fn delete_message(&self, id: i64) -> Result<(), SmError> {
-        sqlx::query!(
+    async fn delete_messages(&self, recipient_id: Uuid, ids: &[i64]) -> Result<Vec<i64>, SmError> {
+        let records = sqlx::query!(
             r#"
             DELETE FROM sm.messages
-            WHERE id = $1
+            WHERE recipient_id = $1 AND id = ANY($2)
+            RETURNING id
             "#,
-            id
+            recipient_id,
+            ids
+        )
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(records.into_iter().map(|r| r.id).collect())
+    }
+
+    async fn purge_trash(&self, trashed_before: DateTime<Utc>) -> Result<u64, SmError> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM sm.messages
+            WHERE deleted_at < $1
+            "#,
+            trashed_before
         )
         .execute(&*self.pool)
         .await
-        .map_err(|_| SmError::Database(DatabaseError::Arbitrary))?;
+        .map_err(database_error)?;
 
-        Ok(())
+        Ok(result.rows_affected())
+    }
+
+    async fn purge_expired(&self) -> Result<u64, SmError> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM sm.messages
+            WHERE expires_at <= NOW()
+            "#
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(result.rows_affected())
     }
 
     async fn list_messages(
         &self,
         recipient_id: Uuid,
-        above_id: Option<i64>,
-    ) -> Result<Vec<(i64, MessageMetadata)>, SmError> {
-        let records = sqlx::query!(
+        trashed: bool,
+        after_id: Option<i64>,
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<MessageSummary>, SmError> {
+        let records = sqlx::query_as!(
+            MessageSummary,
             r#"
-            SELECT id, metadata
-            FROM sm.messages
-            WHERE recipient_id = $1
-            AND ($2::bigint IS NULL OR id > $2)
+            SELECT id AS "id!", metadata AS "metadata!"
+            FROM (
+                SELECT id, metadata
+                FROM sm.messages
+                WHERE recipient_id = $1
+                AND (deleted_at IS NOT NULL) = $2
+                AND (expires_at IS NULL OR expires_at > NOW())
+                AND ($3::bigint IS NULL OR id > $3)
+                AND ($4::bigint IS NULL OR id < $4)
+                ORDER BY CASE WHEN $3::bigint IS NULL AND $4::bigint IS NOT NULL THEN -id ELSE id END
+                LIMIT $5
+            ) page
             ORDER BY id
             "#,
             recipient_id,
-            above_id
+            trashed,
+            after_id,
+            before_id,
+            limit
         )
         .fetch_all(&*self.pool)
         .await
-        .map_err(|_| SmError::Database(DatabaseError::Arbitrary))?;
+        .map_err(database_error)?;
 
-        Ok(records
-            .into_iter()
-            .map(|r| (r.id, MessageMetadata(r.metadata)))
-            .collect())
+        Ok(records)
+    }
+
+    async fn count_messages(&self, recipient_id: Uuid, trashed: bool) -> Result<i64, SmError> {
+        let record = sqlx::query!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM sm.messages
+            WHERE recipient_id = $1 AND (deleted_at IS NOT NULL) = $2
+            AND (expires_at IS NULL OR expires_at > NOW())
+            "#,
+            recipient_id,
+            trashed
+        )
+        .fetch_one(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(record.count)
+    }
+
+    async fn get_mailbox_usage(&self, recipient_id: Uuid) -> Result<MailboxUsage, SmError> {
+        let usage = sqlx::query_as!(
+            MailboxUsage,
+            r#"
+            SELECT message_count, stored_bytes
+            FROM sm.mailbox_usage
+            WHERE user_id = $1
+            "#,
+            recipient_id
+        )
+        .fetch_optional(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(usage.unwrap_or(MailboxUsage {
+            message_count: 0,
+            stored_bytes: 0,
+        }))
     }
 }
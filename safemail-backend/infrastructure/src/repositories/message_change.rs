@@ -0,0 +1,123 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use domain::{
+    error::{DatabaseError, SmError},
+    message_change::{
+        MessageChange, MessageChangeHorizon, MessageChangeKind, MessageChangeRepository,
+    },
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::db::database_error;
+
+#[derive(Clone)]
+pub struct PostgresMessageChangeRepository {
+    pool: Arc<PgPool>,
+}
+
+impl PostgresMessageChangeRepository {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+fn parse_kind(kind: &str) -> Result<MessageChangeKind, SmError> {
+    match kind {
+        "created" => Ok(MessageChangeKind::Created),
+        "metadata_updated" => Ok(MessageChangeKind::MetadataUpdated),
+        "trashed" => Ok(MessageChangeKind::Trashed),
+        "restored" => Ok(MessageChangeKind::Restored),
+        "deleted" => Ok(MessageChangeKind::Deleted),
+        _ => Err(DatabaseError::Arbitrary.into()),
+    }
+}
+
+#[async_trait]
+impl MessageChangeRepository for PostgresMessageChangeRepository {
+    async fn list_changes(
+        &self,
+        recipient_id: Uuid,
+        since_seq: i64,
+        limit: i64,
+    ) -> Result<Vec<MessageChange>, SmError> {
+        let records = sqlx::query!(
+            r#"
+            SELECT seq, message_id, kind, changed_at
+            FROM sm.message_changes
+            WHERE recipient_id = $1 AND seq > $2
+            ORDER BY seq
+            LIMIT $3
+            "#,
+            recipient_id,
+            since_seq,
+            limit
+        )
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        records
+            .into_iter()
+            .map(|r| {
+                Ok(MessageChange {
+                    seq: r.seq,
+                    message_id: r.message_id,
+                    kind: parse_kind(&r.kind)?,
+                    changed_at: r.changed_at,
+                })
+            })
+            .collect()
+    }
+
+    async fn get_horizon(&self, recipient_id: Uuid) -> Result<MessageChangeHorizon, SmError> {
+        let record = sqlx::query_as!(
+            MessageChangeHorizon,
+            r#"
+            SELECT last_seq, pruned_through_seq
+            FROM sm.message_change_counters
+            WHERE recipient_id = $1
+            "#,
+            recipient_id
+        )
+        .fetch_optional(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(record.unwrap_or(MessageChangeHorizon {
+            last_seq: 0,
+            pruned_through_seq: 0,
+        }))
+    }
+
+    async fn prune_changes(&self, changed_before: DateTime<Utc>) -> Result<u64, SmError> {
+        let result = sqlx::query!(
+            r#"
+            WITH pruned AS (
+                DELETE FROM sm.message_changes
+                WHERE changed_at < $1
+                RETURNING recipient_id, seq
+            ),
+            horizons AS (
+                UPDATE sm.message_change_counters counters
+                SET pruned_through_seq = GREATEST(counters.pruned_through_seq, latest.seq)
+                FROM (
+                    SELECT recipient_id, MAX(seq) AS seq
+                    FROM pruned
+                    GROUP BY recipient_id
+                ) latest
+                WHERE counters.recipient_id = latest.recipient_id
+            )
+            SELECT COUNT(*) AS "count!" FROM pruned
+            "#,
+            changed_before
+        )
+        .fetch_one(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(result.count as u64)
+    }
+}
@@ -2,12 +2,14 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use domain::{
-    error::{DatabaseError, SmError},
+    error::SmError,
     onetime_stamp::{OneTimeStampTracker, OneTimeStampTrackerRepository},
 };
 use sqlx::PgPool;
 use uuid::Uuid;
 
+use crate::db::database_error;
+
 #[derive(Clone)]
 pub struct PostgresOneTimeStampRepository {
     pool: Arc<PgPool>,
@@ -21,18 +23,24 @@ impl PostgresOneTimeStampRepository {
 
 #[async_trait]
 impl OneTimeStampTrackerRepository for PostgresOneTimeStampRepository {
-    async fn insert(&self, stamp_id: Uuid, recipient_id: Uuid) -> Result<(), SmError> {
-        let result = sqlx::query!(
+    async fn insert(
+        &self,
+        stamp_id: Uuid,
+        recipient_id: Uuid,
+        sender_id: Uuid,
+    ) -> Result<(), SmError> {
+        sqlx::query!(
             r#"
-            INSERT INTO sm.onetime_stamps (stamp_id, recipient_id)
-            VALUES ($1, $2)
+            INSERT INTO sm.onetime_stamps (stamp_id, recipient_id, sender_id)
+            VALUES ($1, $2, $3)
             "#,
             stamp_id,
-            recipient_id
+            recipient_id,
+            sender_id
         )
         .execute(&*self.pool)
         .await
-        .map_err(|_| SmError::from(DatabaseError::Arbitrary))?;
+        .map_err(database_error)?;
 
         Ok(())
     }
@@ -49,24 +57,8 @@ impl OneTimeStampTrackerRepository for PostgresOneTimeStampRepository {
         )
         .fetch_optional(&*self.pool)
         .await
-        .map_err(|_| SmError::from(DatabaseError::Arbitrary))?;
+        .map_err(database_error)?;
 
         Ok(result)
     }
-
-    async fn set_used_or_revoked(&self, stamp_id: Uuid) -> Result<(), SmError> {
-        sqlx::query!(
-            r#"
-            UPDATE sm.onetime_stamps
-            SET used_or_revoked = true
-            WHERE stamp_id = $1
-            "#,
-            stamp_id
-        )
-        .execute(&*self.pool)
-        .await
-        .map_err(|_| SmError::from(DatabaseError::Arbitrary))?;
-
-        Ok(())
-    }
 }
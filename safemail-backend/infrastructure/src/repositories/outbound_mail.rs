@@ -0,0 +1,179 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use domain::{
+    chrono::{DateTime, Utc},
+    error::SmError,
+    outbound_mail::{
+        DueOutboundMail, NewOutboundMail, OutboundMailRepository, OutboundMailSummary,
+    },
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::db::database_error;
+
+#[derive(Clone)]
+pub struct PostgresOutboundMailRepository {
+    pool: Arc<PgPool>,
+}
+
+impl PostgresOutboundMailRepository {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl OutboundMailRepository for PostgresOutboundMailRepository {
+    async fn enqueue_within_limit(
+        &self,
+        mail: NewOutboundMail,
+        since: DateTime<Utc>,
+        max_recipients: i64,
+    ) -> Result<Option<i64>, SmError> {
+        let mut transaction = self.pool.begin().await.map_err(database_error)?;
+
+        // Held until the transaction ends, so that concurrent mail of the same sender can't
+        // each be counted without the other
+        sqlx::query!(
+            "SELECT pg_advisory_xact_lock(hashtextextended($1::UUID::TEXT, 0))",
+            mail.sender_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(database_error)?;
+
+        let record = sqlx::query!(
+            r#"
+            SELECT COALESCE(SUM(CARDINALITY(recipients)), 0)::BIGINT AS "count!"
+            FROM sm.outbound_mail
+            WHERE sender_id = $1 AND created_at > $2
+            "#,
+            mail.sender_id,
+            since
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .map_err(database_error)?;
+        if record.count + mail.recipients.len() as i64 > max_recipients {
+            return Ok(None);
+        }
+
+        let record = sqlx::query!(
+            r#"
+            INSERT INTO sm.outbound_mail (sender_id, recipients, content, signature)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id
+            "#,
+            mail.sender_id,
+            &mail.recipients,
+            mail.content,
+            mail.signature
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .map_err(database_error)?;
+
+        transaction.commit().await.map_err(database_error)?;
+        Ok(Some(record.id))
+    }
+
+    async fn list_outbound_mail(
+        &self,
+        sender_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<OutboundMailSummary>, SmError> {
+        sqlx::query_as!(
+            OutboundMailSummary,
+            r#"
+            SELECT id, recipients, created_at, attempts, sent_at, failure
+            FROM sm.outbound_mail
+            WHERE sender_id = $1
+            ORDER BY id DESC
+            LIMIT $2
+            "#,
+            sender_id,
+            limit
+        )
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(database_error)
+    }
+
+    async fn claim_due_mail(
+        &self,
+        limit: i64,
+        claimed_until: DateTime<Utc>,
+    ) -> Result<Vec<DueOutboundMail>, SmError> {
+        // Rows another relay is claiming are skipped rather than waited for, and once claimed
+        // they aren't due again until the claim runs out
+        let mut mail = sqlx::query_as!(
+            DueOutboundMail,
+            r#"
+            WITH due AS (
+                SELECT id
+                FROM sm.outbound_mail
+                WHERE sent_at IS NULL AND failure IS NULL AND next_attempt_at <= NOW()
+                ORDER BY next_attempt_at, id
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            UPDATE sm.outbound_mail
+            SET next_attempt_at = $2
+            FROM due
+            WHERE outbound_mail.id = due.id
+            RETURNING outbound_mail.id, sender_id, recipients, content, signature, attempts
+            "#,
+            limit,
+            claimed_until
+        )
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        mail.sort_by_key(|mail| mail.id);
+        Ok(mail)
+    }
+
+    async fn mark_sent(&self, id: i64) -> Result<(), SmError> {
+        sqlx::query!(
+            r#"
+            UPDATE sm.outbound_mail
+            SET sent_at = NOW(), attempts = attempts + 1
+            WHERE id = $1
+            "#,
+            id
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(())
+    }
+
+    async fn record_failure(
+        &self,
+        id: i64,
+        failure: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<(), SmError> {
+        sqlx::query!(
+            r#"
+            UPDATE sm.outbound_mail
+            SET attempts = attempts + 1,
+                next_attempt_at = COALESCE($3, next_attempt_at),
+                failure = CASE WHEN $3::TIMESTAMPTZ IS NULL THEN $2 END
+            WHERE id = $1
+            "#,
+            id,
+            failure,
+            retry_at
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(())
+    }
+}
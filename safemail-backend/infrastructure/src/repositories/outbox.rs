@@ -0,0 +1,155 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use domain::{
+    content::ContentStore,
+    error::{SmError, StorageError},
+    outbox::{OutboxDelivery, OutboxEntry, OutboxRepository, OutboxSummary},
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::db::database_error;
+use crate::services::content_store::ConfiguredContentStore;
+
+#[derive(Clone)]
+pub struct PostgresOutboxRepository {
+    pool: Arc<PgPool>,
+    content_store: ConfiguredContentStore,
+}
+
+impl PostgresOutboxRepository {
+    pub fn new(pool: Arc<PgPool>, content_store: ConfiguredContentStore) -> Self {
+        Self {
+            pool,
+            content_store,
+        }
+    }
+}
+
+#[async_trait]
+impl OutboxRepository for PostgresOutboxRepository {
+    async fn get_entry(&self, sender_id: Uuid, id: i64) -> Result<Option<OutboxEntry>, SmError> {
+        let record = sqlx::query!(
+            r#"
+            SELECT id, sender_id, metadata, content, content_key, created_at,
+                ARRAY(
+                    SELECT blob_hash::text FROM sm.outbox_blobs
+                    WHERE outbox_id = outbox.id
+                    ORDER BY blob_hash
+                ) AS "attachments!"
+            FROM sm.outbox
+            WHERE sender_id = $1 AND id = $2
+            "#,
+            sender_id,
+            id
+        )
+        .fetch_optional(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        let Some(record) = record else {
+            return Ok(None);
+        };
+
+        let deliveries = sqlx::query_as!(
+            OutboxDelivery,
+            r#"
+            SELECT recipient_id, message_id
+            FROM sm.outbox_deliveries
+            WHERE outbox_id = $1
+            ORDER BY message_id
+            "#,
+            record.id
+        )
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        let content = match record.content_key {
+            Some(content_key) => self
+                .content_store
+                .get(&content_key)
+                .await?
+                .and_then(|content| String::from_utf8(content).ok())
+                .ok_or(SmError::Storage(StorageError::Arbitrary))?,
+            None => record.content,
+        };
+
+        Ok(Some(OutboxEntry {
+            id: record.id,
+            sender_id: record.sender_id,
+            metadata: record.metadata,
+            content,
+            created_at: record.created_at,
+            attachments: record.attachments,
+            deliveries,
+        }))
+    }
+
+    async fn list_entries(
+        &self,
+        sender_id: Uuid,
+        after_id: Option<i64>,
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<OutboxSummary>, SmError> {
+        let records = sqlx::query_as!(
+            OutboxSummary,
+            r#"
+            SELECT id AS "id!", metadata AS "metadata!", created_at AS "created_at!"
+            FROM (
+                SELECT id, metadata, created_at
+                FROM sm.outbox
+                WHERE sender_id = $1
+                AND ($2::bigint IS NULL OR id > $2)
+                AND ($3::bigint IS NULL OR id < $3)
+                ORDER BY CASE WHEN $2::bigint IS NULL AND $3::bigint IS NOT NULL THEN -id ELSE id END
+                LIMIT $4
+            ) page
+            ORDER BY id
+            "#,
+            sender_id,
+            after_id,
+            before_id,
+            limit
+        )
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(records)
+    }
+
+    async fn count_entries(&self, sender_id: Uuid) -> Result<i64, SmError> {
+        let record = sqlx::query!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM sm.outbox
+            WHERE sender_id = $1
+            "#,
+            sender_id
+        )
+        .fetch_one(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(record.count)
+    }
+
+    async fn delete_entry(&self, sender_id: Uuid, id: i64) -> Result<bool, SmError> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM sm.outbox
+            WHERE sender_id = $1 AND id = $2
+            "#,
+            sender_id,
+            id
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use domain::{
+    error::SmError,
+    federation::{RemoteUser, RemoteUserRepository},
+    user::User,
+};
+use sqlx::PgPool;
+
+use crate::db::database_error;
+
+#[derive(Clone)]
+pub struct PostgresRemoteUserRepository {
+    pool: Arc<PgPool>,
+}
+
+impl PostgresRemoteUserRepository {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl RemoteUserRepository for PostgresRemoteUserRepository {
+    async fn save_remote_user(&self, host: &str, user: &RemoteUser) -> Result<User, SmError> {
+        let username = format!("{}@{}", user.username, host);
+        let mut transaction = self.pool.begin().await.map_err(database_error)?;
+
+        let existing = sqlx::query!(
+            r#"
+            SELECT user_id
+            FROM sm.remote_users
+            WHERE host = $1 AND remote_id = $2
+            FOR UPDATE
+            "#,
+            host,
+            user.id
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .map_err(database_error)?;
+
+        let local_user = match existing {
+            Some(existing) => {
+                let local_user = sqlx::query_as!(
+                    User,
+                    r#"
+                    UPDATE sm.users
+                    SET username = $2, public_encryption_key = $3, public_verify_key = $4
+                    WHERE id = $1
+                    RETURNING id, username, public_encryption_key, public_verify_key
+                    "#,
+                    existing.user_id,
+                    username,
+                    user.public_encryption_key,
+                    user.public_verify_key
+                )
+                .fetch_one(&mut *transaction)
+                .await
+                .map_err(database_error)?;
+                sqlx::query!(
+                    r#"
+                    UPDATE sm.remote_users
+                    SET updated_at = NOW()
+                    WHERE user_id = $1
+                    "#,
+                    existing.user_id
+                )
+                .execute(&mut *transaction)
+                .await
+                .map_err(database_error)?;
+                local_user
+            }
+            None => {
+                let local_user = sqlx::query_as!(
+                    User,
+                    r#"
+                    INSERT INTO sm.users (username, public_encryption_key, public_verify_key)
+                    VALUES ($1, $2, $3)
+                    RETURNING id, username, public_encryption_key, public_verify_key
+                    "#,
+                    username,
+                    user.public_encryption_key,
+                    user.public_verify_key
+                )
+                .fetch_one(&mut *transaction)
+                .await
+                .map_err(database_error)?;
+                sqlx::query!(
+                    r#"
+                    INSERT INTO sm.remote_users (user_id, host, remote_id)
+                    VALUES ($1, $2, $3)
+                    "#,
+                    local_user.id,
+                    host,
+                    user.id
+                )
+                .execute(&mut *transaction)
+                .await
+                .map_err(database_error)?;
+                local_user
+            }
+        };
+
+        transaction.commit().await.map_err(database_error)?;
+
+        Ok(local_user)
+    }
+}
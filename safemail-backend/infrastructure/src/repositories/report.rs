@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use domain::{
+    error::SmError,
+    report::{NewReport, Report, ReportRepository},
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::db::database_error;
+
+#[derive(Clone)]
+pub struct PostgresReportRepository {
+    pool: Arc<PgPool>,
+}
+
+impl PostgresReportRepository {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ReportRepository for PostgresReportRepository {
+    async fn create_report(&self, report: NewReport) -> Result<Option<i64>, SmError> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO sm.reports (reporter_id, message_id, sender_id, reason)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (reporter_id, message_id) DO NOTHING
+            RETURNING id
+            "#,
+            report.reporter_id,
+            report.message_id,
+            report.sender_id,
+            report.reason
+        )
+        .fetch_optional(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(result.map(|record| record.id))
+    }
+
+    async fn count_reports_against(&self, sender_id: Uuid) -> Result<i64, SmError> {
+        let result = sqlx::query!(
+            r#"
+            SELECT COUNT(DISTINCT reporter_id) AS "count!"
+            FROM sm.reports
+            WHERE sender_id = $1
+            "#,
+            sender_id
+        )
+        .fetch_one(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(result.count)
+    }
+
+    async fn flag_for_review(&self, sender_id: Uuid) -> Result<(), SmError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO sm.review_flags (user_id)
+            VALUES ($1)
+            ON CONFLICT (user_id) DO NOTHING
+            "#,
+            sender_id
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(())
+    }
+
+    async fn list_reports(
+        &self,
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Report>, SmError> {
+        sqlx::query_as!(
+            Report,
+            r#"
+            SELECT reports.id, reports.reporter_id, reports.message_id, reports.sender_id,
+                reports.reason, reports.created_at,
+                review_flags.flagged_at AS "sender_flagged_at?"
+            FROM sm.reports
+            LEFT JOIN sm.review_flags ON review_flags.user_id = reports.sender_id
+            WHERE reports.id < COALESCE($1, 9223372036854775807)
+            ORDER BY reports.id DESC
+            LIMIT $2
+            "#,
+            before_id,
+            limit
+        )
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(database_error)
+    }
+}
@@ -0,0 +1,392 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use domain::{
+    content::ContentStore,
+    error::{DatabaseError, SmError, StorageError},
+    message::{Delivery, MailboxQuota, MessageMetadata, NewMessage},
+    outbox::NewOutboxEntry,
+    scheduled_message::{
+        DueMessage, NewScheduledMessage, ScheduledMessageRepository, ScheduledMessageSummary,
+    },
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{content::settle_pending_content, PostgresMessageRepository};
+use crate::db::database_error;
+use crate::services::content_store::ConfiguredContentStore;
+
+/// Keeps scheduled messages apart from delivered ones, storing bodies the way the message
+/// repository does, and hands them to it once they are due.
+#[derive(Clone)]
+pub struct PostgresScheduledMessageRepository {
+    pool: Arc<PgPool>,
+    content_store: ConfiguredContentStore,
+    message_repository: PostgresMessageRepository,
+}
+
+impl PostgresScheduledMessageRepository {
+    pub fn new(
+        pool: Arc<PgPool>,
+        content_store: ConfiguredContentStore,
+        message_repository: PostgresMessageRepository,
+    ) -> Self {
+        Self {
+            pool,
+            content_store,
+            message_repository,
+        }
+    }
+
+    async fn load_content(
+        &self,
+        content: String,
+        content_key: Option<String>,
+    ) -> Result<String, SmError> {
+        match content_key {
+            Some(content_key) => self
+                .content_store
+                .get(&content_key)
+                .await?
+                .and_then(|content| String::from_utf8(content).ok())
+                .ok_or(SmError::Storage(StorageError::Arbitrary)),
+            None => Ok(content),
+        }
+    }
+
+    async fn insert_scheduled_message(
+        &self,
+        message: &NewScheduledMessage,
+        content_keys: &[Option<String>],
+    ) -> Result<i64, SmError> {
+        let stamp = message
+            .stamp
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()
+            .map_err(|e| SmError::from(DatabaseError::Query(Box::new(e))))?;
+
+        let mut transaction = self.pool.begin().await.map_err(database_error)?;
+
+        let content_key = content_keys.first().cloned().flatten();
+        let content = match content_key {
+            Some(_) => "",
+            None => &message.message.content,
+        };
+        let id = sqlx::query!(
+            r#"
+            INSERT INTO sm.scheduled_messages
+                (sender_id, recipient_id, metadata, content, content_key, expires_at, stamp,
+                deliver_at, sender_seal, onetime_stamp_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            RETURNING id
+            "#,
+            message.sender_id,
+            message.message.recipient_id,
+            message.message.metadata.0,
+            content,
+            content_key,
+            message.message.expires_at,
+            stamp,
+            message.deliver_at,
+            message.message.sender_seal,
+            message.message.onetime_stamp_id
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .map_err(database_error)?
+        .id;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO sm.scheduled_message_blobs (scheduled_id, blob_hash)
+            SELECT $1, blob_hash FROM UNNEST($2::text[]) AS blob_hash
+            WHERE sm.can_access_blob(blob_hash, $3)
+            "#,
+            id,
+            &message.message.attachments,
+            message.sender_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(database_error)?;
+
+        if let Some(entry) = &message.outbox_entry {
+            let content_key = content_keys.get(1).cloned().flatten();
+            let content = match content_key {
+                Some(_) => "",
+                None => &entry.content,
+            };
+            sqlx::query!(
+                r#"
+                INSERT INTO sm.scheduled_sender_copies
+                    (scheduled_id, metadata, content, content_key)
+                VALUES ($1, $2, $3, $4)
+                "#,
+                id,
+                entry.metadata,
+                content,
+                content_key
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(database_error)?;
+        }
+
+        let stored: Vec<String> = content_keys.iter().flatten().cloned().collect();
+        settle_pending_content(&mut transaction, &stored).await?;
+        transaction.commit().await.map_err(database_error)?;
+
+        Ok(id)
+    }
+}
+
+#[async_trait]
+impl ScheduledMessageRepository for PostgresScheduledMessageRepository {
+    async fn schedule_message(&self, message: NewScheduledMessage) -> Result<i64, SmError> {
+        let contents: Vec<&str> = std::iter::once(message.message.content.as_str())
+            .chain(
+                message
+                    .outbox_entry
+                    .iter()
+                    .map(|entry| entry.content.as_str()),
+            )
+            .collect();
+        let content_keys = self.message_repository.store_out_of_line(&contents).await?;
+        let result = self.insert_scheduled_message(&message, &content_keys).await;
+        if result.is_err() {
+            self.message_repository
+                .discard_out_of_line(&content_keys)
+                .await;
+        }
+        result
+    }
+
+    async fn list_scheduled_messages(
+        &self,
+        sender_id: Uuid,
+    ) -> Result<Vec<ScheduledMessageSummary>, SmError> {
+        sqlx::query_as!(
+            ScheduledMessageSummary,
+            r#"
+            SELECT id, recipient_id, deliver_at, created_at, failure
+            FROM sm.scheduled_messages
+            WHERE sender_id = $1
+            ORDER BY id
+            "#,
+            sender_id
+        )
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(database_error)
+    }
+
+    async fn count_scheduled_messages(&self, sender_id: Uuid) -> Result<i64, SmError> {
+        let record = sqlx::query!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM sm.scheduled_messages
+            WHERE sender_id = $1
+            "#,
+            sender_id
+        )
+        .fetch_one(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(record.count)
+    }
+
+    async fn cancel_scheduled_message(&self, sender_id: Uuid, id: i64) -> Result<bool, SmError> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM sm.scheduled_messages
+            WHERE sender_id = $1 AND id = $2
+            "#,
+            sender_id,
+            id
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn list_due_messages(&self, limit: i64) -> Result<Vec<DueMessage>, SmError> {
+        let records = sqlx::query!(
+            r#"
+            SELECT id, sender_id, recipient_id, stamp, attempts
+            FROM sm.scheduled_messages
+            WHERE failure IS NULL AND deliver_at <= NOW()
+            AND (next_attempt_at IS NULL OR next_attempt_at <= NOW())
+            ORDER BY deliver_at, id
+            LIMIT $1
+            "#,
+            limit
+        )
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        records
+            .into_iter()
+            .map(|record| {
+                let stamp = record
+                    .stamp
+                    .map(|stamp| serde_json::from_str(&stamp))
+                    .transpose()
+                    .map_err(|e| SmError::from(DatabaseError::Query(Box::new(e))))?;
+                Ok(DueMessage {
+                    id: record.id,
+                    sender_id: record.sender_id,
+                    recipient_id: record.recipient_id,
+                    stamp,
+                    attempts: record.attempts,
+                })
+            })
+            .collect()
+    }
+
+    async fn deliver_scheduled_message(
+        &self,
+        id: i64,
+        quota: &MailboxQuota,
+    ) -> Result<Option<Delivery>, SmError> {
+        let mut transaction = self.pool.begin().await.map_err(database_error)?;
+
+        // Holding the row lock keeps the sender from cancelling mid-delivery, and other
+        // dispatchers from delivering the message twice
+        let record = sqlx::query!(
+            r#"
+            SELECT sender_id, recipient_id, metadata, content, content_key, expires_at, sender_seal,
+                onetime_stamp_id,
+                ARRAY(
+                    SELECT blob_hash::text FROM sm.scheduled_message_blobs
+                    WHERE scheduled_id = scheduled_messages.id
+                    ORDER BY blob_hash
+                ) AS "attachments!"
+            FROM sm.scheduled_messages
+            WHERE id = $1 AND failure IS NULL
+            FOR UPDATE
+            "#,
+            id
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .map_err(database_error)?;
+        let Some(record) = record else {
+            return Ok(None);
+        };
+
+        let sender_copy = sqlx::query!(
+            r#"
+            SELECT metadata, content, content_key
+            FROM sm.scheduled_sender_copies
+            WHERE scheduled_id = $1
+            "#,
+            id
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .map_err(database_error)?;
+
+        let message = NewMessage {
+            recipient_id: record.recipient_id,
+            metadata: MessageMetadata(record.metadata),
+            content: self
+                .load_content(record.content, record.content_key)
+                .await?,
+            expires_at: record.expires_at,
+            attachments: record.attachments,
+            sender_seal: record.sender_seal,
+            onetime_stamp_id: record.onetime_stamp_id,
+        };
+        let outbox_entry = match sender_copy {
+            Some(sender_copy) => Some(NewOutboxEntry {
+                sender_id: record.sender_id,
+                metadata: sender_copy.metadata,
+                content: self
+                    .load_content(sender_copy.content, sender_copy.content_key)
+                    .await?,
+            }),
+            None => None,
+        };
+
+        // The delivered message gets its own copy of the body, the scheduled one is orphaned
+        // along with the row
+        let contents: Vec<&str> = std::iter::once(message.content.as_str())
+            .chain(outbox_entry.iter().map(|entry| entry.content.as_str()))
+            .collect();
+        let content_keys = self.message_repository.store_out_of_line(&contents).await?;
+        let result = async {
+            let delivery = PostgresMessageRepository::insert_messages(
+                &mut transaction,
+                std::slice::from_ref(&message),
+                outbox_entry.as_ref(),
+                &content_keys,
+                quota,
+            )
+            .await?;
+            sqlx::query!(
+                r#"
+                DELETE FROM sm.scheduled_messages
+                WHERE id = $1
+                "#,
+                id
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(database_error)?;
+            transaction.commit().await.map_err(database_error)?;
+            Ok(Some(delivery))
+        }
+        .await;
+        if result.is_err() {
+            self.message_repository
+                .discard_out_of_line(&content_keys)
+                .await;
+        }
+        result
+    }
+
+    async fn fail_scheduled_message(&self, id: i64, failure: &str) -> Result<(), SmError> {
+        sqlx::query!(
+            r#"
+            UPDATE sm.scheduled_messages
+            SET failure = $2
+            WHERE id = $1
+            "#,
+            id,
+            failure
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(())
+    }
+
+    async fn defer_scheduled_message(
+        &self,
+        id: i64,
+        retry_at: DateTime<Utc>,
+    ) -> Result<(), SmError> {
+        sqlx::query!(
+            r#"
+            UPDATE sm.scheduled_messages
+            SET attempts = attempts + 1, next_attempt_at = $2
+            WHERE id = $1
+            "#,
+            id,
+            retry_at
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(())
+    }
+}
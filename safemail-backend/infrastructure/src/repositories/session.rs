@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use domain::{
     error::{DatabaseError, SessionError, SmError, UserError},
     session::{Session, SessionRepository},
@@ -9,6 +9,8 @@ use domain::{
 use rand::{distributions::Alphanumeric, Rng};
 use sqlx::{types::Uuid, PgPool};
 
+use crate::db::database_error;
+
 #[derive(Clone)]
 pub struct PostgresSessionRepository {
     pub db: Arc<PgPool>,
@@ -20,9 +22,12 @@ impl PostgresSessionRepository {
 }
 #[async_trait]
 impl SessionRepository for PostgresSessionRepository {
-    async fn request_session(&self, user_id: Uuid) -> Result<Session, SmError> {
+    async fn request_session(
+        &self,
+        user_id: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Session, SmError> {
         let now = Utc::now();
-        let session_end = now.checked_add_signed(chrono::Duration::hours(2)).unwrap();
         // generate a random ASCII string of 24 characters
         let challenge_string = rand::thread_rng()
             .sample_iter(&Alphanumeric)
@@ -36,18 +41,12 @@ impl SessionRepository for PostgresSessionRepository {
             user_id,
             challenge_string,
             now,
-            session_end
-        ).fetch_one(&*self.db).await.map_err(|e| {
-            let db = e.as_database_error();
-            let err: SmError = if let Some(sqlx_error) = db {
-                match sqlx_error.kind() {
-                    sqlx::error::ErrorKind::ForeignKeyViolation => UserError::UserNotFound.into(),
-                    _ => DatabaseError::Arbitrary.into(),
-                }
-            } else {
-                DatabaseError::Arbitrary.into()
-            };
-            err
+            expires_at
+        ).fetch_one(&*self.db).await.map_err(|e| match database_error(e) {
+            SmError::Database(DatabaseError::ForeignKeyViolation { .. }) => {
+                UserError::UserNotFound.into()
+            }
+            e => e,
         })?;
         Ok(result)
     }
@@ -62,7 +61,7 @@ impl SessionRepository for PostgresSessionRepository {
         )
         .execute(&*self.db)
         .await
-        .map_err(|_| DatabaseError::Arbitrary)?;
+        .map_err(database_error)?;
         if result.rows_affected() == 0 {
             Err(SessionError::SessionNotFound.into())
         } else {
@@ -78,7 +77,7 @@ impl SessionRepository for PostgresSessionRepository {
         let result = sqlx::query_as!(Session, "SELECT * FROM sm.sessions WHERE session_id = $1 AND (active = true OR $2) AND expires_at_utc > $3", session_id, include_inactive, now) 
             .fetch_optional(&*self.db)
             .await
-            .map_err(|_| DatabaseError::Arbitrary)?;
+            .map_err(database_error)?;
         Ok(result)
     }
     async fn logout_session(&self, session_id: Uuid) -> Result<(), SmError> {
@@ -88,7 +87,7 @@ impl SessionRepository for PostgresSessionRepository {
         )
         .execute(&*self.db)
         .await
-        .map_err(|_| DatabaseError::Arbitrary)?;
+        .map_err(database_error)?;
         Ok(())
     }
 }
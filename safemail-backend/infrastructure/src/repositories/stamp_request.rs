@@ -1,13 +1,16 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use domain::{
-    error::{DatabaseError, SmError},
+    error::SmError,
     stamp_request::{OnetimeStampRequest, StampRequestRepository},
 };
 use sqlx::PgPool;
 use uuid::Uuid;
 
+use crate::db::database_error;
+
 #[derive(Clone)]
 pub struct PostgresStampRequestRepository {
     pool: Arc<PgPool>,
@@ -25,19 +28,23 @@ impl StampRequestRepository for PostgresStampRequestRepository {
         &self,
         difficulty: i64,
         recipient_id: Uuid,
+        sender_id: Uuid,
+        valid_to: DateTime<Utc>,
     ) -> Result<Uuid, SmError> {
         let row = sqlx::query!(
             r#"
-            INSERT INTO sm.onetime_stamp_requests (difficulty, recipient_id)
-            VALUES ($1, $2)
+            INSERT INTO sm.onetime_stamp_requests (difficulty, recipient_id, sender_id, valid_to)
+            VALUES ($1, $2, $3, $4)
             RETURNING stamp_request_id
             "#,
             difficulty,
-            recipient_id
+            recipient_id,
+            sender_id,
+            valid_to
         )
         .fetch_one(&*self.pool)
         .await
-        .map_err(|_| SmError::from(DatabaseError::Arbitrary))?;
+        .map_err(database_error)?;
 
         Ok(row.stamp_request_id)
     }
@@ -56,24 +63,26 @@ impl StampRequestRepository for PostgresStampRequestRepository {
         )
         .fetch_optional(&*self.pool)
         .await
-        .map_err(|_| SmError::from(DatabaseError::Arbitrary))?;
+        .map_err(database_error)?;
 
         Ok(result)
     }
 
-    async fn mark_solved(&self, stamp_request_id: Uuid) -> Result<(), SmError> {
-        sqlx::query!(
+    async fn mark_solved(&self, stamp_request_id: Uuid) -> Result<bool, SmError> {
+        // Conditional, so that of two solutions handed in at once only one gets a stamp
+        let row = sqlx::query!(
             r#"
             UPDATE sm.onetime_stamp_requests
             SET solved_at = CURRENT_TIMESTAMP AT TIME ZONE 'UTC'
-            WHERE stamp_request_id = $1
+            WHERE stamp_request_id = $1 AND solved_at IS NULL
+            RETURNING stamp_request_id
             "#,
             stamp_request_id,
         )
-        .execute(&*self.pool)
+        .fetch_optional(&*self.pool)
         .await
-        .map_err(|_| SmError::from(DatabaseError::Arbitrary))?;
+        .map_err(database_error)?;
 
-        Ok(())
+        Ok(row.is_some())
     }
 }
@@ -0,0 +1,78 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use domain::{error::SmError, stamp_revocation::StampRevocationRepository};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::db::database_error;
+
+#[derive(Clone)]
+pub struct PostgresStampRevocationRepository {
+    pool: Arc<PgPool>,
+}
+
+impl PostgresStampRevocationRepository {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl StampRevocationRepository for PostgresStampRevocationRepository {
+    async fn revoke_stamps(&self, recipient_id: Uuid, sender_id: Uuid) -> Result<(), SmError> {
+        let mut transaction = self.pool.begin().await.map_err(database_error)?;
+
+        sqlx::query!(
+            r#"
+            UPDATE sm.onetime_stamps
+            SET used_or_revoked = true
+            WHERE recipient_id = $1 AND sender_id = $2 AND NOT used_or_revoked
+            "#,
+            recipient_id,
+            sender_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(database_error)?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO sm.periodic_stamp_revocations (recipient_id, sender_id)
+            VALUES ($1, $2)
+            ON CONFLICT (recipient_id, sender_id) DO UPDATE SET revoked_at = NOW()
+            "#,
+            recipient_id,
+            sender_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(database_error)?;
+
+        transaction.commit().await.map_err(database_error)?;
+
+        Ok(())
+    }
+
+    async fn get_periodic_revocation(
+        &self,
+        recipient_id: Uuid,
+        sender_id: Uuid,
+    ) -> Result<Option<DateTime<Utc>>, SmError> {
+        let result = sqlx::query!(
+            r#"
+            SELECT revoked_at
+            FROM sm.periodic_stamp_revocations
+            WHERE recipient_id = $1 AND sender_id = $2
+            "#,
+            recipient_id,
+            sender_id
+        )
+        .fetch_optional(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(result.map(|record| record.revoked_at))
+    }
+}
@@ -2,11 +2,13 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use domain::{
-    error::{DatabaseError, SmError},
+    error::SmError,
     system_key::{SystemKeyPair, SystemKeyRepository},
 };
 use sqlx::PgPool;
 
+use crate::db::database_error;
+
 #[derive(Clone)]
 pub struct PostgresSystemKeyRepository {
     pool: Arc<PgPool>,
@@ -32,7 +34,7 @@ impl SystemKeyRepository for PostgresSystemKeyRepository {
         )
         .execute(&*self.pool)
         .await
-        .map_err(|_| SmError::from(DatabaseError::Arbitrary))?;
+        .map_err(database_error)?;
 
         if result.rows_affected() == 0 {
             return Ok(());
@@ -52,8 +54,23 @@ impl SystemKeyRepository for PostgresSystemKeyRepository {
         )
         .fetch_optional(&*self.pool)
         .await
-        .map_err(|_| SmError::from(DatabaseError::Arbitrary))?;
+        .map_err(database_error)?;
 
         Ok(result)
     }
+
+    async fn update_public_key(&self, public_key: String) -> Result<(), SmError> {
+        sqlx::query!(
+            r#"
+            UPDATE sm.system_sign_keys
+            SET public_key = $1
+            "#,
+            public_key
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(())
+    }
 }
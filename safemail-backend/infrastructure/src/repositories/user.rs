@@ -8,6 +8,8 @@ use domain::{
 use sqlx::PgPool;
 use uuid::Uuid;
 
+use crate::db::database_error;
+
 #[derive(Clone)]
 pub struct PostgresUserRepository {
     pub db: Arc<PgPool>,
@@ -33,17 +35,11 @@ impl UserRepository for PostgresUserRepository {
         )
         .fetch_one(&*self.db)
         .await
-        .map_err(|e| {
-            let db = e.as_database_error();
-            let err: SmError = if let Some(sqlx_error) = db {
-                match sqlx_error.kind() {
-                    sqlx::error::ErrorKind::UniqueViolation => UserError::UserAlreadyExists.into(),
-                    _ => DatabaseError::Arbitrary.into(),
-                }
-            } else {
-                DatabaseError::Arbitrary.into()
-            };
-            err
+        .map_err(|e| match database_error(e) {
+            SmError::Database(DatabaseError::UniqueViolation { .. }) => {
+                UserError::UserAlreadyExists.into()
+            }
+            e => e,
         })?;
 
         Ok(User {
@@ -57,14 +53,24 @@ impl UserRepository for PostgresUserRepository {
         let user = sqlx::query_as!(User, "SELECT * FROM sm.users WHERE username = $1", username)
             .fetch_optional(&*self.db)
             .await
-            .map_err(|_| DatabaseError::Arbitrary)?;
+            .map_err(database_error)?;
         Ok(user)
     }
     async fn find_by_id(&self, id: Uuid) -> Result<Option<User>, SmError> {
         let user = sqlx::query_as!(User, "SELECT * FROM sm.users WHERE id = $1", id)
             .fetch_optional(&*self.db)
             .await
-            .map_err(|_| DatabaseError::Arbitrary)?;
+            .map_err(database_error)?;
         Ok(user)
     }
+    async fn is_admin(&self, id: Uuid) -> Result<bool, SmError> {
+        let result = sqlx::query!(
+            r#"SELECT EXISTS (SELECT 1 FROM sm.admins WHERE user_id = $1) AS "admin!""#,
+            id
+        )
+        .fetch_one(&*self.db)
+        .await
+        .map_err(database_error)?;
+        Ok(result.admin)
+    }
 }
@@ -0,0 +1,89 @@
+use std::{path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use domain::{content::ContentStore, error::SmError};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+mod filesystem;
+mod postgres;
+mod s3;
+pub use filesystem::*;
+pub use postgres::*;
+pub use s3::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentStoreBackend {
+    Postgres,
+    Filesystem,
+    S3,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ContentStoreConfig {
+    pub backend: ContentStoreBackend,
+    /// Root directory of the filesystem backend.
+    pub path: Option<PathBuf>,
+    /// Bucket and credentials of the s3 backend.
+    pub s3: S3Config,
+    /// Message bodies longer than this many bytes are kept in the content store rather than
+    /// inline with the message.
+    pub out_of_line_threshold: usize,
+}
+
+/// The content store backend selected by the configuration.
+#[derive(Clone)]
+pub enum ConfiguredContentStore {
+    Postgres(PostgresContentStore),
+    Filesystem(FilesystemContentStore),
+    S3(S3ContentStore),
+}
+
+impl ConfiguredContentStore {
+    /// Sets up the configured backend. Its settings are expected to have been validated along
+    /// with the rest of the configuration.
+    pub fn new(config: &ContentStoreConfig, pool: Arc<PgPool>) -> Self {
+        match config.backend {
+            ContentStoreBackend::Postgres => Self::Postgres(PostgresContentStore::new(pool)),
+            ContentStoreBackend::Filesystem => Self::Filesystem(FilesystemContentStore::new(
+                config
+                    .path
+                    .clone()
+                    .expect("The filesystem content store needs a path"),
+            )),
+            ContentStoreBackend::S3 => Self::S3(
+                S3ContentStore::new(config.s3.clone())
+                    .expect("Failed to configure the S3 content store"),
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl ContentStore for ConfiguredContentStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), SmError> {
+        match self {
+            Self::Postgres(store) => store.put(key, data).await,
+            Self::Filesystem(store) => store.put(key, data).await,
+            Self::S3(store) => store.put(key, data).await,
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, SmError> {
+        match self {
+            Self::Postgres(store) => store.get(key).await,
+            Self::Filesystem(store) => store.get(key).await,
+            Self::S3(store) => store.get(key).await,
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), SmError> {
+        match self {
+            Self::Postgres(store) => store.delete(key).await,
+            Self::Filesystem(store) => store.delete(key).await,
+            Self::S3(store) => store.delete(key).await,
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use domain::{
+    content::ContentStore,
+    error::{SmError, StorageError},
+};
+use uuid::Uuid;
+
+/// Keeps each object in its own file below a root directory, with `/` in keys mapping to
+/// subdirectories.
+#[derive(Clone)]
+pub struct FilesystemContentStore {
+    root: Arc<PathBuf>,
+}
+
+impl FilesystemContentStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: Arc::new(root.into()),
+        }
+    }
+
+    fn path_of(&self, key: &str) -> Result<PathBuf, SmError> {
+        let mut path = self.root.to_path_buf();
+        for segment in key.split('/') {
+            if segment.is_empty() || segment == "." || segment == ".." || segment.contains('\\') {
+                return Err(StorageError::Arbitrary.into());
+            }
+            path.push(segment);
+        }
+        Ok(path)
+    }
+}
+
+#[async_trait]
+impl ContentStore for FilesystemContentStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), SmError> {
+        let path = self.path_of(key)?;
+        let directory = path.parent().unwrap_or(Path::new("."));
+        tokio::fs::create_dir_all(directory)
+            .await
+            .map_err(|_| SmError::from(StorageError::Arbitrary))?;
+
+        // Write to a temporary file first so readers never see a partially written object
+        let temporary_path = directory.join(format!(".{}.tmp", Uuid::new_v4()));
+        if tokio::fs::write(&temporary_path, data).await.is_err()
+            || tokio::fs::rename(&temporary_path, &path).await.is_err()
+        {
+            let _ = tokio::fs::remove_file(&temporary_path).await;
+            return Err(StorageError::Arbitrary.into());
+        }
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, SmError> {
+        match tokio::fs::read(self.path_of(key)?).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(_) => Err(StorageError::Arbitrary.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), SmError> {
+        match tokio::fs::remove_file(self.path_of(key)?).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(_) => Err(StorageError::Arbitrary.into()),
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use domain::{content::ContentStore, error::SmError};
+use sqlx::PgPool;
+
+use crate::db::database_error;
+
+/// Keeps objects in the `sm.content_objects` table, for deployments without separate storage.
+#[derive(Clone)]
+pub struct PostgresContentStore {
+    pool: Arc<PgPool>,
+}
+
+impl PostgresContentStore {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ContentStore for PostgresContentStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), SmError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO sm.content_objects (key, data)
+            VALUES ($1, $2)
+            ON CONFLICT (key) DO UPDATE SET data = EXCLUDED.data
+            "#,
+            key,
+            data
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, SmError> {
+        let result = sqlx::query!(
+            r#"
+            SELECT data
+            FROM sm.content_objects
+            WHERE key = $1
+            "#,
+            key
+        )
+        .fetch_optional(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(result.map(|r| r.data))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), SmError> {
+        sqlx::query!(
+            r#"
+            DELETE FROM sm.content_objects
+            WHERE key = $1
+            "#,
+            key
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use domain::{
+    content::ContentStore,
+    error::{SmError, StorageError},
+};
+use object_store::{
+    aws::{AmazonS3, AmazonS3Builder},
+    path::Path,
+    ObjectStore, PutPayload,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct S3Config {
+    /// Base URL of an S3-compatible service such as MinIO; AWS itself when unset.
+    pub endpoint: Option<String>,
+    pub bucket: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
+/// Keeps objects in an S3 bucket. Custom endpoints are addressed path-style, which is what
+/// MinIO and most other S3-compatible services expect.
+#[derive(Clone)]
+pub struct S3ContentStore {
+    store: Arc<AmazonS3>,
+}
+
+impl S3ContentStore {
+    pub fn new(config: S3Config) -> Result<Self, SmError> {
+        let mut builder = AmazonS3Builder::new()
+            .with_bucket_name(config.bucket)
+            .with_region(config.region)
+            .with_access_key_id(config.access_key_id)
+            .with_secret_access_key(config.secret_access_key);
+        if let Some(endpoint) = config.endpoint {
+            builder = builder
+                .with_allow_http(endpoint.starts_with("http://"))
+                .with_endpoint(endpoint)
+                .with_virtual_hosted_style_request(false);
+        }
+        let store = builder
+            .build()
+            .map_err(|_| SmError::from(StorageError::Arbitrary))?;
+
+        Ok(Self {
+            store: Arc::new(store),
+        })
+    }
+}
+
+#[async_trait]
+impl ContentStore for S3ContentStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), SmError> {
+        self.store
+            .put(&Path::from(key), PutPayload::from(data))
+            .await
+            .map_err(|_| SmError::from(StorageError::Arbitrary))?;
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, SmError> {
+        let result = match self.store.get(&Path::from(key)).await {
+            Ok(result) => result,
+            Err(object_store::Error::NotFound { .. }) => return Ok(None),
+            Err(_) => return Err(StorageError::Arbitrary.into()),
+        };
+        let data = result
+            .bytes()
+            .await
+            .map_err(|_| SmError::from(StorageError::Arbitrary))?;
+
+        Ok(Some(data.to_vec()))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), SmError> {
+        match self.store.delete(&Path::from(key)).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(_) => Err(StorageError::Arbitrary.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use domain::content::ContentStore;
+    use uuid::Uuid;
+
+    use super::S3ContentStore;
+    use crate::testing::s3_config;
+
+    #[tokio::test]
+    async fn objects_are_kept_in_the_bucket() {
+        let Some(config) = s3_config() else {
+            eprintln!("SM_TEST_S3_ENDPOINT is not set, skipping the S3 content store");
+            return;
+        };
+        let store = S3ContentStore::new(config).unwrap();
+        let key = format!("test/{}", Uuid::new_v4());
+
+        assert_eq!(store.get(&key).await.unwrap(), None);
+        store.put(&key, b"first".to_vec()).await.unwrap();
+        store.put(&key, b"second".to_vec()).await.unwrap();
+        assert_eq!(store.get(&key).await.unwrap(), Some(b"second".to_vec()));
+        store.delete(&key).await.unwrap();
+        assert_eq!(store.get(&key).await.unwrap(), None);
+        // Deleting what isn't there is not an error, the collector may get there twice
+        store.delete(&key).await.unwrap();
+    }
+}
@@ -1,11 +1,18 @@
 use base64::Engine;
 use bcrypt::{hash, verify, DEFAULT_COST};
-use domain::{crypto::CryptographyService, user::PasswordService};
+use domain::{
+    crypto::{ContentHasher, CryptographyService},
+    user::PasswordService,
+};
 use openssl::{
+    encrypt::{Decrypter, Encrypter},
     hash::MessageDigest,
     pkey::PKey,
+    rand::rand_bytes,
     rsa::{Padding, Rsa},
+    sha::Sha256,
     sign::{Signer, Verifier},
+    symm::{decrypt_aead, encrypt_aead, Cipher},
 };
 #[derive(Clone)]
 pub struct BcryptPasswordService;
@@ -20,10 +27,28 @@ impl PasswordService for BcryptPasswordService {
     }
 }
 
+pub struct OpensslContentHasher(Sha256);
+
+impl ContentHasher for OpensslContentHasher {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finish(self) -> String {
+        self.0
+            .finish()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
 #[derive(Clone)]
 pub struct OpensslCryptographyService;
 
 impl CryptographyService for OpensslCryptographyService {
+    type ContentHasher = OpensslContentHasher;
+
     fn validate_public_key(&self, public_key: &str) -> bool {
         let engine = base64::engine::general_purpose::STANDARD;
         let bytes = match engine.decode(public_key) {
@@ -38,31 +63,24 @@ impl CryptographyService for OpensslCryptographyService {
         signature_base64: &str,
         public_key: &str,
     ) -> bool {
-        let engine = base64::engine::general_purpose::STANDARD;
-        let plaintext_bytes = plaintext.as_bytes();
-        let signature = engine
-            .decode(signature_base64)
-            .expect("Expected base64 encoded signature");
-        let public_key = engine
-            .decode(public_key)
-            .expect("Expected base64 encoded public key");
-        let key =
-            PKey::public_key_from_der(&public_key).expect("Public key reading from DER failed");
-        let mut verifier =
-            Verifier::new(MessageDigest::sha256(), &key).expect("Verifier creation failed");
-        verifier.set_rsa_padding(Padding::PKCS1_PSS).unwrap();
-        verifier
-            .set_rsa_pss_saltlen(openssl::sign::RsaPssSaltlen::DIGEST_LENGTH)
-            .unwrap();
-        verifier
-            .verify_oneshot(&signature, plaintext_bytes)
-            .unwrap_or(false)
+        // Signatures and keys come from clients and other servers, anything unreadable simply
+        // doesn't verify
+        let verify = || -> Result<bool, Box<dyn std::error::Error>> {
+            let engine = base64::engine::general_purpose::STANDARD;
+            let signature = engine.decode(signature_base64)?;
+            let key = PKey::public_key_from_der(&engine.decode(public_key)?)?;
+            let mut verifier = Verifier::new(MessageDigest::sha256(), &key)?;
+            verifier.set_rsa_padding(Padding::PKCS1_PSS)?;
+            verifier.set_rsa_pss_saltlen(openssl::sign::RsaPssSaltlen::DIGEST_LENGTH)?;
+            Ok(verifier.verify_oneshot(&signature, plaintext.as_bytes())?)
+        };
+        verify().unwrap_or(false)
     }
 
     fn generate_key_pair(&self) -> Result<(String, String), Box<dyn std::error::Error>> {
         let rsa = Rsa::generate(2048)?;
         let private_key = rsa.private_key_to_pem()?;
-        let public_key = rsa.public_key_to_pem()?;
+        let public_key = rsa.public_key_to_der()?;
 
         let engine = base64::engine::general_purpose::STANDARD;
         let private_key_base64 = engine.encode(&private_key);
@@ -71,14 +89,21 @@ impl CryptographyService for OpensslCryptographyService {
         Ok((public_key_base64, private_key_base64))
     }
 
+    fn public_key_of(&self, private_key: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let engine = base64::engine::general_purpose::STANDARD;
+        let private_key_pem = engine.decode(private_key)?;
+        let key = PKey::private_key_from_pem(&private_key_pem)?;
+        Ok(engine.encode(key.public_key_to_der()?))
+    }
+
     fn produce_signature(
         &self,
         plaintext: &str,
         private_key: &str,
     ) -> Result<String, Box<dyn std::error::Error>> {
         let engine = base64::engine::general_purpose::STANDARD;
-        let private_key_der = engine.decode(private_key)?;
-        let key = PKey::private_key_from_pem(&private_key_der)?;
+        let private_key_pem = engine.decode(private_key)?;
+        let key = PKey::private_key_from_pem(&private_key_pem)?;
 
         let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
         signer.set_rsa_padding(Padding::PKCS1_PSS)?;
@@ -88,4 +113,112 @@ impl CryptographyService for OpensslCryptographyService {
 
         Ok(engine.encode(signature))
     }
+
+    fn seal(
+        &self,
+        plaintext: &[u8],
+        public_key: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let engine = base64::engine::general_purpose::STANDARD;
+        let public_key_der = engine.decode(public_key)?;
+        let key = PKey::public_key_from_der(&public_key_der)?;
+
+        let mut encrypter = Encrypter::new(&key)?;
+        encrypter.set_rsa_padding(Padding::PKCS1_OAEP)?;
+        let mut sealed = vec![0; encrypter.encrypt_len(plaintext)?];
+        let length = encrypter.encrypt(plaintext, &mut sealed)?;
+        sealed.truncate(length);
+
+        Ok(engine.encode(sealed))
+    }
+
+    fn unseal(
+        &self,
+        sealed_base64: &str,
+        private_key: &str,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let engine = base64::engine::general_purpose::STANDARD;
+        let sealed = engine.decode(sealed_base64)?;
+        let private_key_pem = engine.decode(private_key)?;
+        let key = PKey::private_key_from_pem(&private_key_pem)?;
+
+        let mut decrypter = Decrypter::new(&key)?;
+        decrypter.set_rsa_padding(Padding::PKCS1_OAEP)?;
+        let mut plaintext = vec![0; decrypter.decrypt_len(&sealed)?];
+        let length = decrypter.decrypt(&sealed, &mut plaintext)?;
+        plaintext.truncate(length);
+
+        Ok(plaintext)
+    }
+
+    fn encrypt(
+        &self,
+        plaintext: &[u8],
+        public_key: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let engine = base64::engine::general_purpose::STANDARD;
+        let mut key = [0; 32];
+        let mut nonce = [0; 12];
+        rand_bytes(&mut key)?;
+        rand_bytes(&mut nonce)?;
+        let sealed_key = engine.decode(self.seal(&key, public_key)?)?;
+
+        let mut tag = [0; 16];
+        let ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &key,
+            Some(&nonce),
+            &[],
+            plaintext,
+            &mut tag,
+        )?;
+
+        let mut encrypted = Vec::with_capacity(2 + sealed_key.len() + 12 + ciphertext.len() + 16);
+        encrypted.extend_from_slice(&u16::try_from(sealed_key.len())?.to_be_bytes());
+        encrypted.extend_from_slice(&sealed_key);
+        encrypted.extend_from_slice(&nonce);
+        encrypted.extend_from_slice(&ciphertext);
+        encrypted.extend_from_slice(&tag);
+        Ok(engine.encode(encrypted))
+    }
+
+    fn decrypt(
+        &self,
+        encrypted_base64: &str,
+        private_key: &str,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let engine = base64::engine::general_purpose::STANDARD;
+        let encrypted = engine.decode(encrypted_base64)?;
+        let (length, rest) = encrypted.split_first_chunk::<2>().ok_or("Truncated key")?;
+        let length = u16::from_be_bytes(*length) as usize;
+        if rest.len() < length + 12 + 16 {
+            return Err("Truncated ciphertext".into());
+        }
+        let (sealed_key, rest) = rest.split_at(length);
+        let (nonce, rest) = rest.split_at(12);
+        let (ciphertext, tag) = rest.split_at(rest.len() - 16);
+        let key = self.unseal(&engine.encode(sealed_key), private_key)?;
+
+        let plaintext = decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &key,
+            Some(nonce),
+            &[],
+            ciphertext,
+            tag,
+        )?;
+        Ok(plaintext)
+    }
+
+    fn key_fingerprint(&self, public_key: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let engine = base64::engine::general_purpose::STANDARD;
+        let public_key_der = engine.decode(public_key)?;
+        let mut hasher = self.content_hasher();
+        hasher.update(&public_key_der);
+        Ok(hasher.finish())
+    }
+
+    fn content_hasher(&self) -> Self::ContentHasher {
+        OpensslContentHasher(Sha256::new())
+    }
 }
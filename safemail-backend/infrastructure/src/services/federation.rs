@@ -0,0 +1,284 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use domain::{
+    error::{FederationError, SmError},
+    federation::{
+        Address, FederationClient, RemoteDelivery, RemoteUser, ServerIdentity, SignedRequest,
+    },
+    serde::de::DeserializeOwned,
+    stamp::{OneTimeStampRequest, OnetimeStamp},
+};
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect::Policy,
+    Client, RequestBuilder, Response, StatusCode, Url,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::FederationConfig;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Version of the API other servers are spoken to in.
+const API_PREFIX: &str = "/v1";
+
+pub const ORIGIN_HEADER: &str = "X-Safemail-Origin";
+pub const REQUEST_ID_HEADER: &str = "X-Safemail-Request-Id";
+pub const DATE_HEADER: &str = "X-Safemail-Date";
+pub const SIGNATURE_HEADER: &str = "X-Safemail-Signature";
+
+/// How other servers are reached. Plain HTTP is only meant for running several instances
+/// locally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FederationScheme {
+    Https,
+    Http,
+}
+
+impl FederationScheme {
+    fn as_str(&self) -> &'static str {
+        match self {
+            FederationScheme::Https => "https",
+            FederationScheme::Http => "http",
+        }
+    }
+}
+
+/// Whether the address may be on the public internet, as opposed to loopback, private,
+/// link-local, shared, documentation or otherwise special ranges.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                || (a == 100 && b & 0xc0 == 64)
+                || (a == 198 && b & 0xfe == 18)
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80
+                    || first == 0x2001 && ip.segments()[1] == 0x0db8)
+            }
+        },
+    }
+}
+
+/// Resolves peers' host names, leaving out the addresses that aren't public. Checking the
+/// addresses connected to, rather than the names, also covers names that resolve differently
+/// from one lookup to the next.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addresses: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|address| is_public(address.ip()))
+                .collect();
+            if addresses.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            Ok(Box::new(addresses.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Reaches other servers over the configured scheme, by name only.
+#[derive(Clone)]
+pub struct HttpFederationClient {
+    client: Client,
+    scheme: FederationScheme,
+    allow_private_addresses: bool,
+}
+
+impl HttpFederationClient {
+    pub fn new(config: &FederationConfig) -> Self {
+        // A redirect could point anywhere, IP addresses included
+        let mut builder = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .redirect(Policy::none());
+        if !config.allow_private_addresses {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+        let client = builder
+            .build()
+            .expect("Failed to build the federation client");
+        Self {
+            client,
+            scheme: config.scheme,
+            allow_private_addresses: config.allow_private_addresses,
+        }
+    }
+
+    /// Refuses URLs whose host is an IP address, as those are connected to without being
+    /// resolved.
+    fn url(&self, host: &str, path: &str) -> Result<Url, SmError> {
+        let url = Url::parse(&format!("{}://{}{}", self.scheme.as_str(), host, path))
+            .map_err(|_| FederationError::InvalidAddress)?;
+        if !self.allow_private_addresses && url.domain().is_none() {
+            return Err(FederationError::InvalidAddress.into());
+        }
+        Ok(url)
+    }
+
+    fn api_url(&self, host: &str, path: &str) -> Result<Url, SmError> {
+        self.url(host, &format!("{}{}", API_PREFIX, path))
+    }
+
+    async fn post_signed<T: DeserializeOwned>(
+        &self,
+        host: &str,
+        path: &str,
+        request: &SignedRequest,
+    ) -> Result<T, SmError> {
+        let response = send(
+            self.client
+                .post(self.api_url(host, path)?)
+                .header(ORIGIN_HEADER, &request.origin)
+                .header(REQUEST_ID_HEADER, request.id.to_string())
+                .header(DATE_HEADER, request.sent_at.to_rfc3339())
+                .header(SIGNATURE_HEADER, &request.signature)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(request.body.clone()),
+        )
+        .await?;
+        read_response(response).await
+    }
+}
+
+async fn send(request: RequestBuilder) -> Result<Response, SmError> {
+    let response = request
+        .send()
+        .await
+        .map_err(|_| FederationError::PeerUnreachable)?;
+    // Redirects aren't followed, a peer that answers with one is as good as unreachable
+    if response.status().is_redirection() {
+        return Err(FederationError::PeerUnreachable.into());
+    }
+    Ok(response)
+}
+
+async fn read_response<T: DeserializeOwned>(response: Response) -> Result<T, SmError> {
+    let status = response.status();
+    if !status.is_success() {
+        let text = response.text().await.unwrap_or_default();
+        // Peers answer with the error envelope, older ones with plain text
+        let error = serde_json::from_str::<serde_json::Value>(&text)
+            .ok()
+            .and_then(|body| body.get("error").cloned());
+        let field = |name: &str| {
+            error
+                .as_ref()
+                .and_then(|error| error.get(name))
+                .and_then(|value| value.as_str())
+                .map(str::to_string)
+        };
+        return Err(FederationError::RemoteRejected {
+            status: status.as_u16(),
+            code: field("code"),
+            message: field("message").unwrap_or(text),
+        }
+        .into());
+    }
+    response.json().await.map_err(|_| {
+        FederationError::RemoteRejected {
+            status: status.as_u16(),
+            code: None,
+            message: "Malformed response".to_string(),
+        }
+        .into()
+    })
+}
+
+#[async_trait]
+impl FederationClient for HttpFederationClient {
+    async fn fetch_identity(&self, host: &str) -> Result<ServerIdentity, SmError> {
+        let response = send(self.client.get(self.url(host, "/.well-known/safemail")?)).await?;
+        read_response(response).await
+    }
+
+    async fn fetch_user(&self, address: &Address) -> Result<Option<RemoteUser>, SmError> {
+        let response = send(
+            self.client
+                .get(self.api_url(&address.host, &format!("/user/{}", address.username))?),
+        )
+        .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        read_response(response).await.map(Some)
+    }
+
+    async fn deliver_message(
+        &self,
+        host: &str,
+        request: &SignedRequest,
+    ) -> Result<RemoteDelivery, SmError> {
+        self.post_signed(host, "/federation/deliver", request).await
+    }
+
+    async fn request_system_stamp(
+        &self,
+        host: &str,
+        request: &SignedRequest,
+    ) -> Result<OneTimeStampRequest, SmError> {
+        self.post_signed(host, "/federation/stamp/request_system_issue", request)
+            .await
+    }
+
+    async fn issue_system_stamp(
+        &self,
+        host: &str,
+        request: &SignedRequest,
+    ) -> Result<OnetimeStamp, SmError> {
+        self.post_signed(host, "/federation/stamp/system_issue", request)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_public;
+
+    #[test]
+    fn is_public_leaves_out_internal_ranges() {
+        for ip in ["93.184.216.34", "1.1.1.1", "2606:4700:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{} was left out", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{} was let through", ip);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::{sync::Arc, time::Duration};
+
+use domain::{error::SmError, message::NewMessageNotification};
+use sqlx::{postgres::PgListener, PgPool};
+use tokio::sync::broadcast;
+
+use crate::db::database_error;
+
+const NEW_MESSAGE_CHANNEL: &str = "sm_new_message";
+const SUBSCRIBER_BUFFER: usize = 1024;
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Relays `sm_new_message` notifications, raised by a trigger on `sm.messages`, to in-process
+/// subscribers. Every API process listens on its own, so inserts made by any of them are seen.
+#[derive(Clone)]
+pub struct PostgresMessageNotifier {
+    sender: broadcast::Sender<NewMessageNotification>,
+}
+
+impl PostgresMessageNotifier {
+    pub async fn listen(pool: Arc<PgPool>) -> Result<Self, SmError> {
+        let listener = Self::connect(&pool).await?;
+        let (sender, _) = broadcast::channel(SUBSCRIBER_BUFFER);
+        tokio::spawn(Self::relay(pool, listener, sender.clone()));
+        Ok(Self { sender })
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<NewMessageNotification> {
+        self.sender.subscribe()
+    }
+
+    async fn connect(pool: &PgPool) -> Result<PgListener, SmError> {
+        let mut listener = PgListener::connect_with(pool)
+            .await
+            .map_err(database_error)?;
+        listener
+            .listen(NEW_MESSAGE_CHANNEL)
+            .await
+            .map_err(database_error)?;
+        Ok(listener)
+    }
+
+    async fn relay(
+        pool: Arc<PgPool>,
+        mut listener: PgListener,
+        sender: broadcast::Sender<NewMessageNotification>,
+    ) {
+        loop {
+            match listener.recv().await {
+                Ok(notification) => {
+                    if let Ok(message) =
+                        serde_json::from_str::<NewMessageNotification>(notification.payload())
+                    {
+                        // Sending only fails when nobody is subscribed, which is fine
+                        let _ = sender.send(message);
+                    }
+                }
+                Err(_) => {
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    if let Ok(reconnected) = Self::connect(&pool).await {
+                        listener = reconnected;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use domain::{error::SmError, rate_limit::RateLimiter};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+mod memory;
+mod postgres;
+pub use memory::*;
+pub use postgres::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimiterBackend {
+    /// Counts per instance.
+    Memory,
+    /// Shares the counts between every instance using the database.
+    Postgres,
+}
+
+/// The rate limiter backend selected by the configuration.
+#[derive(Clone)]
+pub enum ConfiguredRateLimiter {
+    Memory(MemoryRateLimiter),
+    Postgres(PostgresRateLimiter),
+}
+
+impl ConfiguredRateLimiter {
+    pub fn new(backend: RateLimiterBackend, pool: Arc<PgPool>) -> Self {
+        match backend {
+            RateLimiterBackend::Memory => Self::Memory(MemoryRateLimiter::new()),
+            RateLimiterBackend::Postgres => Self::Postgres(PostgresRateLimiter::new(pool)),
+        }
+    }
+}
+
+#[async_trait]
+impl RateLimiter for ConfiguredRateLimiter {
+    async fn hit(&self, key: &str, window_end: DateTime<Utc>) -> Result<i64, SmError> {
+        match self {
+            Self::Memory(limiter) => limiter.hit(key, window_end).await,
+            Self::Postgres(limiter) => limiter.hit(key, window_end).await,
+        }
+    }
+
+    async fn purge(&self, ended_before: DateTime<Utc>) -> Result<u64, SmError> {
+        match self {
+            Self::Memory(limiter) => limiter.purge(ended_before).await,
+            Self::Postgres(limiter) => limiter.purge(ended_before).await,
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use domain::{error::SmError, rate_limit::RateLimiter};
+
+/// Requests counted per key and window end.
+type Windows = HashMap<(String, DateTime<Utc>), i64>;
+
+/// Counts requests in the memory of this instance, for deployments running a single one.
+#[derive(Clone, Default)]
+pub struct MemoryRateLimiter {
+    windows: Arc<Mutex<Windows>>,
+}
+
+impl MemoryRateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl RateLimiter for MemoryRateLimiter {
+    async fn hit(&self, key: &str, window_end: DateTime<Utc>) -> Result<i64, SmError> {
+        let mut windows = self.windows.lock().unwrap();
+        let hits = windows.entry((key.to_string(), window_end)).or_insert(0);
+        *hits += 1;
+        Ok(*hits)
+    }
+
+    async fn purge(&self, ended_before: DateTime<Utc>) -> Result<u64, SmError> {
+        let mut windows = self.windows.lock().unwrap();
+        let before = windows.len();
+        windows.retain(|(_, window_end), _| *window_end >= ended_before);
+        Ok((before - windows.len()) as u64)
+    }
+}
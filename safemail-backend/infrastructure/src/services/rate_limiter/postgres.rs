@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use domain::{error::SmError, rate_limit::RateLimiter};
+use sqlx::PgPool;
+
+use crate::db::database_error;
+
+/// Counts requests in the `sm.rate_limit_windows` table, so that every instance shares them.
+#[derive(Clone)]
+pub struct PostgresRateLimiter {
+    pool: Arc<PgPool>,
+}
+
+impl PostgresRateLimiter {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl RateLimiter for PostgresRateLimiter {
+    async fn hit(&self, key: &str, window_end: DateTime<Utc>) -> Result<i64, SmError> {
+        let record = sqlx::query!(
+            r#"
+            INSERT INTO sm.rate_limit_windows (key, window_end)
+            VALUES ($1, $2)
+            ON CONFLICT (key, window_end)
+            DO UPDATE SET hits = rate_limit_windows.hits + 1
+            RETURNING hits
+            "#,
+            key,
+            window_end
+        )
+        .fetch_one(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(record.hits)
+    }
+
+    async fn purge(&self, ended_before: DateTime<Utc>) -> Result<u64, SmError> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM sm.rate_limit_windows
+            WHERE window_end < $1
+            "#,
+            ended_before
+        )
+        .execute(&*self.pool)
+        .await
+        .map_err(database_error)?;
+
+        Ok(result.rows_affected())
+    }
+}
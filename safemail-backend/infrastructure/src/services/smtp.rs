@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use domain::{
+    error::{BridgeError, SmError},
+    outbound_mail::SmtpRelay,
+};
+use lettre::{
+    address::Envelope,
+    transport::smtp::{
+        authentication::Credentials,
+        client::{Tls, TlsParameters},
+    },
+    Address, AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+};
+use serde::{Deserialize, Serialize};
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The SMTP server bridged mail is relayed through.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SmtpRelayConfig {
+    pub host: String,
+    pub port: u16,
+    pub starttls: bool,
+    /// Authenticate as this user, if set.
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+/// Relays bridged mail through the configured SMTP server.
+#[derive(Clone)]
+pub struct LettreSmtpRelay {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl LettreSmtpRelay {
+    /// Speaks plain SMTP unless `starttls` is set, and authenticates only if a username is.
+    pub fn new(config: &SmtpRelayConfig) -> Self {
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host)
+            .port(config.port)
+            .timeout(Some(SMTP_TIMEOUT));
+        if config.starttls {
+            let parameters = TlsParameters::new(config.host.clone())
+                .expect("Failed to set up TLS for the SMTP relay");
+            builder = builder.tls(Tls::Required(parameters));
+        }
+        if let Some(username) = &config.username {
+            let password = config.password.clone().unwrap_or_default();
+            builder = builder.credentials(Credentials::new(username.clone(), password));
+        }
+        Self {
+            transport: builder.build(),
+        }
+    }
+}
+
+#[async_trait]
+impl SmtpRelay for LettreSmtpRelay {
+    async fn relay(
+        &self,
+        from: &str,
+        recipients: &[String],
+        message: &[u8],
+    ) -> Result<(), SmError> {
+        let invalid = |e: lettre::address::AddressError| BridgeError::RelayRejected(e.to_string());
+        let from = from.parse::<Address>().map_err(invalid)?;
+        let recipients = recipients
+            .iter()
+            .map(|recipient| recipient.parse::<Address>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid)?;
+        let envelope = Envelope::new(Some(from), recipients)
+            .map_err(|e| SmError::from(BridgeError::RelayRejected(e.to_string())))?;
+
+        match self.transport.send_raw(&envelope, message).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.is_permanent() => Err(BridgeError::RelayRejected(e.to_string()).into()),
+            Err(e) => Err(BridgeError::RelayUnavailable(e.to_string()).into()),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::str::FromStr;
+
+use reqwest::Url;
+use sqlx::{postgres::PgConnectOptions, ConnectOptions, Executor, PgConnection};
+use uuid::Uuid;
+
+use crate::services::content_store::S3Config;
+
+/// A migrated database of its own on the server `DATABASE_URL` points at, dropped along with
+/// the value, so that tests can run side by side without seeing each other's rows.
+pub struct TestDatabase {
+    pub url: String,
+    server_url: String,
+    name: String,
+}
+
+impl TestDatabase {
+    pub async fn create() -> Self {
+        let server_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let name = format!("sm_test_{}", Uuid::new_v4().simple());
+        let mut url = Url::parse(&server_url).expect("DATABASE_URL is not a URL");
+        url.set_path(&name);
+
+        let mut connection = connect(&server_url).await;
+        connection
+            .execute(format!(r#"CREATE DATABASE "{}""#, name).as_str())
+            .await
+            .expect("Failed to create the test database");
+        let mut connection = connect(url.as_str()).await;
+        sqlx::migrate!()
+            .run(&mut connection)
+            .await
+            .expect("Failed to migrate the test database");
+
+        Self {
+            url: url.to_string(),
+            server_url,
+            name,
+        }
+    }
+}
+
+impl TestDatabase {
+    /// Runs a statement against the database, for arranging what the API offers no way to.
+    pub async fn execute(&self, statement: &str) {
+        connect(&self.url)
+            .await
+            .execute(statement)
+            .await
+            .expect("Failed to run a statement against the test database");
+    }
+
+    /// Runs a query returning a single text column, for looking at what the API doesn't show.
+    pub async fn strings(&self, query: &str) -> Vec<String> {
+        sqlx::query_scalar(query)
+            .fetch_all(&mut connect(&self.url).await)
+            .await
+            .expect("Failed to query the test database")
+    }
+}
+
+/// The S3-compatible service to test the S3 content store against, such as MinIO or
+/// `moto_server`, named by `SM_TEST_S3_ENDPOINT`. Its bucket, `SM_TEST_S3_BUCKET` or `safemail`,
+/// has to exist, and `SM_TEST_S3_ACCESS_KEY_ID` and `SM_TEST_S3_SECRET_ACCESS_KEY` default to
+/// `test`. Gives `None`, and the tests skip the S3 store, if no endpoint is set.
+pub fn s3_config() -> Option<S3Config> {
+    let var = |name, default: &str| std::env::var(name).unwrap_or_else(|_| default.to_string());
+    let endpoint = std::env::var("SM_TEST_S3_ENDPOINT").ok()?;
+    Some(S3Config {
+        endpoint: Some(endpoint),
+        bucket: var("SM_TEST_S3_BUCKET", "safemail"),
+        region: "us-east-1".to_string(),
+        access_key_id: var("SM_TEST_S3_ACCESS_KEY_ID", "test"),
+        secret_access_key: var("SM_TEST_S3_SECRET_ACCESS_KEY", "test"),
+    })
+}
+
+async fn connect(url: &str) -> PgConnection {
+    PgConnectOptions::from_str(url)
+        .expect("DATABASE_URL is not a database URL")
+        .disable_statement_logging()
+        .connect()
+        .await
+        .expect("Failed to connect to the database server")
+}
+
+impl Drop for TestDatabase {
+    /// Drops the database on a runtime of its own, as the test's may be shutting down, and
+    /// whatever connections are still open to it with it.
+    fn drop(&mut self) {
+        let server_url = self.server_url.clone();
+        let statement = format!(r#"DROP DATABASE IF EXISTS "{}" WITH (FORCE)"#, self.name);
+        let dropped = std::thread::spawn(move || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Failed to start a runtime")
+                .block_on(async {
+                    let mut connection = connect(&server_url).await;
+                    connection.execute(statement.as_str()).await.map(|_| ())
+                })
+        })
+        .join();
+        if !matches!(dropped, Ok(Ok(()))) {
+            eprintln!("failed to drop the test database {}", self.name);
+        }
+    }
+}